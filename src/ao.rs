@@ -0,0 +1,386 @@
+// 在 CPU 上烘焙逐顶点的环境光遮蔽：瓦片内所有三角形建一棵 BVH，每个顶点沿法线半球发射固定方向的光线，
+// 每个网格最低点所在的水平面当作它的地面，墙脚、天井和窄巷因此变暗
+
+use crate::mesh::Mesh;
+use crate::parallel;
+
+/// 环境光遮蔽参数，每个顶点的光线数和光线的最远距离（米）
+pub struct AoOptions {
+    pub samples: u32,
+    pub distance: f32,
+}
+
+impl AoOptions {
+    /// 解析 `光线数[,距离]` 形式的参数，距离缺省为10米
+    pub fn parse(spec: &str) -> Option<AoOptions> {
+        let mut parts = spec.split(',').map(|x| x.trim());
+        let samples = parts.next()?.parse::<u32>().ok()?;
+        let distance = match parts.next() {
+            Some(x) => x.parse::<f32>().ok()?,
+            None => 10.,
+        };
+        if parts.next().is_some() || !(1..=1024).contains(&samples) || distance.is_nan() || distance <= 0. {
+            return None;
+        }
+        Some(AoOptions { samples, distance })
+    }
+}
+
+// 光线起点沿法线抬高的距离，避免打到顶点自己所在的面
+const BIAS: f32 = 1e-3;
+// 叶子节点最多的三角形数
+const LEAF_SIZE: usize = 4;
+
+struct Node {
+    min: [f32; 3],
+    max: [f32; 3],
+    // 叶子节点为第一个三角形的序号，内部节点为右子节点的序号（左子节点紧跟在后面）
+    offset: u32,
+    count: u32,
+}
+
+/// 三角形的层次包围盒，只用来判断光线在一定距离内是否被挡住
+pub struct Bvh {
+    nodes: Vec<Node>,
+    triangles: Vec<[[f32; 3]; 3]>,
+}
+
+impl Bvh {
+    pub fn new(mut triangles: Vec<[[f32; 3]; 3]>) -> Bvh {
+        let mut nodes = vec![];
+        if !triangles.is_empty() {
+            let len = triangles.len();
+            Self::build(&mut nodes, &mut triangles, 0, len);
+        }
+        Bvh { nodes, triangles }
+    }
+
+    // 按包围盒最长边上的重心中位数递归二分，同样的输入总是得到同样的树
+    fn build(nodes: &mut Vec<Node>, triangles: &mut [[[f32; 3]; 3]], start: usize, end: usize) -> usize {
+        let (min, max) = bounds(triangles[start..end].iter().flatten());
+        let id = nodes.len();
+        nodes.push(Node {
+            min,
+            max,
+            offset: start as u32,
+            count: (end - start) as u32,
+        });
+        if end - start <= LEAF_SIZE {
+            return id;
+        }
+        let (c_min, c_max) = bounds(triangles[start..end].iter().map(centroid).collect::<Vec<_>>().iter());
+        let axis = (0..3)
+            .max_by(|a, b| (c_max[*a] - c_min[*a]).total_cmp(&(c_max[*b] - c_min[*b])))
+            .unwrap();
+        let mid = (end - start) / 2;
+        triangles[start..end]
+            .select_nth_unstable_by(mid, |a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+        Self::build(nodes, triangles, start, start + mid);
+        let right = Self::build(nodes, triangles, start + mid, end);
+        nodes[id].offset = right as u32;
+        nodes[id].count = 0;
+        id
+    }
+
+    /// 从 `origin` 沿 `dir` 在 `max_t` 以内是否碰到三角形
+    pub fn occluded(&self, origin: [f32; 3], dir: [f32; 3], max_t: f32) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inv = dir.map(|x| 1. / x);
+        let mut stack = vec![0usize];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !hit_box(node, origin, inv, max_t) {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.offset as usize;
+                if self.triangles[first..first + node.count as usize]
+                    .iter()
+                    .any(|t| hit_triangle(t, origin, dir, max_t))
+                {
+                    return true;
+                }
+            } else {
+                stack.push(node.offset as usize);
+                stack.push(i + 1);
+            }
+        }
+        false
+    }
+}
+
+fn bounds<'a>(points: impl Iterator<Item = &'a [f32; 3]>) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    points.for_each(|p| {
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    });
+    (min, max)
+}
+
+fn centroid(t: &[[f32; 3]; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|k| (t[0][k] + t[1][k] + t[2][k]) / 3.)
+}
+
+// 光线与包围盒的 slab 求交
+fn hit_box(node: &Node, origin: [f32; 3], inv: [f32; 3], max_t: f32) -> bool {
+    let (mut t0, mut t1) = (0f32, max_t);
+    for k in 0..3 {
+        let a = (node.min[k] - origin[k]) * inv[k];
+        let b = (node.max[k] - origin[k]) * inv[k];
+        // 光线与某个面平行时 0 * inf 为 NaN，min/max 会忽略 NaN
+        t0 = t0.max(a.min(b));
+        t1 = t1.min(a.max(b));
+    }
+    t0 <= t1
+}
+
+// Möller–Trumbore 光线与三角形求交，不区分正反面
+fn hit_triangle(t: &[[f32; 3]; 3], origin: [f32; 3], dir: [f32; 3], max_t: f32) -> bool {
+    let e1 = sub(t[1], t[0]);
+    let e2 = sub(t[2], t[0]);
+    let p = cross(dir, e2);
+    let det = dot(e1, p);
+    if det.abs() < 1e-12 {
+        return false;
+    }
+    let s = sub(origin, t[0]);
+    let u = dot(s, p) / det;
+    if !(0. ..=1.).contains(&u) {
+        return false;
+    }
+    let q = cross(s, e1);
+    let v = dot(dir, q) / det;
+    if v < 0. || u + v > 1. {
+        return false;
+    }
+    let d = dot(e2, q) / det;
+    d > 0. && d <= max_t
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = dot(a, a).sqrt();
+    if len > 0. {
+        a.map(|x| x / len)
+    } else {
+        [0., 0., 1.]
+    }
+}
+
+// 余弦加权的半球方向，用 Hammersley 点集代替随机数，结果可以复现
+fn hemisphere(samples: u32) -> Vec<[f32; 3]> {
+    (0..samples)
+        .map(|i| {
+            let u1 = (i as f32 + 0.5) / samples as f32;
+            let u2 = i.reverse_bits() as f32 / 4294967296.;
+            let r = u1.sqrt();
+            let phi = 2. * std::f32::consts::PI * u2;
+            [r * phi.cos(), r * phi.sin(), (1. - u1).sqrt()]
+        })
+        .collect()
+}
+
+/// 给瓦片内所有网格烘焙逐顶点的环境光遮蔽，结果写进 `Mesh::occlusion`，返回处理的顶点数；
+/// 要在焊接顶点之后调用，各网格用 `threads` 个线程并行计算
+pub fn bake(meshes: &mut [Mesh], options: &AoOptions, threads: usize) -> usize {
+    let triangles: Vec<[[f32; 3]; 3]> = meshes
+        .iter()
+        .flat_map(|mesh| {
+            mesh.index
+                .iter()
+                .map(|t| t.map(|i| mesh.vertex[i as usize].map(|x| x as f32)))
+        })
+        .collect();
+    let bvh = Bvh::new(triangles);
+    let directions = hemisphere(options.samples);
+
+    let counts = parallel::map_mut(meshes, threads, |_, mesh| {
+        // 地形起伏时各建筑的底部高度不同，不能都用瓦片的最低点
+        let ground = mesh.vertex.iter().map(|p| p[2] as f32).fold(f32::MAX, f32::min);
+        let occlusion = mesh
+            .vertex
+            .iter()
+            .zip(mesh.normal.iter())
+            .map(|(p, n)| {
+                let n = normalize(*n);
+                // 以法线为 z 轴的正交基
+                let helper = if n[2].abs() < 0.9 { [0., 0., 1.] } else { [1., 0., 0.] };
+                let t = normalize(cross(helper, n));
+                let b = cross(n, t);
+                let origin = [0, 1, 2].map(|k| p[k] as f32 + n[k] * BIAS);
+                let hits = directions
+                    .iter()
+                    .filter(|d| {
+                        let dir = [0, 1, 2].map(|k| t[k] * d[0] + b[k] * d[1] + n[k] * d[2]);
+                        // 朝下的光线在最远距离内落到地面也算被挡住
+                        let to_ground = (origin[2] - ground) / -dir[2];
+                        (dir[2] < 0. && to_ground <= options.distance)
+                            || bvh.occluded(origin, dir, options.distance)
+                    })
+                    .count();
+                1. - hits as f32 / directions.len() as f32
+            })
+            .collect();
+        mesh.occlusion = Some(occlusion);
+        mesh.vertex.len()
+    });
+    counts.iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Face;
+
+    // 长方体建筑，四面墙和屋顶各自带平面法线，不含底面
+    fn cuboid(id: i32, min: [f64; 3], max: [f64; 3]) -> Mesh {
+        let mut mesh = Mesh::empty(id);
+        let [x0, y0, z0] = min;
+        let [x1, y1, z1] = max;
+        let quads = [
+            ([[x0, y0, z0], [x1, y0, z0], [x1, y0, z1], [x0, y0, z1]], [0., -1., 0.], Face::Wall),
+            ([[x1, y0, z0], [x1, y1, z0], [x1, y1, z1], [x1, y0, z1]], [1., 0., 0.], Face::Wall),
+            ([[x1, y1, z0], [x0, y1, z0], [x0, y1, z1], [x1, y1, z1]], [0., 1., 0.], Face::Wall),
+            ([[x0, y1, z0], [x0, y0, z0], [x0, y0, z1], [x0, y1, z1]], [-1., 0., 0.], Face::Wall),
+            ([[x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1]], [0., 0., 1.], Face::Roof),
+        ];
+        quads.iter().for_each(|(corners, normal, face)| {
+            let base = mesh.vertex.len() as i32;
+            mesh.vertex.extend_from_slice(corners);
+            mesh.normal.extend([*normal; 4]);
+            mesh.uv.extend([[0., 0.]; 4]);
+            mesh.index.push([base, base + 1, base + 2]);
+            mesh.index.push([base, base + 2, base + 3]);
+            mesh.face.extend([*face; 2]);
+        });
+        mesh
+    }
+
+    // 顶点位置和法线都相同的第一个顶点的遮蔽值
+    fn occlusion(mesh: &Mesh, p: [f64; 3], n: [f32; 3]) -> f32 {
+        let i = (0..mesh.vertex.len())
+            .find(|i| mesh.vertex[*i] == p && mesh.normal[*i] == n)
+            .unwrap();
+        mesh.occlusion.as_ref().unwrap()[i]
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(AoOptions::parse("16").map(|x| (x.samples, x.distance)), Some((16, 10.)));
+        assert_eq!(AoOptions::parse("8, 2.5").map(|x| (x.samples, x.distance)), Some((8, 2.5)));
+        for spec in ["0", "2000", "8,0", "8,-1", "8,2,3", "x"] {
+            assert!(AoOptions::parse(spec).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn ray_hits_triangle_within_distance() {
+        let t = [[0., 0., 1.], [2., 0., 1.], [0., 2., 1.]];
+        assert!(hit_triangle(&t, [0.5, 0.5, 0.], [0., 0., 1.], 2.));
+        assert!(!hit_triangle(&t, [0.5, 0.5, 0.], [0., 0., 1.], 0.5));
+        // 背面同样算碰到，反方向、三角形外、平行的光线都不算
+        assert!(hit_triangle(&t, [0.5, 0.5, 2.], [0., 0., -1.], 2.));
+        assert!(!hit_triangle(&t, [0.5, 0.5, 0.], [0., 0., -1.], 2.));
+        assert!(!hit_triangle(&t, [1.5, 1.5, 0.], [0., 0., 1.], 2.));
+        assert!(!hit_triangle(&t, [0.5, 0.5, 1.], [1., 0., 0.], 2.));
+    }
+
+    #[test]
+    fn bvh_agrees_with_brute_force() {
+        // 起伏的网格面，光线从上下两侧沿各个方向射出
+        let height = |x: usize, y: usize| ((x * 7 + y * 3) % 5) as f32 * 0.4;
+        let mut triangles = vec![];
+        for y in 0..12 {
+            for x in 0..12 {
+                let p = |x: usize, y: usize| [x as f32, y as f32, height(x, y)];
+                triangles.push([p(x, y), p(x + 1, y), p(x + 1, y + 1)]);
+                triangles.push([p(x, y), p(x + 1, y + 1), p(x, y + 1)]);
+            }
+        }
+        let bvh = Bvh::new(triangles.clone());
+        assert!(!Bvh::new(vec![]).occluded([0., 0., 0.], [0., 0., 1.], 10.));
+        let directions = hemisphere(32);
+        let mut hits = 0;
+        for k in 0..40 {
+            let origin = [(k * 5 % 13) as f32 + 0.3, (k * 3 % 11) as f32 + 0.6, if k % 2 == 0 { 3. } else { -1. }];
+            for d in &directions {
+                let dir = if k % 2 == 0 { [d[0], d[1], -d[2]] } else { *d };
+                for max_t in [1.5, 4., 20.] {
+                    let expected = triangles.iter().any(|t| hit_triangle(t, origin, dir, max_t));
+                    assert_eq!(bvh.occluded(origin, dir, max_t), expected);
+                    hits += expected as usize;
+                }
+            }
+        }
+        assert!(hits > 0 && hits < 40 * 32 * 3);
+    }
+
+    #[test]
+    fn wall_base_next_to_building_is_darker() {
+        let options = AoOptions::parse("64,10").unwrap();
+        // 建筑 A 东边1米外是更高的建筑 B
+        let mut meshes = vec![cuboid(0, [0., 0., 0.], [10., 10., 10.]), cuboid(1, [11., 0., 0.], [21., 10., 30.])];
+        assert_eq!(bake(&mut meshes, &options, 1), 40);
+        let roof = occlusion(&meshes[0], [0., 0., 10.], [0., 0., 1.]);
+        let open_wall = occlusion(&meshes[0], [0., 0., 0.], [-1., 0., 0.]);
+        let alley_wall = occlusion(&meshes[0], [10., 0., 0.], [1., 0., 0.]);
+        assert_eq!(roof, 1.);
+        assert!(alley_wall < open_wall && open_wall < roof, "{} {} {}", alley_wall, open_wall, roof);
+    }
+
+    #[test]
+    fn each_mesh_uses_its_own_ground() {
+        let options = AoOptions::parse("64,10").unwrap();
+        // B 建在高出5米的坡上，它的墙脚与平地上 A 的墙脚一样暗
+        let mut meshes = vec![cuboid(0, [0., 0., 0.], [10., 10., 10.]), cuboid(1, [11., 0., 5.], [21., 10., 30.])];
+        bake(&mut meshes, &options, 1);
+        let low = occlusion(&meshes[0], [0., 0., 0.], [-1., 0., 0.]);
+        let high = occlusion(&meshes[1], [21., 0., 5.], [1., 0., 0.]);
+        assert!(low < 1.);
+        assert_eq!(low, high);
+    }
+
+    #[test]
+    fn bake_is_repeatable_across_threads() {
+        let options = AoOptions::parse("32,6").unwrap();
+        let build = || {
+            (0..6)
+                .map(|i| {
+                    let x = (i % 3) as f64 * 7.;
+                    let y = (i / 3) as f64 * 9.;
+                    cuboid(i, [x, y, i as f64 * 0.5], [x + 5., y + 6., 4. + i as f64 * 3.])
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut first = build();
+        bake(&mut first, &options, 1);
+        let first: Vec<_> = first.into_iter().map(|m| m.occlusion.unwrap()).collect();
+        for threads in [1, 3, 8] {
+            let mut meshes = build();
+            bake(&mut meshes, &options, threads);
+            let occlusion: Vec<_> = meshes.into_iter().map(|m| m.occlusion.unwrap()).collect();
+            assert_eq!(occlusion, first);
+        }
+    }
+}
@@ -2,10 +2,12 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use gltf::Error;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::io;
 
-fn align_to_multiple_of_four(n: &mut usize) {
-    *n = (*n + 3) & !3;
+// b3dm 各部分需要按8字节对齐
+fn align_to_multiple_of_eight(n: &mut usize) {
+    *n = (*n + 7) & !7;
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -22,11 +24,12 @@ pub struct BatchTable {
     pub batch_id: Vec<u32>,
     pub height: Vec<f32>,
     pub name: Vec<String>,
+    /// shp 的其他属性列，与上面同名的字段不再重复写入
+    #[serde(flatten)]
+    pub properties: BTreeMap<String, Vec<serde_json::Value>>,
 }
 #[repr(C)]
 pub struct MakeB3dm<'a> {
-    pub feature_table_json_byte_length: usize,
-    pub batch_table_json_byte_length: usize,
     pub glb: Option<Cow<'a, [u8]>>,
 }
 
@@ -41,70 +44,41 @@ impl<'a> MakeB3dm<'a> {
     where
         W: io::Write,
     {
+        // 各部分都要从8字节对齐的位置开始：要素表 JSON 补齐到结尾对齐，批量表 JSON 和 glb 补齐到8的倍数
+        let mut feature_table_json_byte_length = 28 + feature_string_vec.len();
+        align_to_multiple_of_eight(&mut feature_table_json_byte_length);
+        feature_table_json_byte_length -= 28;
+        let mut batch_table_json_byte_length = table_string_vec.len();
+        align_to_multiple_of_eight(&mut batch_table_json_byte_length);
+        let glb = self.glb.as_deref().unwrap_or_default();
+        let mut glb_byte_length = glb.len();
+        align_to_multiple_of_eight(&mut glb_byte_length);
+
         // 写入b3dm文件头部
         {
             let magic = b"b3dm";
             let version = 1;
-            let feature_table_json_byte_length = self.feature_table_json_byte_length;
-            //此处b3dm的二进制为空，即为0
-            let feature_table_binary_byte_length = 0;
-            let batch_table_json_byte_length = self.batch_table_json_byte_length;
-            //此处b3dm的二进制为空，即为0
-            let batch_table_binary_byte_length = 0;
-            let mut length = (28
-                + feature_table_json_byte_length
-                + feature_table_binary_byte_length
-                + batch_table_json_byte_length
-                + batch_table_binary_byte_length) as usize;
-            align_to_multiple_of_four(&mut length);
-
-            if let Some(bin) = self.glb.as_ref() {
-                length += bin.len();
-
-                align_to_multiple_of_four(&mut length);
-            }
+            let length = 28 + feature_table_json_byte_length + batch_table_json_byte_length + glb_byte_length;
 
             writer.write_all(&magic[..])?;
             writer.write_u32::<LittleEndian>(version)?;
             writer.write_u32::<LittleEndian>(length as u32)?;
             writer.write_u32::<LittleEndian>(feature_table_json_byte_length as u32)?;
-            writer.write_u32::<LittleEndian>(feature_table_binary_byte_length as u32)?;
+            //此处b3dm的二进制为空，即为0
+            writer.write_u32::<LittleEndian>(0)?;
             writer.write_u32::<LittleEndian>(batch_table_json_byte_length as u32)?;
-            writer.write_u32::<LittleEndian>(batch_table_binary_byte_length as u32)?;
-        }
-
-        // Write JSON chunk header
-        {
-            let mut length1 = feature_string_vec.len();
-            align_to_multiple_of_four(&mut length1);
-            let padding = length1 - feature_string_vec.len();
-
-            writer.write_all(&feature_string_vec)?;
-            for _ in 0..padding {
-                writer.write_u8(0x20)?;
-            }
-        }
-
-        {
-            let mut length2 = table_string_vec.len();
-            align_to_multiple_of_four(&mut length2);
-            let padding = length2 - table_string_vec.len();
-
-            writer.write_all(&table_string_vec)?;
-            for _ in 0..padding {
-                writer.write_u8(0x20)?;
-            }
+            //此处b3dm的二进制为空，即为0
+            writer.write_u32::<LittleEndian>(0)?;
         }
 
-        if let Some(bin) = self.glb.as_ref() {
-            let mut length = bin.len();
-            align_to_multiple_of_four(&mut length);
-            let padding = length - bin.len();
-
-            // writer.write_u32::<LittleEndian>(length as u32)?;
-            writer.write_all(bin)?;
-            for _ in 0..padding {
-                writer.write_u8(0)?;
+        for (data, length, pad) in [
+            (&feature_string_vec[..], feature_table_json_byte_length, 0x20),
+            (&table_string_vec[..], batch_table_json_byte_length, 0x20),
+            (glb, glb_byte_length, 0),
+        ] {
+            writer.write_all(data)?;
+            for _ in 0..length - data.len() {
+                writer.write_u8(pad)?;
             }
         }
 
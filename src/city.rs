@@ -0,0 +1,409 @@
+// 把面、线图层生成的网格写成 CityJSON 1.1 或 CityGML 2.0：面要素为 Building，几何为屋顶、墙面、底面三角形
+// 围成的 Solid；线要素的墙体没有底面，写成 GenericCityObject 的 MultiSurface。坐标换回输入 shp 的坐标系，
+// 属性取自 dbf
+
+use crate::field;
+use crate::mesh::{self, Face, Mesh};
+use crate::tileset::Origin;
+use serde_json::{json, Map, Value};
+use shapefile::dbase::{FieldValue, Record};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// 输入 shp 的坐标系
+pub struct Crs {
+    pub epsg: Option<u32>,
+    /// 经纬度坐标，单位为度
+    pub geographic: bool,
+}
+
+/// 从 shp 旁的 .prj 文件读取坐标系：取最外层的 EPSG 编号，ESRI 格式没有编号时按名称识别 WGS 84 和 CGCS2000
+/// 的经纬度坐标；没有 .prj 文件时与生成 3D Tiles 一样按 WGS 84 经纬度处理
+pub fn read_crs(shp: &str) -> Crs {
+    let prj = Path::new(shp).with_extension("prj");
+    let Ok(wkt) = fs::read_to_string(&prj) else {
+        println!("没有找到{}，按 EPSG:4326 处理", prj.display());
+        return Crs {
+            epsg: Some(4326),
+            geographic: true,
+        };
+    };
+    let wkt = wkt.trim();
+    let geographic = wkt.starts_with("GEOGCS") || wkt.starts_with("GEOGCRS");
+    // WKT 中外层的 AUTHORITY 写在最后
+    let epsg = wkt
+        .rfind("AUTHORITY[\"EPSG\",")
+        .or_else(|| wkt.rfind("ID[\"EPSG\","))
+        .and_then(|i| wkt[i..].split(',').nth(1))
+        .and_then(|x| x.trim_matches(|c: char| !c.is_ascii_digit()).parse().ok())
+        .or_else(|| match geographic {
+            true if wkt.contains("WGS_1984") || wkt.contains("WGS 84") => Some(4326),
+            true if wkt.contains("China_2000") || wkt.contains("CGCS2000") => Some(4490),
+            _ => None,
+        });
+    if epsg.is_none() {
+        println!("{}中没有 EPSG 编号，输出中不写坐标系", prj.display());
+    }
+    Crs { epsg, geographic }
+}
+
+// 一个要素换回输入坐标系后的三角形：坐标为相对原点按 `scale` 量化的整数，量化后退化的三角形去掉；
+// 与网格一一对应，没有三角形的要素也保留，写出时跳过
+struct Solid<'a> {
+    id: &'a str,
+    triangles: Vec<([[i64; 3]; 3], Face)>,
+    /// 有底面的网格是封闭的
+    closed: bool,
+    /// 屋顶都是水平面时为 LOD1，有坡屋顶时为 LOD2
+    lod: u8,
+    height: f64,
+}
+
+fn scale(crs: &Crs) -> [f64; 3] {
+    // 经纬度 1e-8 度约为1毫米
+    match crs.geographic {
+        true => [1e-8, 1e-8, 0.001],
+        false => [0.001; 3],
+    }
+}
+
+fn solids<'a>(meshes: &'a [Mesh], origin: Origin, crs: &Crs) -> Vec<Solid<'a>> {
+    let (_, cy, _) = origin;
+    let scale = scale(crs);
+    meshes
+        .iter()
+        .map(|mesh| {
+            let vertex: Vec<[i64; 3]> = mesh
+                .vertex
+                .iter()
+                .map(|p| {
+                    let d = [mesh::meters_to_lon(p[0], cy), mesh::meters_to_lat(p[1]), p[2]];
+                    [0, 1, 2].map(|k| (d[k] / scale[k]).round() as i64)
+                })
+                .collect();
+            let triangles = mesh
+                .index
+                .iter()
+                .zip(&mesh.face)
+                .map(|(t, f)| (t.map(|i| vertex[i as usize]), *f))
+                .filter(|(t, _)| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+                .collect();
+            let flat = mesh
+                .index
+                .iter()
+                .zip(&mesh.face)
+                .filter(|(_, f)| **f == Face::Roof)
+                .all(|(t, _)| t.iter().all(|i| mesh.normal[*i as usize][2] > 0.999));
+            Solid {
+                id: &mesh.mesh_name,
+                triangles,
+                closed: mesh.face.contains(&Face::Bottom),
+                lod: if flat { 1 } else { 2 },
+                height: mesh.height,
+            }
+        })
+        .collect()
+}
+
+// 局部坐标是相对原点按 `mesh::lon_to_meters` 换算的，换回差值后加上原点就是输入坐标系中的坐标
+fn translate(origin: Origin) -> [f64; 3] {
+    let (cx, cy, h) = origin;
+    [cx, cy, h as f64]
+}
+
+fn surface_type(face: Face) -> &'static str {
+    match face {
+        Face::Roof => "RoofSurface",
+        Face::Wall => "WallSurface",
+        Face::Bottom => "GroundSurface",
+    }
+}
+
+// dbf 属性，空值不写
+fn attributes(record: &Record) -> Map<String, Value> {
+    record
+        .as_ref()
+        .iter()
+        .map(|(name, value)| (name.clone(), field::to_json(value)))
+        .filter(|(_, value)| !value.is_null())
+        .collect()
+}
+
+/// 写成 CityJSON 1.1：顶点按 `transform` 量化成整数并合并重复的点，屋顶、墙面、底面写成语义面
+pub fn write_cityjson(
+    meshes: &[Mesh],
+    records: &[&Record],
+    origin: Origin,
+    crs: &Crs,
+    path: &Path,
+) -> io::Result<()> {
+    let mut vertices: Vec<[i64; 3]> = vec![];
+    let mut seen = HashMap::new();
+    let mut objects = Map::new();
+    let solids = solids(meshes, origin, crs);
+    for (solid, record) in solids.iter().zip(records).filter(|(x, _)| !x.triangles.is_empty()) {
+        let mut values = vec![];
+        let surfaces: Vec<Value> = solid
+            .triangles
+            .iter()
+            .map(|(t, face)| {
+                values.push([Face::Roof, Face::Wall, Face::Bottom].iter().position(|x| x == face).unwrap());
+                let ring: Vec<usize> = t
+                    .iter()
+                    .map(|p| {
+                        *seen.entry(*p).or_insert_with(|| {
+                            vertices.push(*p);
+                            vertices.len() - 1
+                        })
+                    })
+                    .collect();
+                json!([ring])
+            })
+            .collect();
+        let semantics = [Face::Roof, Face::Wall, Face::Bottom].map(|x| json!({ "type": surface_type(x) }));
+        let geometry = match solid.closed {
+            true => json!({
+                "type": "Solid",
+                "lod": solid.lod.to_string(),
+                "boundaries": [surfaces],
+                "semantics": { "surfaces": semantics, "values": [values] }
+            }),
+            false => json!({
+                "type": "MultiSurface",
+                "lod": solid.lod.to_string(),
+                "boundaries": surfaces,
+                "semantics": { "surfaces": semantics, "values": values }
+            }),
+        };
+        let mut attributes = attributes(record);
+        let kind = match solid.closed {
+            true => {
+                attributes.insert("measuredHeight".to_string(), solid.height.into());
+                "Building"
+            }
+            false => "GenericCityObject",
+        };
+        objects.insert(
+            solid.id.to_string(),
+            json!({ "type": kind, "attributes": attributes, "geometry": [geometry] }),
+        );
+    }
+
+    let mut data = json!({
+        "type": "CityJSON",
+        "version": "1.1",
+        "transform": { "scale": scale(crs), "translate": translate(origin) },
+        "CityObjects": objects,
+        "vertices": vertices,
+    });
+    if let Some(epsg) = crs.epsg {
+        data["metadata"] = json!({ "referenceSystem": format!("https://www.opengis.net/def/crs/EPSG/0/{}", epsg) });
+    }
+    fs::write(path, serde_json::to_vec(&data)?)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 写成 CityGML 2.0：建筑为 `bldg:Building`，几何为 lod1Solid（有坡屋顶时为 lod2Solid），
+/// 线要素为 `gen:GenericCityObject`；坐标按 x y z 的顺序写，经纬度坐标为经度在前
+pub fn write_citygml(
+    meshes: &[Mesh],
+    records: &[&Record],
+    origin: Origin,
+    crs: &Crs,
+    path: &Path,
+) -> io::Result<()> {
+    let scale = scale(crs);
+    let translate = translate(origin);
+    let digits = [0, 1, 2].map(|k| (-scale[k].log10()).round() as usize);
+    let solids = solids(meshes, origin, crs);
+    let position = |p: &[i64; 3]| [0, 1, 2].map(|k| translate[k] + p[k] as f64 * scale[k]);
+    let coordinates = |p: [f64; 3]| format!("{:.*} {:.*} {:.*}", digits[0], p[0], digits[1], p[1], digits[2], p[2]);
+    let srs = match crs.epsg {
+        Some(epsg) => format!(" srsName=\"EPSG:{}\" srsDimension=\"3\"", epsg),
+        None => " srsDimension=\"3\"".to_string(),
+    };
+
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    solids.iter().flat_map(|x| &x.triangles).flat_map(|(t, _)| t).for_each(|p| {
+        let p = position(p);
+        min = [0, 1, 2].map(|k| min[k].min(p[k]));
+        max = [0, 1, 2].map(|k| max[k].max(p[k]));
+    });
+
+    let mut out = String::new();
+    out += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    out += "<core:CityModel xmlns:core=\"http://www.opengis.net/citygml/2.0\" \
+            xmlns:bldg=\"http://www.opengis.net/citygml/building/2.0\" \
+            xmlns:gen=\"http://www.opengis.net/citygml/generics/2.0\" \
+            xmlns:gml=\"http://www.opengis.net/gml\">\n";
+    let _ = writeln!(
+        out,
+        "  <gml:boundedBy><gml:Envelope{}><gml:lowerCorner>{}</gml:lowerCorner><gml:upperCorner>{}</gml:upperCorner></gml:Envelope></gml:boundedBy>",
+        srs,
+        coordinates(min),
+        coordinates(max)
+    );
+    for (solid, record) in solids.iter().zip(records).filter(|(x, _)| !x.triangles.is_empty()) {
+        let kind = if solid.closed { "bldg:Building" } else { "gen:GenericCityObject" };
+        out += "  <core:cityObjectMember>\n";
+        let _ = writeln!(out, "    <{} gml:id=\"{}\">", kind, escape(solid.id));
+        // 通用属性按值的类型写成整数、小数或文本
+        record.as_ref().iter().for_each(|(name, value)| {
+            let (tag, text) = match (value, field::to_json(value)) {
+                (_, Value::Null) => return,
+                (FieldValue::Integer(_), x) => ("intAttribute", x.to_string()),
+                (FieldValue::Numeric(_) | FieldValue::Float(_) | FieldValue::Double(_) | FieldValue::Currency(_), x) => {
+                    ("doubleAttribute", x.to_string())
+                }
+                (_, Value::String(x)) => ("stringAttribute", x),
+                (_, x) => ("stringAttribute", x.to_string()),
+            };
+            let _ = writeln!(
+                out,
+                "      <gen:{} name=\"{}\"><gen:value>{}</gen:value></gen:{}>",
+                tag,
+                escape(name),
+                escape(&text),
+                tag
+            );
+        });
+        if solid.closed {
+            let _ = writeln!(out, "      <bldg:measuredHeight uom=\"m\">{}</bldg:measuredHeight>", solid.height);
+            let _ = writeln!(out, "      <bldg:lod{}Solid>", solid.lod);
+            let _ = writeln!(out, "        <gml:Solid{}><gml:exterior><gml:CompositeSurface>", srs);
+        } else {
+            let _ = writeln!(out, "      <gen:lod{}Geometry>", solid.lod);
+            let _ = writeln!(out, "        <gml:MultiSurface{}>", srs);
+        }
+        solid.triangles.iter().for_each(|(t, _)| {
+            // 线性环首尾相同
+            let ring: Vec<String> = [t[0], t[1], t[2], t[0]].iter().map(|p| coordinates(position(p))).collect();
+            let _ = writeln!(
+                out,
+                "          <gml:surfaceMember><gml:Polygon><gml:exterior><gml:LinearRing><gml:posList>{}</gml:posList></gml:LinearRing></gml:exterior></gml:Polygon></gml:surfaceMember>",
+                ring.join(" ")
+            );
+        });
+        if solid.closed {
+            out += "        </gml:CompositeSurface></gml:exterior></gml:Solid>\n";
+            let _ = writeln!(out, "      </bldg:lod{}Solid>", solid.lod);
+        } else {
+            out += "        </gml:MultiSurface>\n";
+            let _ = writeln!(out, "      </gen:lod{}Geometry>", solid.lod);
+        }
+        let _ = writeln!(out, "    </{}>", kind);
+        out += "  </core:cityObjectMember>\n";
+    }
+    out += "</core:CityModel>\n";
+    fs::write(path, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roof::Roof;
+    use std::collections::BTreeMap;
+
+    // 与 shp 的外环一样按顺时针排列
+    const CORNERS: [(f64, f64); 4] = [(116.3901, 39.9001), (116.3901, 39.9002), (116.3902, 39.9002), (116.3902, 39.9001)];
+
+    // 一栋12米高的平顶建筑，原点与生成瓦片时一样取 f32 的经纬度
+    fn building(origin: Origin) -> Mesh {
+        let mut ring: Vec<(f64, f64)> = CORNERS.to_vec();
+        ring.push(CORNERS[0]);
+        let square = geo::Polygon::new(ring.into(), vec![]);
+        let (cx, cy, _) = origin;
+        let mut mesh = Mesh::init(cx, cy, &12., 0., geo::MultiPolygon(vec![square]), 5, &Roof::flat());
+        mesh.weld();
+        mesh
+    }
+
+    #[test]
+    fn cityjson_solid_is_closed() {
+        let origin: Origin = (116.39, 39.9, 0);
+        let mut record = Record::default();
+        record.insert("name".to_string(), FieldValue::Character(Some("a".to_string())));
+        let crs = Crs {
+            epsg: Some(4326),
+            geographic: true,
+        };
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_city");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("0.city.json");
+        write_cityjson(&[building(origin)], &[&record], origin, &crs, &path).unwrap();
+        let data: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+
+        let object = &data["CityObjects"]["mesh_5"];
+        assert_eq!(object["type"], "Building");
+        assert_eq!(object["attributes"], json!({ "name": "a", "measuredHeight": 12. }));
+        let geometry = &object["geometry"][0];
+        assert_eq!((&geometry["type"], &geometry["lod"]), (&json!("Solid"), &json!("1")));
+
+        // 顶点按 transform 还原成经纬度和高度
+        let scale = &data["transform"]["scale"];
+        let translate = &data["transform"]["translate"];
+        let vertices: Vec<[f64; 3]> = data["vertices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| [0, 1, 2].map(|k| p[k].as_f64().unwrap() * scale[k].as_f64().unwrap() + translate[k].as_f64().unwrap()))
+            .collect();
+        assert_eq!(vertices.len(), 8);
+        for p in &vertices {
+            assert!(p[2] == 0. || p[2] == 12.);
+            let nearest = CORNERS
+                .iter()
+                .map(|c| (p[0] - c.0).abs().max((p[1] - c.1).abs()))
+                .fold(f64::MAX, f64::min);
+            assert!(nearest < 2e-8, "{:?}", p);
+        }
+
+        // 外壳的每条边正好被相邻两个面以相反的方向用到一次
+        let shell = geometry["boundaries"][0].as_array().unwrap();
+        let mut edges = BTreeMap::new();
+        let rings: Vec<Vec<usize>> = shell
+            .iter()
+            .map(|surface| {
+                assert_eq!(surface.as_array().unwrap().len(), 1);
+                surface[0].as_array().unwrap().iter().map(|x| x.as_u64().unwrap() as usize).collect()
+            })
+            .collect();
+        for ring in &rings {
+            for k in 0..ring.len() {
+                *edges.entry((ring[k], ring[(k + 1) % ring.len()])).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.iter().all(|((a, b), n)| *n == 1 && edges.get(&(*b, *a)) == Some(&1)), "{:?}", edges);
+
+        // 语义与面一一对应：屋顶在顶部，底面在底部，墙面竖直
+        let values = geometry["semantics"]["values"][0].as_array().unwrap();
+        assert_eq!(values.len(), rings.len());
+        let surfaces = &geometry["semantics"]["surfaces"];
+        let mut kinds = vec![];
+        for (ring, value) in rings.iter().zip(values) {
+            let kind = surfaces[value.as_u64().unwrap() as usize]["type"].as_str().unwrap();
+            let heights: Vec<f64> = ring.iter().map(|i| vertices[*i][2]).collect();
+            match kind {
+                "RoofSurface" => assert!(heights.iter().all(|z| *z == 12.)),
+                "GroundSurface" => assert!(heights.iter().all(|z| *z == 0.)),
+                "WallSurface" => assert!(heights.contains(&0.) && heights.contains(&12.)),
+                _ => panic!("{}", kind),
+            }
+            kinds.push(kind);
+        }
+        kinds.sort();
+        kinds.dedup();
+        assert_eq!(kinds, ["GroundSurface", "RoofSurface", "WallSurface"]);
+        assert_eq!(data["metadata"]["referenceSystem"], "https://www.opengis.net/def/crs/EPSG/0/4326");
+    }
+}
@@ -0,0 +1,112 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use gltf::Error;
+use std::borrow::Cow;
+use std::io;
+
+fn align_to_multiple_of_eight(n: &mut usize) {
+    *n = (*n + 7) & !7;
+}
+
+pub struct MakeCmpt<'a> {
+    // 内部瓦片的完整文件内容，b3dm、i3dm 等
+    pub tiles: Vec<Cow<'a, [u8]>>,
+}
+
+impl<'a> MakeCmpt<'a> {
+    //生成cmpt文件
+    pub fn to_writer<W>(&self, mut writer: W) -> Result<(), Error>
+    where
+        W: io::Write,
+    {
+        // 每个内部瓦片都要从8字节对齐的位置开始，补齐后的长度写回它自己的头部
+        let lengths: Vec<usize> = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let mut length = tile.len();
+                align_to_multiple_of_eight(&mut length);
+                length
+            })
+            .collect();
+
+        // 写入cmpt文件头部
+        {
+            let magic = b"cmpt";
+            let version = 1;
+            let length = 16 + lengths.iter().sum::<usize>();
+
+            writer.write_all(&magic[..])?;
+            writer.write_u32::<LittleEndian>(version)?;
+            writer.write_u32::<LittleEndian>(length as u32)?;
+            writer.write_u32::<LittleEndian>(self.tiles.len() as u32)?;
+        }
+
+        for (tile, length) in self.tiles.iter().zip(lengths) {
+            // 内部瓦片头部前8字节是 magic 和 version，之后是 byteLength
+            writer.write_all(&tile[..8])?;
+            writer.write_u32::<LittleEndian>(length as u32)?;
+            writer.write_all(&tile[12..])?;
+            for _ in 0..length - tile.len() {
+                writer.write_u8(0)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    // 头部为 magic、version 和 byteLength 的内部瓦片，后面跟 `body` 个字节
+    fn tile(magic: &[u8; 4], body: usize) -> Vec<u8> {
+        let mut data = magic.to_vec();
+        data.extend(1u32.to_le_bytes());
+        data.extend((12 + body as u32).to_le_bytes());
+        data.extend((0..body).map(|x| x as u8 + 1));
+        data
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn inner_tiles_are_padded_to_eight_bytes() {
+        let b3dm = tile(b"b3dm", 18);
+        let i3dm = tile(b"i3dm", 4);
+        let cmpt = MakeCmpt {
+            tiles: vec![Cow::Borrowed(&b3dm[..]), Cow::Borrowed(&i3dm[..])],
+        };
+        let mut data = vec![];
+        cmpt.to_writer(&mut data).unwrap();
+        assert_eq!(&data[..4], b"cmpt");
+        assert_eq!(u32_at(&data, 4), 1);
+        assert_eq!(u32_at(&data, 8), data.len());
+        assert_eq!(u32_at(&data, 12), 2);
+        assert_eq!(data.len(), 16 + 32 + 16);
+
+        // 第一个内部瓦片补到32字节，头部的 byteLength 跟着改，内容不变
+        assert_eq!(&data[16..24], &b3dm[..8]);
+        assert_eq!(u32_at(&data, 24), 32);
+        assert_eq!(&data[28..46], &b3dm[12..]);
+        assert!(data[46..48].iter().all(|x| *x == 0));
+        // 第二个从8字节对齐的位置开始，本身已经对齐的长度不变
+        assert_eq!(&data[48..56], &i3dm[..8]);
+        assert_eq!(u32_at(&data, 56), 16);
+        assert_eq!(&data[60..], &i3dm[12..]);
+
+        // 只有一个内部瓦片时也是完整的 cmpt
+        let mut single = vec![];
+        MakeCmpt {
+            tiles: vec![Cow::Borrowed(&i3dm[..])],
+        }
+        .to_writer(&mut single)
+        .unwrap();
+        assert_eq!(u32_at(&single, 8), 32);
+        assert_eq!(u32_at(&single, 12), 1);
+        assert_eq!(&single[16..], &data[48..]);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::field;
+use crate::mesh::Face;
+use crate::pnts;
+use serde::Deserialize;
+use shapefile::dbase::Record;
+use std::collections::BTreeMap;
+
+/// `--config` 指定的 JSON 配置文件，各部分都可以省略
+#[derive(Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub color: Option<ColorConfig>,
+    #[serde(default)]
+    pub style: Option<StyleConfig>,
+    #[serde(default)]
+    pub materials: Option<MaterialsConfig>,
+}
+
+impl Config {
+    /// 读取并检查配置文件，出错时返回错误说明
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("配置文件{}读取错误: {:?}", path, e))?;
+        let mut config: Config =
+            serde_json::from_str(&text).map_err(|e| format!("配置文件{}解析错误: {}", path, e))?;
+        if let Some(color) = &config.color {
+            color.check()?;
+        }
+        if let Some(style) = &config.style {
+            style.check()?;
+        }
+        if let Some(materials) = &mut config.materials {
+            materials.check()?;
+        }
+        Ok(config)
+    }
+}
+
+/// 颜色写进 glTF 的方式：每种颜色一个材质，或者写成顶点颜色 COLOR_0
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Material,
+    Vertex,
+}
+
+/// 按属性给要素着色：给了 `classes` 时按字段值查颜色表，否则按数值字段在 `ramp` 的颜色之间渐变
+///
+/// ```json
+/// {"color": {"field": "landuse", "classes": {"住宅": "#f4d03f", "商业": "#e74c3c"}, "default": "#cccccc"}}
+/// {"color": {"field": "height", "ramp": ["#2c7bb6", "#ffffbf", "#d7191c"], "min": 0, "max": 100, "mode": "vertex"}}
+/// ```
+#[derive(Deserialize)]
+pub struct ColorConfig {
+    pub field: String,
+    #[serde(default)]
+    pub mode: ColorMode,
+    #[serde(default)]
+    pub classes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ramp: Vec<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub default: Option<String>,
+}
+
+impl ColorConfig {
+    fn check(&self) -> Result<(), String> {
+        check_colors(self.classes.values().chain(&self.ramp).chain(&self.default))
+    }
+
+    /// 每个要素的颜色，字段为空或不在颜色表中时取 `default`，没有 `default` 时为 None
+    pub fn colors(&self, records: &[&Record]) -> Vec<Option<[u8; 3]>> {
+        let default = self.default.as_deref().and_then(pnts::parse_hex);
+        if !self.classes.is_empty() {
+            return records
+                .iter()
+                .map(|record| {
+                    class_key(record, &self.field)
+                        .and_then(|key| self.classes.get(&key))
+                        .and_then(|x| pnts::parse_hex(x))
+                        .or(default)
+                })
+                .collect();
+        }
+
+        let values: Vec<Option<f64>> = records
+            .iter()
+            .map(|record| field::get_number(record, &self.field))
+            .collect();
+        let min = self
+            .min
+            .unwrap_or_else(|| values.iter().flatten().cloned().fold(f64::MAX, f64::min));
+        let max = self
+            .max
+            .unwrap_or_else(|| values.iter().flatten().cloned().fold(f64::MIN, f64::max));
+        let stops: Vec<[u8; 3]> = self.ramp.iter().filter_map(|x| pnts::parse_hex(x)).collect();
+        values
+            .iter()
+            .map(|x| {
+                let x = (*x)?;
+                let t = if max > min { (x - min) / (max - min) } else { 0. };
+                Some(match stops.len() {
+                    // 没有给颜色时与点云一样从蓝到红渐变
+                    0 => pnts::ramp(t),
+                    1 => stops[0],
+                    _ => interpolate(&stops, t),
+                })
+            })
+            .map(|x| x.or(default))
+            .collect()
+    }
+}
+
+/// 和 tileset.json 一起输出的 3D Tiles 样式：文本字段按取值分类，数值字段按分位数分级，
+/// `show` 中的表达式全部成立的要素才显示
+///
+/// ```json
+/// {"style": {"field": "height", "breaks": 5, "show": ["${height} > 3"]}}
+/// ```
+#[derive(Deserialize)]
+pub struct StyleConfig {
+    pub field: String,
+    #[serde(default = "default_breaks")]
+    pub breaks: usize,
+    #[serde(default)]
+    pub colors: Vec<String>,
+    pub default: Option<String>,
+    #[serde(default)]
+    pub show: Vec<String>,
+}
+
+fn default_breaks() -> usize {
+    5
+}
+
+impl StyleConfig {
+    fn check(&self) -> Result<(), String> {
+        if self.breaks < 1 {
+            return Err("样式的分级数breaks至少为1".to_string());
+        }
+        check_colors(self.colors.iter().chain(&self.default))
+    }
+}
+
+/// 屋顶、墙面、底面各自的材质，给了这一部分时每个要素按面的类型拆成几个图元，没给的类型用默认材质，
+/// `field` 为按属性选择纹理图片时的类别字段，`facade` 为墙面生成按楼层排列的窗户纹理
+///
+/// ```json
+/// {"materials": {"roof": {"color": "#b0413e", "roughness": 0.9}, "wall": {"color": "#e8e4d8", "metallic": 0}}}
+/// {"materials": {"field": "type", "wall": {"texture": {"image": "facade.jpg", "images": {"商业": "shop.jpg"}, "size": [3, 3]}}}}
+/// {"materials": {"facade": {"floors": "floors", "floor_height": 3, "bay_width": 3.6}}}
+/// ```
+#[derive(Deserialize)]
+pub struct MaterialsConfig {
+    pub field: Option<String>,
+    pub facade: Option<FacadeConfig>,
+    #[serde(default)]
+    pub roof: MaterialConfig,
+    #[serde(default)]
+    pub wall: MaterialConfig,
+    #[serde(default)]
+    pub bottom: MaterialConfig,
+}
+
+impl MaterialsConfig {
+    // 检查参数，同时读入纹理图片
+    fn check(&mut self) -> Result<(), String> {
+        if let Some(facade) = &self.facade {
+            if self.wall.texture.is_some() {
+                return Err("墙面不能同时设置texture和facade".to_string());
+            }
+            facade.check()?;
+        }
+        [&mut self.roof, &mut self.wall, &mut self.bottom].into_iter().try_for_each(|x| x.check())
+    }
+
+    /// 纹理图片文件的内容，加载配置时已经读入并检查过格式
+    pub fn image_data(&self, path: &str) -> Option<&[u8]> {
+        [&self.roof, &self.wall, &self.bottom]
+            .iter()
+            .find_map(|x| x.texture.as_ref()?.data.get(path))
+            .map(Vec::as_slice)
+    }
+
+    pub fn get(&self, face: Face) -> &MaterialConfig {
+        match face {
+            Face::Roof => &self.roof,
+            Face::Wall => &self.wall,
+            Face::Bottom => &self.bottom,
+        }
+    }
+}
+
+/// 一类面的 PBR 参数，颜色为 sRGB
+#[derive(Deserialize)]
+pub struct MaterialConfig {
+    pub color: Option<String>,
+    #[serde(default = "default_metallic")]
+    pub metallic: f32,
+    #[serde(default = "default_roughness")]
+    pub roughness: f32,
+    pub texture: Option<TextureConfig>,
+}
+
+fn default_metallic() -> f32 {
+    0.3
+}
+
+fn default_roughness() -> f32 {
+    0.7
+}
+
+impl Default for MaterialConfig {
+    fn default() -> Self {
+        MaterialConfig {
+            color: None,
+            metallic: default_metallic(),
+            roughness: default_roughness(),
+            texture: None,
+        }
+    }
+}
+
+impl MaterialConfig {
+    fn check(&mut self) -> Result<(), String> {
+        if !(0. ..=1.).contains(&self.metallic) || !(0. ..=1.).contains(&self.roughness) {
+            return Err("材质的metallic和roughness应在0到1之间".to_string());
+        }
+        if let Some(texture) = &mut self.texture {
+            texture.load()?;
+        }
+        check_colors(self.color.iter())
+    }
+}
+
+/// 平铺的纹理图片（PNG 或 JPEG），`size` 为一张图覆盖的宽和高（米），
+/// `images` 按类别字段的取值选择图片，取不到时用 `image`
+#[derive(Deserialize)]
+pub struct TextureConfig {
+    pub image: Option<String>,
+    #[serde(default)]
+    pub images: BTreeMap<String, String>,
+    #[serde(default = "default_texture_size")]
+    pub size: [f32; 2],
+    // 图片路径到文件内容，加载配置时读入
+    #[serde(skip)]
+    pub data: BTreeMap<String, Vec<u8>>,
+}
+
+fn default_texture_size() -> [f32; 2] {
+    [1., 1.]
+}
+
+impl TextureConfig {
+    // 检查尺寸，读入用到的图片，每个文件只读一次
+    fn load(&mut self) -> Result<(), String> {
+        if self.size.iter().any(|x| x.is_nan() || *x <= 0.) {
+            return Err("纹理的size必须大于0".to_string());
+        }
+        for path in self.image.iter().chain(self.images.values()) {
+            if self.data.contains_key(path) {
+                continue;
+            }
+            let data = std::fs::read(path).map_err(|e| format!("纹理图片{}读取错误: {:?}", path, e))?;
+            if image_mime_type(&data).is_none() {
+                return Err(format!("纹理图片{}不是PNG或JPEG格式", path));
+            }
+            self.data.insert(path.clone(), data);
+        }
+        Ok(())
+    }
+
+    /// 要素使用的图片路径，没有对应的图片时为 None
+    pub fn image_for(&self, category: Option<&str>) -> Option<&str> {
+        category
+            .and_then(|x| self.images.get(x))
+            .or(self.image.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// 程序生成的窗户立面纹理，一张图为一层一开间，层高取 `floors` 字段（墙高除以层数），
+/// 字段为空时取 `floor_height`
+#[derive(Deserialize)]
+pub struct FacadeConfig {
+    pub floors: Option<String>,
+    #[serde(default = "default_floor_height")]
+    pub floor_height: f32,
+    #[serde(default = "default_bay_width")]
+    pub bay_width: f32,
+    pub wall_color: Option<String>,
+    pub window_color: Option<String>,
+}
+
+fn default_floor_height() -> f32 {
+    3.
+}
+
+fn default_bay_width() -> f32 {
+    3.6
+}
+
+impl FacadeConfig {
+    fn check(&self) -> Result<(), String> {
+        if [self.floor_height, self.bay_width].iter().any(|x| x.is_nan() || *x <= 0.) {
+            return Err("立面的floor_height和bay_width必须大于0".to_string());
+        }
+        check_colors(self.wall_color.iter().chain(&self.window_color))
+    }
+
+    /// 要素的层高，层数不到1层时按 `floor_height` 计算
+    pub fn floor_height_for(&self, record: &Record, wall_height: f64) -> f32 {
+        let floors = self
+            .floors
+            .as_deref()
+            .and_then(|x| field::get_number(record, x))
+            .map(f64::round)
+            .filter(|x| *x >= 1.);
+        match floors {
+            Some(floors) if wall_height > 0. => (wall_height / floors) as f32,
+            _ => self.floor_height,
+        }
+    }
+}
+
+/// 按文件头判断图片类型
+pub fn image_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else {
+        None
+    }
+}
+
+fn check_colors<'a>(mut colors: impl Iterator<Item = &'a String>) -> Result<(), String> {
+    colors.try_for_each(|x| match pnts::parse_hex(x) {
+        Some(_) => Ok(()),
+        None => Err(format!("颜色{}的格式应为#RRGGBB", x)),
+    })
+}
+
+/// 分类用的字段值，文本去掉首尾空格，数值按最短形式输出（3.0 输出为 3）
+pub fn class_key(record: &Record, name: &str) -> Option<String> {
+    field::get_text(record, name).or_else(|| field::get_number(record, name).map(|x| x.to_string()))
+}
+
+/// 在等间距的几个颜色之间线性插值
+pub fn interpolate(stops: &[[u8; 3]], t: f64) -> [u8; 3] {
+    let t = if t.is_finite() { t.clamp(0., 1.) } else { 0. };
+    let x = t * (stops.len() - 1) as f64;
+    let i = (x.floor() as usize).min(stops.len() - 2);
+    let f = x - i as f64;
+    let (a, b) = (stops[i], stops[i + 1]);
+    [0, 1, 2].map(|k| (a[k] as f64 + (b[k] as f64 - a[k] as f64) * f).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shapefile::dbase::FieldValue;
+
+    fn color(json: serde_json::Value) -> ColorConfig {
+        let config: ColorConfig = serde_json::from_value(json).unwrap();
+        config.check().unwrap();
+        config
+    }
+
+    fn record(value: FieldValue) -> Record {
+        let mut record = Record::default();
+        record.insert("f".to_string(), value);
+        record
+    }
+
+    #[test]
+    fn classes_match_text_and_numbers() {
+        let config = color(serde_json::json!({
+            "field": "f",
+            "classes": {"住宅": "#f4d03f", "3": "#000080"},
+            "default": "#cccccc",
+        }));
+        assert!(config.mode == ColorMode::Material);
+        let records = [
+            record(FieldValue::Character(Some(" 住宅 ".into()))),
+            record(FieldValue::Numeric(Some(3.))),
+            record(FieldValue::Character(Some("商业".into()))),
+            record(FieldValue::Character(None)),
+        ];
+        let records: Vec<&Record> = records.iter().collect();
+        // 文本去掉首尾空格，3.0 按 "3" 查表，查不到和空值取 default
+        let gray = Some([204, 204, 204]);
+        assert_eq!(config.colors(&records), [Some([244, 208, 63]), Some([0, 0, 128]), gray, gray]);
+        let config = color(serde_json::json!({"field": "f", "classes": {"住宅": "#f4d03f"}}));
+        assert_eq!(config.colors(&records)[1..], [None, None, None]);
+    }
+
+    #[test]
+    fn ramp_spans_min_to_max() {
+        let records: Vec<Record> = [Some(0.), Some(25.), Some(50.), Some(150.), None]
+            .into_iter()
+            .map(|x| record(FieldValue::Numeric(x)))
+            .collect();
+        let records: Vec<&Record> = records.iter().collect();
+        let config = color(serde_json::json!({
+            "field": "f",
+            "ramp": ["#000000", "#ff0000", "#ffffff"],
+            "min": 0,
+            "max": 100,
+            "mode": "vertex",
+        }));
+        assert!(config.mode == ColorMode::Vertex);
+        // 超过 max 的取最后一个颜色，空值没有 default 时为 None
+        assert_eq!(
+            config.colors(&records),
+            [Some([0, 0, 0]), Some([128, 0, 0]), Some([255, 0, 0]), Some([255, 255, 255]), None]
+        );
+        // 不给 min、max 时取数据的范围；没给颜色时与点云相同
+        let config = color(serde_json::json!({"field": "f", "default": "#010203"}));
+        let colors = config.colors(&records);
+        assert_eq!(colors[0], Some(pnts::ramp(0.)));
+        assert_eq!(colors[2], Some(pnts::ramp(1. / 3.)));
+        assert_eq!(colors[3], Some(pnts::ramp(1.)));
+        assert_eq!(colors[4], Some([1, 2, 3]));
+        // 只有一个颜色或者取值都相同
+        let config = color(serde_json::json!({"field": "f", "ramp": ["#102030"]}));
+        assert_eq!(config.colors(&records)[3], Some([16, 32, 48]));
+        let config = color(serde_json::json!({"field": "f", "ramp": ["#000000", "#ffffff"], "min": 5, "max": 5}));
+        assert_eq!(config.colors(&records)[3], Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn bad_colors_are_rejected() {
+        let error = |json: serde_json::Value| serde_json::from_value::<ColorConfig>(json).unwrap().check().err();
+        assert_eq!(
+            error(serde_json::json!({"field": "f", "ramp": ["#000000", "red"]})),
+            Some("颜色red的格式应为#RRGGBB".to_string())
+        );
+        assert_eq!(
+            error(serde_json::json!({"field": "f", "classes": {"a": "#12345"}})),
+            Some("颜色#12345的格式应为#RRGGBB".to_string())
+        );
+        assert!(serde_json::from_value::<ColorConfig>(serde_json::json!({"field": "f", "mode": "texture"})).is_err());
+        assert!(serde_json::from_value::<ColorConfig>(serde_json::json!({"ramp": []})).is_err());
+        assert_eq!(interpolate(&[[0, 0, 0], [255, 255, 255]], f64::NAN), [0, 0, 0]);
+        assert_eq!(interpolate(&[[0, 0, 0], [255, 100, 10]], 0.5), [128, 50, 5]);
+    }
+
+    #[test]
+    fn texture_images_are_read_on_load() {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_config");
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = dir.join("tile.png");
+        let text = dir.join("tile.txt");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\nrest").unwrap();
+        std::fs::write(&text, b"not an image").unwrap();
+        let load = |wall: &str, roof: &str| {
+            let config = serde_json::json!({"materials": {
+                "wall": {"texture": {"image": wall, "images": {"商业": wall}}},
+                "roof": {"texture": {"image": roof}},
+            }});
+            let path = dir.join("config.json");
+            std::fs::write(&path, config.to_string()).unwrap();
+            Config::load(path.to_str().unwrap())
+        };
+
+        let png = png.to_str().unwrap();
+        let config = load(png, png).ok().unwrap();
+        let materials = config.materials.unwrap();
+        assert_eq!(materials.wall.texture.as_ref().unwrap().data.len(), 1);
+        assert_eq!(materials.image_data(png), Some(&b"\x89PNG\r\n\x1a\nrest"[..]));
+        assert_eq!(materials.image_data("other.png"), None);
+
+        let text = text.to_str().unwrap();
+        assert_eq!(load(png, text).err(), Some(format!("纹理图片{}不是PNG或JPEG格式", text)));
+        let missing = dir.join("missing.png");
+        let error = load(missing.to_str().unwrap(), png).err().unwrap();
+        assert!(error.starts_with(&format!("纹理图片{}读取错误", missing.display())), "{}", error);
+    }
+}
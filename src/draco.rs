@@ -0,0 +1,823 @@
+// KHR_draco_mesh_compression 使用的 Draco 码流编码（码流版本2.2）
+// 只实现顺序编码：三角形索引差分后熵编码，属性量化后差分预测，再统一用 rANS 熵编码
+
+/// 各属性在 Draco 码流中的唯一id，glTF 扩展里按这个id引用
+pub const POSITION_ID: u32 = 0;
+pub const NORMAL_ID: u32 = 1;
+pub const BATCH_ID: u32 = 2;
+pub const COLOR_ID: u32 = 3;
+pub const TEXCOORD_ID: u32 = 4;
+
+/// Draco 压缩参数，位置、法线和纹理坐标的量化位数
+pub struct DracoOptions {
+    pub position_bits: u8,
+    pub normal_bits: u8,
+    pub uv_bits: u8,
+}
+
+impl DracoOptions {
+    /// 解析 `位置位数[,法线位数[,纹理坐标位数]]` 形式的参数，法线位数缺省为10，纹理坐标缺省为12
+    pub fn parse(spec: &str) -> Option<DracoOptions> {
+        let mut parts = spec.split(',').map(|x| x.trim().parse::<u8>());
+        let position_bits = parts.next()?.ok()?;
+        let normal_bits = match parts.next() {
+            Some(x) => x.ok()?,
+            None => 10,
+        };
+        let uv_bits = match parts.next() {
+            Some(x) => x.ok()?,
+            None => 12,
+        };
+        if parts.next().is_some()
+            || !(1..=16).contains(&position_bits)
+            || !(2..=15).contains(&normal_bits)
+            || !(1..=16).contains(&uv_bits)
+        {
+            return None;
+        }
+        Some(DracoOptions {
+            position_bits,
+            normal_bits,
+            uv_bits,
+        })
+    }
+}
+
+// 属性类型与数据类型的枚举值
+const ATTRIBUTE_POSITION: u8 = 0;
+const ATTRIBUTE_NORMAL: u8 = 1;
+const ATTRIBUTE_COLOR: u8 = 2;
+const ATTRIBUTE_TEX_COORD: u8 = 3;
+const ATTRIBUTE_GENERIC: u8 = 4;
+const DATA_UINT8: u8 = 2;
+const DATA_UINT16: u8 = 4;
+const DATA_FLOAT32: u8 = 9;
+
+// 顺序属性编码器的类型
+const ENCODER_INTEGER: u8 = 1;
+const ENCODER_QUANTIZATION: u8 = 2;
+const ENCODER_NORMALS: u8 = 3;
+
+// 预测方式，差分预测配合 wrap 变换，法线不做预测
+const PREDICTION_NONE: i8 = -2;
+const PREDICTION_DIFFERENCE: i8 = 0;
+const TRANSFORM_WRAP: i8 = 1;
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+// 有符号整数转成非负的符号，与解码端的 ConvertSymbolToSignedInt 对应
+fn to_symbol(value: i32) -> u32 {
+    if value >= 0 {
+        (value as u32) << 1
+    } else {
+        ((-(value + 1)) as u32) << 1 | 1
+    }
+}
+
+/// 用 rANS 编码一组符号，不含符号个数
+fn encode_symbols(buf: &mut Vec<u8>, symbols: &[u32]) {
+    if symbols.is_empty() {
+        return;
+    }
+    let num_symbols = *symbols.iter().max().unwrap() as usize + 1;
+    let mut frequency = vec![0u64; num_symbols];
+    symbols.iter().for_each(|s| frequency[*s as usize] += 1);
+    let unique = frequency.iter().filter(|f| **f > 0).count() as u32;
+
+    // 解码端根据这个位数决定概率精度，精度至少要能给每个出现的符号分到1
+    let bit_length = (32 - unique.leading_zeros()).clamp(1, 18);
+    let precision_bits = (3 * bit_length / 2).clamp(12, 20);
+    let precision = 1u64 << precision_bits;
+    buf.push(1); // 不带标签的原始符号编码
+    buf.push(bit_length as u8);
+
+    // 按频率分配概率，总和必须正好等于精度
+    let total = symbols.len() as u64;
+    let mut probability: Vec<u64> = frequency
+        .iter()
+        .map(|f| match f {
+            0 => 0,
+            f => ((f * precision + total / 2) / total).max(1),
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..num_symbols).filter(|s| frequency[*s] > 0).collect();
+    order.sort_by(|a, b| probability[*b].cmp(&probability[*a]));
+    let sum: u64 = probability.iter().sum();
+    if sum < precision {
+        probability[order[0]] += precision - sum;
+    } else {
+        let mut excess = sum - precision;
+        for s in order {
+            let take = excess.min(probability[s] - 1);
+            probability[s] -= take;
+            excess -= take;
+            if excess == 0 {
+                break;
+            }
+        }
+    }
+
+    // 概率表：低2位是额外字节数，3表示一段连续的零概率符号
+    write_varint(buf, num_symbols as u64);
+    let mut i = 0;
+    while i < num_symbols {
+        let p = probability[i];
+        if p == 0 {
+            let mut run = 0;
+            while run < 63 && i + run + 1 < num_symbols && probability[i + run + 1] == 0 {
+                run += 1;
+            }
+            buf.push((run << 2 | 3) as u8);
+            i += run + 1;
+            continue;
+        }
+        let extra = if p >= 1 << 14 {
+            2
+        } else if p >= 1 << 6 {
+            1
+        } else {
+            0
+        };
+        buf.push((p << 2 | extra) as u8);
+        for b in 0..extra {
+            buf.push((p >> (8 * (b + 1) - 2)) as u8);
+        }
+        i += 1;
+    }
+
+    let mut cumulative = vec![0u64; num_symbols];
+    for s in 1..num_symbols {
+        cumulative[s] = cumulative[s - 1] + probability[s - 1];
+    }
+
+    // 解码端从前往后读，所以要倒序编码，状态最后写在末尾
+    let base = 4 * precision;
+    let mut state = base;
+    let mut data = vec![];
+    symbols.iter().rev().for_each(|s| {
+        let p = probability[*s as usize];
+        while state >= 4 * 256 * p {
+            data.push((state & 0xff) as u8);
+            state >>= 8;
+        }
+        state = (state / p) * precision + state % p + cumulative[*s as usize];
+    });
+    let state = (state - base) as u32;
+    if state < 1 << 6 {
+        data.push(state as u8);
+    } else if state < 1 << 14 {
+        data.extend_from_slice(&((1 << 14) + state).to_le_bytes()[..2]);
+    } else if state < 1 << 22 {
+        data.extend_from_slice(&((2 << 22) + state).to_le_bytes()[..3]);
+    } else {
+        data.extend_from_slice(&((3 << 30) + state).to_le_bytes());
+    }
+    write_varint(buf, data.len() as u64);
+    buf.extend_from_slice(&data);
+}
+
+/// 差分预测加 wrap 变换，写出修正值和变换用到的取值范围
+fn encode_difference(buf: &mut Vec<u8>, values: &[i32], components: usize) {
+    buf.push(PREDICTION_DIFFERENCE as u8);
+    buf.push(TRANSFORM_WRAP as u8);
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    let max_dif = 1 + max - min;
+    let mut max_correction = max_dif / 2;
+    let min_correction = -max_correction;
+    if max_dif % 2 == 0 {
+        max_correction -= 1;
+    }
+    let symbols: Vec<u32> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            // 第一个值以0为预测值，预测值先夹到取值范围内
+            let predicted = if i < components { 0 } else { values[i - components] };
+            let mut correction = v - predicted.clamp(min, max);
+            if correction < min_correction {
+                correction += max_dif;
+            } else if correction > max_correction {
+                correction -= max_dif;
+            }
+            to_symbol(correction)
+        })
+        .collect();
+    buf.push(1); // 熵编码
+    encode_symbols(buf, &symbols);
+    buf.extend_from_slice(&min.to_le_bytes());
+    buf.extend_from_slice(&max.to_le_bytes());
+}
+
+/// 法线按八面体映射成两个分量，取值范围 0..=2^bits-2
+fn octahedral(normal: &[f32; 3], bits: u8) -> [i32; 2] {
+    let max_value = ((1 << bits) - 2) as f64;
+    let [x, y, z] = normal.map(|v| v as f64);
+    let sum = x.abs() + y.abs() + z.abs();
+    let (x, y, z) = if sum > 1e-6 {
+        (x / sum, y / sum, z / sum)
+    } else {
+        (1., 0., 0.)
+    };
+    // x 为负的半球翻折到菱形外侧的四个角上
+    let (s, t) = if x >= 0. {
+        (y, z)
+    } else {
+        (
+            (1. - z.abs()) * if y >= 0. { 1. } else { -1. },
+            (1. - y.abs()) * if z >= 0. { 1. } else { -1. },
+        )
+    };
+    [
+        ((s + 1.) / 2. * max_value).round() as i32,
+        ((t + 1.) / 2. * max_value).round() as i32,
+    ]
+}
+
+// 按包围盒最长边统一量化，返回量化后的值、各分量的最小值和范围
+fn quantize<const N: usize>(values: &[[f32; N]], bits: u8) -> (Vec<i32>, [f32; N], f32) {
+    let mut min = [f32::MAX; N];
+    let mut max = [f32::MIN; N];
+    values.iter().for_each(|p| {
+        for i in 0..N {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    });
+    if values.is_empty() {
+        min = [0.; N];
+        max = [0.; N];
+    }
+    let mut range = (0..N).map(|i| max[i] - min[i]).fold(0., f32::max);
+    if range <= 0. {
+        range = 1.;
+    }
+    let max_quantized = ((1u32 << bits) - 1) as f32;
+    let quantized = values
+        .iter()
+        .flat_map(|p| (0..N).map(move |i| ((p[i] - min[i]) * (max_quantized / range) + 0.5).floor() as i32))
+        .collect();
+    (quantized, min, range)
+}
+
+/// 把一个三角网编码成 Draco 码流，属性id见 `POSITION_ID` 等常量，
+/// 给了顶点颜色时按 RGBA 一起编码，给了纹理坐标时同样量化编码
+#[allow(clippy::too_many_arguments)]
+pub fn encode_mesh(
+    position: &[[f32; 3]],
+    normal: &[[f32; 3]],
+    batch_id: &[u16],
+    color: Option<&[[u8; 4]]>,
+    uv: Option<&[[f32; 2]]>,
+    index: &[[i32; 3]],
+    options: &DracoOptions,
+) -> Vec<u8> {
+    let mut buf = b"DRACO".to_vec();
+    buf.extend_from_slice(&[2, 2]); // 码流版本
+    buf.push(1); // 三角网
+    buf.push(0); // 顺序编码
+    buf.extend_from_slice(&0u16.to_le_bytes()); // 无元数据
+
+    write_varint(&mut buf, index.len() as u64);
+    write_varint(&mut buf, position.len() as u64);
+
+    // 索引逐个与前一个做差，低位是符号位
+    let mut last = 0;
+    let symbols: Vec<u32> = index
+        .iter()
+        .flatten()
+        .map(|i| {
+            let diff = i - last;
+            last = *i;
+            (diff.unsigned_abs() << 1) | (diff < 0) as u32
+        })
+        .collect();
+    let mut connectivity = vec![0];
+    encode_symbols(&mut connectivity, &symbols);
+
+    let mut attributes = vec![1]; // 一个属性解码器
+    let mut declarations = vec![
+        (ATTRIBUTE_POSITION, DATA_FLOAT32, 3, 0, POSITION_ID),
+        (ATTRIBUTE_NORMAL, DATA_FLOAT32, 3, 0, NORMAL_ID),
+        (ATTRIBUTE_GENERIC, DATA_UINT16, 1, 0, BATCH_ID),
+    ];
+    if color.is_some() {
+        declarations.push((ATTRIBUTE_COLOR, DATA_UINT8, 4, 1, COLOR_ID));
+    }
+    if uv.is_some() {
+        declarations.push((ATTRIBUTE_TEX_COORD, DATA_FLOAT32, 2, 0, TEXCOORD_ID));
+    }
+    write_varint(&mut attributes, declarations.len() as u64);
+    for (attribute_type, data_type, components, normalized, id) in &declarations {
+        attributes.extend_from_slice(&[*attribute_type, *data_type, *components, *normalized]);
+        write_varint(&mut attributes, *id as u64);
+    }
+    attributes.extend_from_slice(&[ENCODER_QUANTIZATION, ENCODER_NORMALS, ENCODER_INTEGER]);
+    if color.is_some() {
+        attributes.push(ENCODER_INTEGER);
+    }
+    if uv.is_some() {
+        attributes.push(ENCODER_QUANTIZATION);
+    }
+
+    let (quantized, min, range) = quantize(position, options.position_bits);
+    encode_difference(&mut attributes, &quantized, 3);
+
+    // 法线：平面法线重复很多，不做预测直接熵编码
+    attributes.push(PREDICTION_NONE as u8);
+    attributes.push(1);
+    let symbols: Vec<u32> = normal
+        .iter()
+        .flat_map(|n| octahedral(n, options.normal_bits))
+        .map(to_symbol)
+        .collect();
+    encode_symbols(&mut attributes, &symbols);
+
+    let batch: Vec<i32> = batch_id.iter().map(|x| *x as i32).collect();
+    encode_difference(&mut attributes, &batch, 1);
+
+    if let Some(color) = color {
+        let values: Vec<i32> = color.iter().flatten().map(|x| *x as i32).collect();
+        encode_difference(&mut attributes, &values, 4);
+    }
+
+    let uv = uv.map(|uv| quantize(uv, options.uv_bits));
+    if let Some((quantized, _, _)) = &uv {
+        encode_difference(&mut attributes, quantized, 2);
+    }
+
+    // 量化、八面体变换的参数写在所有属性值之后
+    min.iter()
+        .for_each(|v| attributes.extend_from_slice(&v.to_le_bytes()));
+    attributes.extend_from_slice(&range.to_le_bytes());
+    attributes.push(options.position_bits);
+    attributes.push(options.normal_bits);
+    if let Some((_, min, range)) = &uv {
+        min.iter()
+            .for_each(|v| attributes.extend_from_slice(&v.to_le_bytes()));
+        attributes.extend_from_slice(&range.to_le_bytes());
+        attributes.push(options.uv_bits);
+    }
+
+    // 解码端要求索引之后剩余的字节数不少于每个索引一字节，压缩后太小时改存原始索引
+    if connectivity.len() + attributes.len() < 3 * index.len() {
+        connectivity = vec![1];
+        index.iter().flatten().for_each(|i| {
+            if position.len() < 256 {
+                connectivity.push(*i as u8);
+            } else if position.len() < 1 << 16 {
+                connectivity.extend_from_slice(&(*i as u16).to_le_bytes());
+            } else if position.len() < 1 << 21 {
+                write_varint(&mut connectivity, *i as u64);
+            } else {
+                connectivity.extend_from_slice(&(*i as u32).to_le_bytes());
+            }
+        });
+    }
+    buf.extend_from_slice(&connectivity);
+    buf.extend_from_slice(&attributes);
+    buf
+}
+
+/// 解码后的三角网：三角形索引和各属性的值，属性为唯一id、分量数和逐个顶点排列的分量；
+/// 量化的属性已还原成浮点数，法线已从八面体坐标还原，整数属性原样转成浮点数
+pub struct DecodedMesh {
+    pub index: Vec<[u32; 3]>,
+    pub attributes: Vec<(u32, usize, Vec<f32>)>,
+}
+
+impl DecodedMesh {
+    /// 按唯一id取一个属性的分量数和值
+    pub fn attribute(&self, id: u32) -> Option<(usize, &[f32])> {
+        self.attributes
+            .iter()
+            .find(|x| x.0 == id)
+            .map(|(_, components, values)| (*components, values.as_slice()))
+    }
+}
+
+// 从前往后读码流，越界时返回 None
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|x| x[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|x| u16::from_le_bytes([x[0], x[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            value |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+// 与 `to_symbol` 相反
+fn from_symbol(symbol: u32) -> i32 {
+    if symbol & 1 == 0 {
+        (symbol >> 1) as i32
+    } else {
+        -((symbol >> 1) as i32) - 1
+    }
+}
+
+/// 解码 `encode_symbols` 写出的 `count` 个符号
+fn decode_symbols(reader: &mut Reader, count: usize) -> Option<Vec<u32>> {
+    if count == 0 {
+        return Some(vec![]);
+    }
+    let bit_length = match (reader.u8()?, reader.u8()?) {
+        (1, b @ 1..=18) => b as u32,
+        _ => return None,
+    };
+    let precision_bits = (3 * bit_length / 2).clamp(12, 20);
+    let precision = 1u64 << precision_bits;
+
+    let num_symbols = reader.varint()? as usize;
+    let mut probability = vec![0u64; num_symbols];
+    let mut i = 0;
+    while i < num_symbols {
+        let b = reader.u8()?;
+        let extra = b & 3;
+        if extra == 3 {
+            i += (b >> 2) as usize + 1;
+            continue;
+        }
+        let mut p = (b >> 2) as u64;
+        for k in 0..extra as u64 {
+            p |= (reader.u8()? as u64) << (8 * (k + 1) - 2);
+        }
+        probability[i] = p;
+        i += 1;
+    }
+    if probability.iter().sum::<u64>() != precision {
+        return None;
+    }
+    // 按累计概率查符号
+    let mut lookup = Vec::with_capacity(precision as usize);
+    let mut cumulative = vec![0u64; num_symbols];
+    for (s, p) in probability.iter().enumerate() {
+        cumulative[s] = lookup.len() as u64;
+        lookup.extend(std::iter::repeat_n(s as u32, *p as usize));
+    }
+
+    let length = reader.varint()? as usize;
+    let data = reader.bytes(length)?;
+    let (mut offset, mut state) = match data.last()? >> 6 {
+        0 => (length - 1, (data[length - 1] & 0x3f) as u64),
+        1 if length >= 2 => (length - 2, (u16::from_le_bytes([data[length - 2], data[length - 1]]) & 0x3fff) as u64),
+        2 if length >= 3 => {
+            let x = &data[length - 3..];
+            (length - 3, (x[0] as u64 | (x[1] as u64) << 8 | (x[2] as u64) << 16) & 0x3f_ffff)
+        }
+        3 if length >= 4 => {
+            let x = &data[length - 4..];
+            (length - 4, (u32::from_le_bytes([x[0], x[1], x[2], x[3]]) & 0x3fff_ffff) as u64)
+        }
+        _ => return None,
+    };
+    let base = 4 * precision;
+    state += base;
+    let mut symbols = Vec::with_capacity(count);
+    for _ in 0..count {
+        while state < base && offset > 0 {
+            offset -= 1;
+            state = state * 256 + data[offset] as u64;
+        }
+        let (quotient, remainder) = (state / precision, state % precision);
+        let s = lookup[remainder as usize];
+        state = quotient * probability[s as usize] + remainder - cumulative[s as usize];
+        symbols.push(s);
+    }
+    Some(symbols)
+}
+
+/// 解码 `encode_difference` 写出的属性值
+fn decode_difference(reader: &mut Reader, count: usize, components: usize) -> Option<Vec<i32>> {
+    let prediction = reader.u8()? as i8;
+    if prediction == PREDICTION_NONE {
+        return (reader.u8()? == 1)
+            .then(|| decode_symbols(reader, count))
+            .flatten()
+            .map(|x| x.into_iter().map(from_symbol).collect());
+    }
+    if prediction != PREDICTION_DIFFERENCE || reader.u8()? as i8 != TRANSFORM_WRAP || reader.u8()? != 1 {
+        return None;
+    }
+    let corrections = decode_symbols(reader, count)?;
+    let min = reader.u32()? as i32;
+    let max = reader.u32()? as i32;
+    let max_dif = 1 + max - min;
+    let mut values: Vec<i32> = Vec::with_capacity(count);
+    for (i, symbol) in corrections.into_iter().enumerate() {
+        let predicted = if i < components { 0 } else { values[i - components] };
+        let mut value = predicted.clamp(min, max) + from_symbol(symbol);
+        if value > max {
+            value -= max_dif;
+        } else if value < min {
+            value += max_dif;
+        }
+        values.push(value);
+    }
+    Some(values)
+}
+
+/// 八面体坐标还原成单位法线，与 `octahedral` 相反
+fn from_octahedral(s: i32, t: i32, bits: u8) -> [f32; 3] {
+    let scale = 2. / ((1 << bits) - 2) as f32;
+    let mut y = s as f32 * scale - 1.;
+    let mut z = t as f32 * scale - 1.;
+    let x = 1. - y.abs() - z.abs();
+    let offset = (-x).max(0.);
+    y += if y < 0. { offset } else { -offset };
+    z += if z < 0. { offset } else { -offset };
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+/// 解码 Draco 码流，用来检查压缩后的顶点；只支持本模块写出的顺序编码，
+/// 遇到 edgebreaker 等其他编码方式或码流不完整时返回 None
+pub fn decode_mesh(data: &[u8]) -> Option<DecodedMesh> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.bytes(5)? != b"DRACO" || reader.bytes(4)? != [2, 2, 1, 0] || reader.u16()? != 0 {
+        return None;
+    }
+    let faces = reader.varint()? as usize;
+    let points = reader.varint()? as usize;
+    if faces > data.len() {
+        return None;
+    }
+
+    let indices = match reader.u8()? {
+        0 => {
+            let mut last = 0i64;
+            decode_symbols(&mut reader, faces * 3)?
+                .into_iter()
+                .map(|s| {
+                    let diff = (s >> 1) as i64;
+                    last += if s & 1 == 1 { -diff } else { diff };
+                    last as u32
+                })
+                .collect::<Vec<_>>()
+        }
+        1 => (0..faces * 3)
+            .map(|_| {
+                if points < 256 {
+                    reader.u8().map(u32::from)
+                } else if points < 1 << 16 {
+                    reader.u16().map(u32::from)
+                } else if points < 1 << 21 {
+                    reader.varint().map(|x| x as u32)
+                } else {
+                    reader.u32()
+                }
+            })
+            .collect::<Option<Vec<_>>>()?,
+        _ => return None,
+    };
+    if indices.iter().any(|i| *i as usize >= points) {
+        return None;
+    }
+    let index = indices.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect();
+
+    if reader.u8()? != 1 {
+        return None;
+    }
+    let count = reader.varint()? as usize;
+    let declarations = (0..count)
+        .map(|_| {
+            let components = reader.bytes(4)?[2] as usize;
+            Some((components, reader.varint()? as u32))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let encoders = reader.bytes(count)?.to_vec();
+
+    let mut values = vec![];
+    for ((components, _), encoder) in declarations.iter().zip(&encoders) {
+        let components = if *encoder == ENCODER_NORMALS { 2 } else { *components };
+        values.push(decode_difference(&mut reader, points * components, components)?);
+    }
+
+    let mut attributes = vec![];
+    for (((components, id), encoder), values) in declarations.into_iter().zip(encoders).zip(values) {
+        let decoded = match encoder {
+            ENCODER_QUANTIZATION => {
+                let min = (0..components)
+                    .map(|_| reader.u32().map(f32::from_bits))
+                    .collect::<Option<Vec<_>>>()?;
+                let range = f32::from_bits(reader.u32()?);
+                let bits = reader.u8()?;
+                let delta = range / ((1u32 << bits.clamp(1, 31)) - 1) as f32;
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, q)| *q as f32 * delta + min[i % components])
+                    .collect()
+            }
+            ENCODER_NORMALS => {
+                let bits = reader.u8()?.clamp(2, 30);
+                values
+                    .chunks_exact(2)
+                    .flat_map(|x| from_octahedral(x[0], x[1], bits))
+                    .collect()
+            }
+            ENCODER_INTEGER => values.iter().map(|x| *x as f32).collect(),
+            _ => return None,
+        };
+        attributes.push((id, components, decoded));
+    }
+    (reader.pos == data.len()).then_some(DecodedMesh { index, attributes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glb::Compression;
+
+    // 简单的线性同余序列，生成可重复的测试数据
+    fn numbers(count: usize, seed: u64) -> Vec<f32> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 40) as f32 / (1u64 << 24) as f32
+            })
+            .collect()
+    }
+
+    // `n` 个顶点的网格：位置在 120×40×30 米的范围内，法线为单位向量，三角形随机取顶点
+    #[allow(clippy::type_complexity)]
+    fn sample(n: usize, triangles: usize) -> (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<u16>, Vec<[u8; 4]>, Vec<[f32; 2]>, Vec<[i32; 3]>) {
+        let r = numbers(n * 8 + triangles * 3, n as u64);
+        let position = (0..n).map(|i| [r[i * 3] * 120. - 60., r[i * 3 + 1] * 40. + 5., r[i * 3 + 2] * 30.]).collect();
+        let normal = (0..n)
+            .map(|i| {
+                let v = [r[n * 3 + i * 3] - 0.5, r[n * 3 + i * 3 + 1] - 0.5, r[n * 3 + i * 3 + 2] + 0.1];
+                let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+                v.map(|x| x / length)
+            })
+            .collect();
+        let batch_id = (0..n).map(|i| (i / 7) as u16).collect();
+        let color = (0..n).map(|i| [(i * 37) as u8, (i * 11) as u8, 200, 255]).collect();
+        let uv = (0..n).map(|i| [r[n * 6 + i * 2] * 3., r[n * 6 + i * 2 + 1]]).collect();
+        let index = (0..triangles)
+            .map(|t| [0, 1, 2].map(|k| (r[n * 8 + t * 3 + k] * n as f32) as i32 % n as i32))
+            .collect();
+        (position, normal, batch_id, color, uv, index)
+    }
+
+    fn max_difference(a: &[f32], b: impl Iterator<Item = f32>) -> f32 {
+        a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0., f32::max)
+    }
+
+    // 坐标不超过64，f32 量化、解码时的舍入误差不超过这个值
+    const ROUNDING: f32 = 4. * 64. * f32::EPSILON;
+
+    // 编码后再解码，索引和整数属性不变，量化的属性误差不超过半个步长加舍入误差，法线偏差在量化精度内
+    fn round_trip(n: usize, triangles: usize, with_extras: bool, spec: &str) {
+        let options = DracoOptions::parse(spec).unwrap();
+        let (position, normal, batch_id, color, uv, index) = sample(n, triangles);
+        let data = encode_mesh(
+            &position,
+            &normal,
+            &batch_id,
+            with_extras.then_some(color.as_slice()),
+            with_extras.then_some(uv.as_slice()),
+            &index,
+            &options,
+        );
+        let mesh = decode_mesh(&data).expect("解码失败");
+
+        let expected: Vec<[u32; 3]> = index.iter().map(|t| t.map(|i| i as u32)).collect();
+        assert_eq!(mesh.index, expected);
+
+        let (components, values) = mesh.attribute(POSITION_ID).unwrap();
+        assert_eq!((components, values.len()), (3, n * 3));
+        let range = (0..3)
+            .map(|k| {
+                let min = position.iter().map(|p| p[k]).fold(f32::MAX, f32::min);
+                let max = position.iter().map(|p| p[k]).fold(f32::MIN, f32::max);
+                max - min
+            })
+            .fold(0., f32::max);
+        let step = range / ((1u32 << options.position_bits) - 1) as f32;
+        let error = max_difference(values, position.iter().flatten().copied());
+        assert!(error <= step / 2. + ROUNDING, "位置误差{}超过半个步长{}", error, step / 2.);
+        // 瓦片的包围范围按 `position_error` 外扩，必须盖住实际的误差
+        let distance = values
+            .chunks_exact(3)
+            .zip(&position)
+            .map(|(a, b)| (0..3).map(|k| ((a[k] - b[k]) as f64).powi(2)).sum::<f64>().sqrt())
+            .fold(0., f64::max);
+        assert!(distance <= Compression::Draco(DracoOptions::parse(spec).unwrap()).position_error(range as f64));
+
+        let (components, values) = mesh.attribute(NORMAL_ID).unwrap();
+        assert_eq!((components, values.len()), (3, n * 3));
+        let error = max_difference(values, normal.iter().flatten().copied());
+        assert!(error < 4. / ((1 << options.normal_bits) - 2) as f32, "法线误差{}", error);
+
+        let (_, values) = mesh.attribute(BATCH_ID).unwrap();
+        assert_eq!(values, batch_id.iter().map(|x| *x as f32).collect::<Vec<_>>());
+
+        if with_extras {
+            let (_, values) = mesh.attribute(COLOR_ID).unwrap();
+            assert_eq!(values, color.iter().flatten().map(|x| *x as f32).collect::<Vec<_>>());
+            let (components, values) = mesh.attribute(TEXCOORD_ID).unwrap();
+            assert_eq!(components, 2);
+            let step = 3. / ((1u32 << options.uv_bits) - 1) as f32;
+            let error = max_difference(values, uv.iter().flatten().copied());
+            assert!(error <= step / 2. + ROUNDING, "纹理坐标误差{}超过半个步长{}", error, step / 2.);
+        } else {
+            assert!(mesh.attribute(COLOR_ID).is_none() && mesh.attribute(TEXCOORD_ID).is_none());
+        }
+    }
+
+    #[test]
+    fn round_trip_small_mesh_with_raw_indices() {
+        round_trip(4, 2, false, "14");
+        round_trip(5, 3, true, "8,6,10");
+    }
+
+    #[test]
+    fn round_trip_large_mesh() {
+        round_trip(3000, 5000, true, "14,10,12");
+        round_trip(300, 500, false, "11");
+        round_trip(70000, 1000, true, "16,15,16");
+    }
+
+    // 按 Draco 2.2 码流规范逐字段手工推导的一个三角形：(0,0,0) (2,0,0) (0,1,0)，
+    // 法线都朝上，批次号都是0，位置和法线都用8位。编码结果必须逐字节一致，解码必须还原
+    const GOLDEN: [u8; 136] = [
+        b'D', b'R', b'A', b'C', b'O', 2, 2, 1, 0, 0, 0, // 头：版本2.2、三角网、顺序编码、无元数据
+        1, 3, // 1个三角形、3个点
+        // 索引差 0,+1,+1 即符号 0,2,2：rANS、位数2(精度4096)、3个符号，概率 1365,0,2731
+        0, 1, 2, 3, 0x55, 0x15, 0x03, 0xad, 0x2a, 3, 0x04, 0x70, 0x81,
+        // 1个属性解码器、3个属性：位置、法线、批次号，编码方式为量化、法线、整数
+        1, 3, 0, 9, 3, 0, 0, 1, 9, 3, 0, 1, 4, 4, 1, 0, 2, 2, 3, 1,
+        // 位置量化为 (0,0,0) (255,0,0) (0,128,0)，差分加 wrap 后修正值为 0,0,0,-1,0,0,1,-128,0，
+        // 符号 0,0,0,1,0,0,2,255,0：256个符号、位数3，概率 2731,455,455,…252个零…,455
+        0, 1, 1, 1, 3, 0x80, 0x02, 0xad, 0x2a, 0x1d, 0x07, 0x1d, 0x07, 0xff, 0xff, 0xff, 0xef, 0x1d, 0x07,
+        4, 0x6b, 0x2d, 0x76, 0x87, 0, 0, 0, 0, 0xff, 0, 0, 0, // 状态，wrap 的取值范围 0..=255
+        // 法线八面体坐标 (127,254) 即符号 254,508：不预测，509个符号，两个概率各2048
+        0xfe, 1, 1, 2, 0xfd, 0x03, 0xff, 0xff, 0xff, 0xf7, 0x01, 0x20, 0xff, 0xff, 0xff, 0xf3, 0x01, 0x20,
+        3, 0x00, 0x10, 0x91,
+        // 批次号全为0：只有一个符号，概率等于精度，状态始终停在基数上
+        0, 1, 1, 1, 1, 1, 0x01, 0x40, 1, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+        // 位置的最小值 (0,0,0)、范围2、位置和法线的量化位数
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x40, 8, 8,
+    ];
+
+    #[test]
+    fn golden_triangle_matches_bitstream() {
+        let position = [[0., 0., 0.], [2., 0., 0.], [0., 1., 0.]];
+        let normal = [[0., 0., 1.]; 3];
+        let data = encode_mesh(&position, &normal, &[0; 3], None, None, &[[0, 1, 2]], &DracoOptions::parse("8,8").unwrap());
+        assert_eq!(data, GOLDEN);
+
+        let mesh = decode_mesh(&GOLDEN).unwrap();
+        assert_eq!(mesh.index, vec![[0, 1, 2]]);
+        let (_, values) = mesh.attribute(POSITION_ID).unwrap();
+        assert_eq!(values, [0., 0., 0., 2., 0., 0., 0., 128. * 2. / 255., 0.]);
+        let (_, values) = mesh.attribute(NORMAL_ID).unwrap();
+        assert_eq!(values, [0., 0., 1., 0., 0., 1., 0., 0., 1.]);
+        let (_, values) = mesh.attribute(BATCH_ID).unwrap();
+        assert_eq!(values, [0., 0., 0.]);
+    }
+
+    #[test]
+    fn decode_rejects_truncated_stream() {
+        let (position, normal, batch_id, _, _, index) = sample(50, 40);
+        let data = encode_mesh(&position, &normal, &batch_id, None, None, &index, &DracoOptions::parse("12").unwrap());
+        assert!(decode_mesh(&data).is_some());
+        assert!(decode_mesh(&data[..data.len() - 1]).is_none());
+        assert!(decode_mesh(&data[..data.len() / 2]).is_none());
+    }
+}
@@ -0,0 +1,349 @@
+// 把面、线图层生成的网格导出成桌面软件能打开的格式：Wavefront OBJ（每个要素一个组，附带 MTL 材质文件）、
+// 二进制 PLY 和二进制 STL，坐标为局部的东北天坐标，单位米，原点写在同目录的 origin.json 中；
+// 或者导出成 CityJSON、CityGML（见 `city`），坐标为输入 shp 的坐标系
+
+use crate::city::{self, Crs};
+use crate::config::MaterialsConfig;
+use crate::mesh::{Face, Mesh};
+use crate::pnts;
+use crate::tileset::{self, Origin};
+use serde_json::json;
+use shapefile::dbase::Record;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// 导出的文件格式
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Obj,
+    Ply,
+    Stl,
+    CityJson,
+    CityGml,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "obj" => Some(Format::Obj),
+            "ply" => Some(Format::Ply),
+            "stl" => Some(Format::Stl),
+            "cityjson" => Some(Format::CityJson),
+            "citygml" => Some(Format::CityGml),
+            _ => None,
+        }
+    }
+
+    /// 输出目录名
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Obj => "obj",
+            Format::Ply => "ply",
+            Format::Stl => "stl",
+            Format::CityJson => "cityjson",
+            Format::CityGml => "citygml",
+        }
+    }
+
+    /// 文件扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::CityJson => "city.json",
+            Format::CityGml => "gml",
+            _ => self.name(),
+        }
+    }
+
+    /// 坐标是否为局部坐标，需要另外写出原点
+    pub fn local(&self) -> bool {
+        matches!(self, Format::Obj | Format::Ply | Format::Stl)
+    }
+}
+
+/// 导出的参数
+pub struct ExportOptions<'a> {
+    pub format: Format,
+    /// 局部坐标的原点
+    pub origin: Origin,
+    /// 输入 shp 的坐标系，CityJSON、CityGML 使用
+    pub crs: &'a Crs,
+    pub materials: Option<&'a MaterialsConfig>,
+}
+
+/// 把一个瓦片的网格写到 `path`，`records` 与网格一一对应；OBJ 的材质文件与它同名，扩展名为 .mtl
+pub fn write(meshes: &[Mesh], records: &[&Record], options: &ExportOptions, path: &Path) -> io::Result<()> {
+    match options.format {
+        Format::Obj => write_obj(meshes, options.materials, path),
+        Format::Ply => write_ply(meshes, path),
+        Format::Stl => write_stl(meshes, path),
+        Format::CityJson => city::write_cityjson(meshes, records, options.origin, options.crs, path),
+        Format::CityGml => city::write_citygml(meshes, records, options.origin, options.crs, path),
+    }
+}
+
+/// 把局部坐标的原点写到 origin.json：经纬度（EPSG:4326）和高度，以及从局部坐标到地心坐标（EPSG:4978）的列主序矩阵
+pub fn write_origin(origin: Origin, dir: &Path) -> io::Result<()> {
+    let (lon, lat, h) = origin;
+    let transform = tileset::enu_frame(lon, lat, h as f64);
+    let data = json!({
+        "longitude": lon,
+        "latitude": lat,
+        "height": h,
+        "crs": "EPSG:4326",
+        "axes": ["east", "north", "up"],
+        "unit": "metre",
+        "transform": transform,
+    });
+    std::fs::write(dir.join("origin.json"), serde_json::to_string_pretty(&data)?)
+}
+
+// 材质按面的类型和要素颜色区分，与 glb 中的材质一致；名称里不能有 #，颜色用下划线连接
+fn material_name(face: Face, color: Option<[u8; 3]>) -> String {
+    let name = match face {
+        Face::Roof => "roof",
+        Face::Wall => "wall",
+        Face::Bottom => "bottom",
+    };
+    match color {
+        Some(c) => format!("{}_{:02x}{:02x}{:02x}", name, c[0], c[1], c[2]),
+        None => name.to_string(),
+    }
+}
+
+fn write_obj(meshes: &[Mesh], materials: Option<&MaterialsConfig>, path: &Path) -> io::Result<()> {
+    let mtl = path.with_extension("mtl");
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "# 局部坐标：x向东，y向北，z向上，单位米，原点见 origin.json")?;
+    writeln!(out, "mtllib {}", mtl.file_name().unwrap().to_string_lossy())?;
+
+    let mut used: Vec<(Face, Option<[u8; 3]>)> = vec![];
+    // OBJ 的顶点序号从1开始，在整个文件中连续编号
+    let mut base = 1;
+    for mesh in meshes.iter().filter(|x| !x.index.is_empty()) {
+        writeln!(out, "g {}", mesh.mesh_name)?;
+        for p in &mesh.vertex {
+            writeln!(out, "v {:.4} {:.4} {:.4}", p[0], p[1], p[2])?;
+        }
+        for n in &mesh.normal {
+            writeln!(out, "vn {:.4} {:.4} {:.4}", n[0], n[1], n[2])?;
+        }
+        let mut current = None;
+        for (t, face) in mesh.index.iter().zip(&mesh.face) {
+            let key = (*face, mesh.color);
+            if current != Some(key) {
+                writeln!(out, "usemtl {}", material_name(key.0, key.1))?;
+                current = Some(key);
+                if !used.contains(&key) {
+                    used.push(key);
+                }
+            }
+            let [a, b, c] = t.map(|i| i as usize + base);
+            writeln!(out, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        base += mesh.vertex.len();
+    }
+    out.flush()?;
+
+    // 要素颜色优先，其次是这类面配置的颜色，都没有时为白色
+    let mut out = BufWriter::new(File::create(mtl)?);
+    for (face, color) in used {
+        let rgb = color
+            .or_else(|| materials.and_then(|x| x.get(face).color.as_deref()).and_then(pnts::parse_hex))
+            .unwrap_or([255, 255, 255]);
+        writeln!(out, "newmtl {}", material_name(face, color))?;
+        writeln!(
+            out,
+            "Kd {:.4} {:.4} {:.4}",
+            rgb[0] as f32 / 255.,
+            rgb[1] as f32 / 255.,
+            rgb[2] as f32 / 255.
+        )?;
+        writeln!(out, "illum 1")?;
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+// 二进制 PLY：顶点带法线，面带要素序号 batch_id，与 b3dm 的 batchId 相同
+fn write_ply(meshes: &[Mesh], path: &Path) -> io::Result<()> {
+    let vertices: usize = meshes.iter().map(|x| x.vertex.len()).sum();
+    let faces: usize = meshes.iter().map(|x| x.index.len()).sum();
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(
+        out,
+        "ply\nformat binary_little_endian 1.0\ncomment local ENU coordinates in metres, origin in origin.json\n\
+         element vertex {}\nproperty float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         element face {}\nproperty list uchar int vertex_indices\nproperty int batch_id\nend_header\n",
+        vertices, faces
+    )?;
+    for mesh in meshes {
+        for (p, n) in mesh.vertex.iter().zip(&mesh.normal) {
+            for x in p.map(|x| x as f32).iter().chain(n) {
+                out.write_all(&x.to_le_bytes())?;
+            }
+        }
+    }
+    let mut base = 0;
+    for mesh in meshes {
+        for t in &mesh.index {
+            out.write_all(&[3])?;
+            for i in t {
+                out.write_all(&(i + base).to_le_bytes())?;
+            }
+            out.write_all(&mesh.id.to_le_bytes())?;
+        }
+        base += mesh.vertex.len() as i32;
+    }
+    out.flush()
+}
+
+// 二进制 STL：80字节文件头、三角形数，每个三角形为面法线、三个顶点和2字节的属性
+fn write_stl(meshes: &[Mesh], path: &Path) -> io::Result<()> {
+    let triangles: usize = meshes.iter().map(|x| x.index.len()).sum();
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut header = [b' '; 80];
+    let text = b"local ENU coordinates in metres, origin in origin.json";
+    header[..text.len()].copy_from_slice(text);
+    out.write_all(&header)?;
+    out.write_all(&(triangles as u32).to_le_bytes())?;
+    for mesh in meshes {
+        for t in &mesh.index {
+            let [a, b, c] = t.map(|i| mesh.vertex[i as usize]);
+            let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let n = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt().max(f64::MIN_POSITIVE);
+            for p in [n.map(|x| x / len), a, b, c] {
+                for x in p {
+                    out.write_all(&(x as f32).to_le_bytes())?;
+                }
+            }
+            out.write_all(&[0, 0])?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roof::Roof;
+
+    fn dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_export").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 两栋平顶的方形建筑，要素序号为 3 和 7
+    fn meshes() -> Vec<Mesh> {
+        [(3, 116.39), (7, 116.3903)]
+            .iter()
+            .map(|(id, lon)| {
+                let square = geo::Polygon::new(
+                    vec![(*lon, 39.9), (lon + 0.0001, 39.9), (lon + 0.0001, 39.9001), (*lon, 39.9001), (*lon, 39.9)].into(),
+                    vec![],
+                );
+                let mut mesh = Mesh::init(116.39, 39.9, &12., 0., geo::MultiPolygon(vec![square]), *id, &Roof::flat());
+                mesh.weld();
+                mesh
+            })
+            .collect()
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn obj_indices_are_continuous() {
+        let meshes = meshes();
+        let path = dir("obj").join("0.obj");
+        write_obj(&meshes, None, &path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut groups: Vec<(String, usize, Vec<usize>)> = vec![];
+        for line in text.lines() {
+            let mut parts = line.split(' ');
+            match parts.next() {
+                Some("g") => groups.push((parts.next().unwrap().to_string(), 0, vec![])),
+                Some("v") => groups.last_mut().unwrap().1 += 1,
+                Some("f") => {
+                    // 顶点和法线同一序号
+                    parts.for_each(|x| {
+                        let (v, n) = x.split_once("//").unwrap();
+                        assert_eq!(v, n);
+                        groups.last_mut().unwrap().2.push(v.parse().unwrap());
+                    })
+                }
+                _ => (),
+            }
+        }
+        let names: Vec<&str> = groups.iter().map(|x| x.0.as_str()).collect();
+        assert_eq!(names, ["mesh_3", "mesh_7"]);
+        let mut base = 1;
+        for ((_, vertices, indices), mesh) in groups.iter().zip(&meshes) {
+            assert_eq!(*vertices, mesh.vertex.len());
+            assert_eq!(indices.len(), mesh.index.len() * 3);
+            // 每组用到的正好是自己的顶点，接着上一组编号
+            let mut used = indices.clone();
+            used.sort();
+            used.dedup();
+            assert_eq!(used, (base..base + vertices).collect::<Vec<_>>());
+            base += vertices;
+        }
+        let mtl = std::fs::read_to_string(path.with_extension("mtl")).unwrap();
+        assert!(text.contains("mtllib 0.mtl") && mtl.contains("newmtl wall") && mtl.contains("newmtl roof"));
+    }
+
+    #[test]
+    fn ply_header_matches_body() {
+        let meshes = meshes();
+        let path = dir("ply").join("0.ply");
+        write_ply(&meshes, &path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let end = data.windows(11).position(|x| x == b"end_header\n").unwrap() + 11;
+        let header = String::from_utf8_lossy(&data[..end]).into_owned();
+        let count = |element: &str| -> usize {
+            let line = header.lines().find(|x| x.starts_with(&format!("element {} ", element))).unwrap();
+            line.rsplit(' ').next().unwrap().parse().unwrap()
+        };
+        let vertices: usize = meshes.iter().map(|x| x.vertex.len()).sum();
+        let faces: usize = meshes.iter().map(|x| x.index.len()).sum();
+        assert_eq!((count("vertex"), count("face")), (vertices, faces));
+        // 顶点为6个 float，面为1字节的个数、3个 int 序号和 int 的 batch_id
+        assert_eq!(data.len() - end, vertices * 24 + faces * 17);
+        let body = &data[end + vertices * 24..];
+        let records: Vec<(u8, [usize; 3], usize)> = body
+            .chunks_exact(17)
+            .map(|x| (x[0], [1, 5, 9].map(|o| u32_at(x, o)), u32_at(x, 13)))
+            .collect();
+        assert!(records.iter().all(|(n, t, _)| *n == 3 && t.iter().all(|i| *i < vertices)));
+        let first = meshes[0].index.len();
+        assert!(records[..first].iter().all(|x| x.2 == 3 && x.1.iter().all(|i| *i < meshes[0].vertex.len())));
+        assert!(records[first..].iter().all(|x| x.2 == 7 && x.1.iter().all(|i| *i >= meshes[0].vertex.len())));
+    }
+
+    #[test]
+    fn stl_has_fifty_bytes_per_triangle() {
+        let meshes = meshes();
+        let path = dir("stl").join("0.stl");
+        write_stl(&meshes, &path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let triangles: usize = meshes.iter().map(|x| x.index.len()).sum();
+        assert_eq!(u32_at(&data, 80), triangles);
+        assert_eq!(data.len(), 84 + 50 * triangles);
+        // 法线为单位向量，属性字节为0
+        data[84..].chunks_exact(50).for_each(|t| {
+            let n: Vec<f32> = (0..3).map(|k| f32::from_le_bytes(t[k * 4..k * 4 + 4].try_into().unwrap())).collect();
+            assert!(((n[0] * n[0] + n[1] * n[1] + n[2] * n[2]) - 1.).abs() < 1e-5);
+            assert_eq!(&t[48..], [0, 0]);
+        });
+    }
+}
@@ -0,0 +1,176 @@
+use crate::config::FacadeConfig;
+use crate::pnts;
+
+// 一张纹理为一层一开间，边长取2的幂便于生成 mipmap
+const SIZE: usize = 64;
+
+/// 按立面配置生成窗户纹理的 PNG 数据，图片上边为楼层顶部，下边为楼层底部
+pub fn facade_png(config: &FacadeConfig) -> Vec<u8> {
+    let wall = config.wall_color.as_deref().and_then(pnts::parse_hex).unwrap_or([232, 228, 216]);
+    let glass = config.window_color.as_deref().and_then(pnts::parse_hex).unwrap_or([74, 96, 120]);
+    let frame = wall.map(|x| (x as f32 * 0.6) as u8);
+    let mut pixels = vec![];
+    (0..SIZE).for_each(|row| {
+        (0..SIZE).for_each(|col| {
+            // 窗洞占开间中部，窗台离地约三成层高，窗框两像素宽，中间一道竖梃
+            let window = (12..46).contains(&col) && (10..44).contains(&row);
+            let inner = (14..44).contains(&col) && (12..42).contains(&row);
+            let mullion = (28..30).contains(&col);
+            let color = if !window {
+                wall
+            } else if !inner || mullion {
+                frame
+            } else {
+                glass
+            };
+            pixels.extend(color);
+        })
+    });
+    encode_png(SIZE as u32, SIZE as u32, &pixels)
+}
+
+// 按 RGB 写出 PNG，数据块用不压缩的 deflate 块，小图不需要真正压缩
+fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    // 每行开头加一个滤波类型0
+    let mut raw = vec![];
+    rgb.chunks(width as usize * 3).for_each(|row| {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    });
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(65535).collect();
+    blocks.iter().enumerate().for_each(|(i, block)| {
+        zlib.push((i == blocks.len() - 1) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    });
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8位深度、RGB、标准压缩和滤波、不隔行
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", vec![])]
+        .iter()
+        .for_each(|(kind, data)| {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let start = png.len();
+            png.extend_from_slice(*kind);
+            png.extend_from_slice(data);
+            let crc = crc32(&png[start..]);
+            png.extend_from_slice(&crc.to_be_bytes());
+        });
+    png
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    data.iter().for_each(|byte| {
+        crc ^= *byte as u32;
+        (0..8).for_each(|_| {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        });
+    });
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    data.iter().for_each(|byte| {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 逐块检查 CRC，返回各块的类型和内容
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut offset = 8;
+        let mut chunks = vec![];
+        while offset < png.len() {
+            let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let body = &png[offset + 4..offset + 8 + length];
+            let crc = u32::from_be_bytes(png[offset + 8 + length..offset + 12 + length].try_into().unwrap());
+            assert_eq!(crc32(body), crc);
+            chunks.push((String::from_utf8(body[..4].to_vec()).unwrap(), body[4..].to_vec()));
+            offset += 12 + length;
+        }
+        assert_eq!(offset, png.len());
+        chunks
+    }
+
+    // 解开不压缩的 deflate 块，检查每块的 LEN/NLEN、最后一块的标志和 adler32
+    fn inflate_stored(zlib: &[u8]) -> (Vec<u8>, usize) {
+        assert_eq!(&zlib[..2], [0x78, 0x01]);
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        let (mut offset, mut raw, mut blocks) = (2, vec![], 0);
+        loop {
+            let last = zlib[offset];
+            let len = u16::from_le_bytes([zlib[offset + 1], zlib[offset + 2]]);
+            assert_eq!(u16::from_le_bytes([zlib[offset + 3], zlib[offset + 4]]), !len);
+            raw.extend_from_slice(&zlib[offset + 5..offset + 5 + len as usize]);
+            offset += 5 + len as usize;
+            blocks += 1;
+            if last == 1 {
+                break;
+            }
+            assert_eq!(last, 0);
+        }
+        assert_eq!(zlib[offset..], adler32(&raw).to_be_bytes());
+        (raw, blocks)
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(b""), 1);
+        // 累加超过 65521 时取模，与 zlib.adler32 一致
+        assert_eq!(adler32(&[255; 5552]), 0xf18f_9b8c);
+    }
+
+    #[test]
+    fn facade_png_decodes_to_window_grid() {
+        let config: FacadeConfig =
+            serde_json::from_value(serde_json::json!({"wall_color": "#c8c8c8", "window_color": "#102030"})).unwrap();
+        let chunks = chunks(&facade_png(&config));
+        let kinds: Vec<_> = chunks.iter().map(|x| x.0.as_str()).collect();
+        assert_eq!(kinds, ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 64, 0, 0, 0, 64, 8, 2, 0, 0, 0]);
+        let (raw, blocks) = inflate_stored(&chunks[1].1);
+        assert_eq!(blocks, 1);
+        assert_eq!(raw.len(), 64 * (1 + 64 * 3));
+        let pixel = |row: usize, col: usize| {
+            let start = row * (1 + 64 * 3);
+            assert_eq!(raw[start], 0);
+            raw[start + 1 + col * 3..start + 4 + col * 3].to_vec()
+        };
+        assert_eq!(pixel(0, 0), [200, 200, 200]);
+        assert_eq!(pixel(63, 63), [200, 200, 200]);
+        assert_eq!(pixel(20, 20), [16, 32, 48]);
+        // 窗框和竖梃为墙色的六成
+        assert_eq!(pixel(10, 20), [120, 120, 120]);
+        assert_eq!(pixel(20, 28), [120, 120, 120]);
+    }
+
+    #[test]
+    fn large_image_is_split_into_stored_blocks() {
+        let rgb: Vec<u8> = (0..200 * 200 * 3).map(|i| (i % 251) as u8).collect();
+        let chunks = chunks(&encode_png(200, 200, &rgb));
+        let (raw, blocks) = inflate_stored(&chunks[1].1);
+        assert_eq!(blocks, 2);
+        let rows: Vec<u8> = raw.chunks(601).flat_map(|row| row[1..].to_vec()).collect();
+        assert_eq!(rows, rgb);
+    }
+}
@@ -0,0 +1,78 @@
+use shapefile::dbase::{FieldValue, Record};
+use std::collections::BTreeMap;
+
+/// 按字段名读取属性，dbf 字段名最长10个字符，找不到时再按截断后的名字查找
+pub fn get_field<'a>(record: &'a Record, name: &str) -> Option<&'a FieldValue> {
+    record
+        .get(name)
+        .or_else(|| name.get(..10).and_then(|short| record.get(short)))
+}
+
+/// 读取数值字段，兼容 dbf 中的各种数值类型，空值返回 None
+pub fn get_number(record: &Record, name: &str) -> Option<f64> {
+    match get_field(record, name)? {
+        FieldValue::Numeric(x) => *x,
+        FieldValue::Float(x) => x.map(|x| x as f64),
+        FieldValue::Double(x) => Some(*x),
+        FieldValue::Integer(x) => Some(*x as f64),
+        FieldValue::Currency(x) => Some(*x),
+        FieldValue::Character(Some(x)) => x.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// 读取文本字段，空值返回 None
+pub fn get_text(record: &Record, name: &str) -> Option<String> {
+    match get_field(record, name)? {
+        FieldValue::Character(x) => x.as_ref().map(|x| x.trim().to_string()),
+        FieldValue::Memo(x) => Some(x.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// 把属性值转成批量表里的 JSON 值，日期按 `YYYY-MM-DD` 输出
+pub fn to_json(value: &FieldValue) -> serde_json::Value {
+    match value {
+        FieldValue::Character(x) => x.as_ref().map(|x| x.trim().into()).unwrap_or_default(),
+        FieldValue::Numeric(x) => x.map(Into::into).unwrap_or_default(),
+        FieldValue::Float(x) => x.map(Into::into).unwrap_or_default(),
+        FieldValue::Logical(x) => x.map(Into::into).unwrap_or_default(),
+        FieldValue::Date(x) => x
+            .map(|d| format!("{:04}-{:02}-{:02}", d.year(), d.month(), d.day()).into())
+            .unwrap_or_default(),
+        FieldValue::DateTime(x) => {
+            let (d, t) = (x.date(), x.time());
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                d.year(),
+                d.month(),
+                d.day(),
+                t.hours(),
+                t.minutes(),
+                t.seconds()
+            )
+            .into()
+        }
+        FieldValue::Integer(x) => (*x).into(),
+        FieldValue::Currency(x) | FieldValue::Double(x) => (*x).into(),
+        FieldValue::Memo(x) => x.trim().into(),
+    }
+}
+
+/// 按字段整理成批量表的列，每列的长度等于要素数，缺少的值填 null
+pub fn batch_columns(records: &[&Record]) -> BTreeMap<String, Vec<serde_json::Value>> {
+    let mut columns: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+    records.iter().for_each(|record| {
+        record.as_ref().keys().for_each(|name| {
+            columns.entry(name.clone()).or_default();
+        });
+    });
+    columns.iter_mut().for_each(|(name, column)| {
+        column.extend(
+            records
+                .iter()
+                .map(|record| record.get(name).map(to_json).unwrap_or_default()),
+        );
+    });
+    columns
+}
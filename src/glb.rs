@@ -3,7 +3,13 @@ use std::mem;
 
 use json::validation::Checked::Valid;
 use std::borrow::Cow;
-use crate::mesh::Mesh;
+use crate::config::{self, ColorMode, MaterialConfig, MaterialsConfig};
+use crate::draco;
+use crate::facade;
+use crate::mesh::{Face, Mesh};
+use crate::meshopt;
+use crate::parallel;
+use crate::pnts;
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
@@ -42,7 +48,304 @@ fn align_to_multiple_of_four(n: &mut u32) {
     *n = (*n + 3) & !3;
 }
 
-pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
+// glTF 的颜色因子和顶点颜色都是线性空间，配置里的颜色按 sRGB 换算
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// 嵌入的纹理图片：配置的图片文件，或者程序生成的窗户立面
+#[derive(Clone, PartialEq)]
+enum Image {
+    File(String),
+    Facade,
+}
+
+// 材质按面的类型、要素颜色和纹理图片区分
+type MaterialKey = (Option<Face>, Option<[u8; 3]>, Option<Image>);
+
+/// 网格数据的压缩方式
+pub enum Compression {
+    None,
+    Draco(draco::DracoOptions),
+    Meshopt(meshopt::MeshoptOptions),
+}
+
+impl Compression {
+    // 压缩后的 glb 必须支持的扩展
+    fn extensions(&self) -> Vec<String> {
+        let names: &[&str] = match self {
+            Compression::None => &[],
+            Compression::Draco(_) => &["KHR_draco_mesh_compression"],
+            Compression::Meshopt(_) => &["EXT_meshopt_compression", "KHR_mesh_quantization"],
+        };
+        names.iter().map(|x| x.to_string()).collect()
+    }
+
+    /// 量化后顶点位置偏离原值的最大距离（米），`size` 为瓦片内顶点包围盒的最长边；
+    /// 三个分量各自有误差，距离取单个分量误差的 √3 倍
+    pub fn position_error(&self, size: f64) -> f64 {
+        let error = match self {
+            Compression::None => 0.,
+            // 每个图元在自己的包围盒内量化，范围不超过整个瓦片；量化和解码时步长、坐标都是 f32，
+            // 舍入后会略超过半个步长，按一整个步长计
+            Compression::Draco(options) => size / ((1u32 << options.position_bits) - 1) as f64,
+            // 坐标除以半边长后量化，步长见 `meshopt::quantize_snorm`
+            Compression::Meshopt(options) => {
+                let step = (32768 >> (options.position_bits - 1)) as f64;
+                size / 2. * step / i16::MAX as f64 / 2.
+            }
+        };
+        error * 3f64.sqrt()
+    }
+}
+
+// 一个图元在排进 buffer 之前的数据，压缩时已经编码好
+struct Part {
+    face: Option<Face>,
+    image: Option<Image>,
+    position: Vec<[f32; 3]>,
+    normal: Vec<[f32; 3]>,
+    index: Vec<[i32; 3]>,
+    uv: Option<Vec<[f32; 2]>>,
+    color: Option<Vec<[u8; 4]>>,
+    encoded: Encoded,
+}
+
+// 压缩编码的结果
+enum Encoded {
+    None,
+    Draco(Vec<u8>),
+    // 每个数据流为解压后的长度、编码后的数据、步长和模式，以及量化后位置、法线的范围
+    Meshopt {
+        streams: Vec<(usize, Vec<u8>, usize, &'static str)>,
+        bounds: [[f32; 3]; 4],
+    },
+}
+
+// 拆分图元、计算纹理坐标和顶点颜色并完成压缩编码，只依赖这一个网格，可以在多个线程里同时进行
+fn prepare_parts(
+    idx: usize,
+    mesh: &Mesh,
+    compression: &Compression,
+    color_mode: ColorMode,
+    materials: Option<&MaterialsConfig>,
+    center: [f32; 3],
+    extent: f32,
+) -> Vec<Part> {
+    let linear = mesh
+        .color
+        .map(|c| c.map(|x| (srgb_to_linear(x) * 255.).round() as u8))
+        .unwrap_or([255; 3]);
+    let vertex_color = match color_mode {
+        ColorMode::Vertex => Some([linear[0], linear[1], linear[2], 255]),
+        ColorMode::Material => None,
+    };
+    // 指定了分面材质时按屋顶、墙面、底面拆成几个图元，访问器和 bufferView 依次排列
+    let parts: Vec<Cow<Mesh>> = match materials.map(|_| mesh.split()) {
+        Some(parts) if !parts.is_empty() => parts.into_iter().map(Cow::Owned).collect(),
+        _ => vec![Cow::Borrowed(mesh)],
+    };
+    parts
+        .iter()
+        .map(|part| {
+            let mut normal = part.normal.clone();
+            let mut index = part.index.clone();
+            // 这类面配置了纹理时按要素的类别选图片，墙面生成立面时一层一开间重复一次，
+            // 纹理坐标换算成图片的重复次数，v 轴朝下
+            let face = part.face.first().copied();
+            let facade = materials
+                .and_then(|x| x.facade.as_ref())
+                .filter(|_| face == Some(Face::Wall));
+            let (image, size) = match (materials, face, facade) {
+                (Some(_), Some(_), Some(facade)) => (
+                    Some(Image::Facade),
+                    [facade.bay_width, mesh.floor_height.unwrap_or(facade.floor_height)],
+                ),
+                (Some(materials), Some(face), None) => match &materials.get(face).texture {
+                    Some(texture) => (
+                        texture
+                            .image_for(mesh.category.as_deref())
+                            .map(|x| Image::File(x.to_string())),
+                        texture.size,
+                    ),
+                    None => (None, [1., 1.]),
+                },
+                _ => (None, [1., 1.]),
+            };
+            let mut uv: Option<Vec<[f32; 2]>> = image.as_ref().map(|_| {
+                part.uv
+                    .iter()
+                    .map(|t| [t[0] / size[0], -t[1] / size[1]])
+                    .collect()
+            });
+            let mut position: Vec<[f32; 3]> = part
+                .vertex
+                .iter()
+                .map(|p| {
+                    let x = p[0] as f32;
+                    let y = p[1] as f32;
+                    let z = p[2] as f32;
+                    [x, y, z]
+                })
+                .collect();
+            let mut occlusion = part.occlusion.clone();
+
+            if let Compression::Meshopt(_) = compression {
+                // 先优化三角形顺序，再按首次使用的顺序重排顶点
+                meshopt::optimize_vertex_cache(&mut index, position.len());
+                meshopt::optimize_overdraw(&mut index, &position);
+                let remap = meshopt::optimize_vertex_fetch(&mut index, position.len());
+                position = remap.iter().map(|i| position[*i]).collect();
+                normal = remap.iter().map(|i| normal[*i]).collect();
+                uv = uv.map(|uv| remap.iter().map(|i| uv[*i]).collect());
+                occlusion = occlusion.map(|x| remap.iter().map(|i| x[*i]).collect());
+            }
+
+            // 顶点颜色为要素颜色乘上烘焙的环境光遮蔽，两者都没有时不写 COLOR_0
+            let color: Option<Vec<[u8; 4]>> = match (&occlusion, vertex_color) {
+                (Some(occlusion), _) => {
+                    let c = vertex_color.unwrap_or([255; 4]);
+                    Some(
+                        occlusion
+                            .iter()
+                            .map(|a| [c[0], c[1], c[2]].map(|x| (x as f32 * a).round() as u8))
+                            .map(|x| [x[0], x[1], x[2], c[3]])
+                            .collect(),
+                    )
+                }
+                (None, Some(c)) => Some(vec![c; position.len()]),
+                (None, None) => None,
+            };
+
+            let encoded = match compression {
+                Compression::Draco(options) => {
+                    let batch_id = vec![idx as u16; position.len()];
+                    Encoded::Draco(draco::encode_mesh(
+                        &position,
+                        &normal,
+                        &batch_id,
+                        color.as_deref(),
+                        uv.as_deref(),
+                        &index,
+                        options,
+                    ))
+                }
+                Compression::Meshopt(options) => {
+                    // 位置量化成 i16、法线量化成 i8，访问器的范围也换成归一化后的值
+                    let mut position_data = vec![];
+                    let mut normal_data = vec![];
+                    let mut batch_data = vec![];
+                    let mut min = [f32::MAX; 3];
+                    let mut max = [f32::MIN; 3];
+                    let mut n_min = [f32::MAX; 3];
+                    let mut n_max = [f32::MIN; 3];
+                    position.iter().zip(normal.iter()).for_each(|(p, n)| {
+                        for k in 0..3 {
+                            let q = meshopt::quantize_snorm(
+                                (p[k] - center[k]) / extent,
+                                options.position_bits,
+                                i16::MAX as i32,
+                            );
+                            position_data.extend_from_slice(&(q as i16).to_le_bytes());
+                            min[k] = min[k].min(q as f32 / i16::MAX as f32);
+                            max[k] = max[k].max(q as f32 / i16::MAX as f32);
+                            let q = meshopt::quantize_snorm(n[k], options.normal_bits, i8::MAX as i32);
+                            normal_data.push(q as i8 as u8);
+                            n_min[k] = n_min[k].min(q as f32 / i8::MAX as f32);
+                            n_max[k] = n_max[k].max(q as f32 / i8::MAX as f32);
+                        }
+                        // 每个顶点补齐到4字节的倍数
+                        position_data.extend_from_slice(&[0, 0]);
+                        normal_data.push(0);
+                        batch_data.extend_from_slice(&(idx as u16).to_le_bytes());
+                        batch_data.extend_from_slice(&[0, 0]);
+                    });
+                    let index_data: Vec<u8> = index
+                        .iter()
+                        .flatten()
+                        .flat_map(|i| (*i as u16).to_le_bytes())
+                        .collect();
+
+                    let mut streams = vec![
+                        (index_data, 2, "TRIANGLES"),
+                        (position_data, 8, "ATTRIBUTES"),
+                        (normal_data, 4, "ATTRIBUTES"),
+                        (batch_data, 4, "ATTRIBUTES"),
+                    ];
+                    if let Some(color) = &color {
+                        streams.push((color.concat(), 4, "ATTRIBUTES"));
+                    }
+                    // 纹理坐标会超出 0 到 1 的范围，保持 f32 不量化
+                    if let Some(uv) = &uv {
+                        let data = uv.iter().flatten().flat_map(|x| x.to_le_bytes()).collect();
+                        streams.push((data, 8, "ATTRIBUTES"));
+                    }
+                    let streams = streams
+                        .into_iter()
+                        .map(|(raw, stride, mode)| {
+                            let data = match mode {
+                                "TRIANGLES" => meshopt::encode_index_buffer(&index),
+                                _ => meshopt::encode_vertex_buffer(&raw, stride),
+                            };
+                            (raw.len(), data, stride, mode)
+                        })
+                        .collect();
+                    Encoded::Meshopt {
+                        streams,
+                        bounds: [min, max, n_min, n_max],
+                    }
+                }
+                Compression::None => Encoded::None,
+            };
+            Part {
+                face,
+                image,
+                position,
+                normal,
+                index,
+                uv,
+                color,
+                encoded,
+            }
+        })
+        .collect()
+}
+
+/// 把网格写成 glb，`color_mode` 决定要素颜色写成材质还是顶点颜色 COLOR_0，
+/// 给了 `materials` 时屋顶、墙面、底面分别使用各自的材质；
+/// 各网格的拆分和压缩编码用 `threads` 个线程并行，buffer 仍按网格顺序排列
+pub fn get_glb(
+    meshes: Vec<Mesh>,
+    compression: &Compression,
+    color_mode: ColorMode,
+    materials: Option<&MaterialsConfig>,
+    threads: usize,
+) -> Vec<u8> {
+    // meshopt 压缩时位置量化到整个瓦片的包围盒内，节点上统一用这里的平移和缩放还原
+    let (tile_min, tile_max) = bounding_coords(
+        &meshes
+            .iter()
+            .flat_map(|mesh| mesh.vertex.iter().map(|p| p.map(|x| x as f32)))
+            .collect(),
+    );
+    let center: [f32; 3] = [0, 1, 2].map(|k| (tile_min[k] + tile_max[k]) / 2.);
+    let mut extent = (0..3).map(|k| (tile_max[k] - tile_min[k]) / 2.).fold(0., f32::max);
+    if extent <= 0. {
+        extent = 1.;
+    }
+    // meshopt 压缩时解压后的数据只占回退 buffer 的位置，实际数据在扩展里引用
+    let mut fallback_offset = 0;
+    let mut meshopt_views = vec![];
+
+    // Draco 压缩时每个图元对应的 bufferView，以及图元是否带顶点颜色和纹理坐标
+    let mut draco_views = vec![];
+    let mut material_keys: Vec<MaterialKey> = vec![];
+
     let mut offset = 0;
     let mut accessors_vec = vec![];
     let mut buffer_views_vec = vec![];
@@ -50,186 +353,423 @@ pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
     let mut nodes_vec = vec![];
     let mut scenes_vec = vec![];
     let mut res_vec = vec![];
-    meshes.iter().enumerate().for_each(|(idx, mesh)| {
-        let position = &mesh.vertex;
-        let normal = &mesh.normal;
-        let index = &mesh.index;
+    // 拆分、顶点重排和压缩编码各网格互不相关，先并行算好，再按顺序排进 buffer
+    let prepared = parallel::map(&meshes, threads, |idx, mesh| {
+        prepare_parts(idx, mesh, compression, color_mode, materials, center, extent)
+    });
+    meshes.iter().zip(prepared).enumerate().for_each(|(idx, (mesh, parts))| {
         let mesh_name = mesh.mesh_name.clone();
-        let position = position
-            .iter()
-            .map(|p| {
-                let x = p[0] as f32;
-                let y = p[1] as f32;
-                let z = p[2] as f32;
-                [x, y, z]
-            })
-            .collect();
+        let mut primitives = vec![];
+        parts.into_iter().for_each(|part| {
+            let base = accessors_vec.len();
+            let view_base = buffer_views_vec.len();
+            let Part {
+                face,
+                image,
+                position,
+                normal,
+                index,
+                uv,
+                color,
+                encoded,
+            } = part;
+            // 材质按面的类型、要素颜色和纹理图片去重
+            let key = (
+                materials.and(face),
+                match color_mode {
+                    ColorMode::Material => mesh.color,
+                    ColorMode::Vertex => None,
+                },
+                image,
+            );
+            let material = match material_keys.iter().position(|x| *x == key) {
+                Some(i) => i,
+                None => {
+                    material_keys.push(key);
+                    material_keys.len() - 1
+                }
+            };
 
-        let (min, max) = bounding_coords(&position);
-        let (n_min, n_max) = bounding_coords(normal);
-        let max_i = calc_max(index);
+            let (mut min, mut max) = bounding_coords(&position);
+            let (mut n_min, mut n_max) = bounding_coords(&normal);
+            let max_i = calc_max(&index);
 
-        let mut indeice_buffer_length = (index.len() * 3 * mem::size_of::<u16>()) as u32;
-        while indeice_buffer_length % 4 != 0 {
-            indeice_buffer_length += 1
-        }
-        let indices_buffer_view = json::buffer::View {
-            buffer: json::Index::new(0),
-            byte_length: indeice_buffer_length,
-            byte_offset: Some(offset),
-            byte_stride: Some(mem::size_of::<u16>() as u32),
-            extensions: Default::default(),
-            extras: Default::default(),
-            name: None,
-            target: Some(Valid(json::buffer::Target::ElementArrayBuffer)),
-        };
+            // Draco 压缩时整个图元的数据都在一个 bufferView 里，访问器不再引用 bufferView
+            match encoded {
+                Encoded::Draco(data) => {
+                    draco_views.push((view_base, color.is_some(), uv.is_some()));
+                    let mut draco_buffer_length = data.len() as u32;
+                    align_to_multiple_of_four(&mut draco_buffer_length);
+                    buffer_views_vec.push(json::buffer::View {
+                        buffer: json::Index::new(0),
+                        byte_length: data.len() as u32,
+                        byte_offset: Some(offset),
+                        byte_stride: None,
+                        extensions: Default::default(),
+                        extras: Default::default(),
+                        name: None,
+                        target: None,
+                    });
+                    offset += draco_buffer_length;
+                    res_vec.extend(data);
+                    while res_vec.len() % 4 != 0 {
+                        res_vec.push(0); // pad to multiple of four bytes
+                    }
+                }
+                Encoded::Meshopt { streams, bounds } => {
+                    // 访问器的范围换成量化、归一化后的值
+                    [min, max, n_min, n_max] = bounds;
+                    for (raw_length, data, stride, mode) in streams {
+                        let (target, byte_stride) = match mode {
+                            "TRIANGLES" => (json::buffer::Target::ElementArrayBuffer, None),
+                            _ => (json::buffer::Target::ArrayBuffer, Some(stride as u32)),
+                        };
+                        buffer_views_vec.push(json::buffer::View {
+                            buffer: json::Index::new(1),
+                            byte_length: raw_length as u32,
+                            byte_offset: Some(fallback_offset),
+                            byte_stride,
+                            extensions: Default::default(),
+                            extras: Default::default(),
+                            name: None,
+                            target: Some(Valid(target)),
+                        });
+                        meshopt_views.push(serde_json::json!({
+                            "buffer": 0,
+                            "byteOffset": offset,
+                            "byteLength": data.len(),
+                            "byteStride": stride,
+                            "count": raw_length / stride,
+                            "mode": mode,
+                        }));
+                        let mut raw_length = raw_length as u32;
+                        align_to_multiple_of_four(&mut raw_length);
+                        fallback_offset += raw_length;
+                        let mut data_length = data.len() as u32;
+                        align_to_multiple_of_four(&mut data_length);
+                        offset += data_length;
+                        res_vec.extend(data);
+                        while res_vec.len() % 4 != 0 {
+                            res_vec.push(0); // pad to multiple of four bytes
+                        }
+                    }
+                }
+                Encoded::None => {
+                    let mut indeice_buffer_length = (index.len() * 3 * mem::size_of::<u16>()) as u32;
+                    while indeice_buffer_length % 4 != 0 {
+                        indeice_buffer_length += 1
+                    }
+                    let indices_buffer_view = json::buffer::View {
+                        buffer: json::Index::new(0),
+                        byte_length: indeice_buffer_length,
+                        byte_offset: Some(offset),
+                        byte_stride: Some(mem::size_of::<u16>() as u32),
+                        extensions: Default::default(),
+                        extras: Default::default(),
+                        name: None,
+                        target: Some(Valid(json::buffer::Target::ElementArrayBuffer)),
+                    };
 
-        offset = offset + indeice_buffer_length;
+                    offset = offset + indeice_buffer_length;
 
-        let mut position_buffer_length = (position.len() * mem::size_of::<[f32; 3]>()) as u32;
-        while position_buffer_length % 4 != 0 {
-            position_buffer_length += 1
-        }
-        let position_buffer_view = json::buffer::View {
-            buffer: json::Index::new(0),
-            byte_length: position_buffer_length,
-            byte_offset: Some(offset),
-            byte_stride: Some(mem::size_of::<[f32; 3]>() as u32),
-            extensions: Default::default(),
-            extras: Default::default(),
-            name: None,
-            target: Some(Valid(json::buffer::Target::ArrayBuffer)),
-        };
-        offset = offset + position_buffer_length;
+                    let mut position_buffer_length = (position.len() * mem::size_of::<[f32; 3]>()) as u32;
+                    while position_buffer_length % 4 != 0 {
+                        position_buffer_length += 1
+                    }
+                    let position_buffer_view = json::buffer::View {
+                        buffer: json::Index::new(0),
+                        byte_length: position_buffer_length,
+                        byte_offset: Some(offset),
+                        byte_stride: Some(mem::size_of::<[f32; 3]>() as u32),
+                        extensions: Default::default(),
+                        extras: Default::default(),
+                        name: None,
+                        target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                    };
+                    offset = offset + position_buffer_length;
 
-        let mut normal_buffer_length = (normal.len() * mem::size_of::<[f32; 3]>()) as u32;
-        while normal_buffer_length % 4 != 0 {
-            normal_buffer_length += 1
-        }
-        let normal_buffer_view = json::buffer::View {
-            buffer: json::Index::new(0),
-            byte_length: normal_buffer_length,
-            byte_offset: Some(offset),
-            byte_stride: Some(mem::size_of::<[f32; 3]>() as u32),
-            extensions: Default::default(),
-            extras: Default::default(),
-            name: None,
-            target: Some(Valid(json::buffer::Target::ArrayBuffer)),
-        };
-        offset = offset + normal_buffer_length;
+                    let mut normal_buffer_length = (normal.len() * mem::size_of::<[f32; 3]>()) as u32;
+                    while normal_buffer_length % 4 != 0 {
+                        normal_buffer_length += 1
+                    }
+                    let normal_buffer_view = json::buffer::View {
+                        buffer: json::Index::new(0),
+                        byte_length: normal_buffer_length,
+                        byte_offset: Some(offset),
+                        byte_stride: Some(mem::size_of::<[f32; 3]>() as u32),
+                        extensions: Default::default(),
+                        extras: Default::default(),
+                        name: None,
+                        target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                    };
+                    offset = offset + normal_buffer_length;
 
-        let mut mesh_buffer_length = (position.len() * mem::size_of::<u16>()) as u32;
-        while mesh_buffer_length % 4 != 0 {
-            mesh_buffer_length += 1
-        }
-        let mesh1_buffer_view = json::buffer::View {
-            buffer: json::Index::new(0),
-            byte_length: mesh_buffer_length,
-            byte_offset: Some(offset),
-            byte_stride: Some(mem::size_of::<u16>() as u32),
-            extensions: Default::default(),
-            extras: Default::default(),
-            name: None,
-            target: Some(Valid(json::buffer::Target::ElementArrayBuffer)),
-        };
-        offset = offset + mesh_buffer_length;
-
-        let idc_acc = json::Accessor {
-            buffer_view: Some(json::Index::new((4 * idx) as u32)),
-            byte_offset: 0,
-            count: (index.len() * 3) as u32,
-            component_type: Valid(json::accessor::GenericComponentType(
-                json::accessor::ComponentType::U16,
-            )),
-            extensions: Default::default(),
-            extras: Default::default(),
-            type_: Valid(json::accessor::Type::Scalar),
-            min: Some(json::Value::from(vec![0.])),
-            max: Some(json::Value::from(vec![max_i as f32])),
-            name: None,
-            normalized: false,
-            sparse: None,
-        };
-        let pos_acc = json::Accessor {
-            buffer_view: Some(json::Index::new((4 * idx + 1) as u32)),
-            byte_offset: 0,
-            count: position.len() as u32,
-            component_type: Valid(json::accessor::GenericComponentType(
-                json::accessor::ComponentType::F32,
-            )),
-            extensions: Default::default(),
-            extras: Default::default(),
-            type_: Valid(json::accessor::Type::Vec3),
-            min: Some(json::Value::from(Vec::from(min))),
-            max: Some(json::Value::from(Vec::from(max))),
-            name: None,
-            normalized: false,
-            sparse: None,
-        };
-        let nor_acc = json::Accessor {
-            buffer_view: Some(json::Index::new((4 * idx + 2) as u32)),
-            byte_offset: 0,
-            count: normal.len() as u32,
-            component_type: Valid(json::accessor::GenericComponentType(
-                json::accessor::ComponentType::F32,
-            )),
-            extensions: Default::default(),
-            extras: Default::default(),
-            type_: Valid(json::accessor::Type::Vec3),
-            min: Some(json::Value::from(Vec::from(n_min))),
-            max: Some(json::Value::from(Vec::from(n_max))),
-            name: None,
-            normalized: false,
-            sparse: None,
-        };
+                    let mut mesh_buffer_length = (position.len() * mem::size_of::<u16>()) as u32;
+                    while mesh_buffer_length % 4 != 0 {
+                        mesh_buffer_length += 1
+                    }
+                    let mesh1_buffer_view = json::buffer::View {
+                        buffer: json::Index::new(0),
+                        byte_length: mesh_buffer_length,
+                        byte_offset: Some(offset),
+                        byte_stride: Some(mem::size_of::<u16>() as u32),
+                        extensions: Default::default(),
+                        extras: Default::default(),
+                        name: None,
+                        target: Some(Valid(json::buffer::Target::ElementArrayBuffer)),
+                    };
+                    offset = offset + mesh_buffer_length;
 
-        let mesh_acc = json::Accessor {
-            buffer_view: Some(json::Index::new((4 * idx + 3) as u32)),
-            byte_offset: 0,
-            count: position.len() as u32,
-            component_type: Valid(json::accessor::GenericComponentType(
-                json::accessor::ComponentType::U16,
-            )),
-            extensions: Default::default(),
-            extras: Default::default(),
-            type_: Valid(json::accessor::Type::Scalar),
-            min: Some(json::Value::from(vec![idx as f32])),
-            max: Some(json::Value::from(vec![idx as f32])),
-            name: None,
-            normalized: false,
-            sparse: None,
-        };
+                    buffer_views_vec.push(indices_buffer_view);
+                    buffer_views_vec.push(position_buffer_view);
+                    buffer_views_vec.push(normal_buffer_view);
+                    buffer_views_vec.push(mesh1_buffer_view);
 
-        let primitive = json::mesh::Primitive {
-            attributes: {
-                let mut map = std::collections::HashMap::new();
-                map.insert(
-                    Valid(json::mesh::Semantic::Positions),
-                    json::Index::new((4 * idx + 1) as u32),
-                );
-                map.insert(
-                    Valid(json::mesh::Semantic::Normals),
-                    json::Index::new((4 * idx + 2) as u32),
-                );
-                map.insert(
-                    Valid(json::mesh::Semantic::Extras("BATCHID".to_string())),
-                    json::Index::new((4 * idx + 3) as u32),
-                );
-                map
-            },
-            extensions: Default::default(),
-            extras: Default::default(),
-            indices: Some(json::Index::new((4 * idx) as u32)),
-            material: Some(json::Index::new(0)),
-            mode: Valid(json::mesh::Mode::Triangles),
-            targets: None,
-        };
+                    // 顶点颜色按 RGBA 存放，每个顶点正好4字节
+                    let color_data = color.as_ref().map(|c| c.concat());
+                    if let Some(data) = &color_data {
+                        buffer_views_vec.push(json::buffer::View {
+                            buffer: json::Index::new(0),
+                            byte_length: data.len() as u32,
+                            byte_offset: Some(offset),
+                            byte_stride: Some(4),
+                            extensions: Default::default(),
+                            extras: Default::default(),
+                            name: None,
+                            target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                        });
+                        offset += data.len() as u32;
+                    }
+                    let uv_data: Option<Vec<u8>> =
+                        uv.as_ref().map(|uv| uv.iter().flatten().flat_map(|x| x.to_le_bytes()).collect());
+                    if let Some(data) = &uv_data {
+                        buffer_views_vec.push(json::buffer::View {
+                            buffer: json::Index::new(0),
+                            byte_length: data.len() as u32,
+                            byte_offset: Some(offset),
+                            byte_stride: Some(mem::size_of::<[f32; 2]>() as u32),
+                            extensions: Default::default(),
+                            extras: Default::default(),
+                            name: None,
+                            target: Some(Valid(json::buffer::Target::ArrayBuffer)),
+                        });
+                        offset += data.len() as u32;
+                    }
+
+                    // 计算存储的buffer
+                    index.iter().for_each(|ps| {
+                        ps.iter().for_each(|p| {
+                            let p = *p as u16;
+                            let temp = p.to_le_bytes();
+                            temp.iter().for_each(|u| res_vec.push(*u))
+                        })
+                    });
+                    while res_vec.len() % 4 != 0 {
+                        res_vec.push(0); // pad to multiple of four bytes
+                    }
+                    position.iter().for_each(|ps| {
+                        ps.iter().for_each(|p| {
+                            let temp = p.to_le_bytes();
+                            temp.iter().for_each(|u| res_vec.push(*u))
+                        })
+                    });
+                    while res_vec.len() % 4 != 0 {
+                        res_vec.push(0); // pad to multiple of four bytes
+                    }
+                    normal.iter().for_each(|ps| {
+                        ps.iter().for_each(|p| {
+                            let temp = p.to_le_bytes();
+                            temp.iter().for_each(|u| res_vec.push(*u))
+                        })
+                    });
+                    while res_vec.len() % 4 != 0 {
+                        res_vec.push(0); // pad to multiple of four bytes
+                    }
+                    position.iter().for_each(|_| {
+                        let p = idx as u16;
+                        let temp = p.to_le_bytes();
+                        temp.iter().for_each(|u| res_vec.push(*u))
+                    });
+                    while res_vec.len() % 4 != 0 {
+                        res_vec.push(0); // pad to multiple of four bytes
+                    }
+                    if let Some(data) = color_data {
+                        res_vec.extend(data);
+                    }
+                    if let Some(data) = uv_data {
+                        res_vec.extend(data);
+                    }
+                }
+            }
+            let view = |i: usize| match compression {
+                Compression::Draco(_) => None,
+                _ => Some(json::Index::new((view_base + i) as u32)),
+            };
+            let (position_type, normal_type, normalized) = match compression {
+                Compression::Meshopt(_) => (
+                    json::accessor::ComponentType::I16,
+                    json::accessor::ComponentType::I8,
+                    true,
+                ),
+                _ => (
+                    json::accessor::ComponentType::F32,
+                    json::accessor::ComponentType::F32,
+                    false,
+                ),
+            };
+
+            let idc_acc = json::Accessor {
+                buffer_view: view(0),
+                byte_offset: 0,
+                count: (index.len() * 3) as u32,
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::U16,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Scalar),
+                min: Some(json::Value::from(vec![0.])),
+                max: Some(json::Value::from(vec![max_i as f32])),
+                name: None,
+                normalized: false,
+                sparse: None,
+            };
+            let pos_acc = json::Accessor {
+                buffer_view: view(1),
+                byte_offset: 0,
+                count: position.len() as u32,
+                component_type: Valid(json::accessor::GenericComponentType(position_type)),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec3),
+                min: Some(json::Value::from(Vec::from(min))),
+                max: Some(json::Value::from(Vec::from(max))),
+                name: None,
+                normalized,
+                sparse: None,
+            };
+            let nor_acc = json::Accessor {
+                buffer_view: view(2),
+                byte_offset: 0,
+                count: normal.len() as u32,
+                component_type: Valid(json::accessor::GenericComponentType(normal_type)),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec3),
+                min: Some(json::Value::from(Vec::from(n_min))),
+                max: Some(json::Value::from(Vec::from(n_max))),
+                name: None,
+                normalized,
+                sparse: None,
+            };
+
+            let mesh_acc = json::Accessor {
+                buffer_view: view(3),
+                byte_offset: 0,
+                count: position.len() as u32,
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::U16,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Scalar),
+                min: Some(json::Value::from(vec![idx as f32])),
+                max: Some(json::Value::from(vec![idx as f32])),
+                name: None,
+                normalized: false,
+                sparse: None,
+            };
+
+            let color_acc = json::Accessor {
+                buffer_view: view(4),
+                byte_offset: 0,
+                count: position.len() as u32,
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::U8,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec4),
+                min: None,
+                max: None,
+                name: None,
+                normalized: true,
+                sparse: None,
+            };
+            // 纹理坐标排在顶点颜色之后
+            let uv_slot = 4 + color.is_some() as usize;
+            let uv_acc = json::Accessor {
+                buffer_view: view(uv_slot),
+                byte_offset: 0,
+                count: position.len() as u32,
+                component_type: Valid(json::accessor::GenericComponentType(
+                    json::accessor::ComponentType::F32,
+                )),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(json::accessor::Type::Vec2),
+                min: None,
+                max: None,
+                name: None,
+                normalized: false,
+                sparse: None,
+            };
+
+            let primitive = json::mesh::Primitive {
+                attributes: {
+                    let mut map = std::collections::HashMap::new();
+                    map.insert(
+                        Valid(json::mesh::Semantic::Positions),
+                        json::Index::new((base + 1) as u32),
+                    );
+                    map.insert(
+                        Valid(json::mesh::Semantic::Normals),
+                        json::Index::new((base + 2) as u32),
+                    );
+                    map.insert(
+                        Valid(json::mesh::Semantic::Extras("BATCHID".to_string())),
+                        json::Index::new((base + 3) as u32),
+                    );
+                    if color.is_some() {
+                        map.insert(
+                            Valid(json::mesh::Semantic::Colors(0)),
+                            json::Index::new((base + 4) as u32),
+                        );
+                    }
+                    if uv.is_some() {
+                        map.insert(
+                            Valid(json::mesh::Semantic::TexCoords(0)),
+                            json::Index::new((base + uv_slot) as u32),
+                        );
+                    }
+                    map
+                },
+                extensions: Default::default(),
+                extras: Default::default(),
+                indices: Some(json::Index::new(base as u32)),
+                material: Some(json::Index::new(material as u32)),
+                mode: Valid(json::mesh::Mode::Triangles),
+                targets: None,
+            };
+
+            accessors_vec.push(idc_acc);
+            accessors_vec.push(pos_acc);
+            accessors_vec.push(nor_acc);
+            accessors_vec.push(mesh_acc);
+            if color.is_some() {
+                accessors_vec.push(color_acc);
+            }
+            if uv.is_some() {
+                accessors_vec.push(uv_acc);
+            }
+            primitives.push(primitive);
+        });
 
         let mesh = json::Mesh {
             extensions: Default::default(),
             extras: Default::default(),
             name: Some(mesh_name),
-            primitives: vec![primitive],
+            primitives,
             weights: None,
         };
 
@@ -242,8 +782,14 @@ pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
             mesh: Some(json::Index::new(idx as u32)),
             name: None,
             rotation: None,
-            scale: None,
-            translation: None,
+            scale: match compression {
+                Compression::Meshopt(_) => Some([extent; 3]),
+                _ => None,
+            },
+            translation: match compression {
+                Compression::Meshopt(_) => Some(center),
+                _ => None,
+            },
             skin: None,
             weights: None,
         };
@@ -251,55 +797,78 @@ pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
         nodes_vec.push(node);
         scenes_vec.push(json::Index::new(idx as u32));
 
-        accessors_vec.push(idc_acc);
-        accessors_vec.push(pos_acc);
-        accessors_vec.push(nor_acc);
-        accessors_vec.push(mesh_acc);
-        buffer_views_vec.push(indices_buffer_view);
-        buffer_views_vec.push(position_buffer_view);
-        buffer_views_vec.push(normal_buffer_view);
-        buffer_views_vec.push(mesh1_buffer_view);
-
         meshes_vec.push(mesh);
+    });
 
-        // 计算存储的buffer
-        index.iter().for_each(|ps| {
-            ps.iter().for_each(|p| {
-                let p = *p as u16;
-                let temp = p.to_le_bytes();
-                temp.iter().for_each(|u| res_vec.push(*u))
-            })
-        });
-        while res_vec.len() % 4 != 0 {
-            res_vec.push(0); // pad to multiple of four bytes
+    // 纹理图片原样放在 buffer 末尾，每张图片只存一份，这些 bufferView 排在所有图元之后
+    let mut image_paths: Vec<&Image> = vec![];
+    material_keys.iter().for_each(|(_, _, image)| {
+        if let Some(image) = image {
+            if !image_paths.contains(&image) {
+                image_paths.push(image);
+            }
         }
-        position.iter().for_each(|ps| {
-            ps.iter().for_each(|p| {
-                let temp = p.to_le_bytes();
-                temp.iter().for_each(|u| res_vec.push(*u))
-            })
+    });
+    let mut images_vec = vec![];
+    let mut textures_vec = vec![];
+    image_paths.iter().enumerate().for_each(|(i, image)| {
+        let (name, data) = match image {
+            Image::File(path) => (
+                path.clone(),
+                materials.and_then(|x| x.image_data(path)).unwrap_or_default().to_vec(),
+            ),
+            Image::Facade => (
+                "facade".to_string(),
+                facade::facade_png(materials.and_then(|x| x.facade.as_ref()).unwrap()),
+            ),
+        };
+        images_vec.push(json::Image {
+            buffer_view: Some(json::Index::new(buffer_views_vec.len() as u32)),
+            mime_type: config::image_mime_type(&data).map(|x| json::image::MimeType(x.to_string())),
+            name: Some(name),
+            uri: None,
+            extensions: Default::default(),
+            extras: Default::default(),
         });
-        while res_vec.len() % 4 != 0 {
-            res_vec.push(0); // pad to multiple of four bytes
-        }
-        normal.iter().for_each(|ps| {
-            ps.iter().for_each(|p| {
-                let temp = p.to_le_bytes();
-                temp.iter().for_each(|u| res_vec.push(*u))
-            })
+        textures_vec.push(json::Texture {
+            name: None,
+            sampler: Some(json::Index::new(0)),
+            source: json::Index::new(i as u32),
+            extensions: Default::default(),
+            extras: Default::default(),
         });
-        while res_vec.len() % 4 != 0 {
-            res_vec.push(0); // pad to multiple of four bytes
-        }
-        position.iter().for_each(|_| {
-            let p = idx as u16;
-            let temp = p.to_le_bytes();
-            temp.iter().for_each(|u| res_vec.push(*u))
+        buffer_views_vec.push(json::buffer::View {
+            buffer: json::Index::new(0),
+            byte_length: data.len() as u32,
+            byte_offset: Some(offset),
+            byte_stride: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            target: None,
         });
+        let mut data_length = data.len() as u32;
+        align_to_multiple_of_four(&mut data_length);
+        offset += data_length;
+        res_vec.extend(data);
         while res_vec.len() % 4 != 0 {
             res_vec.push(0); // pad to multiple of four bytes
         }
     });
+    // 纹理在墙面和屋顶上平铺重复
+    let samplers_vec = if image_paths.is_empty() {
+        vec![]
+    } else {
+        vec![json::texture::Sampler {
+            mag_filter: Some(Valid(json::texture::MagFilter::Linear)),
+            min_filter: Some(Valid(json::texture::MinFilter::LinearMipmapLinear)),
+            name: None,
+            wrap_s: Valid(json::texture::WrappingMode::Repeat),
+            wrap_t: Valid(json::texture::WrappingMode::Repeat),
+            extensions: Default::default(),
+            extras: Default::default(),
+        }]
+    };
 
     let buffer = json::Buffer {
         byte_length: offset,
@@ -309,25 +878,70 @@ pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
         uri: None,
     };
 
-    let pbr = json::material::PbrMetallicRoughness {
-        metallic_factor: json::material::StrengthFactor(0.3),
-        roughness_factor: json::material::StrengthFactor(0.7),
-        base_color_factor: Default::default(),
-        base_color_texture: None,
-        metallic_roughness_texture: None,
-        extensions: None,
-        extras: Default::default(),
-    };
+    // 要素颜色优先，其次是这类面配置的颜色
+    let default_material = MaterialConfig::default();
+    let materials: Vec<json::material::Material> = material_keys
+        .iter()
+        .map(|(face, color, image)| {
+            let config = match (materials, face) {
+                (Some(materials), Some(face)) => materials.get(*face),
+                _ => &default_material,
+            };
+            let mut name = match face {
+                Some(Face::Roof) => "roof",
+                Some(Face::Wall) => "wall",
+                Some(Face::Bottom) => "bottom",
+                None => "default",
+            }
+            .to_string();
+            let rgb = color.or_else(|| config.color.as_deref().and_then(pnts::parse_hex));
+            if let Some(c) = color {
+                name += &format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+            }
+            let pbr = json::material::PbrMetallicRoughness {
+                metallic_factor: json::material::StrengthFactor(config.metallic),
+                roughness_factor: json::material::StrengthFactor(config.roughness),
+                base_color_factor: match rgb {
+                    Some(c) => json::material::PbrBaseColorFactor([
+                        srgb_to_linear(c[0]),
+                        srgb_to_linear(c[1]),
+                        srgb_to_linear(c[2]),
+                        1.,
+                    ]),
+                    None => Default::default(),
+                },
+                base_color_texture: image.as_ref().map(|image| json::texture::Info {
+                    index: json::Index::new(image_paths.iter().position(|x| *x == image).unwrap() as u32),
+                    tex_coord: 0,
+                    extensions: None,
+                    extras: Default::default(),
+                }),
+                metallic_roughness_texture: None,
+                extensions: None,
+                extras: Default::default(),
+            };
+            json::material::Material {
+                name: Some(name),
+                pbr_metallic_roughness: pbr,
+                ..Default::default()
+            }
+        })
+        .collect();
 
-    let material = json::material::Material {
-        name: Some("default".to_string()),
-        pbr_metallic_roughness: pbr,
-        ..Default::default()
-    };
+    let mut buffers = vec![buffer];
+    if let Compression::Meshopt(_) = compression {
+        buffers.push(json::Buffer {
+            byte_length: fallback_offset,
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            uri: None,
+        });
+    }
 
     let root = json::Root {
         accessors: accessors_vec,
-        buffers: vec![buffer],
+        buffers,
         buffer_views: buffer_views_vec,
         meshes: meshes_vec,
         nodes: nodes_vec,
@@ -338,10 +952,52 @@ pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
             name: None,
             nodes: scenes_vec,
         }],
-        materials: vec![material],
+        materials,
+        images: images_vec,
+        textures: textures_vec,
+        samplers: samplers_vec,
+        extensions_used: compression.extensions(),
+        extensions_required: compression.extensions(),
         ..Default::default()
     };
 
+    // gltf-json 没有压缩扩展的定义，序列化后再写进图元或 bufferView
+    let mut root = json::serialize::to_value(&root).expect("Serialization error");
+    if let Compression::Draco(_) = compression {
+        let primitives = root["meshes"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .flat_map(|mesh| mesh["primitives"].as_array_mut().unwrap().iter_mut());
+        primitives.zip(draco_views).for_each(|(primitive, (view, colored, textured))| {
+            let mut attributes = serde_json::json!({
+                "POSITION": draco::POSITION_ID,
+                "NORMAL": draco::NORMAL_ID,
+                "_BATCHID": draco::BATCH_ID,
+            });
+            if colored {
+                attributes["COLOR_0"] = draco::COLOR_ID.into();
+            }
+            if textured {
+                attributes["TEXCOORD_0"] = draco::TEXCOORD_ID.into();
+            }
+            primitive["extensions"] = serde_json::json!({
+                "KHR_draco_mesh_compression": {
+                    "bufferView": view,
+                    "attributes": attributes,
+                }
+            });
+        });
+    }
+    if let Compression::Meshopt(_) = compression {
+        let views = root["bufferViews"].as_array_mut().unwrap();
+        views.iter_mut().zip(meshopt_views).for_each(|(view, extension)| {
+            view["extensions"] = serde_json::json!({ "EXT_meshopt_compression": extension });
+        });
+        root["buffers"][1]["extensions"] = serde_json::json!({
+            "EXT_meshopt_compression": { "fallback": true }
+        });
+    }
     let json_string = json::serialize::to_string(&root).expect("Serialization error");
     let mut json_offset = json_string.len() as u32;
     align_to_multiple_of_four(&mut json_offset);
@@ -356,3 +1012,98 @@ pub fn get_glb(meshes: Vec<Mesh>) -> Vec<u8> {
     };
     glb.to_vec().unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roof::Roof;
+    use serde_json::Value;
+
+    fn building(id: i32, color: Option<[u8; 3]>) -> Mesh {
+        let ring = vec![(116.39, 39.9), (116.39, 39.9001), (116.3901, 39.9001), (116.3901, 39.9), (116.39, 39.9)];
+        let polygon = geo::Polygon::new(ring.into(), vec![]);
+        let mut mesh = Mesh::init(116.39, 39.9, &9., 0., geo::MultiPolygon(vec![polygon]), id, &Roof::flat());
+        mesh.color = color;
+        mesh
+    }
+
+    fn glb_json(glb: &[u8]) -> Value {
+        let length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        serde_json::from_slice(&glb[20..20 + length]).unwrap()
+    }
+
+    // 每个图元的材质名和三角形数
+    fn primitives(json: &Value) -> Vec<Vec<(String, u64)>> {
+        json["meshes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|mesh| {
+                mesh["primitives"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|p| {
+                        let material = &json["materials"][p["material"].as_u64().unwrap() as usize];
+                        let count = json["accessors"][p["indices"].as_u64().unwrap() as usize]["count"].as_u64().unwrap();
+                        (material["name"].as_str().unwrap().to_string(), count / 3)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn faces_get_their_own_materials() {
+        let materials: MaterialsConfig = serde_json::from_value(serde_json::json!({
+            "roof": {"color": "#b0413e", "metallic": 0.1, "roughness": 0.9},
+            "wall": {"color": "#ffffff", "metallic": 0},
+        }))
+        .unwrap();
+        let meshes = vec![building(0, None), building(1, Some([255, 0, 0]))];
+        let count = |face: Face| meshes[0].face.iter().filter(|x| **x == face).count() as u64;
+        let expected = [count(Face::Roof), count(Face::Wall), count(Face::Bottom)];
+        let glb = get_glb(meshes, &Compression::None, ColorMode::Material, Some(&materials), 1);
+        let json = glb_json(&glb);
+
+        // 同一个要素按面的类型拆成图元，三角形不重不漏；要素颜色另起材质
+        let mut parts = primitives(&json);
+        parts.iter_mut().for_each(|x| x.sort());
+        assert_eq!(
+            parts[0],
+            [("bottom".to_string(), expected[2]), ("roof".to_string(), expected[0]), ("wall".to_string(), expected[1])]
+        );
+        let names: Vec<_> = parts[1].iter().map(|x| x.0.as_str()).collect();
+        assert_eq!(names, ["bottom#ff0000", "roof#ff0000", "wall#ff0000"]);
+
+        let material = |name: &str| {
+            let m = json["materials"].as_array().unwrap().iter().find(|m| m["name"] == name).unwrap();
+            let pbr = &m["pbrMetallicRoughness"];
+            let factor = |key: &str, default: f64| pbr.get(key).map_or(default, |x| x.as_f64().unwrap());
+            let color: Vec<f64> = pbr
+                .get("baseColorFactor")
+                .map_or(vec![1.; 4], |x| x.as_array().unwrap().iter().map(|x| x.as_f64().unwrap()).collect());
+            (color, factor("metallicFactor", 1.), factor("roughnessFactor", 1.))
+        };
+        let (color, metallic, roughness) = material("roof");
+        assert!((color[0] - srgb_to_linear(0xb0) as f64).abs() < 1e-6 && color[3] == 1.);
+        assert!((metallic - 0.1).abs() < 1e-6 && (roughness - 0.9).abs() < 1e-6);
+        let (color, metallic, roughness) = material("wall");
+        assert_eq!(color, [1.; 4]);
+        assert!(metallic == 0. && (roughness - 0.7).abs() < 1e-6);
+        // 没有配置的底面用缺省的 0.3、0.7，要素颜色覆盖配置的颜色
+        let (_, metallic, roughness) = material("bottom");
+        assert!((metallic - 0.3).abs() < 1e-6 && (roughness - 0.7).abs() < 1e-6);
+        let (color, ..) = material("roof#ff0000");
+        assert_eq!(color, [1., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn without_materials_each_mesh_is_one_primitive() {
+        let meshes = vec![building(0, None), building(1, None)];
+        let total = meshes[0].index.len() as u64;
+        let json = glb_json(&get_glb(meshes, &Compression::None, ColorMode::Material, None, 1));
+        assert_eq!(primitives(&json), [[("default".to_string(), total)], [("default".to_string(), total)]]);
+        assert_eq!(json["materials"].as_array().unwrap().len(), 1);
+    }
+}
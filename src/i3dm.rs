@@ -0,0 +1,590 @@
+use crate::field;
+use crate::mesh;
+use crate::shptiff::ShpTiff;
+use crate::tileset::Origin;
+use byteorder::{LittleEndian, WriteBytesExt};
+use gltf::Error;
+use serde::{Deserialize, Serialize};
+use shapefile::dbase;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+// i3dm 各部分需要按8字节对齐
+fn align_to_multiple_of_eight(n: &mut usize) {
+    *n = (*n + 7) & !7;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BinaryBodyReference {
+    #[serde(rename = "byteOffset")]
+    pub byte_offset: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[repr(C)]
+pub struct FeatureTable {
+    #[serde(rename = "INSTANCES_LENGTH")]
+    pub instances_length: u32,
+    #[serde(rename = "POSITION")]
+    pub position: BinaryBodyReference,
+    #[serde(rename = "NORMAL_UP")]
+    pub normal_up: BinaryBodyReference,
+    #[serde(rename = "NORMAL_RIGHT")]
+    pub normal_right: BinaryBodyReference,
+    #[serde(rename = "SCALE")]
+    pub scale: BinaryBodyReference,
+}
+
+/// 单个实例，坐标为瓦片局部的东北天坐标，旋转角为正北起顺时针的度数
+pub struct Instance {
+    pub position: [f32; 3],
+    pub rotation: f64,
+    pub scale: f32,
+}
+
+/// 生成要素表及其二进制部分，依次存放位置、上方向、右方向和缩放
+///
+/// 实例的x、y轴对应右方向和上方向，tileset 的 gltfUpAxis 为Y时模型先被转成z轴朝上，
+/// 此时上方向要取水平的正北，为Z时上方向直接取天顶
+pub fn get_feature_table(instances: &[Instance], y_up: bool) -> (FeatureTable, Vec<u8>) {
+    let n = instances.len() as u32;
+    let mut binary = vec![];
+    instances.iter().for_each(|x| {
+        x.position
+            .iter()
+            .for_each(|v| binary.write_f32::<LittleEndian>(*v).unwrap());
+    });
+    instances.iter().for_each(|x| {
+        let r = x.rotation.to_radians();
+        let up = if y_up {
+            [r.sin() as f32, r.cos() as f32, 0.]
+        } else {
+            [0., 0., 1.]
+        };
+        up.iter()
+            .for_each(|v| binary.write_f32::<LittleEndian>(*v).unwrap());
+    });
+    instances.iter().for_each(|x| {
+        // 模型的x轴默认指向正东，绕竖直轴顺时针旋转
+        let r = x.rotation.to_radians();
+        [r.cos() as f32, -r.sin() as f32, 0.]
+            .iter()
+            .for_each(|v| binary.write_f32::<LittleEndian>(*v).unwrap());
+    });
+    instances
+        .iter()
+        .for_each(|x| binary.write_f32::<LittleEndian>(x.scale).unwrap());
+    let feature_table = FeatureTable {
+        instances_length: n,
+        position: BinaryBodyReference { byte_offset: 0 },
+        normal_up: BinaryBodyReference { byte_offset: 12 * n },
+        normal_right: BinaryBodyReference { byte_offset: 24 * n },
+        scale: BinaryBodyReference { byte_offset: 36 * n },
+    };
+    (feature_table, binary)
+}
+
+/// 模型默认场景在 glTF 坐标下的范围：从根节点起逐层乘上节点的平移、旋转和缩放，
+/// 把每个图元包围盒的八个角点变换到场景坐标后合并；没有网格时为 None
+pub fn model_bounds(gltf: &gltf::Gltf) -> Option<([f32; 3], [f32; 3])> {
+    let scene = gltf.default_scene().or_else(|| gltf.scenes().next())?;
+    let identity = [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1.]];
+    let mut bounds = None;
+    scene.nodes().for_each(|node| node_bounds(&node, &identity, &mut bounds));
+    bounds
+}
+
+// `parent` 为父节点到场景的列主序矩阵
+fn node_bounds(node: &gltf::Node, parent: &[[f32; 4]; 4], bounds: &mut Option<([f32; 3], [f32; 3])>) {
+    let local = node.transform().matrix();
+    let world: [[f32; 4]; 4] = local.map(|col| [0, 1, 2, 3].map(|r| (0..4).map(|k| parent[k][r] * col[k]).sum()));
+    if let Some(mesh) = node.mesh() {
+        mesh.primitives().for_each(|primitive| {
+            let b = primitive.bounding_box();
+            (0..8).for_each(|i| {
+                let corner = [0, 1, 2].map(|k| if i >> k & 1 == 1 { b.max[k] } else { b.min[k] });
+                let p = [0, 1, 2].map(|r| (0..3).map(|k| world[k][r] * corner[k]).sum::<f32>() + world[3][r]);
+                let (min, max) = bounds.get_or_insert((p, p));
+                for k in 0..3 {
+                    min[k] = min[k].min(p[k]);
+                    max[k] = max[k].max(p[k]);
+                }
+            });
+        });
+    }
+    node.children().for_each(|child| node_bounds(&child, &world, bounds));
+}
+
+/// 实例化用的模型：文件内容、解析后的 glTF 和它在 glTF 坐标下的范围
+pub struct Model {
+    pub path: String,
+    pub data: Vec<u8>,
+    pub gltf: gltf::Gltf,
+    min: [f32; 3],
+    max: [f32; 3],
+    // 绕竖轴旋转时水平方向离竖轴最远的距离
+    radius: f32,
+}
+
+impl Model {
+    /// 读取并解析模型，出错时返回错误说明
+    pub fn load(path: &str) -> Result<Model, String> {
+        let data = fs::read(path).map_err(|e| format!("模型文件{}读取错误: {:?}", path, e))?;
+        let gltf = gltf::Gltf::from_slice(&data).map_err(|e| format!("模型文件{}解析错误: {:?}", path, e))?;
+        // 模型在本地坐标下的范围，算上节点的变换，glTF 为y轴朝上；模型里没有网格时按一个点处理
+        let (min, max) = model_bounds(&gltf).unwrap_or(([0.; 3], [0.; 3]));
+        // 绕竖轴旋转时水平方向离竖轴最远的是模型包围盒的角点
+        let radius = [min[0], max[0]]
+            .iter()
+            .flat_map(|x| [min[2], max[2]].map(|z| x.hypot(z)))
+            .fold(0f32, f32::max);
+        Ok(Model {
+            path: path.to_string(),
+            data,
+            gltf,
+            min,
+            max,
+            radius,
+        })
+    }
+
+    /// glb 直接内嵌到 i3dm 中，gltf 按 uri 引用
+    pub fn is_glb(&self) -> bool {
+        self.data.starts_with(b"glTF")
+    }
+
+    fn file_name(&self) -> String {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|x| x.to_str())
+            .unwrap_or("model.gltf")
+            .to_string()
+    }
+
+    /// gltf 模型需要复制到输出目录的文件：模型本身，以及它按相对路径引用的 buffer 和图片，放在相同的相对位置；
+    /// 内嵌的 data URI 不用复制，绝对路径和指到模型目录以外的 uri 复制后就失效了，直接报错；glb 不需要复制
+    pub fn resources(&self) -> Result<Vec<(String, Vec<u8>)>, String> {
+        if self.is_glb() {
+            return Ok(vec![]);
+        }
+        let buffers = self.gltf.buffers().filter_map(|x| match x.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+        let images = self.gltf.images().filter_map(|x| match x.source() {
+            gltf::image::Source::Uri { uri, .. } => Some(uri),
+            gltf::image::Source::View { .. } => None,
+        });
+        let dir = Path::new(&self.path).parent().unwrap_or(Path::new(""));
+        let mut files = vec![(self.file_name(), self.data.clone())];
+        for uri in buffers.chain(images).filter(|x| !x.starts_with("data:")) {
+            let relative = Path::new(&percent_decode(uri)).to_path_buf();
+            let inside = relative
+                .components()
+                .all(|x| matches!(x, Component::Normal(_) | Component::CurDir));
+            if uri.contains(':') || !inside {
+                return Err(format!("模型引用的文件{}不是模型所在目录下的相对路径，请改用glb模型", uri));
+            }
+            let data = fs::read(dir.join(&relative))
+                .map_err(|e| format!("模型引用的文件{}读取错误: {}", dir.join(&relative).display(), e))?;
+            files.push((relative.to_str().unwrap_or(uri).to_string(), data));
+        }
+        Ok(files)
+    }
+}
+
+// uri 中 %XX 形式的转义还原成原来的字节
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|x| std::str::from_utf8(x).ok());
+        match (bytes[i], hex.and_then(|x| u8::from_str_radix(x, 16).ok())) {
+            (b'%', Some(x)) => {
+                out.push(x);
+                i += 3;
+            }
+            (x, _) => {
+                out.push(x);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 把点要素生成 i3dm，`origin` 为瓦片局部坐标的原点（经度、纬度、高度），不给时取点的中心和最低地形高度，
+/// `y_up` 与 tileset 的 gltfUpAxis 一致，返回文件内容、包住所有实例的局部坐标点和实际使用的原点；
+/// 要素中没有点时为 None。gltf 模型按文件名引用，需要先把 `Model::resources` 写到输出目录
+pub fn get_i3dm(
+    features: &[(geo::Geometry<f64>, dbase::Record)],
+    shp_tiff: &Option<ShpTiff>,
+    model: &Model,
+    scale_field: &str,
+    rotation_field: &str,
+    origin: Option<Origin>,
+    y_up: bool,
+) -> Option<(Vec<u8>, Vec<[f64; 3]>, Origin)> {
+    let mut points = vec![];
+    features.iter().for_each(|(geometry, record)| match geometry {
+        geo::Geometry::Point(p) => points.push((*p, record)),
+        geo::Geometry::MultiPoint(x) => x.iter().for_each(|p| points.push((*p, record))),
+        _ => {}
+    });
+    if points.is_empty() {
+        return None;
+    }
+
+    let mut min_x = f64::MAX;
+    let mut max_x = f64::MIN;
+    let mut min_y = f64::MAX;
+    let mut max_y = f64::MIN;
+    // 有地形时把点贴到地形上
+    let terrain: Vec<i32> = points
+        .iter()
+        .map(|(p, _)| {
+            min_x = min_x.min(p.x());
+            max_x = max_x.max(p.x());
+            min_y = min_y.min(p.y());
+            max_y = max_y.max(p.y());
+            match shp_tiff {
+                Some(tiff_entity) => tiff_entity.get_height_by_geo_info(p.x() as f32, p.y() as f32),
+                None => 0,
+            }
+        })
+        .collect();
+    let (cx, cy, bottom_h) = origin.unwrap_or((
+        (max_x + min_x) / 2.,
+        (max_y + min_y) / 2.,
+        terrain.iter().cloned().min().unwrap_or(0),
+    ));
+
+    let instances: Vec<Instance> = points
+        .iter()
+        .zip(&terrain)
+        .map(|((p, record), h)| {
+            let scale = field::get_number(record, scale_field).unwrap_or(1.) as f32;
+            let z = (h - bottom_h) as f32;
+            Instance {
+                position: [
+                    mesh::lon_to_meters(p.x() - cx, cy) as f32,
+                    mesh::lat_to_meters(p.y() - cy) as f32,
+                    z,
+                ],
+                rotation: field::get_number(record, rotation_field).unwrap_or(0.),
+                scale,
+            }
+        })
+        .collect();
+
+    // 每个实例取绕竖轴旋转后仍能包住模型的长方体，用它的八个角点求包围范围
+    let corners: Vec<[f64; 3]> = instances
+        .iter()
+        .flat_map(|x| {
+            let r = (model.radius * x.scale.abs()) as f64;
+            let p = x.position.map(|v| v as f64);
+            [model.min[1], model.max[1]].into_iter().flat_map(move |v| {
+                let z = p[2] + (v * x.scale) as f64;
+                [[-r, -r], [r, -r], [-r, r], [r, r]].map(|c| [p[0] + c[0], p[1] + c[1], z])
+            })
+        })
+        .collect();
+
+    let (gltf_format, gltf) = if model.is_glb() {
+        (1, Cow::Borrowed(model.data.as_slice()))
+    } else {
+        (0, Cow::Owned(model.file_name().into_bytes()))
+    };
+
+    let records: Vec<&dbase::Record> = points.iter().map(|(_, record)| *record).collect();
+    let (feature_table, feature_binary) = get_feature_table(&instances, y_up);
+    let test1 = serde_json::to_string(&field::batch_columns(&records)).expect("Serialization error");
+    let test2 = serde_json::to_string(&feature_table).expect("Serialization error");
+    let i3dm = MakeI3dm { gltf_format, gltf };
+    let mut data = vec![];
+    i3dm.to_writer(&mut data, test2.into_bytes(), feature_binary, test1.into_bytes())
+        .expect("glTF binary output error");
+    Some((data, corners, (cx, cy, bottom_h)))
+}
+
+pub struct MakeI3dm<'a> {
+    // 0 表示 gltf 字段是模型的 uri，1 表示内嵌的 glb
+    pub gltf_format: u32,
+    pub gltf: Cow<'a, [u8]>,
+}
+
+impl<'a> MakeI3dm<'a> {
+    //生成i3dm文件
+    pub fn to_writer<W>(
+        &self,
+        mut writer: W,
+        feature_string_vec: Vec<u8>,
+        feature_binary_vec: Vec<u8>,
+        table_string_vec: Vec<u8>,
+    ) -> Result<(), Error>
+    where
+        W: io::Write,
+    {
+        // 要素表 JSON 的结尾要让二进制部分从8字节对齐的位置开始
+        let mut feature_table_json_byte_length = 32 + feature_string_vec.len();
+        align_to_multiple_of_eight(&mut feature_table_json_byte_length);
+        feature_table_json_byte_length -= 32;
+        let mut feature_table_binary_byte_length = feature_binary_vec.len();
+        align_to_multiple_of_eight(&mut feature_table_binary_byte_length);
+        let mut batch_table_json_byte_length = table_string_vec.len();
+        align_to_multiple_of_eight(&mut batch_table_json_byte_length);
+        let mut gltf_byte_length = self.gltf.len();
+        align_to_multiple_of_eight(&mut gltf_byte_length);
+
+        // 写入i3dm文件头部
+        {
+            let magic = b"i3dm";
+            let version = 1;
+            let length = 32
+                + feature_table_json_byte_length
+                + feature_table_binary_byte_length
+                + batch_table_json_byte_length
+                + gltf_byte_length;
+
+            writer.write_all(&magic[..])?;
+            writer.write_u32::<LittleEndian>(version)?;
+            writer.write_u32::<LittleEndian>(length as u32)?;
+            writer.write_u32::<LittleEndian>(feature_table_json_byte_length as u32)?;
+            writer.write_u32::<LittleEndian>(feature_table_binary_byte_length as u32)?;
+            writer.write_u32::<LittleEndian>(batch_table_json_byte_length as u32)?;
+            //批量表全部写在 JSON 中，二进制为空
+            writer.write_u32::<LittleEndian>(0)?;
+            writer.write_u32::<LittleEndian>(self.gltf_format)?;
+        }
+
+        for (data, length, pad) in [
+            (&feature_string_vec, feature_table_json_byte_length, 0x20),
+            (&feature_binary_vec, feature_table_binary_byte_length, 0),
+            (&table_string_vec, batch_table_json_byte_length, 0x20),
+        ] {
+            writer.write_all(data)?;
+            for _ in 0..length - data.len() {
+                writer.write_u8(pad)?;
+            }
+        }
+
+        writer.write_all(&self.gltf)?;
+        for _ in 0..gltf_byte_length - self.gltf.len() {
+            writer.write_u8(0)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shapefile::dbase::{FieldValue, Record};
+    use std::convert::TryInto;
+    use std::path::PathBuf;
+
+    // 一个三角形的模型，范围为 [-1, 0, -1] ~ [1, 2, 1]，`buffer` 为 buffer 的 uri
+    fn model_json(buffer: &str, images: &[&str]) -> String {
+        let images: Vec<String> = images.iter().map(|x| format!("{{\"uri\":\"{}\"}}", x)).collect();
+        format!(
+            concat!(
+                "{{\"asset\":{{\"version\":\"2.0\"}},\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],\"nodes\":[{{\"mesh\":0}}],",
+                "\"meshes\":[{{\"primitives\":[{{\"attributes\":{{\"POSITION\":0}}}}]}}],",
+                "\"accessors\":[{{\"bufferView\":0,\"componentType\":5126,\"count\":3,\"type\":\"VEC3\",",
+                "\"min\":[-1,0,-1],\"max\":[1,2,1]}}],",
+                "\"bufferViews\":[{{\"buffer\":0,\"byteLength\":36}}],\"buffers\":[{{\"byteLength\":36,\"uri\":\"{}\"}}]{}}}"
+            ),
+            buffer,
+            if images.is_empty() { String::new() } else { format!(",\"images\":[{}]", images.join(",")) }
+        )
+    }
+
+    // 只有 JSON 块的 glb，buffer 用 data URI 内嵌
+    fn glb() -> Vec<u8> {
+        let mut json = model_json(&format!("data:application/octet-stream;base64,{}", "A".repeat(48)), &[]).into_bytes();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        let mut data = b"glTF".to_vec();
+        [2, 20 + json.len() as u32, json.len() as u32, 0x4E4F534A]
+            .iter()
+            .for_each(|x| data.extend(x.to_le_bytes()));
+        data.extend(json);
+        data
+    }
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_i3dm").join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn point(lon: f64, lat: f64, scale: Option<f64>) -> (geo::Geometry<f64>, Record) {
+        let mut record = Record::default();
+        record.insert("scale".to_string(), FieldValue::Numeric(scale));
+        record.insert("rotation".to_string(), FieldValue::Numeric(Some(90.)));
+        (geo::Point::new(lon, lat).into(), record)
+    }
+
+    fn header(data: &[u8], k: usize) -> usize {
+        u32::from_le_bytes(data[k * 4..k * 4 + 4].try_into().unwrap()) as usize
+    }
+
+    fn position(data: &[u8], n: usize) -> [f32; 3] {
+        let start = 32 + header(data, 3) + n * 12;
+        [0, 1, 2].map(|k| f32::from_le_bytes(data[start + k * 4..start + k * 4 + 4].try_into().unwrap()))
+    }
+
+    #[test]
+    fn glb_model_is_embedded() {
+        let path = dir("glb").join("tree.glb");
+        std::fs::write(&path, glb()).unwrap();
+        let model = Model::load(path.to_str().unwrap()).unwrap();
+        assert!(model.is_glb());
+        assert!(model.resources().unwrap().is_empty());
+
+        // 坐标取 f32 能精确表示的值，原点就在第一个点上
+        let features = [point(116.5, 40., Some(2.)), point(116.5078125, 40., None)];
+        let (data, corners, origin) = get_i3dm(&features, &None, &model, "scale", "rotation", None, true).unwrap();
+        assert_eq!(origin, (116.5 + 1. / 256., 40., 0));
+        assert_eq!(&data[..4], b"i3dm");
+        assert_eq!(header(&data, 2), data.len());
+        assert_eq!(data.len() % 8, 0);
+        assert_eq!(header(&data, 7), 1);
+        let feature: serde_json::Value = serde_json::from_slice(&data[32..32 + header(&data, 3)]).unwrap();
+        assert_eq!(feature["INSTANCES_LENGTH"], 2);
+        let east = mesh::lon_to_meters(1. / 256., 40.) as f32;
+        assert_eq!(position(&data, 0), [-east, 0., 0.]);
+        assert_eq!(position(&data, 1), [east, 0., 0.]);
+        // glb 紧跟在批量表后面，原样写入
+        let start = 32 + header(&data, 3) + header(&data, 4) + header(&data, 5);
+        assert_eq!(&data[start..start + model.data.len()], model.data.as_slice());
+
+        // 放大两倍的实例绕竖轴转任意角度都在半径 2√2、高 0~4 的范围内
+        assert_eq!(corners.len(), 16);
+        let r = 2. * 2f64.sqrt();
+        for c in &corners[..8] {
+            assert!(((c[0] + east as f64).abs() - r).abs() < 1e-5);
+            assert!((c[1].abs() - r).abs() < 1e-5);
+            assert!(c[2] == 0. || c[2] == 4.);
+        }
+        for c in &corners[8..] {
+            assert!(c[2] == 0. || c[2] == 2.);
+        }
+    }
+
+    #[test]
+    fn gltf_model_is_referenced_with_resources() {
+        let dir = dir("gltf");
+        std::fs::write(dir.join("model.gltf"), model_json("mesh.bin", &["tex%20a.png", "data:image/png;base64,AA=="]))
+            .unwrap();
+        std::fs::write(dir.join("mesh.bin"), [0; 36]).unwrap();
+        std::fs::write(dir.join("tex a.png"), b"png").unwrap();
+        let model = Model::load(dir.join("model.gltf").to_str().unwrap()).unwrap();
+        assert!(!model.is_glb());
+        let resources = model.resources().unwrap();
+        let names: Vec<&str> = resources.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["model.gltf", "mesh.bin", "tex a.png"]);
+        assert_eq!(resources[2].1, b"png");
+
+        let features = [point(116.5, 40., None)];
+        let origin = Some((116.5, 40., 0));
+        let (data, corners, _) = get_i3dm(&features, &None, &model, "scale", "rotation", origin, false).unwrap();
+        assert_eq!(header(&data, 7), 0);
+        let start = 32 + header(&data, 3) + header(&data, 4) + header(&data, 5);
+        assert_eq!(&data[start..], b"model.gltf\0\0\0\0\0\0");
+        assert_eq!(position(&data, 0), [0., 0., 0.]);
+        assert!(corners.iter().all(|c| c[2] == 0. || c[2] == 2.));
+    }
+
+    #[test]
+    fn resources_outside_model_dir_are_rejected() {
+        let dir = dir("outside");
+        for uri in ["../mesh.bin", "C:/mesh.bin", "/tmp/mesh.bin", "missing.bin"] {
+            let path = dir.join("model.gltf");
+            std::fs::write(&path, model_json(uri, &[])).unwrap();
+            let model = Model::load(path.to_str().unwrap()).unwrap();
+            assert!(model.resources().is_err(), "{}", uri);
+        }
+        std::fs::write(dir.join("broken.gltf"), b"{").unwrap();
+        assert!(Model::load(dir.join("broken.gltf").to_str().unwrap()).is_err());
+        assert!(Model::load(dir.join("none.glb").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn features_without_points_give_nothing() {
+        let path = dir("empty").join("tree.glb");
+        std::fs::write(&path, glb()).unwrap();
+        let model = Model::load(path.to_str().unwrap()).unwrap();
+        let line: geo::Geometry<f64> = geo::LineString::from(vec![(116.5, 40.), (116.6, 40.)]).into();
+        assert!(get_i3dm(&[(line, Record::default())], &None, &model, "scale", "rotation", None, true).is_none());
+    }
+
+    #[test]
+    fn percent_decode_restores_escaped_bytes() {
+        assert_eq!(percent_decode("tex%20a.png"), "tex a.png");
+        assert_eq!(percent_decode("%E7%BA%B9%E7%90%86.png"), "纹理.png");
+        // 不完整或不是十六进制的转义原样保留
+        assert_eq!(percent_decode("a%zz%2"), "a%zz%2");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn sections_are_padded_to_eight_bytes() {
+        let i3dm = MakeI3dm {
+            gltf_format: 1,
+            gltf: Cow::Owned(b"glTF!".to_vec()),
+        };
+        let mut data = vec![];
+        i3dm.to_writer(&mut data, b"{\"a\":1}".to_vec(), vec![1; 12], b"{}".to_vec()).unwrap();
+        // 要素表 JSON 从第32字节开始，补空格到40；二进制补0到16字节；批量表 JSON 补空格到8；glb 补0到8
+        assert_eq!((0..8).map(|k| header(&data, k)).skip(1).collect::<Vec<_>>(), [1, 72, 8, 16, 8, 0, 1]);
+        assert_eq!(data.len(), 72);
+        assert_eq!(&data[32..40], b"{\"a\":1} ");
+        assert_eq!(&data[40..52], [1; 12]);
+        assert_eq!(&data[52..56], [0; 4]);
+        assert_eq!(&data[56..64], b"{}      ");
+        assert_eq!(&data[64..72], b"glTF!\0\0\0");
+    }
+
+    #[test]
+    fn feature_table_orients_instances() {
+        let instances = [
+            Instance {
+                position: [1., 2., 3.],
+                rotation: 0.,
+                scale: 2.,
+            },
+            Instance {
+                position: [4., 5., 6.],
+                rotation: 90.,
+                scale: 0.5,
+            },
+        ];
+        let floats = |binary: &[u8]| -> Vec<f32> {
+            binary.chunks(4).map(|x| (f32::from_le_bytes(x.try_into().unwrap()) * 1e6).round() / 1e6).collect()
+        };
+        let (table, binary) = get_feature_table(&instances, true);
+        let json = serde_json::to_value(&table).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "INSTANCES_LENGTH": 2,
+                "POSITION": {"byteOffset": 0},
+                "NORMAL_UP": {"byteOffset": 24},
+                "NORMAL_RIGHT": {"byteOffset": 48},
+                "SCALE": {"byteOffset": 72},
+            })
+        );
+        // y 轴朝上的模型：上方向为水平的正北，转90度后朝东；右方向从正东顺时针转到正南
+        assert_eq!(
+            floats(&binary),
+            [1., 2., 3., 4., 5., 6., 0., 1., 0., 1., 0., 0., 1., 0., 0., 0., -1., 0., 2., 0.5]
+        );
+        let (_, binary) = get_feature_table(&instances, false);
+        assert_eq!(floats(&binary[24..48]), [0., 0., 1., 0., 0., 1.]);
+        assert_eq!(floats(&binary[48..72]), [1., 0., 0., 0., -1., 0.]);
+    }
+}
@@ -0,0 +1,371 @@
+// 面、线图层的要素索引：第一遍扫描 shp 时只记下每个要素的序号、外包矩形、点数和高度，不保留几何，
+// 按内存上限把要素分成若干瓦片，生成瓦片时再按序号从 shp 中读回这个瓦片的要素
+
+use crate::shptiff::ShpTiff;
+use crate::tileset::Origin;
+use geo::Centroid;
+use shapefile::dbase;
+
+/// 每个输入点在生成网格、焊接、编码 glb 的过程中大约占用的内存（字节），
+/// 按平均每个点生成十几个顶点估计，圆顶等顶点多的屋顶会偏多一些
+pub const BYTES_PER_POINT: usize = 4096;
+
+/// 点云中每个点在生成 pnts 的过程中大约占用的内存（字节），包括坐标、颜色和批量表中的一行属性
+pub const BYTES_PER_CLOUD_POINT: usize = 256;
+
+/// 索引中的一个要素
+pub struct Entry {
+    /// 要素在 shp 中的序号
+    pub index: usize,
+    /// 外包矩形 [西, 南, 东, 北]（度）
+    pub bbox: [f64; 4],
+    /// 各部分的点数之和
+    pub points: usize,
+    pub height: f32,
+}
+
+/// 第一遍扫描的结果
+pub struct FeatureIndex {
+    /// 会生成网格的面和线要素，按在 shp 中的顺序排列
+    pub entries: Vec<Entry>,
+    /// 与 `entries` 一一对应，只保留扫描时指定的字段，用来计算全局的颜色和样式
+    pub records: Vec<dbase::Record>,
+    /// 局部坐标的原点：经纬度范围的中心，高度取各部分中心处最低的地形高度
+    pub origin: Origin,
+}
+
+/// 一个瓦片：要素在索引中的位置（升序），以及划分时所在的矩形范围 [西, 南, 东, 北]
+pub struct Tile {
+    pub entries: Vec<usize>,
+    pub rect: [f64; 4],
+}
+
+/// 逐个读取 shp 中的要素建立索引，`fields` 为需要保留下来的属性字段，
+/// 高度字段缺失或类型不对时与之前一样直接报错
+pub fn scan(filename: &str, height: &str, shp_tiff: &Option<ShpTiff>, fields: &[&str]) -> FeatureIndex {
+    let mut reader = open(filename);
+    let mut entries = vec![];
+    let mut records = vec![];
+    let mut skipped = 0;
+    let mut min_x = f64::MAX;
+    let mut max_x = f64::MIN;
+    let mut min_y = f64::MAX;
+    let mut max_y = f64::MIN;
+    let mut bottom_h: Option<i32> = None;
+    reader.iter_shapes_and_records().enumerate().for_each(|(index, item)| {
+        let (shape, record) = item.expect("shp文件读取错误");
+        let geometry = match geo::Geometry::<f64>::try_from(shape) {
+            Ok(geometry @ (geo::Geometry::MultiPolygon(_) | geo::Geometry::MultiLineString(_))) => geometry,
+            _ => {
+                skipped += 1;
+                return;
+            }
+        };
+        let height = match record.get(height) {
+            Some(dbase::FieldValue::Float(Some(x))) => *x,
+            Some(_) => panic!("高度字段{}必须是浮点类型", height),
+            None => panic!("高度字段{}不存在，请重试", height),
+        };
+        let mut bbox = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        let mut points = 0;
+        feature_parts(&geometry).into_iter().for_each(|(center, line)| {
+            if let Some(tiff_entity) = shp_tiff {
+                let h = tiff_entity.get_height_by_geo_info(center.x() as f32, center.y() as f32);
+                bottom_h = Some(bottom_h.map_or(h, |x| i32::min(x, h)));
+            }
+            line.points().for_each(|p| {
+                min_x = min_x.min(p.x());
+                max_x = max_x.max(p.x());
+                min_y = min_y.min(p.y());
+                max_y = max_y.max(p.y());
+            });
+        });
+        // 内环也会生成墙面，点数按全部坐标计算
+        geo::CoordsIter::coords_iter(&geometry).for_each(|c| {
+            bbox = [bbox[0].min(c.x), bbox[1].min(c.y), bbox[2].max(c.x), bbox[3].max(c.y)];
+            points += 1;
+        });
+        entries.push(Entry {
+            index,
+            bbox,
+            points,
+            height,
+        });
+        records.push(keep_fields(&record, fields));
+    });
+    if skipped > 0 {
+        println!("跳过{}个不支持的要素", skipped);
+    }
+    FeatureIndex {
+        entries,
+        records,
+        origin: ((max_x + min_x) / 2., (max_y + min_y) / 2., bottom_h.unwrap_or(0)),
+    }
+}
+
+/// 点图层的索引，与 `scan` 相同只记下外包矩形和点数；`Entry::height` 为要素中最低的点的高度，
+/// 原点的高度取全部点中最低的
+pub fn scan_points(filename: &str, shp_tiff: &Option<ShpTiff>, fields: &[&str]) -> FeatureIndex {
+    let mut reader = open(filename);
+    let mut entries = vec![];
+    let mut records = vec![];
+    let mut extent = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    reader.iter_shapes_and_records().enumerate().for_each(|(index, item)| {
+        let (shape, record) = item.expect("shp文件读取错误");
+        let points = shape_points(&shape);
+        if points.is_empty() {
+            return;
+        }
+        let mut bbox = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        let mut height = f64::MAX;
+        points.iter().for_each(|(p, z)| {
+            bbox = [bbox[0].min(p[0]), bbox[1].min(p[1]), bbox[2].max(p[0]), bbox[3].max(p[1])];
+            height = height.min(point_height(*p, *z, shp_tiff));
+        });
+        extent = [extent[0].min(bbox[0]), extent[1].min(bbox[1]), extent[2].max(bbox[2]), extent[3].max(bbox[3])];
+        entries.push(Entry {
+            index,
+            bbox,
+            points: points.len(),
+            height: height as f32,
+        });
+        records.push(keep_fields(&record, fields));
+    });
+    let bottom = entries.iter().map(|x| x.height).fold(f32::MAX, f32::min);
+    FeatureIndex {
+        origin: (
+            (extent[0] + extent[2]) / 2.,
+            (extent[1] + extent[3]) / 2.,
+            if entries.is_empty() { 0 } else { bottom.floor() as i32 },
+        ),
+        entries,
+        records,
+    }
+}
+
+/// 点要素的各个点（经纬度）和自带的高程，PointZ、MultipointZ 以外没有高程；不是点的要素为空
+pub fn shape_points(shape: &shapefile::Shape) -> Vec<([f64; 2], Option<f64>)> {
+    match shape {
+        shapefile::Shape::PointZ(p) => vec![([p.x, p.y], Some(p.z))],
+        shapefile::Shape::Point(p) => vec![([p.x, p.y], None)],
+        shapefile::Shape::PointM(p) => vec![([p.x, p.y], None)],
+        shapefile::Shape::MultipointZ(x) => x.points().iter().map(|p| ([p.x, p.y], Some(p.z))).collect(),
+        shapefile::Shape::Multipoint(x) => x.points().iter().map(|p| ([p.x, p.y], None)).collect(),
+        shapefile::Shape::MultipointM(x) => x.points().iter().map(|p| ([p.x, p.y], None)).collect(),
+        _ => vec![],
+    }
+}
+
+/// 点的高度：有自带的高程时直接使用，否则有地形时贴到地形上，都没有时为0
+pub fn point_height(p: [f64; 2], z: Option<f64>, shp_tiff: &Option<ShpTiff>) -> f64 {
+    match (z, shp_tiff) {
+        (Some(z), _) => z,
+        (None, Some(tiff_entity)) => tiff_entity.get_height_by_geo_info(p[0] as f32, p[1] as f32) as f64,
+        (None, None) => 0.,
+    }
+}
+
+// 只保留扫描时指定的字段，字段名在 dbf 中最多10个字符，按截断后的名称也能匹配
+fn keep_fields(record: &dbase::Record, fields: &[&str]) -> dbase::Record {
+    let mut kept = dbase::Record::default();
+    record.as_ref().iter().for_each(|(name, value)| {
+        if fields.iter().any(|x| x == name || x.get(..10) == Some(name.as_str())) {
+            kept.insert(name.clone(), value.clone());
+        }
+    });
+    kept
+}
+
+/// 按估计的内存占用把要素分成瓦片：每个点占 `bytes_per_point` 字节（`BYTES_PER_POINT` 或 `BYTES_PER_CLOUD_POINT`），
+/// 超过 `budget` 字节的范围沿较长的一边在要素中心的中位数处一分为二，直到每块都不超过上限或只剩一个要素；
+/// 不给上限时全部要素放在一个瓦片里
+pub fn partition(entries: &[Entry], budget: Option<usize>, bytes_per_point: usize) -> Vec<Tile> {
+    let mut tiles = vec![];
+    let all: Vec<usize> = (0..entries.len()).collect();
+    let budget = budget.map(|x| (x, bytes_per_point));
+    split(entries, all, [f64::MIN, f64::MIN, f64::MAX, f64::MAX], budget, &mut tiles);
+    tiles
+}
+
+// `budget` 为内存上限和每个点的占用
+fn split(
+    entries: &[Entry],
+    mut items: Vec<usize>,
+    rect: [f64; 4],
+    budget: Option<(usize, usize)>,
+    tiles: &mut Vec<Tile>,
+) {
+    let cost = |bytes_per_point: usize| items.iter().map(|i| entries[*i].points * bytes_per_point).sum::<usize>();
+    if items.len() <= 1 || budget.is_none_or(|(limit, bytes_per_point)| cost(bytes_per_point) <= limit) {
+        items.sort_unstable();
+        tiles.push(Tile { entries: items, rect });
+        return;
+    }
+    let center = |i: &usize, k: usize| (entries[*i].bbox[k] + entries[*i].bbox[k + 2]) / 2.;
+    let extent = |k: usize| {
+        let lo = items.iter().map(|i| center(i, k)).fold(f64::MAX, f64::min);
+        let hi = items.iter().map(|i| center(i, k)).fold(f64::MIN, f64::max);
+        hi - lo
+    };
+    let k = if extent(0) >= extent(1) { 0 } else { 1 };
+    items.sort_by(|a, b| center(a, k).total_cmp(&center(b, k)).then(a.cmp(b)));
+    let right = items.split_off(items.len() / 2);
+    let mid = (center(items.last().unwrap(), k) + center(&right[0], k)) / 2.;
+    let (mut low, mut high) = (rect, rect);
+    low[k + 2] = mid;
+    high[k] = mid;
+    split(entries, items, low, budget, tiles);
+    split(entries, right, high, budget, tiles);
+}
+
+/// shp 是否为点图层，按文件头中的类型判断
+pub fn is_point_layer(filename: &str) -> bool {
+    let reader = shapefile::ShapeReader::from_path(filename)
+        .expect("无法正确的打开shp文件,请确保shp类型为面、线或点再试");
+    matches!(
+        reader.header().shape_type,
+        shapefile::ShapeType::Point
+            | shapefile::ShapeType::PointZ
+            | shapefile::ShapeType::PointM
+            | shapefile::ShapeType::Multipoint
+            | shapefile::ShapeType::MultipointZ
+            | shapefile::ShapeType::MultipointM
+    )
+}
+
+/// 按索引读回一个瓦片的要素，顺序与 `tile.entries` 相同
+pub fn read(filename: &str, entries: &[Entry], tile: &Tile) -> Vec<(geo::Geometry<f64>, dbase::Record)> {
+    read_shapes(filename, entries, tile)
+        .into_iter()
+        .map(|(shape, record)| {
+            let geometry = geo::Geometry::<f64>::try_from(shape).expect("shp文件读取错误");
+            (geometry, record)
+        })
+        .collect()
+}
+
+/// 与 `read` 相同，但不转换成 geo 的几何，PointZ 的高程因此能保留下来
+pub fn read_shapes(filename: &str, entries: &[Entry], tile: &Tile) -> Vec<(shapefile::Shape, dbase::Record)> {
+    let mut reader = open(filename);
+    tile.entries
+        .iter()
+        .map(|i| {
+            reader.seek(entries[*i].index).expect("shp文件读取错误");
+            reader
+                .iter_shapes_and_records()
+                .next()
+                .expect("shp文件读取错误")
+                .expect("shp文件读取错误")
+        })
+        .collect()
+}
+
+/// 要素的各个部分：面取外环，线取折线本身，同时给出用来查询地形高度的中心点
+fn feature_parts(geometry: &geo::Geometry<f64>) -> Vec<(geo::Point<f64>, &geo::LineString<f64>)> {
+    match geometry {
+        geo::Geometry::MultiPolygon(polygons) => polygons
+            .iter()
+            .filter_map(|poly| Some((poly.centroid()?, poly.exterior())))
+            .collect(),
+        geo::Geometry::MultiLineString(lines) => lines
+            .iter()
+            .filter_map(|line| Some((line.centroid()?, line)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn open(filename: &str) -> shapefile::Reader<std::io::BufReader<std::fs::File>> {
+    shapefile::Reader::from_path(filename).expect("无法正确的打开shp文件,请确保shp类型为面、线或点再试")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shapefile::dbase::{FieldValue, Record, TableWriterBuilder};
+    use std::convert::TryInto;
+
+    // 线图层，第 i 条线有 i + 2 个点，记录长度各不相同，读回时只能靠 shx 中的偏移定位
+    fn line_layer(name: &str, count: usize) -> String {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_index");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let table = TableWriterBuilder::new()
+            .add_float_field("height".try_into().unwrap(), 10, 2)
+            .add_character_field("name".try_into().unwrap(), 10);
+        let writer = shapefile::Writer::from_path(&path, table).unwrap();
+        let lines: Vec<shapefile::Polyline> = (0..count)
+            .map(|i| {
+                let points = (0..i + 2).map(|k| shapefile::Point::new(116.39 + k as f64 * 1e-4, 39.9 + i as f64 * 1e-3));
+                shapefile::Polyline::new(points.collect())
+            })
+            .collect();
+        let records: Vec<Record> = (0..count)
+            .map(|i| {
+                let mut record = Record::default();
+                record.insert("height".to_string(), FieldValue::Float(Some(i as f32)));
+                record.insert("name".to_string(), FieldValue::Character(Some(format!("l{}", i))));
+                record
+            })
+            .collect();
+        writer.write_shapes_and_records(lines.iter().zip(records.iter())).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn entry(index: usize, lon: f64, lat: f64, points: usize) -> Entry {
+        Entry {
+            index,
+            bbox: [lon, lat, lon, lat],
+            points,
+            height: 0.,
+        }
+    }
+
+    #[test]
+    fn read_shapes_seeks_to_each_entry() {
+        let filename = line_layer("lines.shp", 6);
+        let index = scan(&filename, "height", &None, &[]);
+        assert_eq!(index.entries.iter().map(|x| x.points).collect::<Vec<_>>(), [2, 3, 4, 5, 6, 7]);
+        // 向后、向前跳着读，同一个要素读两次
+        let tile = Tile {
+            entries: vec![5, 2, 0, 3, 3, 1],
+            rect: [0.; 4],
+        };
+        let shapes = read_shapes(&filename, &index.entries, &tile);
+        for ((shape, record), i) in shapes.iter().zip(&tile.entries) {
+            let shapefile::Shape::Polyline(line) = shape else {
+                panic!("不是线要素");
+            };
+            assert_eq!(line.total_point_count(), i + 2);
+            assert_eq!(record.get("name"), Some(&FieldValue::Character(Some(format!("l{}", i)))));
+        }
+        // 索引中的位置与 shp 中的序号不同时按序号读
+        let odd: Vec<Entry> = index.entries.into_iter().filter(|x| x.index % 2 == 1).collect();
+        let tile = Tile {
+            entries: vec![2, 0, 1],
+            rect: [0.; 4],
+        };
+        let names: Vec<_> = read(&filename, &odd, &tile).into_iter().map(|(_, record)| record.get("name").cloned()).collect();
+        let expected: Vec<_> = ["l5", "l1", "l3"].iter().map(|x| Some(FieldValue::Character(Some(x.to_string())))).collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn partition_splits_longer_side_at_median() {
+        // 南北排开的四个要素，每块只放得下两个，沿纬度在中间两个要素之间切开
+        let entries: Vec<Entry> = (0..4).map(|i| entry(i, 116.39 + i as f64 * 1e-4, 39.9 + i as f64 * 0.01, 10)).collect();
+        let tiles = partition(&entries, Some(20 * BYTES_PER_POINT), BYTES_PER_POINT);
+        assert_eq!(tiles.iter().map(|x| x.entries.clone()).collect::<Vec<_>>(), [vec![0, 1], vec![2, 3]]);
+        assert!((tiles[0].rect[3] - 39.915).abs() < 1e-9 && tiles[0].rect[3] == tiles[1].rect[1]);
+        assert_eq!((tiles[0].rect[0], tiles[0].rect[2], tiles[0].rect[1]), (f64::MIN, f64::MAX, f64::MIN));
+        // 刚好等于上限时不再分，超过上限的单个要素自成一块
+        assert_eq!(partition(&entries, Some(40 * BYTES_PER_POINT), BYTES_PER_POINT).len(), 1);
+        let mut entries = entries;
+        entries[1].points = 1000;
+        let tiles = partition(&entries, Some(20 * BYTES_PER_POINT), BYTES_PER_POINT);
+        assert_eq!(tiles.iter().map(|x| x.entries.clone()).collect::<Vec<_>>(), [vec![0], vec![1], vec![2, 3]]);
+        // 点云按每个点的占用计算，同样的上限放得下全部点
+        assert_eq!(partition(&entries, Some(70 * BYTES_PER_POINT), BYTES_PER_POINT).len(), 3);
+        assert_eq!(partition(&entries, Some(70 * BYTES_PER_POINT), BYTES_PER_CLOUD_POINT).len(), 1);
+    }
+}
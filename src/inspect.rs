@@ -0,0 +1,561 @@
+// 读取输出的瓦片文件：拆出 b3dm、i3dm、pnts、cmpt 的文件头和各部分，检查长度和对齐，统计内嵌 glb 的内容，
+// `inspect` 子命令把这些信息打印出来
+
+use gltf::accessor::{DataType, Dimensions};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// b3dm、i3dm 或 pnts 拆出来的各部分
+pub struct TileFile<'a> {
+    pub magic: String,
+    pub version: u32,
+    pub byte_length: usize,
+    pub feature_table: Value,
+    pub feature_binary: &'a [u8],
+    pub batch_table: Option<Value>,
+    pub batch_binary: &'a [u8],
+    /// 内嵌的 glb；i3dm 的 gltfFormat 为0时是模型的 uri，pnts 没有这一部分
+    pub glb: &'a [u8],
+    /// i3dm 的 gltfFormat，b3dm 为 None
+    pub gltf_format: Option<u32>,
+}
+
+/// 内嵌 glb 的统计，范围已经按节点的变换换算
+#[derive(Default)]
+pub struct GltfStats {
+    pub meshes: usize,
+    pub primitives: usize,
+    pub accessors: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    pub min: Option<[f64; 3]>,
+    pub max: Option<[f64; 3]>,
+    /// 访问器的分量类型和维数，如 `FLOAT VEC3`，以及个数
+    pub components: BTreeMap<String, usize>,
+    pub extensions: Vec<String>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// 按 3D Tiles 1.0 拆开 b3dm、i3dm 或 pnts，长度不对、没有按8字节对齐等问题写进 `issues`，
+/// 无法继续拆分时返回错误
+pub fn parse_tile<'a>(data: &'a [u8], issues: &mut Vec<String>) -> Result<TileFile<'a>, String> {
+    if data.len() < 4 {
+        return Err("文件不足4字节".to_string());
+    }
+    let magic = String::from_utf8_lossy(&data[..4]).to_string();
+    let header_length = match magic.as_str() {
+        "b3dm" => 28,
+        "i3dm" => 32,
+        "pnts" => 28,
+        _ => return Err(format!("不支持的文件类型{:?}", magic)),
+    };
+    if data.len() < header_length {
+        return Err(format!("{}文件头需要{}字节，文件只有{}字节", magic, header_length, data.len()));
+    }
+    let version = read_u32(data, 4);
+    if version != 1 {
+        issues.push(format!("版本为{}，应为1", version));
+    }
+    let byte_length = read_u32(data, 8) as usize;
+    if byte_length != data.len() {
+        issues.push(format!("文件头中的 byteLength 为{}，实际为{}字节", byte_length, data.len()));
+    }
+    if !byte_length.is_multiple_of(8) {
+        issues.push(format!("byteLength {}不是8的倍数", byte_length));
+    }
+    let lengths = [12, 16, 20, 24].map(|x| read_u32(data, x) as usize);
+    let names = ["要素表 JSON", "要素表二进制", "批量表 JSON", "批量表二进制"];
+    let mut offset = header_length;
+    let mut sections = vec![];
+    for (name, length) in names.iter().zip(lengths) {
+        if offset + length > data.len() {
+            return Err(format!("{}的长度{}超出文件末尾", name, length));
+        }
+        sections.push(&data[offset..offset + length]);
+        offset += length;
+        // JSON 要补齐到结尾落在8字节对齐的位置，二进制部分从那里开始，长度也是8的倍数
+        if name.ends_with("JSON") && !offset.is_multiple_of(8) {
+            issues.push(format!("{} 结束于第{}字节，没有按8字节对齐", name, offset));
+        }
+        if !name.ends_with("JSON") && !length.is_multiple_of(8) {
+            issues.push(format!("{}的长度{}不是8的倍数", name, length));
+        }
+    }
+    let gltf_format = (magic == "i3dm").then(|| read_u32(data, 28));
+    let end = byte_length.min(data.len());
+    let glb = &data[offset.min(end)..end];
+
+    let feature_table = match parse_json(sections[0]) {
+        Ok(x @ Value::Object(_)) => x,
+        Ok(_) => {
+            issues.push("要素表 JSON 不是对象".to_string());
+            Value::Null
+        }
+        Err(e) => {
+            issues.push(format!("要素表 JSON 解析错误: {}", e));
+            Value::Null
+        }
+    };
+    let batch_table = match sections[2] {
+        [] => None,
+        json => match parse_json(json) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                issues.push(format!("批量表 JSON 解析错误: {}", e));
+                None
+            }
+        },
+    };
+    Ok(TileFile {
+        magic,
+        version,
+        byte_length,
+        feature_table,
+        feature_binary: sections[1],
+        batch_table,
+        batch_binary: sections[3],
+        glb,
+        gltf_format,
+    })
+}
+
+// JSON 部分用空格补齐长度，补的不是空格时解析会出错
+fn parse_json(data: &[u8]) -> Result<Value, serde_json::Error> {
+    serde_json::from_slice(data)
+}
+
+/// 拆开 cmpt，返回内部的各个瓦片
+pub fn parse_cmpt<'a>(data: &'a [u8], issues: &mut Vec<String>) -> Result<Vec<&'a [u8]>, String> {
+    if data.len() < 16 {
+        return Err("cmpt文件头需要16字节".to_string());
+    }
+    let byte_length = read_u32(data, 8) as usize;
+    if byte_length != data.len() {
+        issues.push(format!("文件头中的 byteLength 为{}，实际为{}字节", byte_length, data.len()));
+    }
+    let count = read_u32(data, 12) as usize;
+    let mut tiles = vec![];
+    let mut offset = 16;
+    for i in 0..count {
+        if offset + 12 > data.len() {
+            return Err(format!("第{}个内部瓦片超出文件末尾", i));
+        }
+        let length = read_u32(data, offset + 8) as usize;
+        if length < 12 || offset + length > data.len() {
+            return Err(format!("第{}个内部瓦片的长度{}不正确", i, length));
+        }
+        if !length.is_multiple_of(8) {
+            issues.push(format!("第{}个内部瓦片的长度{}不是8的倍数", i, length));
+        }
+        tiles.push(&data[offset..offset + length]);
+        offset += length;
+    }
+    if offset != byte_length.min(data.len()) {
+        issues.push(format!("内部瓦片共{}字节，与 byteLength 不一致", offset - 16));
+    }
+    Ok(tiles)
+}
+
+/// 按 glb 文件头中的长度截取，去掉内嵌在瓦片里时后面补齐8字节用的0；不是 glb 或长度不对时原样返回
+pub fn trim_glb(glb: &[u8]) -> &[u8] {
+    match glb.get(8..12) {
+        Some(x) if glb.starts_with(b"glTF") => {
+            let length = u32::from_le_bytes([x[0], x[1], x[2], x[3]]) as usize;
+            glb.get(..length).unwrap_or(glb)
+        }
+        _ => glb,
+    }
+}
+
+/// 检查 glb 的文件头和数据块，再统计网格、访问器、顶点和三角形；glb 无法解析时返回 None
+pub fn gltf_stats(glb: &[u8], issues: &mut Vec<String>) -> Option<GltfStats> {
+    if glb.len() < 12 || &glb[..4] != b"glTF" {
+        issues.push("内嵌的数据不是 glb".to_string());
+        return None;
+    }
+    if read_u32(glb, 4) != 2 {
+        issues.push(format!("glb 版本为{}，应为2", read_u32(glb, 4)));
+    }
+    let length = read_u32(glb, 8) as usize;
+    // 内嵌在瓦片里时 glb 后面可能补了不到8字节的0
+    if length > glb.len() || glb.len() - length >= 8 {
+        issues.push(format!("glb 文件头中的长度为{}，实际为{}字节", length, glb.len()));
+    }
+    let mut offset = 12;
+    let mut chunk = 0;
+    while offset + 8 <= length.min(glb.len()) {
+        let chunk_length = read_u32(glb, offset) as usize;
+        let chunk_type = read_u32(glb, offset + 4);
+        let expected = if chunk == 0 { 0x4E4F534A } else { 0x004E4942 };
+        if chunk < 2 && chunk_type != expected {
+            issues.push(format!("glb 第{}个数据块的类型为{:#x}，应为{:#x}", chunk, chunk_type, expected));
+        }
+        if !chunk_length.is_multiple_of(4) {
+            issues.push(format!("glb 第{}个数据块的长度{}不是4的倍数", chunk, chunk_length));
+        }
+        offset += 8 + chunk_length;
+        chunk += 1;
+    }
+    if offset != length.min(glb.len()) {
+        issues.push(format!("glb 数据块的长度之和与文件头中的长度{}不一致", length));
+    }
+
+    let gltf = match gltf::Gltf::from_slice(trim_glb(glb)) {
+        Ok(x) => x,
+        Err(gltf::Error::Validation(errors)) => {
+            let gltf = gltf::Gltf::from_slice_without_validation(trim_glb(glb)).ok()?;
+            // Draco 压缩后访问器没有 bufferView，数据在扩展里
+            let draco = gltf.extensions_used().any(|x| x == "KHR_draco_mesh_compression");
+            errors
+                .iter()
+                .filter(|(path, e)| {
+                    !(draco && path.as_str().ends_with(".bufferView") && *e == gltf::json::validation::Error::Missing)
+                })
+                .for_each(|(path, e)| issues.push(format!("glTF 校验错误: {}: {}", path, e)));
+            gltf
+        }
+        Err(e) => {
+            issues.push(format!("glTF 读取错误: {}", e));
+            return None;
+        }
+    };
+    if let (Some(buffer), Some(blob)) = (gltf.buffers().next(), &gltf.blob) {
+        if buffer.length() > blob.len() || blob.len() - buffer.length() >= 4 {
+            issues.push(format!("buffer 的长度{}与 BIN 数据块的长度{}不一致", buffer.length(), blob.len()));
+        }
+    }
+
+    let mut stats = GltfStats {
+        meshes: gltf.meshes().len(),
+        accessors: gltf.accessors().len(),
+        extensions: gltf.extensions_used().map(|x| x.to_string()).collect(),
+        ..Default::default()
+    };
+    gltf.accessors().for_each(|a| {
+        let name = format!("{} {}", component_name(a.data_type()), dimensions_name(a.dimensions()));
+        *stats.components.entry(name).or_default() += 1;
+    });
+    gltf.meshes().for_each(|mesh| {
+        mesh.primitives().for_each(|p| {
+            stats.primitives += 1;
+            let vertices = p.get(&gltf::Semantic::Positions).map_or(0, |a| a.count());
+            let indices = p.indices().map_or(vertices, |a| a.count());
+            stats.vertices += vertices;
+            stats.triangles += match p.mode() {
+                gltf::mesh::Mode::Triangles => indices / 3,
+                gltf::mesh::Mode::TriangleStrip | gltf::mesh::Mode::TriangleFan => indices.saturating_sub(2),
+                _ => 0,
+            };
+        });
+    });
+    // 顶点范围按场景中各节点的变换换算
+    let identity = [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1.]];
+    gltf.scenes().for_each(|scene| {
+        scene.nodes().for_each(|node| node_bounds(&node, identity, &mut stats));
+    });
+    Some(stats)
+}
+
+fn node_bounds(node: &gltf::Node, parent: [[f32; 4]; 4], stats: &mut GltfStats) {
+    let matrix = multiply(parent, node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        mesh.primitives().for_each(|p| {
+            let bounds = p.get(&gltf::Semantic::Positions).and_then(|a| Some((a.min()?, a.max()?)));
+            let Some((min, max)) = bounds else { return };
+            let get = |v: &Value, k: usize| v.get(k).and_then(Value::as_f64).unwrap_or(0.);
+            (0..8).for_each(|i| {
+                let c = [0, 1, 2].map(|k| if i >> k & 1 == 1 { get(&max, k) } else { get(&min, k) });
+                let p = [0, 1, 2].map(|k| {
+                    (0..3).map(|j| matrix[j][k] as f64 * c[j]).sum::<f64>() + matrix[3][k] as f64
+                });
+                let (lo, hi) = (stats.min.unwrap_or(p), stats.max.unwrap_or(p));
+                stats.min = Some([0, 1, 2].map(|k| lo[k].min(p[k])));
+                stats.max = Some([0, 1, 2].map(|k| hi[k].max(p[k])));
+            });
+        });
+    }
+    node.children().for_each(|child| node_bounds(&child, matrix, stats));
+}
+
+// 列主序的 4×4 矩阵相乘
+fn multiply(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut m = [[0.; 4]; 4];
+    for c in 0..4 {
+        for r in 0..4 {
+            m[c][r] = (0..4).map(|k| a[k][r] * b[c][k]).sum();
+        }
+    }
+    m
+}
+
+fn component_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::I8 => "BYTE",
+        DataType::U8 => "UNSIGNED_BYTE",
+        DataType::I16 => "SHORT",
+        DataType::U16 => "UNSIGNED_SHORT",
+        DataType::U32 => "UNSIGNED_INT",
+        DataType::F32 => "FLOAT",
+    }
+}
+
+fn dimensions_name(dimensions: Dimensions) -> &'static str {
+    match dimensions {
+        Dimensions::Scalar => "SCALAR",
+        Dimensions::Vec2 => "VEC2",
+        Dimensions::Vec3 => "VEC3",
+        Dimensions::Vec4 => "VEC4",
+        Dimensions::Mat2 => "MAT2",
+        Dimensions::Mat3 => "MAT3",
+        Dimensions::Mat4 => "MAT4",
+    }
+}
+
+/// 打印一个瓦片文件的信息，返回发现的问题数；文件无法读取或拆分时返回错误
+pub fn inspect(path: &str) -> Result<usize, String> {
+    let data = fs::read(path).map_err(|e| format!("文件{}读取错误: {}", path, e))?;
+    println!("文件: {}", path);
+    let mut issues = vec![];
+    match data.get(..4) {
+        Some(b"cmpt") => {
+            let tiles = parse_cmpt(&data, &mut issues)?;
+            println!("cmpt，{}字节，内部瓦片{}个", data.len(), tiles.len());
+            print_issues(&issues);
+            let mut count = issues.len();
+            for (i, tile) in tiles.iter().enumerate() {
+                println!();
+                println!("第{}个内部瓦片:", i);
+                count += print_tile(tile)?;
+            }
+            Ok(count)
+        }
+        Some(b"glTF") => {
+            println!("glb，{}字节", data.len());
+            if let Some(stats) = gltf_stats(&data, &mut issues) {
+                print_stats(&stats);
+            }
+            print_issues(&issues);
+            Ok(issues.len())
+        }
+        _ => print_tile(&data),
+    }
+}
+
+fn print_tile(data: &[u8]) -> Result<usize, String> {
+    let mut issues = vec![];
+    let tile = parse_tile(data, &mut issues)?;
+    println!("{} 版本{}，byteLength {}", tile.magic, tile.version, tile.byte_length);
+    println!(
+        "要素表二进制{}字节，批量表二进制{}字节，glTF {}字节",
+        tile.feature_binary.len(),
+        tile.batch_binary.len(),
+        tile.glb.len()
+    );
+    println!("要素表:");
+    println!("{}", serde_json::to_string_pretty(&tile.feature_table).unwrap_or_default());
+    if let Some(batch_table) = &tile.batch_table {
+        println!("批量表:");
+        println!("{}", serde_json::to_string_pretty(batch_table).unwrap_or_default());
+    }
+    match tile.gltf_format {
+        _ if tile.magic == "pnts" => {
+            if !tile.glb.is_empty() {
+                issues.push(format!("批量表之后还有{}字节", tile.glb.len()));
+            }
+        }
+        Some(0) => println!("模型 uri: {}", String::from_utf8_lossy(tile.glb).trim_end_matches(['\0', ' '])),
+        _ => {
+            if let Some(stats) = gltf_stats(tile.glb, &mut issues) {
+                print_stats(&stats);
+            }
+        }
+    }
+    print_issues(&issues);
+    Ok(issues.len())
+}
+
+fn print_stats(stats: &GltfStats) {
+    println!(
+        "glTF: 网格{}个，图元{}个，访问器{}个，顶点{}个，三角形{}个",
+        stats.meshes, stats.primitives, stats.accessors, stats.vertices, stats.triangles
+    );
+    if let (Some(min), Some(max)) = (stats.min, stats.max) {
+        println!(
+            "范围: [{:.3}, {:.3}, {:.3}] ~ [{:.3}, {:.3}, {:.3}]",
+            min[0], min[1], min[2], max[0], max[1], max[2]
+        );
+    }
+    if !stats.extensions.is_empty() {
+        println!("扩展: {}", stats.extensions.join(", "));
+    }
+    println!("访问器类型:");
+    stats.components.iter().for_each(|(name, count)| println!("  {} × {}", name, count));
+}
+
+fn print_issues(issues: &[String]) {
+    if issues.is_empty() {
+        println!("没有发现问题");
+    } else {
+        println!("问题{}个:", issues.len());
+        issues.iter().for_each(|x| println!("  {}", x));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b3dm::MakeB3dm;
+    use crate::cmpt::MakeCmpt;
+    use crate::i3dm;
+    use std::borrow::Cow;
+
+    // 只有 JSON 块的 glb，长度为4的倍数但不是8的倍数
+    fn glb() -> Vec<u8> {
+        let mut json = br#"{"asset":{"version":"2.0"},"scenes":[{"nodes":[]}]}"#.to_vec();
+        json.resize(json.len().div_ceil(8) * 8, b' ');
+        let mut data = b"glTF".to_vec();
+        [2, 20 + json.len() as u32, json.len() as u32, 0x4E4F534A]
+            .iter()
+            .for_each(|x| data.extend(x.to_le_bytes()));
+        data.extend(json);
+        data
+    }
+
+    #[test]
+    fn padded_glb_in_tile_is_read() {
+        let glb = glb();
+        assert_eq!(glb.len() % 8, 4);
+        let mut data = vec![];
+        i3dm::MakeI3dm {
+            gltf_format: 1,
+            gltf: Cow::Borrowed(&glb),
+        }
+        .to_writer(&mut data, br#"{"INSTANCES_LENGTH":0}"#.to_vec(), vec![], vec![])
+        .unwrap();
+        let mut issues = vec![];
+        let tile = parse_tile(&data, &mut issues).unwrap();
+        assert_eq!(tile.glb.len(), glb.len() + 4);
+        assert_eq!(trim_glb(tile.glb), glb.as_slice());
+        assert!(gltf_stats(tile.glb, &mut issues).is_some());
+        assert!(issues.is_empty(), "{:?}", issues);
+        // 不是 glb 或长度超出时原样返回
+        assert_eq!(trim_glb(b"model.gltf\0\0"), b"model.gltf\0\0");
+        assert_eq!(trim_glb(&glb[..40]), &glb[..40]);
+    }
+
+    fn b3dm(glb: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        MakeB3dm {
+            glb: Some(Cow::Borrowed(glb)),
+        }
+        .to_writer(&mut data, br#"{"BATCH_LENGTH":0}"#.to_vec(), br#"{"name":[]}"#.to_vec())
+        .unwrap();
+        data
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: usize) {
+        data[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn b3dm_sections_are_split() {
+        let glb = glb();
+        let data = b3dm(&glb);
+        let mut issues = vec![];
+        let tile = parse_tile(&data, &mut issues).unwrap();
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!((tile.magic.as_str(), tile.version, tile.byte_length), ("b3dm", 1, data.len()));
+        assert_eq!(tile.feature_table["BATCH_LENGTH"], 0);
+        assert!(tile.batch_table.unwrap()["name"].is_array());
+        assert_eq!(trim_glb(tile.glb), glb.as_slice());
+        assert_eq!(tile.gltf_format, None);
+    }
+
+    #[test]
+    fn misaligned_b3dm_is_reported() {
+        // 要素表 JSON 不补齐，结尾和后面的批量表都不在8字节对齐的位置
+        let json = br#"{"BATCH_LENGTH":0}"#;
+        let mut data = b"b3dm".to_vec();
+        [1, 0, json.len(), 0, 0, 0].iter().for_each(|x| data.extend((*x as u32).to_le_bytes()));
+        data.extend_from_slice(json);
+        data.extend(glb());
+        let length = data.len();
+        set_u32(&mut data, 8, length);
+        let mut issues = vec![];
+        let tile = parse_tile(&data, &mut issues).unwrap();
+        assert_eq!(tile.feature_table["BATCH_LENGTH"], 0);
+        assert!(issues.iter().any(|x| x.contains("要素表 JSON 结束于第46字节")), "{:?}", issues);
+        assert!(issues.iter().any(|x| x.contains("不是8的倍数")), "{:?}", issues);
+
+        // byteLength 与实际长度不符、版本不对
+        let mut data = b3dm(&glb());
+        set_u32(&mut data, 4, 2);
+        let length = data.len() + 8;
+        set_u32(&mut data, 8, length);
+        let mut issues = vec![];
+        parse_tile(&data, &mut issues).unwrap();
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert!(issues[0].contains("版本为2") && issues[1].contains("byteLength"));
+    }
+
+    #[test]
+    fn truncated_tiles_are_errors() {
+        let data = b3dm(&glb());
+        let mut issues = vec![];
+        assert!(parse_tile(&data[..3], &mut issues).is_err());
+        assert!(parse_tile(&data[..20], &mut issues).err().unwrap().contains("28字节"));
+        // 批量表 JSON 的长度超出文件末尾
+        let mut bad = data.clone();
+        set_u32(&mut bad, 20, data.len());
+        assert!(parse_tile(&bad, &mut issues).err().unwrap().contains("批量表 JSON"));
+        assert!(parse_tile(b"glTF\x02\0\0\0", &mut issues).err().unwrap().contains("不支持"));
+        // 截掉 glb 的末尾只是长度不符
+        let mut issues = vec![];
+        let tile = parse_tile(&data[..data.len() - 8], &mut issues).unwrap();
+        assert!(issues[0].contains("byteLength"), "{:?}", issues);
+        assert_eq!(tile.glb.len(), glb().len() - 4);
+    }
+
+    #[test]
+    fn cmpt_tiles_are_split_and_checked() {
+        let inner = b3dm(&glb());
+        let mut data = vec![];
+        MakeCmpt {
+            tiles: vec![Cow::Borrowed(&inner[..]), Cow::Borrowed(&inner[..])],
+        }
+        .to_writer(&mut data)
+        .unwrap();
+        let mut issues = vec![];
+        let tiles = parse_cmpt(&data, &mut issues).unwrap();
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(tiles, vec![&inner[..], &inner[..]]);
+
+        // 少算一个内部瓦片时长度对不上
+        let mut fewer = data.clone();
+        set_u32(&mut fewer, 12, 1);
+        let mut issues = vec![];
+        assert_eq!(parse_cmpt(&fewer, &mut issues).unwrap().len(), 1);
+        assert!(issues[0].contains("与 byteLength 不一致"), "{:?}", issues);
+
+        // 内部瓦片的长度不是8的倍数
+        let mut odd = b"cmpt".to_vec();
+        [1, 36, 1].iter().for_each(|x| odd.extend((*x as u32).to_le_bytes()));
+        odd.extend_from_slice(b"b3dm");
+        [1, 20].iter().for_each(|x| odd.extend((*x as u32).to_le_bytes()));
+        odd.extend([0; 8]);
+        let mut issues = vec![];
+        assert_eq!(parse_cmpt(&odd, &mut issues).unwrap(), vec![&odd[16..]]);
+        assert_eq!(issues, vec!["第0个内部瓦片的长度20不是8的倍数".to_string()]);
+
+        let mut issues = vec![];
+        assert!(parse_cmpt(&data[..12], &mut issues).is_err());
+        assert!(parse_cmpt(&data[..data.len() - 8], &mut issues).unwrap_err().contains("第1个"));
+        let mut short = data.clone();
+        set_u32(&mut short, 16 + 8, 8);
+        assert!(parse_cmpt(&short, &mut issues).unwrap_err().contains("长度8不正确"));
+    }
+}
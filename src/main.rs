@@ -1,26 +1,135 @@
+mod ao;
 mod b3dm;
+mod city;
+mod cmpt;
+mod config;
+mod draco;
+mod export;
+mod facade;
+mod field;
 mod glb;
+mod i3dm;
+mod index;
+mod inspect;
 mod mesh;
+mod meshopt;
+mod parallel;
+mod pipeline;
+mod pnts;
+mod roof;
 mod shptiff;
+mod skeleton;
+mod style;
+mod terrain;
 mod tileset;
+mod upgrade;
+mod validate;
 
-use shapefile::{read_as,PolygonZ,dbase};
-use geo::Centroid;
+use shapefile::dbase;
 use geotiff_rs::GeoTiff;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::Path;
 use std::process::exit;
 use std::time::Instant;
+use tileset::Origin;
 
 fn main() {
-    get_3dtiles_file()
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("inspect") => inspect_file(args.get(2)),
+        Some("validate") => validate_tileset(args.get(2)),
+        Some("upgrade") => upgrade_tiles(),
+        _ => get_3dtiles_file(),
+    }
+}
+
+/// `inspect` 子命令：打印 b3dm、i3dm、pnts、cmpt 或 glb 的文件头、要素表、批量表和 glTF 统计，发现问题时退出码不为0
+fn inspect_file(path: Option<&String>) {
+    let path = path.unwrap_or_else(|| {
+        println!("用法: shp_to_3dtiles inspect <瓦片文件>");
+        exit(-1);
+    });
+    match inspect::inspect(path) {
+        Ok(0) => (),
+        Ok(_) => exit(1),
+        Err(e) => {
+            println!("{}", e);
+            exit(-1);
+        }
+    }
+}
+
+/// `validate` 子命令：检查 tileset.json 和它引用的全部内容，把 JSON 格式的报告打印出来，有错误时退出码为1
+fn validate_tileset(path: Option<&String>) {
+    let path = path.unwrap_or_else(|| {
+        println!("用法: shp_to_3dtiles validate <tileset.json>");
+        exit(-1);
+    });
+    let report = validate::validate(path);
+    println!("{}", serde_json::to_string_pretty(&report).expect("Serialization error"));
+    if !report.valid {
+        exit(1);
+    }
+}
+
+/// `upgrade` 子命令：输入 tileset.json 时把整个 tileset 升级到 3D Tiles 1.1 写到输出目录，
+/// 输入 b3dm 时只把它转成 glb，`--up-axis` 为 b3dm 的 gltfUpAxis，本工具生成的面、线瓦片为Z
+fn upgrade_tiles() {
+    let (args, options) = parse_args(env::args().collect());
+    let input = match args.get(2) {
+        Some(x) => x,
+        None => {
+            println!("用法: shp_to_3dtiles upgrade <tileset.json> <输出目录>");
+            println!("      shp_to_3dtiles upgrade <b3dm文件> [glb文件] [--up-axis Z]");
+            exit(-1);
+        }
+    };
+    if input.ends_with(".b3dm") {
+        let z_up = match option_or(&options, "up-axis", "Z") {
+            "Z" | "z" => true,
+            "Y" | "y" => false,
+            _ => {
+                println!("参数--up-axis取值为Y或Z");
+                exit(-1);
+            }
+        };
+        let output = args.get(3).cloned().unwrap_or(input.trim_end_matches(".b3dm").to_string() + ".glb");
+        let glb = fs::read(input)
+            .map_err(|e| format!("文件{}读取错误: {}", input, e))
+            .and_then(|x| upgrade::b3dm_to_glb(&x, z_up))
+            .unwrap_or_else(|e| {
+                println!("{}", e);
+                exit(-1);
+            });
+        fs::write(&output, glb).unwrap_or_else(|e| {
+            println!("文件{}写入错误: {}", output, e);
+            exit(-1);
+        });
+        println!("已写出{}", output);
+        return;
+    }
+    let output = args.get(3).unwrap_or_else(|| {
+        println!("请指定升级后的输出目录");
+        exit(-1);
+    });
+    match upgrade::upgrade(input, output) {
+        Ok(summary) => println!(
+            "升级完成: tileset {}个，b3dm 转 glb {}个，其他内容 {}个",
+            summary.tilesets, summary.glbs, summary.copied
+        ),
+        Err(e) => {
+            println!("{}", e);
+            exit(-1);
+        }
+    }
 }
 
 fn get_3dtiles_file() {
     let now = Instant::now();
-    let args: Vec<String> = env::args().into_iter().collect();
+    let (args, options) = parse_args(env::args().collect());
     let filename = match args.get(1) {
         Some(arg) => arg,
         None => {
@@ -36,6 +145,81 @@ fn get_3dtiles_file() {
         }
     };
 
+    let roof_shape = option_or(&options, "roof-shape", "roof_shape");
+    let roof_height = option_or(&options, "roof-height", "roof_height");
+    let roof_direction = option_or(&options, "roof-direction", "roof_direction");
+    let wall_thickness = match option_or(&options, "wall-thickness", "0.3").parse::<f64>() {
+        Ok(x) if x > 0. => x,
+        _ => {
+            println!("参数--wall-thickness必须是大于0的数值");
+            exit(-1);
+        }
+    };
+    let compression = match (options.get("draco"), options.get("meshopt")) {
+        (Some(_), Some(_)) => {
+            println!("参数--draco和--meshopt不能同时使用");
+            exit(-1);
+        }
+        (Some(spec), None) => glb::Compression::Draco(draco::DracoOptions::parse(spec).unwrap_or_else(|| {
+            println!("参数--draco的格式为 位置量化位数[,法线量化位数[,纹理坐标量化位数]]，位置1~16位，法线2~15位，纹理坐标1~16位");
+            exit(-1);
+        })),
+        (None, Some(spec)) => glb::Compression::Meshopt(meshopt::MeshoptOptions::parse(spec).unwrap_or_else(|| {
+            println!("参数--meshopt的格式为 位置量化位数[,法线量化位数]，位置2~16位，法线2~8位");
+            exit(-1);
+        })),
+        (None, None) => glb::Compression::None,
+    };
+
+    let ao = options.get("ao").map(|spec| {
+        ao::AoOptions::parse(spec).unwrap_or_else(|| {
+            println!("参数--ao的格式为 光线数[,距离]，光线数1~1024，距离为大于0的米数");
+            exit(-1);
+        })
+    });
+
+    let volume = match options.get("bounding-volume") {
+        Some(name) => tileset::VolumeKind::parse(name).unwrap_or_else(|| {
+            println!("参数--bounding-volume取值为region、box或sphere");
+            exit(-1);
+        }),
+        None => tileset::VolumeKind::default(),
+    };
+
+    // 导出成 OBJ、PLY、STL、CityJSON 或 CityGML 时不生成 3D Tiles，文件写到与格式同名的目录
+    let export = options.get("export").map(|name| {
+        export::Format::parse(name).unwrap_or_else(|| {
+            println!("参数--export取值为obj、ply、stl、cityjson或citygml");
+            exit(-1);
+        })
+    });
+
+    let threads = match options.get("threads").map(|x| x.parse::<usize>()) {
+        Some(Ok(x)) if x > 0 => x,
+        Some(_) => {
+            println!("参数--threads必须是大于0的整数");
+            exit(-1);
+        }
+        None => parallel::default_threads(),
+    };
+
+    // 内存上限（MB），按估计的占用把要素分成多个瓦片，逐个生成
+    let max_memory = options.get("max-memory").map(|x| match x.parse::<usize>() {
+        Ok(x) if x > 0 => x << 20,
+        _ => {
+            println!("参数--max-memory必须是大于0的整数，单位MB");
+            exit(-1);
+        }
+    });
+
+    let config = match options.get("config") {
+        Some(path) => config::Config::load(path).unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(-1);
+        }),
+        None => config::Config::default(),
+    };
+
     let mut shp_tiff = None;
 
     match args.get(3) {
@@ -58,6 +242,10 @@ fn get_3dtiles_file() {
             let xr = trans_form_line.next().unwrap();
             let yr = trans_form_line.next().unwrap();
             let tiff_data = GeoTiff::from_file(path);
+            let (width, height) = fs::read(path)
+                .ok()
+                .and_then(|data| shptiff::tiff_size(&data))
+                .unwrap_or((0, 0));
             match tiff_data {
                 Ok(x) => {
                     shp_tiff = Some(shptiff::ShpTiff {
@@ -66,6 +254,8 @@ fn get_3dtiles_file() {
                         yr: yr.parse::<f32>().unwrap(),
                         px: px.parse::<f32>().unwrap(),
                         py: py.parse::<f32>().unwrap(),
+                        width,
+                        height,
                     })
                 }
                 Err(e) => println!("文件读取错误: {:?}", e),
@@ -74,172 +264,357 @@ fn get_3dtiles_file() {
         None => (),
     };
 
-    let polygons = read_as::<_, PolygonZ, dbase::Record>(filename)
-        .expect("无法正确的打开shp文件,请确保shp类型全为多面再试");
-
-    let mut max_x = 0.;
-    let mut min_x = 0.;
-    let mut max_y = 0.;
-    let mut min_y = 0.;
-    // let mut min_h = 0.;
-    let mut max_h = 0.;
-    let mut cx = 0.;
-    let mut cy = 0.;
-    let mut bottom_h = 0;
-    let mut first = true;
-    let mut temp_h = 0;
-
-    for (polygon, polygon_record) in polygons.clone() {
-        let geo_polygon: geo::MultiPolygon<f64> = polygon.into();
-        geo_polygon.iter().for_each(|poly| {
-            let center_id = poly.centroid().unwrap();
-            match &shp_tiff {
-                Some(tiff_entity) => {
-                    temp_h = tiff_entity
-                        .get_height_by_geo_info(center_id.x() as f32, center_id.y() as f32);
-                    if first {
-                        bottom_h = temp_h;
-                        first = false
-                    } else {
-                        bottom_h = i32::min(bottom_h, temp_h)
-                    }
-                }
-                None => {}
+    // 用同一份地形生成 quantized-mesh 地形瓦片，与建筑的高程一致
+    if let Some(level) = options.get("terrain") {
+        let level = match (level.parse::<u32>(), &shp_tiff) {
+            (Ok(x), Some(_)) if x <= 20 => x,
+            (_, None) => {
+                println!("参数--terrain需要同时给出tif地形文件");
+                exit(-1);
+            }
+            _ => {
+                println!("参数--terrain为最大层级，取值0~20");
+                exit(-1);
             }
-            let line = poly.exterior();
-            line.points().for_each(|p| {
-                max_x = f32::max(max_x, p.x() as f32);
-                if min_x == 0. {
-                    min_x = p.x() as f32;
-                } else {
-                    min_x = f32::min(min_x, p.x() as f32);
-                };
-
-                max_y = f32::max(max_y, p.y() as f32);
-                if min_y == 0. {
-                    min_y = p.y() as f32;
-                } else {
-                    min_y = f32::min(min_y, p.y() as f32);
-                }
-            })
-        });
-        let polygon_height = match polygon_record.get(height) {
-            Some(dbase::FieldValue::Float(Some(x))) => x,
-            Some(_) => panic!("高度字段{}必须是浮点类型", height),
-            None => panic!("高度字段{}不存在，请重试", height),
-        };
-        max_h = f32::max(max_h, *polygon_height + temp_h as f32);
-        cx = (max_x + min_x) / 2.;
-        cy = (max_y + min_y) / 2.;
-        let bw = max_x - min_x;
-        let bh = max_y - min_y;
-        let test = tileset::Tiles {
-            asset: tileset::Asset {
-                gltf_up_axis: "Z".to_string(),
-                version: "1.0".to_string(),
-            },
-            geometric_error: 200,
-            root: tileset::Root {
-                bounding_volume: tileset::get_root_bounding_volume(
-                    min_x,
-                    max_x,
-                    min_y,
-                    max_y,
-                    bottom_h as f32,
-                    max_h,
-                ),
-                geometric_error: 200,
-                refine: "REPLACE".to_string(),
-                children: vec![tileset::Child {
-                    content: tileset::Content {
-                        uri: "0.b3dm".to_string(),
-                    },
-                    geometric_error: 100.,
-                    refine: "REPLACE".to_string(),
-                    // bounding_volume: tileset::get_root_bounding_volume(
-                    //     min_x, max_x, min_y, max_y, max_h,
-                    // ),
-                    bounding_volume: tileset::get_child_bounding_volume(
-                        cx,
-                        cy,
-                        bw,
-                        bh,
-                        bottom_h as f32,
-                        max_h,
-                    ),
-                    transform: tileset::get_transform(cx, cy, bottom_h as f32),
-                }],
-            },
-        };
-        let _ = fs::create_dir("b3dm");
-        let data = serde_json::to_string(&test).expect("Serialization error");
-        // println!("{:?}", data);
-        std::fs::write("b3dm/tileset.json", &mut data.as_bytes()).unwrap();
-    }
-
-    let mut id = 0;
-    let mut meshes = vec![];
-    let mut ids = vec![];
-    let mut hight_vec = vec![];
-    let mut names = vec![];
-    for (polygon, polygon_record) in polygons {
-        let geo_polygon: geo::MultiPolygon<f64> = polygon.into();
-        let the_bottom = 0.;
-        let polygon_h = match polygon_record.get(height) {
-            Some(dbase::FieldValue::Float(Some(x))) => x,
-            Some(_) => panic!("高度字段{}必须是浮点类型", height),
-            None => panic!("高度字段{}不存在，请重试", height),
         };
-        // let polygon_h_with_bottom = polygon_h + the_bottom;
-        let mesh = mesh::Mesh::init(
-            cx as f64,
-            cy as f64,
-            polygon_h,
-            the_bottom,
-            geo_polygon,
-            id,
-        );
-        meshes.push(mesh);
-        ids.push(id as u32);
-        // hight_vec.push(polygon_h_with_bottom);
-        hight_vec.push(*polygon_h);
-        names.push("mesh_".to_string() + &id.to_string());
-        id = id + 1;
-    }
-
-    let test_batch_table = b3dm::BatchTable {
-        batch_id: ids,
-        height: hight_vec,
-        name: names,
-    };
-    let test_feature_table = b3dm::FeatureTable {
-        batch_length: id as u32,
+        match terrain::write_terrain(shp_tiff.as_ref().unwrap(), level, "terrain", threads) {
+            Ok(count) => println!("地形瓦片: {}块，写到terrain目录", count),
+            Err(e) => {
+                println!("{}", e);
+                exit(-1);
+            }
+        }
+    }
+
+    // 点图层给了模型时生成 i3dm，用同一个模型实例化每个点，否则生成点云 pnts
+    if index::is_point_layer(filename) {
+        if options.contains_key("model") {
+            let features = pipeline::read_features(filename);
+            let model = load_model(&options);
+            let (data, corners, origin) = i3dm::get_i3dm(
+                &features,
+                &shp_tiff,
+                &model,
+                option_or(&options, "scale-field", "scale"),
+                option_or(&options, "rotation-field", "rotation"),
+                None,
+                true,
+            )
+            .unwrap_or_else(|| {
+                println!("shp文件中没有点要素");
+                exit(-1);
+            });
+            let frame = tileset::enu_frame(origin.0, origin.1, origin.2 as f64);
+            write_output("0.i3dm", &data);
+            let bounds = tileset::Bounds::new(&corners, &frame, 0.).unwrap();
+            write_tileset(&[("0.i3dm".to_string(), bounds)], "Y", volume, Some(origin));
+        } else {
+            let children = pnts::get_pnts(
+                filename,
+                &shp_tiff,
+                options.get("color").map(String::as_str),
+                max_memory,
+                write_output,
+            );
+            if children.is_empty() {
+                println!("shp文件中没有点要素");
+                exit(-1);
+            }
+            write_tileset(&children, "Y", volume, None);
+        }
+        println!("执行时间: {}", now.elapsed().as_millis());
+        return;
+    }
+
+    // 第一遍只建索引，不保留几何；颜色和样式要看全部要素的取值，相应的字段单独留下
+    let mut fields = vec![];
+    if let Some(color) = &config.color {
+        fields.push(color.field.as_str());
+    }
+    if let Some(style) = &config.style {
+        fields.push(style.field.as_str());
+    }
+    let index::FeatureIndex {
+        entries,
+        records,
+        origin,
+    } = index::scan(filename, height, &shp_tiff, &fields);
+    if entries.is_empty() {
+        println!("shp文件中没有可以生成模型的要素");
+        exit(-1);
+    }
+
+    // 按配置给每个要素取颜色
+    let colors = match &config.color {
+        Some(color) => color.colors(&records.iter().collect::<Vec<_>>()),
+        None => vec![None; entries.len()],
     };
+    let color_mode = config.color.as_ref().map(|x| x.mode).unwrap_or_default();
+
+    // 样式按全部要素的批量表生成，各瓦片共用一份
+    if let Some(style) = &config.style {
+        let ids: Vec<usize> = (0..entries.len()).collect();
+        let heights = entries.iter().map(|x| x.height).collect();
+        let batch_table = pipeline::batch_table(&ids, heights, &records.iter().collect::<Vec<_>>());
+        let batch_table = serde_json::to_value(&batch_table).expect("Serialization error");
+        let data = style::get_style(style, &batch_table).unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(-1);
+        });
+        write_output("style.json", data.to_string().as_bytes());
+    }
+    drop(records);
+
+    // 没有给内存上限时所有要素放在一个瓦片里
+    let tiles = index::partition(&entries, max_memory, index::BYTES_PER_POINT);
+    if tiles.len() > 1 {
+        println!("按内存上限分成{}个瓦片", tiles.len());
+    }
+
+    // 同时给了点图层时，每个瓦片和落在它范围内的实例打包成一个 cmpt 瓦片
+    if export.is_some() && options.contains_key("points") {
+        println!("参数--export不能与--points同时使用");
+        exit(-1);
+    }
+    let points = options.get("points").map(|points_file| {
+        let points = pipeline::read_features(points_file);
+        if !points.iter().all(|(geometry, _)| is_point(geometry)) {
+            println!("--points指定的shp必须是点图层");
+            exit(-1);
+        }
+        points
+    });
 
-    let test1 = serde_json::to_string(&test_batch_table).expect("Serialization error");
-    let test2 = serde_json::to_string(&test_feature_table).expect("Serialization error");
-    let mut feature_table_json_byte_length = test2.len();
-    align_to_multiple_of_four(&mut feature_table_json_byte_length);
-    let mut batch_table_json_byte_length = test1.len();
-    align_to_multiple_of_four(&mut batch_table_json_byte_length);
-
-    let vec2 = test1.into_bytes();
-    let vec1 = test2.into_bytes();
-
-    let glb = glb::get_glb(meshes);
-    let b33dm = b3dm::MakeB3dm {
-        feature_table_json_byte_length,
-        batch_table_json_byte_length,
-        glb: Some(Cow::Owned(glb)),
+    let mesh_options = pipeline::MeshOptions {
+        origin,
+        roof_shape,
+        roof_height,
+        roof_direction,
+        wall_thickness,
+        materials: config.materials.as_ref(),
+        ao: ao.as_ref(),
+    };
+    let frame = tileset::enu_frame(origin.0, origin.1, origin.2 as f64);
+    // 只有 CityJSON、CityGML 写输入的坐标系，其他格式为局部坐标，不读 .prj
+    let crs = match export {
+        Some(format) if !format.local() => city::read_crs(filename),
+        _ => city::Crs {
+            epsg: Some(4326),
+            geographic: true,
+        },
+    };
+    let export_options = export.map(|format| export::ExportOptions {
+        format,
+        origin,
+        crs: &crs,
+        materials: config.materials.as_ref(),
+    });
+    // 每个点只归一个瓦片；模型只读取一次，gltf 模型引用的文件也只复制一次
+    let points = points.map(|points| pipeline::assign_points(points, &tiles));
+    let model = points.as_ref().map(|_| load_model(&options));
+    // 各瓦片互不相关，在固定数量的线程里同时读取、生成、编码和写出，剩下的线程分给瓦片内部；
+    // 给了内存上限时，同时处理的瓦片按估计的占用之和不超过上限
+    let cost = |tile: &index::Tile| -> usize {
+        tile.entries.iter().map(|i| entries[*i].points * index::BYTES_PER_POINT).sum()
+    };
+    let workers = threads.min(tiles.len());
+    // 按平均占用估计能同时处理几个瓦片，线程平分给它们
+    let concurrent = match max_memory {
+        Some(budget) => (budget * tiles.len() / tiles.iter().map(cost).sum::<usize>().max(1)).clamp(1, workers),
+        None => workers,
     };
+    let tile_threads = (threads / concurrent).max(1);
+    let results = parallel::map_budget(&tiles, workers, max_memory, cost, |t, tile| {
+        let mut stats = pipeline::MeshStats::default();
+        let features = index::read(filename, &entries, tile);
+        let heights: Vec<f32> = tile.entries.iter().map(|i| entries[*i].height).collect();
+        let meshes = pipeline::build_meshes(
+            &features,
+            &tile.entries,
+            &heights,
+            &colors,
+            &mesh_options,
+            tile_threads,
+            &mut stats,
+        );
+        let empty = meshes.iter().all(|mesh| mesh.vertex.is_empty());
+        if let Some(options) = &export_options {
+            if empty {
+                return (stats, None, None);
+            }
+            let dir = Path::new(options.format.name());
+            fs::create_dir_all(dir).expect("I/O error");
+            let path = dir.join(format!("{}.{}", t, options.format.extension()));
+            let records: Vec<&dbase::Record> = features.iter().map(|(_, record)| record).collect();
+            export::write(&meshes, &records, options, &path).expect("I/O error");
+            return (stats, None, Some(path));
+        }
+        let mut contents = vec![];
+        let mut vertices = vec![];
+        let mut margin = 0.;
+        if !empty {
+            let records: Vec<&dbase::Record> = features.iter().map(|(_, record)| record).collect();
+            let batch_table = pipeline::batch_table(&tile.entries, heights, &records);
+            let content = pipeline::encode_b3dm(
+                meshes,
+                &batch_table,
+                &compression,
+                color_mode,
+                config.materials.as_ref(),
+                tile_threads,
+            );
+            contents.push(Cow::Owned(content.data));
+            vertices = content.vertices;
+            margin = content.margin;
+        }
+        // 瓦片里没有建筑时只打包实例，实例照样保留
+        if let (Some(points), Some(model)) = (&points, &model) {
+            let i3dm = i3dm::get_i3dm(
+                &points[t],
+                &shp_tiff,
+                model,
+                option_or(&options, "scale-field", "scale"),
+                option_or(&options, "rotation-field", "rotation"),
+                Some(origin),
+                false,
+            );
+            if let Some((data, corners, _)) = i3dm {
+                vertices.extend(corners);
+                contents.push(Cow::Owned(data));
+            }
+        }
+        if contents.is_empty() {
+            return (stats, None, None);
+        }
 
-    let writer = std::fs::File::create("b3dm/0.b3dm").expect("I/O error");
-    b33dm
-        .to_writer(writer, vec1, vec2)
-        .expect("glTF binary output error");
+        let uri = match &points {
+            Some(_) => {
+                let uri = format!("{}.cmpt", t);
+                let cmpt = cmpt::MakeCmpt { tiles: contents };
+                let mut data = vec![];
+                cmpt.to_writer(&mut data).expect("glTF binary output error");
+                write_output(&uri, &data);
+                uri
+            }
+            None => {
+                let uri = format!("{}.b3dm", t);
+                write_output(&uri, &contents[0]);
+                uri
+            }
+        };
+        let bounds = tileset::Bounds::new(&vertices, &frame, margin).unwrap();
+        (stats, Some((uri, bounds)), None)
+    });
+    // 统计和子瓦片按瓦片的顺序合并，与线程数无关
+    let mut stats = pipeline::MeshStats::default();
+    let mut children = vec![];
+    let mut exported = vec![];
+    results.into_iter().for_each(|(tile_stats, child, path)| {
+        stats.before += tile_stats.before;
+        stats.after += tile_stats.after;
+        stats.removed += tile_stats.removed;
+        stats.occluded += tile_stats.occluded;
+        children.extend(child);
+        exported.extend(path);
+    });
+    println!("顶点焊接: {} -> {}，去掉退化三角形{}个", stats.before, stats.after, stats.removed);
+    if let Some(ao) = &ao {
+        println!("环境光遮蔽: {}个顶点，每个顶点{}条光线", stats.occluded, ao.samples);
+    }
+    if let Some(format) = export {
+        if exported.is_empty() {
+            println!("shp文件中没有可以生成模型的要素");
+            exit(-1);
+        }
+        let dir = Path::new(format.name());
+        if format.local() {
+            export::write_origin(origin, dir).expect("I/O error");
+            println!("导出{}个文件到{}目录，局部坐标的原点写在origin.json", exported.len(), dir.display());
+        } else {
+            println!("导出{}个文件到{}目录", exported.len(), dir.display());
+        }
         println!("执行时间: {}", now.elapsed().as_millis());
+        return;
+    }
+    if children.is_empty() {
+        println!("shp文件中没有可以生成模型的要素");
+        exit(-1);
+    }
+    write_tileset(&children, "Z", volume, Some(origin));
+    println!("执行时间: {}", now.elapsed().as_millis());
+}
+
+// 把瓦片内容写到 b3dm 目录，uri 可以带子目录，目录不存在时先建好；写不了时提示并退出，不再继续生成
+fn write_output(uri: &str, data: &[u8]) {
+    let path = Path::new("b3dm").join(uri);
+    let dir = path.parent().unwrap_or(Path::new("b3dm"));
+    if let Err(e) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, data)) {
+        println!("文件{}写入错误: {}", path.display(), e);
+        exit(-1);
+    }
+}
+
+fn write_tileset(
+    children: &[(String, tileset::Bounds)],
+    gltf_up_axis: &str,
+    volume: tileset::VolumeKind,
+    origin: Option<Origin>,
+) {
+    if let Err(e) = tileset::write_tileset(children, gltf_up_axis, volume, origin) {
+        println!("文件b3dm/tileset.json写入错误: {}", e);
+        exit(-1);
+    }
+}
+
+/// 点图层需要用 --model 指定实例化的模型
+fn require_model(options: &HashMap<String, String>) -> &str {
+    match options.get("model") {
+        Some(x) => x,
+        None => {
+            println!("点图层需要用--model指定实例化的glTF模型");
+            exit(-1);
+        }
+    }
+}
+
+// 读取 --model 指定的模型，gltf 模型连同它引用的文件复制到输出目录
+fn load_model(options: &HashMap<String, String>) -> i3dm::Model {
+    let model = i3dm::Model::load(require_model(options)).unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(-1);
+    });
+    let resources = model.resources().unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(-1);
+    });
+    resources.iter().for_each(|(uri, data)| write_output(uri, data));
+    model
+}
+
+fn is_point(geometry: &geo::Geometry<f64>) -> bool {
+    matches!(geometry, geo::Geometry::Point(_) | geo::Geometry::MultiPoint(_))
+}
+
+/// 拆分命令行参数，`--key value` 形式的为可选参数，其余按顺序作为位置参数
+fn parse_args(args: Vec<String>) -> (Vec<String>, HashMap<String, String>) {
+    let mut positional = vec![];
+    let mut options = HashMap::new();
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some(key) => {
+                let value = iter.next().unwrap_or_else(|| {
+                    println!("参数--{}缺少取值", key);
+                    exit(-1);
+                });
+                options.insert(key.to_string(), value);
+            }
+            None => positional.push(arg),
+        }
+    }
+    (positional, options)
 }
 
-fn align_to_multiple_of_four(n: &mut usize) {
-    *n = (*n + 3) & !3;
+fn option_or<'a>(options: &'a HashMap<String, String>, key: &str, default: &'a str) -> &'a str {
+    options.get(key).map(String::as_str).unwrap_or(default)
 }
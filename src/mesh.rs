@@ -1,23 +1,78 @@
 use gfx_maths::{Vec2,Vec3};
 use  earcutr::{flatten,earcut};
+use crate::roof::Roof;
 
+/// 三角形所在面的类型，可以分别指定材质
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Face {
+    Roof,
+    Wall,
+    Bottom,
+}
+
+#[derive(Clone)]
 pub struct Mesh {
     pub vertex: Vec<[f64; 3]>,
     pub mesh_name: String,
+    /// 要素序号，与批量表的 batchId 相同
+    pub id: i32,
     pub index: Vec<[i32; 3]>,
+    /// 与 `index` 一一对应，每个三角形所在面的类型
+    pub face: Vec<Face>,
     pub normal: Vec<[f32; 3]>,
+    /// 纹理坐标，单位为米：墙面 u 沿墙水平、v 为离地高度，屋顶和底面取平面坐标
+    pub uv: Vec<[f32; 2]>,
     pub height: f64,
+    /// 墙面从底部到屋檐的高度，坡屋顶的高度不算在内
+    pub wall_height: f64,
+    /// 生成窗户立面时的层高，纹理在竖直方向一层重复一次
+    pub floor_height: Option<f32>,
+    /// 按属性着色时要素的颜色（sRGB）
+    pub color: Option<[u8; 3]>,
+    /// 按属性选择纹理时要素的类别
+    pub category: Option<String>,
+    /// 烘焙的环境光遮蔽，与顶点一一对应，1为完全不遮挡
+    pub occlusion: Option<Vec<f32>>,
 }
 
-fn lon_to_meters(diff: f64, lat: f64) -> f64 {
+pub fn lon_to_meters(diff: f64, lat: f64) -> f64 {
     return diff.to_radians() / 0.000000156785 * lat.to_radians().cos();
 }
 
-fn lat_to_meters(diff: f64) -> f64 {
+pub fn lat_to_meters(diff: f64) -> f64 {
     return diff.to_radians() / 0.000000157891;
 }
 
+/// `lon_to_meters` 的逆运算，把局部坐标的米数换回经度差
+pub fn meters_to_lon(x: f64, lat: f64) -> f64 {
+    (x * 0.000000156785 / lat.to_radians().cos()).to_degrees()
+}
+
+/// `lat_to_meters` 的逆运算，把局部坐标的米数换回纬度差
+pub fn meters_to_lat(y: f64) -> f64 {
+    (y * 0.000000157891).to_degrees()
+}
+
 impl Mesh {
+    /// 没有几何的网格，不能生成模型的要素用它占位，网格与批量表的序号仍然一一对应
+    pub fn empty(id: i32) -> Mesh {
+        Mesh {
+            vertex: vec![],
+            mesh_name: "mesh_".to_string() + &id.to_string(),
+            id,
+            index: vec![],
+            face: vec![],
+            normal: vec![],
+            uv: vec![],
+            height: 0.,
+            wall_height: 0.,
+            floor_height: None,
+            color: None,
+            category: None,
+            occlusion: None,
+        }
+    }
+
     pub fn init(
         center_x: f64,
         center_y: f64,
@@ -25,18 +80,35 @@ impl Mesh {
         bottom: f64,
         mult_polygon: geo::MultiPolygon<f64>,
         id: i32,
+        roof: &Roof,
     ) -> Mesh {
         let mut vertex: Vec<[f64; 3]> = Vec::new();
         let mut index: Vec<[i32; 3]> = Vec::new();
-        let id = id.to_string();
-        let mesh_name = "mesh_".to_string() + &id;
+        let mut face = vec![];
+        let name = id.to_string();
+        let mesh_name = "mesh_".to_string() + &name;
         let mut normal = vec![];
+        let mut uv = vec![];
+        // 坡屋顶的高度包含在总高度内，墙体只建到屋檐；屋顶比建筑还高时整栋只有屋顶
+        let roof = &roof.clamp(*height as f64 - bottom);
+        let wall_top = if roof.is_flat() {
+            *height as f64
+        } else {
+            f64::max(bottom, *height as f64 - roof.height)
+        };
         mult_polygon.into_iter().for_each(|polygon| {
+            if !polygon.interiors().is_empty() {
+                println!("要素{}有{}个内环，暂不支持，墙体和屋顶只按外环生成", id, polygon.interiors().len());
+            }
+            let base = vertex.len() as i32;
             let line = polygon.exterior();
             let len: Vec<_> = line.points().collect();
             // 读取的线的点数，会默认把第一个点重复一次，所以会多出来一次
             let len = len.len();
             let mut idx1 = 0;
+            // 沿外环累计的长度，从外面看墙面时 u 从左向右增加
+            let mut distance = 0.;
+            let mut last: Option<(f64, f64)> = None;
             while idx1 < len {
                 let point = &line[idx1];
                 let (x, y) = point.x_y();
@@ -45,26 +117,36 @@ impl Mesh {
                 // println!("{} {}",point_x,point_y);
                 let px = lon_to_meters(point_x, center_y);
                 let py = lat_to_meters(point_y);
+                if let Some((lx, ly)) = last {
+                    distance += ((px - lx) * (px - lx) + (py - ly) * (py - ly)).sqrt();
+                }
+                last = Some((px, py));
+                let wall_uv = [[-distance as f32, 0.], [-distance as f32, (wall_top - bottom) as f32]];
                 vertex.push([px, py, bottom]);
-                vertex.push([px, py, *height as f64]);
+                vertex.push([px, py, wall_top]);
+                uv.extend(wall_uv);
                 if idx1 != 0 && idx1 != len - 1 {
                     vertex.push([px, py, bottom]);
-                    vertex.push([px, py, *height as f64]);
+                    vertex.push([px, py, wall_top]);
+                    uv.extend(wall_uv);
                 }
                 idx1 = idx1 + 1
             }
-            let vertex_num: i32 = (vertex.len() / 2) as i32;
+            let vertex_num: i32 = (vertex.len() as i32 - base) / 2;
             // println!("{}",vertex_num);
             let mut n: i32 = 0;
             while n < vertex_num {
                 if n != vertex_num - 1 {
-                    index.push([2 * n, 2 * n + 1, 2 * (n + 1) + 1]);
-                    index.push([2 * (n + 1), 2 * n, 2 * (n + 1) + 1]);
+                    index.push([base + 2 * n, base + 2 * n + 1, base + 2 * (n + 1) + 1]);
+                    index.push([base + 2 * (n + 1), base + 2 * n, base + 2 * (n + 1) + 1]);
                 }
                 n = n + 2;
             }
-            normal = Self::calc_normal(0, vertex_num, &vertex);
-            let pt_count = 2 * vertex_num;
+            face.resize(index.len(), Face::Wall);
+            normal.extend(Self::calc_normal(base, vertex_num, &vertex));
+            let pt_count = vertex.len() as i32;
+            // 平顶时上下底面的点交替存放，坡屋顶只需要底面
+            let stride = if roof.is_flat() { 2 } else { 1 };
             // 内部含有环的情况
             // 暂不完成
             //上下底面
@@ -80,9 +162,13 @@ impl Mesh {
                 ear_cut_polygon[0].push(vec![px, py]);
                 // println!("{} {}", px, py);
                 vertex.push([px, py, bottom]);
-                vertex.push([px, py, *height as f64]);
                 normal.push([0.0, 0.0, -1.0]);
-                normal.push([0.0, 0., 1.]);
+                uv.push([px as f32, py as f32]);
+                if roof.is_flat() {
+                    vertex.push([px, py, wall_top]);
+                    normal.push([0.0, 0., 1.]);
+                    uv.push([px as f32, py as f32]);
+                }
                 idx2 = idx2 + 1
             }
             ear_cut_polygon.push(vec![]);
@@ -92,29 +178,438 @@ impl Mesh {
             let mut idx = 0;
             let tri_len = triangles.len();
             while idx < tri_len {
-                let p1 = pt_count + 2 * triangles[idx] as i32;
-                let p2 = pt_count + 2 * triangles[idx + 2] as i32;
-                let p3 = pt_count + 2 * triangles[idx + 1] as i32;
+                let p1 = pt_count + stride * triangles[idx] as i32;
+                let p2 = pt_count + stride * triangles[idx + 2] as i32;
+                let p3 = pt_count + stride * triangles[idx + 1] as i32;
                 index.push([p1, p2, p3]);
                 idx = idx + 3;
             }
-            let mut idx4 = 0;
-            while idx4 < tri_len {
-                let p1 = pt_count + 2 * triangles[idx4] as i32 + 1;
-                let p2 = pt_count + 2 * triangles[idx4 + 1] as i32 + 1;
-                let p3 = pt_count + 2 * triangles[idx4 + 2] as i32 + 1;
-                index.push([p1, p2, p3]);
-                idx4 = idx4 + 3;
+            face.resize(index.len(), Face::Bottom);
+            if roof.is_flat() {
+                let mut idx4 = 0;
+                while idx4 < tri_len {
+                    let p1 = pt_count + 2 * triangles[idx4] as i32 + 1;
+                    let p2 = pt_count + 2 * triangles[idx4 + 1] as i32 + 1;
+                    let p3 = pt_count + 2 * triangles[idx4 + 2] as i32 + 1;
+                    index.push([p1, p2, p3]);
+                    idx4 = idx4 + 3;
+                }
+                face.resize(index.len(), Face::Roof);
+                return;
             }
+            // 坡屋顶每个三角面单独存点，法向取面法向
+            let ring: Vec<[f64; 2]> = ear_cut_polygon[0].iter().map(|p| [p[0], p[1]]).collect();
+            // 与墙面一样沿外环累计长度，山墙的纹理接着下面的墙面
+            let mut ring_distance = vec![0.];
+            ring.windows(2).for_each(|w| {
+                let (dx, dy) = (w[1][0] - w[0][0], w[1][1] - w[0][1]);
+                ring_distance.push(ring_distance[ring_distance.len() - 1] + (dx * dx + dy * dy).sqrt());
+            });
+            roof.build(&ring, wall_top, &name).into_iter().for_each(|t| {
+                let face_normal = Self::face_normal(&t);
+                let first = vertex.len() as i32;
+                // 竖直的山墙算作墙面
+                let kind = if face_normal[2].abs() < 1e-3 { Face::Wall } else { Face::Roof };
+                let gable_uv = match kind {
+                    Face::Wall => Self::gable_uv(&t, &ring, &ring_distance, bottom),
+                    _ => t.map(|p| [p[0] as f32, p[1] as f32]),
+                };
+                t.iter().zip(gable_uv).for_each(|(p, coord)| {
+                    vertex.push(*p);
+                    normal.push(face_normal);
+                    uv.push(coord);
+                });
+                index.push([first, first + 1, first + 2]);
+                face.push(kind);
+            });
         });
 
         Mesh {
             vertex,
             mesh_name,
+            id,
             index,
+            face,
             normal,
+            uv,
             height: *height as f64,
+            wall_height: wall_top - bottom,
+            floor_height: None,
+            color: None,
+            category: None,
+            occlusion: None,
+        }
+    }
+
+    /// 把折线沿两侧各偏移半个厚度，拉伸成竖直的墙体，首尾相接的折线按闭合的围墙处理
+    pub fn init_wall(
+        center_x: f64,
+        center_y: f64,
+        height: &f32,
+        bottom: f64,
+        lines: geo::MultiLineString<f64>,
+        id: i32,
+        thickness: f64,
+    ) -> Mesh {
+        let mut vertex: Vec<[f64; 3]> = Vec::new();
+        let mut index: Vec<[i32; 3]> = Vec::new();
+        let mut face = vec![];
+        let mut normal = vec![];
+        let mut uv = vec![];
+        let top = *height as f64;
+        let half = thickness / 2.;
+        lines.into_iter().for_each(|line| {
+            let mut points: Vec<[f64; 2]> = vec![];
+            line.points().for_each(|p| {
+                let px = lon_to_meters(p.x() - center_x, center_y);
+                let py = lat_to_meters(p.y() - center_y);
+                if points
+                    .last()
+                    .is_none_or(|q| (q[0] - px).abs() > 1e-6 || (q[1] - py).abs() > 1e-6)
+                {
+                    points.push([px, py]);
+                }
+            });
+            let closed = points.len() > 3 && {
+                let (a, b) = (points[0], points[points.len() - 1]);
+                (a[0] - b[0]).abs() <= 1e-6 && (a[1] - b[1]).abs() <= 1e-6
+            };
+            if closed {
+                points.pop();
+            }
+            let n = points.len();
+            if n < 2 {
+                return;
+            }
+            // 每段的左法向
+            let seg_count = if closed { n } else { n - 1 };
+            let seg_normal: Vec<[f64; 2]> = (0..seg_count)
+                .map(|i| {
+                    let (a, b) = (points[i], points[(i + 1) % n]);
+                    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+                    let len = (dx * dx + dy * dy).sqrt();
+                    [-dy / len, dx / len]
+                })
+                .collect();
+            // 转角处按斜接偏移，过尖的转角限制斜接长度
+            let left: Vec<[f64; 2]> = (0..n)
+                .map(|i| {
+                    let (n0, n1) = match (closed, i) {
+                        (false, 0) => (seg_normal[0], seg_normal[0]),
+                        (false, i) if i == n - 1 => (seg_normal[i - 1], seg_normal[i - 1]),
+                        (_, i) => (seg_normal[(i + seg_count - 1) % seg_count], seg_normal[i % seg_count]),
+                    };
+                    let d = 1. + n0[0] * n1[0] + n0[1] * n1[1];
+                    let miter = if d < 0.25 {
+                        n1
+                    } else {
+                        [(n0[0] + n1[0]) / d, (n0[1] + n1[1]) / d]
+                    };
+                    [miter[0] * half, miter[1] * half]
+                })
+                .collect();
+            let side = |i: usize, s: f64, z: f64| -> [f64; 3] {
+                [points[i][0] + s * left[i][0], points[i][1] + s * left[i][1], z]
+            };
+            let h = (top - bottom) as f32;
+            let mut distance = 0.;
+            for (i, seg) in seg_normal.iter().enumerate() {
+                let j = (i + 1) % n;
+                let nl = [seg[0], seg[1], 0.];
+                // 侧面的 u 沿中线累计，两侧方向相反，从外面看都是从左向右
+                let (a, b) = (points[i], points[j]);
+                let (ui, uj) = (distance, distance + ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt());
+                distance = uj;
+                let (ui, uj) = (ui as f32, uj as f32);
+                // 左右两个侧面和顶面
+                let quad = [side(j, 1., bottom), side(i, 1., bottom), side(i, 1., top), side(j, 1., top)];
+                Self::push_quad(
+                    &mut vertex,
+                    &mut index,
+                    &mut normal,
+                    &mut uv,
+                    quad,
+                    nl,
+                    [[-uj, 0.], [-ui, 0.], [-ui, h], [-uj, h]],
+                );
+                let quad = [side(i, -1., bottom), side(j, -1., bottom), side(j, -1., top), side(i, -1., top)];
+                Self::push_quad(
+                    &mut vertex,
+                    &mut index,
+                    &mut normal,
+                    &mut uv,
+                    quad,
+                    [-nl[0], -nl[1], 0.],
+                    [[ui, 0.], [uj, 0.], [uj, h], [ui, h]],
+                );
+                face.resize(index.len(), Face::Wall);
+                let quad = [side(i, -1., top), side(j, -1., top), side(j, 1., top), side(i, 1., top)];
+                Self::push_quad(
+                    &mut vertex,
+                    &mut index,
+                    &mut normal,
+                    &mut uv,
+                    quad,
+                    [0., 0., 1.],
+                    quad.map(|p| [p[0] as f32, p[1] as f32]),
+                );
+                face.resize(index.len(), Face::Roof);
+            }
+            if !closed {
+                // 两端的封口
+                let start = [-seg_normal[0][1], seg_normal[0][0], 0.];
+                let end = [seg_normal[n - 2][1], -seg_normal[n - 2][0], 0.];
+                let quad = [side(0, 1., bottom), side(0, -1., bottom), side(0, -1., top), side(0, 1., top)];
+                let cap_normal = start.map(|x| x as f32);
+                Self::push_quad(
+                    &mut vertex,
+                    &mut index,
+                    &mut normal,
+                    &mut uv,
+                    quad,
+                    start,
+                    quad.map(|p| Self::vertical_uv(&p, cap_normal, bottom)),
+                );
+                let quad = [side(n - 1, -1., bottom), side(n - 1, 1., bottom), side(n - 1, 1., top), side(n - 1, -1., top)];
+                let cap_normal = end.map(|x| x as f32);
+                Self::push_quad(
+                    &mut vertex,
+                    &mut index,
+                    &mut normal,
+                    &mut uv,
+                    quad,
+                    end,
+                    quad.map(|p| Self::vertical_uv(&p, cap_normal, bottom)),
+                );
+                face.resize(index.len(), Face::Wall);
+            }
+        });
+
+        Mesh {
+            vertex,
+            mesh_name: "mesh_".to_string() + &id.to_string(),
+            id,
+            index,
+            face,
+            normal,
+            uv,
+            height: top,
+            wall_height: top - bottom,
+            floor_height: None,
+            color: None,
+            category: None,
+            occlusion: None,
+        }
+    }
+
+    /// 合并位置、法线和纹理坐标都相同的顶点，去掉退化三角形，返回焊接前后的顶点数和去掉的三角形数
+    ///
+    /// 一个 Mesh 对应一个要素，batchId 和颜色相同，只需要比较顶点自身的属性，已烘焙的环境光遮蔽也要相同；
+    /// 墙体和底面、顶面法线不同，共用位置的点不会被合并
+    pub fn weld(&mut self) -> (usize, usize, usize) {
+        let before = self.vertex.len();
+        let mut remap = Vec::with_capacity(before);
+        let mut vertex = vec![];
+        let mut normal = vec![];
+        let mut uv = vec![];
+        let mut occlusion = vec![];
+        let mut seen = std::collections::HashMap::new();
+        self.vertex.iter().zip(self.normal.iter()).zip(self.uv.iter()).enumerate().for_each(|(k, ((p, n), t))| {
+            let o = self.occlusion.as_ref().map_or(1., |x| x[k]);
+            let key = (p.map(f64::to_bits), n.map(f32::to_bits), t.map(f32::to_bits), o.to_bits());
+            let i = *seen.entry(key).or_insert_with(|| {
+                vertex.push(*p);
+                normal.push(*n);
+                uv.push(*t);
+                occlusion.push(o);
+                vertex.len() as i32 - 1
+            });
+            remap.push(i);
+        });
+
+        let triangles = self.index.len();
+        let (index, face): (Vec<[i32; 3]>, Vec<Face>) = self
+            .index
+            .iter()
+            .zip(self.face.iter())
+            .map(|(t, f)| (t.map(|i| remap[i as usize]), *f))
+            .filter(|(t, _)| {
+                if t[0] == t[1] || t[1] == t[2] || t[2] == t[0] {
+                    return false;
+                }
+                // 三点共线的面积为零，同样去掉
+                let [a, b, c] = t.map(|i| vertex[i as usize]);
+                let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+                let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+                let cross = [
+                    u[1] * v[2] - u[2] * v[1],
+                    u[2] * v[0] - u[0] * v[2],
+                    u[0] * v[1] - u[1] * v[0],
+                ];
+                cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2] > 1e-18
+            })
+            .unzip();
+        let removed = triangles - index.len();
+
+        // 去掉三角形后可能有顶点不再被引用，按使用顺序重新编号
+        let mut used = vec![-1; vertex.len()];
+        let mut compact_vertex = vec![];
+        let mut compact_normal = vec![];
+        let mut compact_uv = vec![];
+        let mut compact_occlusion = vec![];
+        self.index = index
+            .into_iter()
+            .map(|t| {
+                t.map(|i| {
+                    if used[i as usize] < 0 {
+                        used[i as usize] = compact_vertex.len() as i32;
+                        compact_vertex.push(vertex[i as usize]);
+                        compact_normal.push(normal[i as usize]);
+                        compact_uv.push(uv[i as usize]);
+                        compact_occlusion.push(occlusion[i as usize]);
+                    }
+                    used[i as usize]
+                })
+            })
+            .collect();
+        self.face = face;
+        self.vertex = compact_vertex;
+        self.normal = compact_normal;
+        self.uv = compact_uv;
+        if self.occlusion.is_some() {
+            self.occlusion = Some(compact_occlusion);
         }
+        (before, self.vertex.len(), removed)
+    }
+
+    /// 按面的类型拆成屋顶、墙面、底面几个网格，每个只保留用到的顶点，没有三角形的类型不输出
+    pub fn split(&self) -> Vec<Mesh> {
+        [Face::Roof, Face::Wall, Face::Bottom]
+            .into_iter()
+            .filter_map(|kind| {
+                let mut used = vec![-1; self.vertex.len()];
+                let mut vertex = vec![];
+                let mut normal = vec![];
+                let mut uv = vec![];
+                let mut source = vec![];
+                let index: Vec<[i32; 3]> = self
+                    .index
+                    .iter()
+                    .zip(self.face.iter())
+                    .filter(|(_, f)| **f == kind)
+                    .map(|(t, _)| {
+                        t.map(|i| {
+                            if used[i as usize] < 0 {
+                                used[i as usize] = vertex.len() as i32;
+                                vertex.push(self.vertex[i as usize]);
+                                normal.push(self.normal[i as usize]);
+                                uv.push(self.uv[i as usize]);
+                                source.push(i as usize);
+                            }
+                            used[i as usize]
+                        })
+                    })
+                    .collect();
+                if index.is_empty() {
+                    return None;
+                }
+                Some(Mesh {
+                    vertex,
+                    mesh_name: self.mesh_name.clone(),
+                    id: self.id,
+                    face: vec![kind; index.len()],
+                    index,
+                    normal,
+                    uv,
+                    height: self.height,
+                    wall_height: self.wall_height,
+                    floor_height: self.floor_height,
+                    color: self.color,
+                    category: self.category.clone(),
+                    occlusion: self
+                        .occlusion
+                        .as_ref()
+                        .map(|x| source.iter().map(|i| x[*i]).collect()),
+                })
+            })
+            .collect()
+    }
+
+    // 四边形按逆时针给出，拆成两个三角形，四个点共用同一个法向
+    fn push_quad(
+        vertex: &mut Vec<[f64; 3]>,
+        index: &mut Vec<[i32; 3]>,
+        normal: &mut Vec<[f32; 3]>,
+        uv: &mut Vec<[f32; 2]>,
+        quad: [[f64; 3]; 4],
+        face_normal: [f64; 3],
+        quad_uv: [[f32; 2]; 4],
+    ) {
+        let first = vertex.len() as i32;
+        quad.iter().for_each(|p| {
+            vertex.push(*p);
+            normal.push([face_normal[0] as f32, face_normal[1] as f32, face_normal[2] as f32]);
+        });
+        uv.extend(quad_uv);
+        index.push([first, first + 1, first + 2]);
+        index.push([first, first + 2, first + 3]);
+    }
+
+    // 竖直面的纹理坐标：u 沿面水平向右，v 为离地高度
+    fn vertical_uv(p: &[f64; 3], face_normal: [f32; 3], bottom: f64) -> [f32; 2] {
+        let u = -p[0] as f32 * face_normal[1] + p[1] as f32 * face_normal[0];
+        [u, (p[2] - bottom) as f32]
+    }
+
+    // 山墙三角形落在外环的某条边上，u 取该边起点的累计长度加上沿边的距离，与墙面的 u 一致
+    fn gable_uv(t: &[[f64; 3]; 3], ring: &[[f64; 2]], ring_distance: &[f64], bottom: f64) -> [[f32; 2]; 3] {
+        let offset = |p: &[f64; 3], a: [f64; 2], b: [f64; 2]| {
+            let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+            let len = (dx * dx + dy * dy).sqrt().max(1e-12);
+            let along = ((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / len;
+            let across = ((p[0] - a[0]) * dy - (p[1] - a[1]) * dx).abs() / len;
+            (along, across)
+        };
+        // 三个点离边所在直线最近，且都在边的范围内
+        let edge = (0..ring.len().saturating_sub(1))
+            .map(|i| {
+                let len = ring_distance[i + 1] - ring_distance[i];
+                let error: f64 = t
+                    .iter()
+                    .map(|p| {
+                        let (along, across) = offset(p, ring[i], ring[i + 1]);
+                        across + (-along).max(0.) + (along - len).max(0.)
+                    })
+                    .sum();
+                (i, error)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|x| x.0);
+        t.map(|p| {
+            let distance = match edge {
+                // 端点直接用墙面上的累计长度，保证能与墙顶的点合并
+                Some(i) if p[0] == ring[i][0] && p[1] == ring[i][1] => ring_distance[i],
+                Some(i) if p[0] == ring[i + 1][0] && p[1] == ring[i + 1][1] => ring_distance[i + 1],
+                Some(i) => ring_distance[i] + offset(&p, ring[i], ring[i + 1]).0,
+                None => 0.,
+            };
+            [-distance as f32, (p[2] - bottom) as f32]
+        })
+    }
+
+    fn face_normal(t: &[[f64; 3]; 3]) -> [f32; 3] {
+        let u = Vec3::new(
+            (t[1][0] - t[0][0]) as f32,
+            (t[1][1] - t[0][1]) as f32,
+            (t[1][2] - t[0][2]) as f32,
+        );
+        let v = Vec3::new(
+            (t[2][0] - t[0][0]) as f32,
+            (t[2][1] - t[0][1]) as f32,
+            (t[2][2] - t[0][2]) as f32,
+        );
+        let n = u.cross(v).normalized();
+        [n.x, n.y, n.z]
     }
 
     fn calc_normal(base_cut: i32, pt_num: i32, vertex: &Vec<[f64; 3]>) -> Vec<[f32; 3]> {
@@ -148,3 +643,218 @@ impl Mesh {
         normal
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个三角形的三个角：位置、法线、纹理坐标和环境光遮蔽，以及所在面的类型
+    #[allow(clippy::type_complexity)]
+    fn corners(mesh: &Mesh) -> Vec<([([f64; 3], [f32; 3], [f32; 2], f32); 3], Face)> {
+        mesh.index
+            .iter()
+            .zip(&mesh.face)
+            .map(|(t, f)| {
+                let corner = |i: i32| {
+                    let i = i as usize;
+                    (mesh.vertex[i], mesh.normal[i], mesh.uv[i], mesh.occlusion.as_ref().unwrap()[i])
+                };
+                (t.map(corner), *f)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roof_higher_than_building_is_clamped() {
+        let square = geo::Polygon::new(
+            vec![(116.39, 39.9), (116.3901, 39.9), (116.3901, 39.9001), (116.39, 39.9001), (116.39, 39.9)].into(),
+            vec![],
+        );
+        let roof = Roof {
+            shape: crate::roof::RoofShape::Gabled,
+            height: 20.,
+            direction: None,
+        };
+        let mesh = Mesh::init(116.39, 39.9, &10., 0., geo::MultiPolygon(vec![square]), 0, &roof);
+        let top = mesh.vertex.iter().map(|p| p[2]).fold(f64::MIN, f64::max);
+        let bottom = mesh.vertex.iter().map(|p| p[2]).fold(f64::MAX, f64::min);
+        assert_eq!((bottom, top), (0., 10.));
+        // 墙体退化成0高，整栋只有屋顶
+        assert_eq!(mesh.wall_height, 0.);
+        assert!(mesh.face.contains(&Face::Roof));
+    }
+
+    #[test]
+    fn weld_keeps_attributes_aligned() {
+        let mut mesh = Mesh::empty(7);
+        mesh.color = Some([10, 20, 30]);
+        // 逐个三角形写出顶点，四边形的对角线和屋顶、墙面交界处都有重复的顶点
+        let mut add = |points: [[f64; 3]; 3], normal: [f32; 3], uv: [[f32; 2]; 3], occlusion: [f32; 3], face: Face| {
+            let base = mesh.vertex.len() as i32;
+            mesh.vertex.extend(points);
+            mesh.normal.extend([normal; 3]);
+            mesh.uv.extend(uv);
+            mesh.occlusion.get_or_insert_with(Vec::new).extend(occlusion);
+            mesh.index.push([base, base + 1, base + 2]);
+            mesh.face.push(face);
+        };
+        let up = [0., 0., 1.];
+        let south = [0., -1., 0.];
+        // 屋顶
+        add([[0., 0., 3.], [4., 0., 3.], [4., 2., 3.]], up, [[0., 0.], [4., 0.], [4., 2.]], [1., 1., 0.9], Face::Roof);
+        add([[0., 0., 3.], [4., 2., 3.], [0., 2., 3.]], up, [[0., 0.], [4., 2.], [0., 2.]], [1., 0.9, 0.8], Face::Roof);
+        // 南墙，与屋顶共用上沿的位置，法线不同
+        add([[0., 0., 0.], [4., 0., 0.], [4., 0., 3.]], south, [[0., 0.], [4., 0.], [4., 3.]], [0.5, 0.6, 1.], Face::Wall);
+        add([[0., 0., 0.], [4., 0., 3.], [0., 0., 3.]], south, [[0., 0.], [4., 3.], [0., 3.]], [0.5, 1., 1.], Face::Wall);
+        // 纹理接缝：位置、法线相同，纹理坐标不同
+        add([[4., 0., 0.], [6., 0., 0.], [4., 0., 3.]], south, [[0., 0.], [2., 0.], [0., 3.]], [0.6, 0.6, 1.], Face::Wall);
+        // 位置、法线、纹理坐标都相同，环境光遮蔽不同
+        add([[6., 0., 0.], [6., 0., 3.], [4., 0., 3.]], south, [[2., 0.], [2., 3.], [0., 3.]], [0.65, 0.7, 1.], Face::Wall);
+        add([[6., 0., 3.], [4., 0., 3.], [6., 0., 6.]], south, [[2., 3.], [0., 3.], [2., 6.]], [0.7, 1., 1.], Face::Wall);
+        // 退化三角形：两个角焊接后相同、三点共线
+        add([[0., 0., 0.], [4., 0., 0.], [0., 0., 0.]], south, [[0., 0.], [4., 0.], [0., 0.]], [0.5, 0.6, 0.5], Face::Bottom);
+        add([[0., 0., 0.], [2., 0., 0.], [4., 0., 0.]], south, [[0., 0.], [2., 0.], [4., 0.]], [0.5, 0.5, 0.6], Face::Bottom);
+
+        let mut expected = corners(&mesh);
+        expected.truncate(7);
+        let before = mesh.vertex.len();
+        assert_eq!(mesh.weld(), (before, 14, 2));
+        assert_eq!(corners(&mesh), expected);
+        assert_eq!(mesh.normal.len(), mesh.vertex.len());
+        assert_eq!(mesh.uv.len(), mesh.vertex.len());
+        assert_eq!(mesh.occlusion.as_ref().unwrap().len(), mesh.vertex.len());
+        assert_eq!(mesh.face.len(), mesh.index.len());
+        assert_eq!(mesh.color, Some([10, 20, 30]));
+    }
+
+    // 外环按 shp 的约定顺时针，东西约 17 米、南北约 11 米
+    fn building(height: f32, roof: &Roof) -> Mesh {
+        let ring = vec![(116.39, 39.9), (116.39, 39.9001), (116.3902, 39.9001), (116.3902, 39.9), (116.39, 39.9)];
+        let polygon = geo::Polygon::new(ring.into(), vec![]);
+        Mesh::init(116.39, 39.9, &height, 0., geo::MultiPolygon(vec![polygon]), 0, roof)
+    }
+
+    // 墙面从外面看 u 向右增加、v 为离地高度，都以米为单位，纹理不会镜像或拉伸
+    fn assert_wall_uv(mesh: &Mesh, t: &[i32; 3]) {
+        let n = mesh.normal[t[0] as usize];
+        let right = [-n[1] as f64, n[0] as f64];
+        t.iter().for_each(|a| {
+            let (p, uv) = (mesh.vertex[*a as usize], mesh.uv[*a as usize]);
+            assert!((uv[1] as f64 - p[2]).abs() < 1e-4, "{:?} {:?}", p, uv);
+            t.iter().for_each(|b| {
+                let (q, other) = (mesh.vertex[*b as usize], mesh.uv[*b as usize]);
+                let along = (q[0] - p[0]) * right[0] + (q[1] - p[1]) * right[1];
+                assert!((other[0] as f64 - uv[0] as f64 - along).abs() < 1e-3, "{:?} {:?}", p, q);
+            });
+        });
+    }
+
+    #[test]
+    fn wall_and_roof_uv_are_in_meters() {
+        let mesh = building(9., &Roof::flat());
+        assert_eq!(mesh.uv.len(), mesh.vertex.len());
+        let mut perimeter = 0.;
+        mesh.index.iter().zip(&mesh.face).for_each(|(t, face)| match face {
+            Face::Wall => {
+                assert_wall_uv(&mesh, t);
+                perimeter = t.iter().map(|i| -mesh.uv[*i as usize][0]).fold(perimeter, f32::max);
+            }
+            // 屋顶和底面按水平位置平铺
+            _ => t.iter().for_each(|i| {
+                let p = mesh.vertex[*i as usize];
+                assert_eq!(mesh.uv[*i as usize], [p[0] as f32, p[1] as f32]);
+            }),
+        });
+        // u 沿外环累计，绕一圈为周长
+        let width = lon_to_meters(0.0002, 39.9);
+        let depth = lat_to_meters(0.0001);
+        assert!((perimeter as f64 - 2. * (width + depth)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn gable_uv_continues_wall() {
+        let roof = Roof {
+            shape: crate::roof::RoofShape::Gabled,
+            height: 4.,
+            direction: None,
+        };
+        let mesh = building(12., &roof);
+        let eave: Vec<_> = (0..mesh.vertex.len())
+            .filter(|i| mesh.vertex[*i][2] == 8. && mesh.normal[*i][2] == 0.)
+            .map(|i| (mesh.vertex[i], mesh.uv[i]))
+            .collect();
+        let mut gables = 0;
+        mesh.index.iter().zip(&mesh.face).for_each(|(t, face)| {
+            let top = t.iter().map(|i| mesh.vertex[*i as usize][2]).fold(f64::MIN, f64::max);
+            match face {
+                Face::Wall => {
+                    assert_wall_uv(&mesh, t);
+                    if top > 8. {
+                        gables += 1;
+                        // 山墙在屋檐上的点与下面墙顶的点纹理坐标相同
+                        t.iter().map(|i| *i as usize).filter(|i| mesh.vertex[*i][2] == 8.).for_each(|i| {
+                            assert!(eave.iter().any(|(p, uv)| *p == mesh.vertex[i] && *uv == mesh.uv[i]));
+                        });
+                    }
+                }
+                _ => t.iter().for_each(|i| {
+                    let p = mesh.vertex[*i as usize];
+                    assert_eq!(mesh.uv[*i as usize], [p[0] as f32, p[1] as f32]);
+                }),
+            }
+        });
+        assert_eq!(gables, 2);
+    }
+
+    #[test]
+    fn polyline_wall_is_closed_above_ground() {
+        // 先向东再向北的折线，转角处斜接
+        let line = geo::LineString::from(vec![(116.39, 39.9), (116.3902, 39.9), (116.3902, 39.9001)]);
+        let mesh = Mesh::init_wall(116.39, 39.9, &3., 0., geo::MultiLineString(vec![line]), 5, 0.4);
+        assert_eq!(mesh.id, 5);
+        assert_eq!(mesh.face.len(), mesh.index.len());
+        assert_eq!((mesh.height, mesh.wall_height), (3., 3.));
+
+        // 三角形的绕向与法线一致，侧面和封口水平朝外，顶面朝上
+        mesh.index.iter().zip(&mesh.face).for_each(|(t, face)| {
+            let [a, b, c] = t.map(|i| mesh.vertex[i as usize]);
+            let cross = Mesh::face_normal(&[a, b, c]);
+            let n = mesh.normal[t[0] as usize];
+            assert!(t.iter().all(|i| mesh.normal[*i as usize] == n));
+            assert!(cross.iter().zip(n).map(|(x, y)| x * y).sum::<f32>() > 0.999, "{:?} {:?}", cross, n);
+            match face {
+                Face::Roof => assert!(n == [0., 0., 1.] && [a, b, c].iter().all(|p| p[2] == 3.)),
+                _ => assert_eq!(n[2], 0.),
+            }
+        });
+
+        // 除了贴地的底边，每条边都正好与一条反向的边相接
+        let key = |p: [f64; 3]| p.map(|x| (x * 1e6).round() as i64);
+        let mut edges = std::collections::HashMap::new();
+        mesh.index.iter().for_each(|t| {
+            (0..3).for_each(|k| {
+                let (a, b) = (mesh.vertex[t[k] as usize], mesh.vertex[t[(k + 1) % 3] as usize]);
+                *edges.entry((key(a), key(b))).or_insert(0) += 1;
+            })
+        });
+        edges.iter().for_each(|((a, b), count)| {
+            assert_eq!(*count, 1);
+            if a[2] != 0 || b[2] != 0 {
+                assert_eq!(edges.get(&(*b, *a)), Some(&1), "{:?} {:?}", a, b);
+            }
+        });
+
+        // 起点的封口宽为墙厚，转角两侧按斜接偏移 √2 倍的半厚
+        let start: Vec<_> = mesh.vertex.iter().filter(|p| p[0] == 0.).collect();
+        assert!(start.iter().any(|p| (p[1] - 0.2).abs() < 1e-9));
+        assert!(start.iter().any(|p| (p[1] + 0.2).abs() < 1e-9));
+        let corner = [lon_to_meters(0.0002, 39.9), 0.];
+        let miter = mesh
+            .vertex
+            .iter()
+            .map(|p| ((p[0] - corner[0]).powi(2) + (p[1] - corner[1]).powi(2)).sqrt())
+            .filter(|d| *d > 0.21 && *d < 1.)
+            .fold(0., f64::max);
+        assert!((miter - 0.2 * 2f64.sqrt()).abs() < 1e-9, "{}", miter);
+    }
+}
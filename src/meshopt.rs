@@ -0,0 +1,765 @@
+// EXT_meshopt_compression 使用的 meshoptimizer 顶点、索引编码（顶点格式版本0，索引格式版本1），
+// 以及写出前对索引做的顶点缓存、过度绘制和顶点读取顺序优化
+
+/// meshopt 压缩参数，位置和法线的量化位数
+pub struct MeshoptOptions {
+    pub position_bits: u8,
+    pub normal_bits: u8,
+}
+
+impl MeshoptOptions {
+    /// 解析 `位置位数[,法线位数]` 形式的参数，法线位数缺省为8
+    pub fn parse(spec: &str) -> Option<MeshoptOptions> {
+        let mut parts = spec.split(',').map(|x| x.trim().parse::<u8>());
+        let position_bits = parts.next()?.ok()?;
+        let normal_bits = match parts.next() {
+            Some(x) => x.ok()?,
+            None => 8,
+        };
+        if parts.next().is_some()
+            || !(2..=16).contains(&position_bits)
+            || !(2..=8).contains(&normal_bits)
+        {
+            return None;
+        }
+        Some(MeshoptOptions {
+            position_bits,
+            normal_bits,
+        })
+    }
+}
+
+/// 把 -1..=1 的值量化成有符号归一化整数，`max` 为存储类型的最大值
+///
+/// 位数小于存储类型时按 2^(存储位数-位数) 的步长取整，低位全是零，压缩时几乎不占空间
+pub fn quantize_snorm(v: f32, bits: u8, max: i32) -> i32 {
+    let step = ((max + 1) >> (bits - 1)) as f32;
+    let q = (v.clamp(-1., 1.) * max as f32 / step).round() * step;
+    q.clamp(-max as f32, max as f32) as i32
+}
+
+// Forsyth 顶点缓存优化模拟的缓存大小
+const CACHE_SIZE: usize = 32;
+// 过度绘制优化划分簇时模拟的缓存大小
+const CLUSTER_CACHE_SIZE: u32 = 16;
+
+fn vertex_score(cache_position: usize, live: u32) -> f32 {
+    if live == 0 {
+        return -1.;
+    }
+    let mut score = 0.;
+    if cache_position < 3 {
+        score = 0.75;
+    } else if cache_position < CACHE_SIZE {
+        score = (1. - (cache_position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5);
+    }
+    score + 2. / (live as f32).sqrt()
+}
+
+/// 顶点缓存优化：按 Forsyth 的打分贪心挑选下一个三角形，让相邻三角形尽量共用缓存里的顶点
+pub fn optimize_vertex_cache(index: &mut [[i32; 3]], vertex_count: usize) {
+    let mut live = vec![0u32; vertex_count];
+    index.iter().flatten().for_each(|v| live[*v as usize] += 1);
+    // 每个顶点相邻的三角形，按偏移量存在一个数组里
+    let mut offsets = vec![0; vertex_count + 1];
+    for v in 0..vertex_count {
+        offsets[v + 1] = offsets[v] + live[v] as usize;
+    }
+    let mut adjacency = vec![0; offsets[vertex_count]];
+    let mut fill = offsets.clone();
+    index.iter().enumerate().for_each(|(f, t)| {
+        t.iter().for_each(|v| {
+            adjacency[fill[*v as usize]] = f;
+            fill[*v as usize] += 1;
+        })
+    });
+
+    let mut cache_position = vec![CACHE_SIZE; vertex_count];
+    let mut score: Vec<f32> = (0..vertex_count)
+        .map(|v| vertex_score(CACHE_SIZE, live[v]))
+        .collect();
+    let mut emitted = vec![false; index.len()];
+    let mut cache: Vec<usize> = vec![];
+    let mut result = Vec::with_capacity(index.len());
+    let mut cursor = 0;
+    let mut best = None;
+    while result.len() < index.len() {
+        // 缓存里没有可用的三角形时按原顺序取下一个
+        let face = best.unwrap_or_else(|| {
+            while emitted[cursor] {
+                cursor += 1;
+            }
+            cursor
+        });
+        emitted[face] = true;
+        result.push(index[face]);
+
+        index[face].iter().for_each(|v| live[*v as usize] -= 1);
+        let mut next: Vec<usize> = vec![];
+        index[face]
+            .iter()
+            .map(|v| *v as usize)
+            .chain(cache.iter().copied())
+            .for_each(|v| {
+                if !next.contains(&v) {
+                    next.push(v);
+                }
+            });
+        next.iter().enumerate().for_each(|(p, v)| {
+            cache_position[*v] = p.min(CACHE_SIZE);
+            score[*v] = vertex_score(cache_position[*v], live[*v]);
+        });
+
+        best = None;
+        let mut best_score = f32::MIN;
+        next.iter().for_each(|v| {
+            adjacency[offsets[*v]..offsets[*v + 1]].iter().for_each(|f| {
+                if !emitted[*f] {
+                    let s: f32 = index[*f].iter().map(|x| score[*x as usize]).sum();
+                    if s > best_score {
+                        best_score = s;
+                        best = Some(*f);
+                    }
+                }
+            })
+        });
+        next.truncate(CACHE_SIZE);
+        cache = next;
+    }
+    index.copy_from_slice(&result);
+}
+
+/// 过度绘制优化：三个顶点都不在缓存里的三角形作为新簇的开始，朝外的簇先画
+pub fn optimize_overdraw(index: &mut [[i32; 3]], position: &[[f32; 3]]) {
+    if index.is_empty() {
+        return;
+    }
+    let mut timestamps = vec![0u32; position.len()];
+    let mut timestamp = CLUSTER_CACHE_SIZE + 1;
+    let mut starts = vec![];
+    index.iter().enumerate().for_each(|(i, t)| {
+        let mut misses = 0;
+        t.iter().for_each(|v| {
+            if timestamp - timestamps[*v as usize] > CLUSTER_CACHE_SIZE {
+                timestamps[*v as usize] = timestamp;
+                timestamp += 1;
+                misses += 1;
+            }
+        });
+        if i == 0 || misses == 3 {
+            starts.push(i);
+        }
+    });
+
+    let point = |v: i32| position[v as usize].map(|x| x as f64);
+    let mut centroid = [0.; 3];
+    index.iter().flatten().for_each(|v| {
+        let p = point(*v);
+        (0..3).for_each(|k| centroid[k] += p[k] / (3 * index.len()) as f64);
+    });
+
+    // 簇的排序值为簇中心相对整体中心的偏移在簇平均法线上的投影
+    let mut clusters: Vec<(f64, usize, usize)> = starts
+        .iter()
+        .enumerate()
+        .map(|(k, start)| {
+            let end = starts.get(k + 1).copied().unwrap_or(index.len());
+            let mut center = [0.; 3];
+            let mut normal = [0.; 3];
+            let mut area_sum = 0.;
+            index[*start..end].iter().for_each(|t| {
+                let [p0, p1, p2] = t.map(point);
+                let u = [p1[0] - p0[0], p1[1] - p0[1], p1[2] - p0[2]];
+                let w = [p2[0] - p0[0], p2[1] - p0[1], p2[2] - p0[2]];
+                let n = [
+                    u[1] * w[2] - u[2] * w[1],
+                    u[2] * w[0] - u[0] * w[2],
+                    u[0] * w[1] - u[1] * w[0],
+                ];
+                let area = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+                (0..3).for_each(|k| {
+                    center[k] += (p0[k] + p1[k] + p2[k]) / 3. * area;
+                    normal[k] += n[k];
+                });
+                area_sum += area;
+            });
+            if area_sum > 0. {
+                (0..3).for_each(|k| center[k] /= area_sum);
+            }
+            let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+            let key = if length > 0. {
+                (0..3)
+                    .map(|k| (center[k] - centroid[k]) * normal[k] / length)
+                    .sum()
+            } else {
+                0.
+            };
+            (key, *start, end)
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let sorted: Vec<[i32; 3]> = clusters
+        .iter()
+        .flat_map(|(_, start, end)| index[*start..*end].to_vec())
+        .collect();
+    index.copy_from_slice(&sorted);
+}
+
+/// 按首次使用的顺序重排顶点，返回新顺序下每个顶点原来的序号，没用到的顶点被去掉
+pub fn optimize_vertex_fetch(index: &mut [[i32; 3]], vertex_count: usize) -> Vec<usize> {
+    let mut remap = vec![usize::MAX; vertex_count];
+    let mut order = vec![];
+    index.iter_mut().flatten().for_each(|v| {
+        let old = *v as usize;
+        if remap[old] == usize::MAX {
+            remap[old] = order.len();
+            order.push(old);
+        }
+        *v = remap[old] as i32;
+    });
+    order
+}
+
+// 16个字节一组，按 0、2、4、8 位中编码后最短的一种存放，超出范围的值单独跟在后面
+fn encode_bytes(out: &mut Vec<u8>, buffer: &[u8]) {
+    let header = out.len();
+    out.resize(header + (buffer.len() / 16).div_ceil(4), 0);
+    buffer.chunks(16).enumerate().for_each(|(g, group)| {
+        let size = |bits: usize| match bits {
+            0 if group.iter().all(|x| *x == 0) => 0,
+            0 => usize::MAX,
+            8 => 16,
+            bits => 2 * bits + group.iter().filter(|x| **x as usize >= (1 << bits) - 1).count(),
+        };
+        let (code, bits) = [(0, 0), (1, 2), (2, 4), (3, 8)]
+            .into_iter()
+            .min_by_key(|(_, bits)| size(*bits))
+            .unwrap();
+        out[header + g / 4] |= code << ((g % 4) * 2);
+        match bits {
+            0 => (),
+            8 => out.extend_from_slice(group),
+            bits => {
+                let sentinel = ((1 << bits) - 1) as u8;
+                group.chunks(8 / bits).for_each(|values| {
+                    let byte = values
+                        .iter()
+                        .fold(0u8, |byte, x| (byte << bits) | (*x).min(sentinel));
+                    out.push(byte);
+                });
+                group
+                    .iter()
+                    .filter(|x| **x >= sentinel)
+                    .for_each(|x| out.push(*x));
+            }
+        }
+    });
+}
+
+/// 顶点编码：按块把每个字节与上一个顶点的同一字节做差，差值逐字节分组压缩
+pub fn encode_vertex_buffer(data: &[u8], vertex_size: usize) -> Vec<u8> {
+    debug_assert!(vertex_size.is_multiple_of(4) && vertex_size <= 256);
+    let mut out = vec![0xa0];
+    let mut first = vec![0u8; vertex_size];
+    if data.len() >= vertex_size {
+        first.copy_from_slice(&data[..vertex_size]);
+    }
+    let mut last = first.clone();
+    let block_size = ((8192 / vertex_size) & !15).min(256);
+    data.chunks(block_size * vertex_size).for_each(|block| {
+        let count = block.len() / vertex_size;
+        let mut buffer = vec![0u8; (count + 15) & !15];
+        for k in 0..vertex_size {
+            let mut p = last[k];
+            for i in 0..count {
+                let v = block[i * vertex_size + k];
+                let d = v.wrapping_sub(p);
+                buffer[i] = (d << 1) ^ ((d as i8 >> 7) as u8);
+                p = v;
+            }
+            encode_bytes(&mut out, &buffer);
+        }
+        last.copy_from_slice(&block[(count - 1) * vertex_size..]);
+    });
+    // 末尾补齐到32字节并存放第一个顶点，解码时作为差分的起点
+    out.resize(out.len() + 32usize.saturating_sub(vertex_size), 0);
+    out.extend_from_slice(&first);
+    out
+}
+
+fn write_vbyte(out: &mut Vec<u8>, mut v: u32) {
+    loop {
+        out.push((v & 127) as u8 | if v > 127 { 128 } else { 0 });
+        v >>= 7;
+        if v == 0 {
+            break;
+        }
+    }
+}
+
+// 与上一个单独存放的索引做差，低位是符号位
+fn encode_index(index: u32, last: u32) -> u32 {
+    let d = index.wrapping_sub(last);
+    (d << 1) ^ ((d as i32 >> 31) as u32)
+}
+
+// 常见的两个顶点 FIFO 编码组合，用半字节查表
+const CODE_AUX_TABLE: [u8; 16] = [
+    0x00, 0x76, 0x87, 0x56, 0x67, 0x78, 0xa9, 0x86, 0x65, 0x89, 0x68, 0x98, 0x01, 0x69, 0, 0,
+];
+
+/// 三角形索引编码：利用最近的16条边和16个顶点，大多数三角形只需要一个字节
+pub fn encode_index_buffer(index: &[[i32; 3]]) -> Vec<u8> {
+    let mut edge_fifo = [[u32::MAX; 2]; 16];
+    let mut vertex_fifo = [u32::MAX; 16];
+    let mut edge_offset = 0;
+    let mut vertex_offset = 0;
+    let mut next = 0u32;
+    let mut last = 0u32;
+    let mut codes = Vec::with_capacity(index.len());
+    let mut data = vec![];
+
+    let find_vertex = |fifo: &[u32; 16], offset: usize, v: u32| {
+        (0..16).find(|i| fifo[(offset + 15 - i) & 15] == v)
+    };
+    let push_vertex = |fifo: &mut [u32; 16], offset: &mut usize, v: u32| {
+        fifo[*offset] = v;
+        *offset = (*offset + 1) & 15;
+    };
+    let push_edge = |fifo: &mut [[u32; 2]; 16], offset: &mut usize, a: u32, b: u32| {
+        fifo[*offset] = [a, b];
+        *offset = (*offset + 1) & 15;
+    };
+
+    for t in index {
+        let t = t.map(|v| v as u32);
+        // 找一条已经出现过的边，三角形顺带旋转成 a、b 在这条边上
+        let edge = (0..15).find_map(|i| {
+            let [e0, e1] = edge_fifo[(edge_offset + 15 - i) & 15];
+            (0..3)
+                .find(|r| e0 == t[*r] && e1 == t[(r + 1) % 3])
+                .map(|r| (i, r))
+        });
+        match edge {
+            Some((fe, r)) => {
+                let (a, b, c) = (t[r], t[(r + 1) % 3], t[(r + 2) % 3]);
+                let mut fec = match find_vertex(&vertex_fifo, vertex_offset, c) {
+                    Some(fc) if (1..13).contains(&fc) => fc,
+                    _ if c == next => {
+                        next += 1;
+                        0
+                    }
+                    _ => 15,
+                };
+                if fec == 15 {
+                    // 与上一个单独存放的索引相差1时不用再存
+                    if c.wrapping_add(1) == last {
+                        fec = 13;
+                        last = c;
+                    } else if c == last.wrapping_add(1) {
+                        fec = 14;
+                        last = c;
+                    }
+                }
+                codes.push((fe << 4 | fec) as u8);
+                if fec == 15 {
+                    write_vbyte(&mut data, encode_index(c, last));
+                    last = c;
+                }
+                if fec == 0 || fec >= 13 {
+                    push_vertex(&mut vertex_fifo, &mut vertex_offset, c);
+                }
+                push_edge(&mut edge_fifo, &mut edge_offset, c, b);
+                push_edge(&mut edge_fifo, &mut edge_offset, a, c);
+            }
+            None => {
+                let r = if t[1] == next {
+                    1
+                } else if t[2] == next {
+                    2
+                } else {
+                    0
+                };
+                let (a, b, c) = (t[r], t[(r + 1) % 3], t[(r + 2) % 3]);
+                // 0、1、2 重新开始编号时清空顶点 FIFO
+                let reset = a == 0 && b == 1 && c == 2 && next > 0;
+                if reset {
+                    next = 0;
+                    vertex_fifo = [u32::MAX; 16];
+                }
+                let fb = find_vertex(&vertex_fifo, vertex_offset, b);
+                let fc = find_vertex(&vertex_fifo, vertex_offset, c);
+                let mut free = |v: u32, found: Option<usize>| match found {
+                    Some(f) if f < 14 => f + 1,
+                    _ if v == next => {
+                        next += 1;
+                        0
+                    }
+                    _ => 15,
+                };
+                let fea = free(a, None);
+                let feb = free(b, fb);
+                let fec = free(c, fc);
+                let code_aux = (feb << 4 | fec) as u8;
+                match CODE_AUX_TABLE[..14].iter().position(|x| *x == code_aux) {
+                    Some(i) if fea == 0 && !reset => codes.push(0xf0 | i as u8),
+                    _ => {
+                        codes.push((0xf0 | 14 | fea) as u8);
+                        data.push(code_aux);
+                    }
+                }
+                for (v, fe) in [(a, fea), (b, feb), (c, fec)] {
+                    if fe == 15 {
+                        write_vbyte(&mut data, encode_index(v, last));
+                        last = v;
+                    }
+                }
+                for (v, fe) in [(a, fea), (b, feb), (c, fec)] {
+                    if fe == 0 || fe == 15 {
+                        push_vertex(&mut vertex_fifo, &mut vertex_offset, v);
+                    }
+                }
+                push_edge(&mut edge_fifo, &mut edge_offset, b, a);
+                push_edge(&mut edge_fifo, &mut edge_offset, c, b);
+                push_edge(&mut edge_fifo, &mut edge_offset, a, c);
+            }
+        }
+    }
+
+    let mut out = vec![0xe1];
+    out.extend_from_slice(&codes);
+    out.extend_from_slice(&data);
+    // 查找表同时作为解码时的越界保护
+    out.extend_from_slice(&CODE_AUX_TABLE);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按 meshoptimizer 的解码规则还原顶点数据（格式版本0）
+    fn decode_vertex_buffer(data: &[u8], count: usize, vertex_size: usize) -> Vec<u8> {
+        assert_eq!(data[0], 0xa0);
+        let mut p = 1;
+        let mut last = data[data.len() - vertex_size..].to_vec();
+        let block_size = ((8192 / vertex_size) & !15).min(256);
+        let mut out = vec![0u8; count * vertex_size];
+        let mut offset = 0;
+        while offset < count {
+            let block = block_size.min(count - offset);
+            let aligned = (block + 15) & !15;
+            for k in 0..vertex_size {
+                let header_size = (aligned / 16).div_ceil(4);
+                let header = data[p..p + header_size].to_vec();
+                p += header_size;
+                let mut buffer = vec![];
+                for g in 0..aligned / 16 {
+                    match (header[g / 4] >> ((g % 4) * 2)) & 3 {
+                        0 => buffer.extend([0; 16]),
+                        3 => {
+                            buffer.extend_from_slice(&data[p..p + 16]);
+                            p += 16;
+                        }
+                        code => {
+                            let bits = if code == 1 { 2 } else { 4 };
+                            let sentinel = (1u8 << bits) - 1;
+                            let mut extra = p + 2 * bits;
+                            for byte in &data[p..p + 2 * bits] {
+                                for j in 0..8 / bits {
+                                    let e = (byte >> (8 - bits * (j + 1))) & sentinel;
+                                    if e == sentinel {
+                                        buffer.push(data[extra]);
+                                        extra += 1;
+                                    } else {
+                                        buffer.push(e);
+                                    }
+                                }
+                            }
+                            p = extra;
+                        }
+                    }
+                }
+                let mut previous = last[k];
+                for (i, u) in buffer[..block].iter().enumerate() {
+                    let d = (u >> 1) ^ (u & 1).wrapping_neg();
+                    previous = previous.wrapping_add(d);
+                    out[(offset + i) * vertex_size + k] = previous;
+                }
+            }
+            last = out[(offset + block - 1) * vertex_size..(offset + block) * vertex_size].to_vec();
+            offset += block;
+        }
+        assert_eq!(data.len() - p, vertex_size.max(32), "尾部应为补齐和第一个顶点");
+        out
+    }
+
+    fn read_vbyte(data: &[u8], p: &mut usize) -> u32 {
+        let mut value = 0;
+        for shift in (0..35).step_by(7) {
+            let b = data[*p];
+            *p += 1;
+            value |= ((b & 127) as u32) << shift;
+            if b < 128 {
+                break;
+            }
+        }
+        value
+    }
+
+    // 按 meshoptimizer 的解码规则还原三角形索引（格式版本1）
+    fn decode_index_buffer(data: &[u8], count: usize) -> Vec<[u32; 3]> {
+        assert_eq!(data[0], 0xe1);
+        let mut edge_fifo = [[u32::MAX; 2]; 16];
+        let mut vertex_fifo = [u32::MAX; 16];
+        let (mut edge_offset, mut vertex_offset) = (0usize, 0usize);
+        let (mut next, mut last) = (0u32, 0u32);
+        let mut p = 1 + count / 3;
+        let safe_end = data.len() - 16;
+        let table = &data[safe_end..];
+        let mut out = vec![];
+
+        let push_vertex = |fifo: &mut [u32; 16], offset: &mut usize, v: u32, advance: bool| {
+            fifo[*offset] = v;
+            *offset = (*offset + advance as usize) & 15;
+        };
+        let read_index = |p: &mut usize, last: &mut u32| {
+            let v = read_vbyte(data, p);
+            *last = last.wrapping_add((v >> 1) ^ (v & 1).wrapping_neg());
+            *last
+        };
+        let vertex = |fifo: &[u32; 16], offset: usize, f: u8| fifo[(offset + 16 - f as usize) & 15];
+        for &ct in &data[1..1 + count / 3] {
+            let (a, b, c);
+            if ct < 0xf0 {
+                let fe = (ct >> 4) as usize;
+                [a, b] = edge_fifo[(edge_offset + 15 - fe) & 15];
+                let fec = ct & 15;
+                c = match fec {
+                    0 => {
+                        next += 1;
+                        next - 1
+                    }
+                    1..=12 => vertex(&vertex_fifo, vertex_offset, fec + 1),
+                    13 | 14 => {
+                        last = if fec == 13 { last.wrapping_sub(1) } else { last.wrapping_add(1) };
+                        last
+                    }
+                    _ => read_index(&mut p, &mut last),
+                };
+                push_vertex(&mut vertex_fifo, &mut vertex_offset, c, fec == 0 || fec >= 13);
+                edge_fifo[edge_offset] = [c, b];
+                edge_fifo[(edge_offset + 1) & 15] = [a, c];
+                edge_offset = (edge_offset + 2) & 15;
+            } else {
+                let (fea, code_aux) = if ct < 0xfe {
+                    (0, table[(ct & 15) as usize])
+                } else {
+                    p += 1;
+                    (if ct == 0xfe { 0 } else { 15 }, data[p - 1])
+                };
+                let (feb, fec) = (code_aux >> 4, code_aux & 15);
+                if ct >= 0xfe && code_aux == 0 {
+                    next = 0;
+                }
+                // 0 为新顶点，1..=14 在顶点 FIFO 中，15 单独存放，在三个顶点都确定后再读
+                let mut take = |fe: u8| match fe {
+                    0 => {
+                        next += 1;
+                        next - 1
+                    }
+                    15 => 0,
+                    f => vertex(&vertex_fifo, vertex_offset, f),
+                };
+                let mut a0 = take(fea);
+                let mut b0 = take(feb);
+                let mut c0 = take(fec);
+                if fea == 15 {
+                    a0 = read_index(&mut p, &mut last);
+                }
+                if feb == 15 {
+                    b0 = read_index(&mut p, &mut last);
+                }
+                if fec == 15 {
+                    c0 = read_index(&mut p, &mut last);
+                }
+                (a, b, c) = (a0, b0, c0);
+                push_vertex(&mut vertex_fifo, &mut vertex_offset, a, true);
+                push_vertex(&mut vertex_fifo, &mut vertex_offset, b, feb == 0 || feb == 15);
+                push_vertex(&mut vertex_fifo, &mut vertex_offset, c, fec == 0 || fec == 15);
+                for edge in [[b, a], [c, b], [a, c]] {
+                    edge_fifo[edge_offset] = edge;
+                    edge_offset = (edge_offset + 1) & 15;
+                }
+            }
+            out.push([a, b, c]);
+        }
+        assert_eq!(p, safe_end, "索引数据应正好读到查找表之前");
+        out
+    }
+
+    // 解码出的三角形与原三角形相同，只允许旋转顶点顺序，朝向不变
+    fn assert_same_triangles(decoded: &[[u32; 3]], index: &[[i32; 3]]) {
+        assert_eq!(decoded.len(), index.len());
+        decoded.iter().zip(index).enumerate().for_each(|(i, (d, t))| {
+            let t = t.map(|v| v as u32);
+            let rotated = (0..3).any(|r| [t[r], t[(r + 1) % 3], t[(r + 2) % 3]] == *d);
+            assert!(rotated, "第{}个三角形解码为{:?}，原为{:?}", i, d, t);
+        });
+    }
+
+    fn round_trip_index(index: &[[i32; 3]]) {
+        let data = encode_index_buffer(index);
+        assert_same_triangles(&decode_index_buffer(&data, index.len() * 3), index);
+    }
+
+    fn round_trip_vertex(data: &[u8], vertex_size: usize) {
+        let encoded = encode_vertex_buffer(data, vertex_size);
+        assert_eq!(decode_vertex_buffer(&encoded, data.len() / vertex_size, vertex_size), data);
+    }
+
+    // 平缓变化的顶点数据，字节差分后多数落在2位、4位分组，也有少量超出范围的值
+    fn vertices(count: usize, vertex_size: usize) -> Vec<u8> {
+        (0..count * vertex_size)
+            .map(|i| {
+                let (v, k) = (i / vertex_size, i % vertex_size);
+                match k % 4 {
+                    0 => (v * 3 + k) as u8,
+                    1 => (v / 5) as u8,
+                    2 => if v % 13 == 0 { 0xc7 } else { 7 },
+                    _ => (v * v * 31 + k) as u8,
+                }
+            })
+            .collect()
+    }
+
+    // 宽 `w` 格、高 `h` 格的网格，每格两个三角形
+    fn grid(w: i32, h: i32) -> Vec<[i32; 3]> {
+        (0..h)
+            .flat_map(|y| {
+                (0..w).flat_map(move |x| {
+                    let v = y * (w + 1) + x;
+                    [[v, v + 1, v + w + 2], [v, v + w + 2, v + w + 1]]
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertex_buffer_round_trip() {
+        // 少于16个、不是16的倍数、跨块的顶点数
+        for count in [1, 3, 15, 16, 17, 100, 257, 1000] {
+            for vertex_size in [4, 8, 12] {
+                round_trip_vertex(&vertices(count, vertex_size), vertex_size);
+            }
+        }
+        round_trip_vertex(&[], 8);
+    }
+
+    #[test]
+    fn index_buffer_round_trip() {
+        // 少于16个顶点
+        round_trip_index(&[[0, 1, 2]]);
+        round_trip_index(&[[0, 1, 2], [2, 1, 3], [3, 4, 0]]);
+        // 顶点数不是16的倍数，超出 FIFO 后要单独存放索引
+        round_trip_index(&grid(7, 5));
+        round_trip_index(&grid(40, 3));
+        let mut shuffled = grid(9, 9);
+        shuffled.reverse();
+        round_trip_index(&shuffled);
+        // 按顶点缓存优化、顶点读取顺序重排之后的索引
+        let mut index = grid(23, 11);
+        optimize_vertex_cache(&mut index, 24 * 12);
+        optimize_vertex_fetch(&mut index, 24 * 12);
+        round_trip_index(&index);
+    }
+
+    #[test]
+    fn index_buffer_degenerate_triangles() {
+        round_trip_index(&[[0, 0, 0]]);
+        round_trip_index(&[[0, 1, 1], [1, 1, 2], [2, 0, 2], [0, 1, 2], [3, 3, 3], [2, 1, 3]]);
+        let mut index = grid(5, 4);
+        index.insert(3, [6, 6, 7]);
+        index.insert(10, [30, 2, 30]);
+        index.push([29, 29, 29]);
+        round_trip_index(&index);
+        // 0、1、2 重新开始编号
+        round_trip_index(&[[0, 1, 2], [2, 1, 3], [0, 1, 2], [0, 1, 2]]);
+    }
+
+    // meshoptimizer 自带测试里的索引数据：重复的三角形、相邻的边和 FIFO 外的顶点，编码结果与参考编码器逐字节一致
+    #[test]
+    fn index_buffer_matches_reference() {
+        let index = [[0, 1, 2], [2, 1, 3], [0, 1, 2], [2, 1, 5], [2, 1, 4]];
+        let reference = [
+            0xe1, 0xf0, 0x10, 0xfe, 0x1f, 0x3d, 0x00, 0x0a, 0x00, 0x76, 0x87, 0x56, 0x67, 0x78, 0xa9, 0x86, 0x65,
+            0x89, 0x68, 0x98, 0x01, 0x69, 0x00, 0x00,
+        ];
+        assert_eq!(encode_index_buffer(&index), reference);
+        assert_eq!(decode_index_buffer(&reference, 15), index.map(|t| t.map(|v| v as u32)));
+    }
+
+    // meshoptimizer 自带测试里的4个顶点，每个顶点为3个 u16 位置、2个 u8 法线、2个 u16 纹理坐标
+    #[test]
+    fn vertex_buffer_matches_reference() {
+        let mut data = vec![];
+        for (x, y, u, v) in [(0u16, 0u16, 0u16, 0u16), (300, 0, 500, 0), (0, 300, 0, 500), (300, 300, 500, 500)] {
+            [x, y, 0].iter().for_each(|c| data.extend_from_slice(&c.to_le_bytes()));
+            data.extend_from_slice(&[0, 0]);
+            [u, v].iter().for_each(|c| data.extend_from_slice(&c.to_le_bytes()));
+        }
+        let mut reference = vec![
+            0xa0, 0x01, 0x3f, 0x00, 0x00, 0x00, 0x58, 0x57, 0x58, 0x01, 0x26, 0x00, 0x00, 0x00, 0x01, 0x0c, 0x00,
+            0x00, 0x00, 0x58, 0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x3f, 0x00, 0x00, 0x00,
+            0x17, 0x18, 0x17, 0x01, 0x26, 0x00, 0x00, 0x00, 0x01, 0x0c, 0x00, 0x00, 0x00, 0x17, 0x01, 0x08, 0x00,
+            0x00, 0x00,
+        ];
+        // 补齐到32字节，最后是第一个顶点
+        reference.extend([0; 32]);
+        assert_eq!(encode_vertex_buffer(&data, 12), reference);
+        assert_eq!(decode_vertex_buffer(&reference, 4, 12), data);
+    }
+
+    // 三角形按顶点顺序旋转到最小的顶点在前，排序后比较，朝向不同的三角形不相等
+    fn triangle_set(index: &[[i32; 3]]) -> Vec<[i32; 3]> {
+        let mut set: Vec<[i32; 3]> = index
+            .iter()
+            .map(|t| {
+                let r = (0..3).min_by_key(|r| t[*r]).unwrap();
+                [t[r], t[(r + 1) % 3], t[(r + 2) % 3]]
+            })
+            .collect();
+        set.sort();
+        set
+    }
+
+    #[test]
+    fn optimizations_keep_triangle_set() {
+        // 起伏的网格加一个没用到的顶点
+        let mut index = grid(12, 9);
+        index.push([3, 3, 20]);
+        index.push([0, 1, 14]);
+        let mut position: Vec<[f32; 3]> = (0..10 * 13)
+            .map(|v| [(v % 13) as f32, (v / 13) as f32, ((v * 7) % 5) as f32 * 0.3])
+            .collect();
+        position.push([100., 100., 100.]);
+        let original = triangle_set(&index);
+
+        optimize_vertex_cache(&mut index, position.len());
+        assert_eq!(triangle_set(&index), original);
+        optimize_overdraw(&mut index, &position);
+        assert_eq!(triangle_set(&index), original);
+
+        let order = optimize_vertex_fetch(&mut index, position.len());
+        assert_eq!(order.len(), position.len() - 1);
+        assert!(!order.contains(&(position.len() - 1)));
+        // 新序号按首次出现的顺序递增
+        let mut next = 0;
+        index.iter().flatten().for_each(|v| {
+            assert!(*v as usize <= next);
+            next = next.max(*v as usize + 1);
+        });
+        let restored: Vec<[i32; 3]> = index.iter().map(|t| t.map(|v| order[v as usize] as i32)).collect();
+        assert_eq!(triangle_set(&restored), original);
+    }
+}
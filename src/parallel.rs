@@ -0,0 +1,111 @@
+// 固定数量的工作线程处理一组互不相关的任务，结果按输入的顺序排列，与线程数无关
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// 未指定 `--threads` 时使用的线程数
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(|x| x.get()).unwrap_or(1)
+}
+
+/// 用最多 `threads` 个线程对每个元素调用 `f`（参数为序号和元素），返回值按元素的顺序排列；
+/// 线程各自从队列里取下一个序号，耗时不均匀的任务也能分摊开
+pub fn map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &T) -> R + Sync,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return items.iter().enumerate().map(|(i, x)| f(i, x)).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        (0..threads).for_each(|_| {
+            scope.spawn(|| {
+                let mut done = vec![];
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= items.len() {
+                        break;
+                    }
+                    done.push((i, f(i, &items[i])));
+                }
+                results.lock().unwrap().extend(done);
+            });
+        });
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// 与 `map` 相同，但同时处理的元素按 `cost` 估计的占用之和不超过 `budget`：线程取到元素后等其他线程释放出足够的额度再处理，
+/// 超过上限的单个元素按上限计，只能独自处理；不给上限时与 `map` 相同
+pub fn map_budget<T, R, C, F>(items: &[T], threads: usize, budget: Option<usize>, cost: C, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    C: Fn(&T) -> usize + Sync,
+    F: Fn(usize, &T) -> R + Sync,
+{
+    let Some(budget) = budget else {
+        return map(items, threads, f);
+    };
+    let used = Mutex::new(0);
+    let released = Condvar::new();
+    map(items, threads, |i, x| {
+        let cost = cost(x).min(budget);
+        *released
+            .wait_while(used.lock().unwrap(), |used| *used + cost > budget)
+            .unwrap() += cost;
+        let result = f(i, x);
+        *used.lock().unwrap() -= cost;
+        released.notify_all();
+        result
+    })
+}
+
+/// 与 `map` 相同，但元素可以修改
+pub fn map_mut<T, R, F>(items: &mut [T], threads: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(usize, &mut T) -> R + Sync,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return items.iter_mut().enumerate().map(|(i, x)| f(i, x)).collect();
+    }
+    // 每个元素单独加锁，序号从队列里取，同一个元素只会被一个线程拿到
+    let cells: Vec<Mutex<&mut T>> = items.iter_mut().map(Mutex::new).collect();
+    map(&cells, threads, |i, cell| f(i, &mut cell.lock().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn map_budget_limits_items_in_flight() {
+        let items: Vec<usize> = (0..24).map(|i| [3, 5, 2, 12][i % 4]).collect();
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let results = map_budget(&items, 8, Some(10), |x| *x, |i, x| {
+            let now = in_flight.fetch_add(*x.min(&10), Ordering::SeqCst) + x.min(&10);
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(2));
+            in_flight.fetch_sub(*x.min(&10), Ordering::SeqCst);
+            i * 100 + x
+        });
+        // 结果按元素的顺序排列，同时处理的占用不超过上限，超过上限的元素按上限计
+        assert_eq!(results, items.iter().enumerate().map(|(i, x)| i * 100 + x).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= 10);
+        assert!(peak.load(Ordering::SeqCst) > 5);
+        assert_eq!(map_budget(&items, 8, None, |x| *x, |_, x| *x), items);
+    }
+}
@@ -0,0 +1,473 @@
+// 面、线图层的转换按阶段拆开：读取（`index::scan`）→ 分块（`index::partition`）→ 逐块读回要素（`index::read`）
+// → 生成网格 → 编码成 b3dm → 所有瓦片写完后写一次 tileset.json（`tileset::write_tileset`）。
+// 每个阶段只依赖上一阶段的结果，可以单独调用
+
+use crate::ao::{self, AoOptions};
+use crate::b3dm;
+use crate::config::{self, ColorMode, MaterialsConfig};
+use crate::field;
+use crate::glb::{self, Compression};
+use crate::index::Tile;
+use crate::mesh::{self, Mesh};
+use crate::parallel;
+use crate::roof;
+use crate::tileset::Origin;
+use geo::Centroid;
+use shapefile::dbase;
+use std::borrow::Cow;
+
+/// 读取shp中的全部要素，面、线和点都转成 geo 的几何类型，其余类型跳过
+pub fn read_features(filename: &str) -> Vec<(geo::Geometry<f64>, dbase::Record)> {
+    let shapes = shapefile::read(filename).expect("无法正确的打开shp文件,请确保shp类型为面、线或点再试");
+    let total = shapes.len();
+    let features: Vec<_> = shapes
+        .into_iter()
+        .filter_map(|(shape, record)| match geo::Geometry::<f64>::try_from(shape) {
+            Ok(
+                geometry @ (geo::Geometry::MultiPolygon(_)
+                | geo::Geometry::MultiLineString(_)
+                | geo::Geometry::Point(_)
+                | geo::Geometry::MultiPoint(_)),
+            ) => Some((geometry, record)),
+            _ => None,
+        })
+        .collect();
+    if features.len() < total {
+        println!("跳过{}个不支持的要素", total - features.len());
+    }
+    features
+}
+
+/// 把 `--points` 的点要素分到瓦片，与瓦片的网格打包在一起：点（多点取中心）落在哪个瓦片的划分范围内
+/// 就归哪个瓦片，边界上的点归前一个瓦片；返回值与 `tiles` 一一对应
+pub fn assign_points(
+    points: Vec<(geo::Geometry<f64>, dbase::Record)>,
+    tiles: &[Tile],
+) -> Vec<Vec<(geo::Geometry<f64>, dbase::Record)>> {
+    let mut assigned: Vec<Vec<_>> = tiles.iter().map(|_| vec![]).collect();
+    points.into_iter().for_each(|(geometry, record)| {
+        let tile = geometry.centroid().and_then(|p| {
+            tiles.iter().position(|x| {
+                let r = x.rect;
+                p.x() >= r[0] && p.y() >= r[1] && p.x() <= r[2] && p.y() <= r[3]
+            })
+        });
+        if let Some(t) = tile {
+            assigned[t].push((geometry, record));
+        }
+    });
+    assigned
+}
+
+/// 生成网格的参数
+pub struct MeshOptions<'a> {
+    /// 局部坐标的原点
+    pub origin: Origin,
+    pub roof_shape: &'a str,
+    pub roof_height: &'a str,
+    pub roof_direction: &'a str,
+    pub wall_thickness: f64,
+    pub materials: Option<&'a MaterialsConfig>,
+    pub ao: Option<&'a AoOptions>,
+}
+
+/// 生成网格时的统计：焊接前后的顶点数、去掉的退化三角形数和烘焙环境光遮蔽的顶点数
+#[derive(Default)]
+pub struct MeshStats {
+    pub before: usize,
+    pub after: usize,
+    pub removed: usize,
+    pub occluded: usize,
+}
+
+/// 编码好的瓦片内容，以及计算包围范围用的局部坐标顶点和量化误差
+pub struct Content {
+    pub data: Vec<u8>,
+    pub vertices: Vec<[f64; 3]>,
+    pub margin: f64,
+}
+
+/// 把一个瓦片的要素生成网格，再焊接顶点、烘焙环境光遮蔽；`ids` 为要素在全部要素中的序号，
+/// `heights` 与要素一一对应，`colors` 按序号取；各要素互不相关，用 `threads` 个线程并行
+pub fn build_meshes(
+    features: &[(geo::Geometry<f64>, dbase::Record)],
+    ids: &[usize],
+    heights: &[f32],
+    colors: &[Option<[u8; 3]>],
+    options: &MeshOptions,
+    threads: usize,
+    stats: &mut MeshStats,
+) -> Vec<Mesh> {
+    let (cx, cy, _) = options.origin;
+    let mut meshes = parallel::map(features, threads, |n, (geometry, record)| {
+        let id = ids[n];
+        let the_bottom = 0.;
+        let polygon_h = &heights[n];
+        let mut mesh = match geometry {
+            geo::Geometry::MultiPolygon(geo_polygon) => {
+                let roof = roof::Roof::from_record(
+                    record,
+                    options.roof_shape,
+                    options.roof_height,
+                    options.roof_direction,
+                );
+                mesh::Mesh::init(
+                    cx,
+                    cy,
+                    polygon_h,
+                    the_bottom,
+                    geo_polygon.clone(),
+                    id as i32,
+                    &roof,
+                )
+            }
+            geo::Geometry::MultiLineString(lines) => mesh::Mesh::init_wall(
+                cx,
+                cy,
+                polygon_h,
+                the_bottom,
+                lines.clone(),
+                id as i32,
+                options.wall_thickness,
+            ),
+            // 索引里只有面和线，直接传进来的其他几何跳过，留一个空网格占位
+            _ => {
+                println!("要素{}不是面或线，跳过", id);
+                mesh::Mesh::empty(id as i32)
+            }
+        };
+        mesh.color = colors[id];
+        // 按类别字段选纹理图片
+        mesh.category = options
+            .materials
+            .and_then(|x| x.field.as_deref())
+            .and_then(|x| config::class_key(record, x));
+        // 窗户立面按要素的层数确定层高
+        mesh.floor_height = options
+            .materials
+            .and_then(|x| x.facade.as_ref())
+            .map(|x| x.floor_height_for(record, mesh.wall_height));
+        mesh
+    });
+
+    // 合并重复顶点、去掉退化三角形
+    parallel::map_mut(&mut meshes, threads, |_, mesh| mesh.weld())
+        .iter()
+        .for_each(|x| {
+            stats.before += x.0;
+            stats.after += x.1;
+            stats.removed += x.2;
+        });
+
+    // 烘焙环境光遮蔽，写进顶点颜色；遮挡只在同一个瓦片内计算
+    if let Some(ao) = options.ao {
+        stats.occluded += ao::bake(&mut meshes, ao, threads);
+    }
+    meshes
+}
+
+/// 批量表：序号和名称按要素在全部要素中的位置编号，分成多个瓦片时也不重复；
+/// 其他属性也写进批量表，与已有的列同名时跳过
+pub fn batch_table(ids: &[usize], heights: Vec<f32>, records: &[&dbase::Record]) -> b3dm::BatchTable {
+    let mut properties = field::batch_columns(records);
+    ["batchId", "height", "name"].iter().for_each(|x| {
+        properties.remove(*x);
+    });
+    b3dm::BatchTable {
+        batch_id: ids.iter().map(|x| *x as u32).collect(),
+        height: heights,
+        name: ids.iter().map(|x| "mesh_".to_string() + &x.to_string()).collect(),
+        properties,
+    }
+}
+
+/// 把网格和批量表编码成 b3dm
+pub fn encode_b3dm(
+    meshes: Vec<Mesh>,
+    batch_table: &b3dm::BatchTable,
+    compression: &Compression,
+    color_mode: ColorMode,
+    materials: Option<&MaterialsConfig>,
+    threads: usize,
+) -> Content {
+    let test_feature_table = b3dm::FeatureTable {
+        batch_length: meshes.len() as u32,
+    };
+
+    let test1 = serde_json::to_string(batch_table).expect("Serialization error");
+    let test2 = serde_json::to_string(&test_feature_table).expect("Serialization error");
+
+    let vec2 = test1.into_bytes();
+    let vec1 = test2.into_bytes();
+
+    // 包围范围按最终的顶点坐标计算，再按量化误差外扩
+    let vertices: Vec<[f64; 3]> = meshes
+        .iter()
+        .flat_map(|mesh| mesh.vertex.iter().map(|p| p.map(|x| x as f32 as f64)))
+        .collect();
+    let size = (0..3)
+        .map(|k| {
+            let min = vertices.iter().map(|p| p[k]).fold(f64::MAX, f64::min);
+            let max = vertices.iter().map(|p| p[k]).fold(f64::MIN, f64::max);
+            max - min
+        })
+        .fold(0., f64::max);
+    let margin = compression.position_error(size);
+
+    let glb = glb::get_glb(meshes, compression, color_mode, materials, threads);
+    let b33dm = b3dm::MakeB3dm {
+        glb: Some(Cow::Owned(glb)),
+    };
+
+    let mut data = vec![];
+    b33dm
+        .to_writer(&mut data, vec1, vec2)
+        .expect("glTF binary output error");
+    Content {
+        data,
+        vertices,
+        margin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{self, Entry};
+    use shapefile::dbase::{FieldValue, Record, TableWriterBuilder};
+    use std::convert::TryInto;
+    use std::path::PathBuf;
+
+    // 中心在 (lon, lat)、边长约 size 度的矩形
+    fn square(lon: f64, lat: f64, size: f64) -> Vec<(f64, f64)> {
+        let h = size / 2.;
+        vec![(lon - h, lat - h), (lon - h, lat + h), (lon + h, lat + h), (lon + h, lat - h), (lon - h, lat - h)]
+    }
+
+    fn record(height: f32, name: &str) -> Record {
+        let mut record = Record::default();
+        record.insert("height".to_string(), FieldValue::Float(Some(height)));
+        record.insert("name".to_string(), FieldValue::Character(Some(name.to_string())));
+        record
+    }
+
+    // 在临时目录写一个面图层，三个矩形自西向东排开，高度分别为 10、20、30
+    fn polygon_layer(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_pipeline");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let table = TableWriterBuilder::new()
+            .add_float_field("height".try_into().unwrap(), 10, 2)
+            .add_character_field("name".try_into().unwrap(), 20);
+        let writer = shapefile::Writer::from_path(&path, table).unwrap();
+        let shapes: Vec<shapefile::Polygon> = (0..3)
+            .map(|i| {
+                let ring = square(116.39 + i as f64 * 0.01, 39.9, 0.0004)
+                    .into_iter()
+                    .map(|(x, y)| shapefile::Point::new(x, y))
+                    .collect();
+                shapefile::Polygon::new(shapefile::PolygonRing::Outer(ring))
+            })
+            .collect();
+        let records: Vec<Record> = (0..3).map(|i| record(10. * (i + 1) as f32, &format!("b{}", i))).collect();
+        writer.write_shapes_and_records(shapes.iter().zip(records.iter())).unwrap();
+        path
+    }
+
+    fn entry(index: usize, lon: f64, points: usize) -> Entry {
+        Entry {
+            index,
+            bbox: [lon - 0.001, 39.9, lon + 0.001, 39.902],
+            points,
+            height: 10.,
+        }
+    }
+
+    fn options<'a>() -> MeshOptions<'a> {
+        MeshOptions {
+            origin: (116.39, 39.9, 0),
+            roof_shape: "roof_shape",
+            roof_height: "roof_height",
+            roof_direction: "roof_direction",
+            wall_thickness: 0.3,
+            materials: None,
+            ao: None,
+        }
+    }
+
+    fn features() -> Vec<(geo::Geometry<f64>, Record)> {
+        let polygon = geo::Polygon::new(square(116.39, 39.9, 0.0002).into(), vec![]);
+        let line = geo::LineString::from(vec![(116.391, 39.9), (116.3912, 39.9), (116.3912, 39.9002)]);
+        vec![
+            (geo::MultiPolygon(vec![polygon]).into(), record(12., "a")),
+            (geo::MultiLineString(vec![line]).into(), record(3., "b")),
+            (geo::Point::new(116.39, 39.9).into(), record(5., "c")),
+        ]
+    }
+
+    #[test]
+    fn scan_indexes_every_feature() {
+        let path = polygon_layer("scan.shp");
+        let index = index::scan(path.to_str().unwrap(), "height", &None, &["name"]);
+        assert_eq!(index.entries.len(), 3);
+        for (i, entry) in index.entries.iter().enumerate() {
+            assert_eq!(entry.index, i);
+            assert_eq!(entry.height, 10. * (i + 1) as f32);
+            // 外环闭合，首尾是同一个点
+            assert_eq!(entry.points, 5);
+            let lon = 116.39 + i as f64 * 0.01;
+            assert!((entry.bbox[0] - (lon - 0.0002)).abs() < 1e-9 && (entry.bbox[2] - (lon + 0.0002)).abs() < 1e-9);
+        }
+        // 只留下指定的字段
+        assert_eq!(index.records.len(), 3);
+        assert!(index.records.iter().all(|x| x.get("name").is_some() && x.get("height").is_none()));
+        assert!((index.origin.0 - 116.4).abs() < 1e-4 && (index.origin.1 - 39.9).abs() < 1e-4);
+        assert_eq!(index.origin.2, 0);
+    }
+
+    #[test]
+    fn partition_respects_budget() {
+        let entries: Vec<Entry> = (0..7).map(|i| entry(i, 116. + i as f64 * 0.01, 10 + i)).collect();
+        let all = index::partition(&entries, None, index::BYTES_PER_POINT);
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].entries, (0..7).collect::<Vec<_>>());
+
+        let budget = 25 * index::BYTES_PER_POINT;
+        let tiles = index::partition(&entries, Some(budget), index::BYTES_PER_POINT);
+        assert!(tiles.len() > 1);
+        let mut seen: Vec<usize> = tiles.iter().flat_map(|x| x.entries.clone()).collect();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..7).collect::<Vec<_>>());
+        for tile in &tiles {
+            let cost: usize = tile.entries.iter().map(|i| entries[*i].points * index::BYTES_PER_POINT).sum();
+            assert!(cost <= budget || tile.entries.len() == 1);
+            assert!(tile.entries.windows(2).all(|x| x[0] < x[1]));
+            // 要素中心落在瓦片的划分范围内
+            for i in &tile.entries {
+                let b = entries[*i].bbox;
+                let (x, y) = ((b[0] + b[2]) / 2., (b[1] + b[3]) / 2.);
+                let r = tile.rect;
+                assert!(x >= r[0] && x <= r[2] && y >= r[1] && y <= r[3]);
+            }
+        }
+    }
+
+    #[test]
+    fn read_returns_tile_features_in_order() {
+        let path = polygon_layer("read.shp");
+        let filename = path.to_str().unwrap();
+        let index = index::scan(filename, "height", &None, &[]);
+        // 每个要素5个点，上限放不下两个要素，每个瓦片一个要素
+        let tiles = index::partition(&index.entries, Some(6 * index::BYTES_PER_POINT), index::BYTES_PER_POINT);
+        assert_eq!(tiles.len(), 3);
+        for tile in &tiles {
+            let features = index::read(filename, &index.entries, tile);
+            assert_eq!(features.len(), tile.entries.len());
+            for ((geometry, record), i) in features.iter().zip(&tile.entries) {
+                assert!(matches!(geometry, geo::Geometry::MultiPolygon(_)));
+                assert_eq!(record.get("name"), Some(&FieldValue::Character(Some(format!("b{}", i)))));
+                assert_eq!(record.get("height"), Some(&FieldValue::Float(Some(index.entries[*i].height))));
+            }
+        }
+    }
+
+    #[test]
+    fn read_features_keeps_records_in_order() {
+        let path = polygon_layer("features.shp");
+        let features = read_features(path.to_str().unwrap());
+        assert_eq!(features.len(), 3);
+        for (i, (geometry, record)) in features.iter().enumerate() {
+            let geo::Geometry::MultiPolygon(polygons) = geometry else {
+                panic!("要素{}不是面", i);
+            };
+            assert_eq!(polygons.0[0].exterior().0.len(), 5);
+            assert_eq!(record.get("name"), Some(&FieldValue::Character(Some(format!("b{}", i)))));
+        }
+    }
+
+    #[test]
+    fn assign_points_puts_each_point_in_one_tile() {
+        let tiles = [
+            Tile {
+                entries: vec![0],
+                rect: [f64::MIN, f64::MIN, 116.4, f64::MAX],
+            },
+            Tile {
+                entries: vec![1],
+                rect: [116.4, f64::MIN, f64::MAX, f64::MAX],
+            },
+        ];
+        let points: Vec<_> = [116.39, 116.4, 116.41]
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (geo::Point::new(*x, 39.9).into(), record(1., &format!("p{}", i))))
+            .chain([(geo::MultiPoint::from(vec![(116.405, 39.9), (116.425, 39.9)]).into(), record(1., "m"))])
+            .chain([(geo::MultiPoint::<f64>(vec![]).into(), record(1., "empty"))])
+            .collect();
+        let assigned = assign_points(points, &tiles);
+        let names = |t: usize| -> Vec<String> {
+            assigned[t]
+                .iter()
+                .map(|(_, record)| field::get_text(record, "name").unwrap())
+                .collect()
+        };
+        // 边界上的点归前一个瓦片，多点按中心分，没有点的要素丢掉
+        assert_eq!(names(0), ["p0", "p1"]);
+        assert_eq!(names(1), ["p2", "m"]);
+    }
+
+    #[test]
+    fn build_meshes_skips_unsupported_geometry() {
+        let features = features();
+        let ids = [4, 5, 6];
+        let heights = [12., 3., 5.];
+        let colors = vec![None, None, None, None, Some([255, 0, 0]), None, None];
+        let mut stats = MeshStats::default();
+        let meshes = build_meshes(&features, &ids, &heights, &colors, &options(), 2, &mut stats);
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(
+            meshes.iter().map(|x| x.mesh_name.as_str()).collect::<Vec<_>>(),
+            ["mesh_4", "mesh_5", "mesh_6"]
+        );
+        assert_eq!(meshes.iter().map(|x| x.id).collect::<Vec<_>>(), [4, 5, 6]);
+        assert!(!meshes[0].index.is_empty() && !meshes[1].index.is_empty());
+        assert!(meshes[2].vertex.is_empty() && meshes[2].index.is_empty());
+        assert_eq!(meshes[0].color, Some([255, 0, 0]));
+        assert!(meshes[0].vertex.iter().all(|p| p[2] >= 0. && p[2] <= 12.));
+        assert_eq!(stats.after, meshes.iter().map(|x| x.vertex.len()).sum::<usize>());
+        assert!(stats.before >= stats.after);
+    }
+
+    #[test]
+    fn encode_b3dm_writes_padded_tile() {
+        let features = features();
+        let ids = [0, 1, 2];
+        let heights = vec![12., 3., 5.];
+        let mut stats = MeshStats::default();
+        let meshes = build_meshes(&features, &ids, &heights, &[None; 3], &options(), 1, &mut stats);
+        let count: usize = meshes.iter().map(|x| x.vertex.len()).sum();
+        let records: Vec<&Record> = features.iter().map(|(_, record)| record).collect();
+        let table = batch_table(&ids, heights, &records);
+        let content = encode_b3dm(meshes, &table, &Compression::None, ColorMode::default(), None, 1);
+
+        let data = &content.data;
+        let header = |k: usize| u32::from_le_bytes(data[k * 4..k * 4 + 4].try_into().unwrap()) as usize;
+        assert_eq!(&data[..4], b"b3dm");
+        assert_eq!(header(1), 1);
+        assert_eq!(header(2), data.len());
+        assert_eq!(data.len() % 8, 0);
+        let feature_json = &data[28..28 + header(3)];
+        let feature: serde_json::Value = serde_json::from_slice(feature_json).unwrap();
+        assert_eq!(feature["BATCH_LENGTH"], 3);
+        let start = 28 + header(3) + header(4);
+        let batch: serde_json::Value = serde_json::from_slice(&data[start..start + header(5)]).unwrap();
+        assert_eq!(batch["batchId"], serde_json::json!([0, 1, 2]));
+        assert_eq!(batch["name"][1], "mesh_1");
+        // 每一段都从8字节对齐的位置开始，glb 紧跟在批量表后面
+        let glb = start + header(5) + header(6);
+        assert_eq!(glb % 8, 0);
+        assert_eq!(&data[glb..glb + 4], b"glTF");
+        assert_eq!(content.vertices.len(), count);
+        assert_eq!(content.margin, 0.);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::field;
+use crate::index;
+use crate::shptiff::ShpTiff;
+use crate::tileset::{self, Bounds};
+use byteorder::{LittleEndian, WriteBytesExt};
+use gltf::Error;
+use serde::{Deserialize, Serialize};
+use shapefile::dbase;
+use std::io;
+
+// pnts 各部分需要按8字节对齐
+fn align_to_multiple_of_eight(n: &mut usize) {
+    *n = (*n + 7) & !7;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BinaryBodyReference {
+    #[serde(rename = "byteOffset")]
+    pub byte_offset: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[repr(C)]
+pub struct FeatureTable {
+    #[serde(rename = "POINTS_LENGTH")]
+    pub points_length: u32,
+    #[serde(rename = "RTC_CENTER")]
+    pub rtc_center: [f64; 3],
+    #[serde(rename = "POSITION")]
+    pub position: BinaryBodyReference,
+    #[serde(rename = "RGB", skip_serializing_if = "Option::is_none")]
+    pub rgb: Option<BinaryBodyReference>,
+}
+
+/// 生成要素表及其二进制部分，`positions` 为相对 `rtc_center` 的地心坐标
+pub fn get_feature_table(
+    positions: &[[f32; 3]],
+    colors: Option<&[[u8; 3]]>,
+    rtc_center: [f64; 3],
+) -> (FeatureTable, Vec<u8>) {
+    let mut binary = vec![];
+    positions.iter().flatten().for_each(|v| {
+        binary.write_f32::<LittleEndian>(*v).unwrap();
+    });
+    let rgb = colors.map(|colors| {
+        let byte_offset = binary.len() as u32;
+        colors.iter().flatten().for_each(|v| binary.write_u8(*v).unwrap());
+        BinaryBodyReference { byte_offset }
+    });
+    let feature_table = FeatureTable {
+        points_length: positions.len() as u32,
+        rtc_center,
+        position: BinaryBodyReference { byte_offset: 0 },
+        rgb,
+    };
+    (feature_table, binary)
+}
+
+/// 按 0~1 的取值从蓝经绿到红渐变
+pub fn ramp(t: f64) -> [u8; 3] {
+    let t = if t.is_finite() { t.clamp(0., 1.) } else { 0. };
+    let stops = [[0., 0., 255.], [0., 255., 255.], [0., 255., 0.], [255., 255., 0.], [255., 0., 0.]];
+    let x = t * (stops.len() - 1) as f64;
+    let i = (x.floor() as usize).min(stops.len() - 2);
+    let f = x - i as f64;
+    let (a, b) = (stops[i], stops[i + 1]);
+    [
+        (a[0] + (b[0] - a[0]) * f) as u8,
+        (a[1] + (b[1] - a[1]) * f) as u8,
+        (a[2] + (b[2] - a[2]) * f) as u8,
+    ]
+}
+
+/// 解析 `#RRGGBB` 形式的颜色
+pub fn parse_hex(value: &str) -> Option<[u8; 3]> {
+    let hex = value.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// 把点要素生成点云 pnts，PointZ 直接使用自带的高程，二维点有地形时贴到地形上；与建筑一样先建索引，
+/// 给了内存上限时按估计的占用分成多个瓦片，每个瓦片生成一个 pnts 交给 `write`（uri 和文件内容），
+/// 坐标为相对瓦片中心 RTC_CENTER 的地心坐标；返回各瓦片的 uri 和包围范围，没有点要素时为空
+pub fn get_pnts(
+    filename: &str,
+    shp_tiff: &Option<ShpTiff>,
+    color: Option<&str>,
+    max_memory: Option<usize>,
+    mut write: impl FnMut(&str, &[u8]),
+) -> Vec<(String, Bounds)> {
+    // 颜色按全部点的取值渐变，颜色字段在建索引时留下
+    let fields: Vec<&str> = color.map(|x| x.split(',').map(str::trim).collect()).unwrap_or_default();
+    let index::FeatureIndex {
+        entries,
+        records,
+        origin,
+    } = index::scan_points(filename, shp_tiff, &fields);
+    if entries.is_empty() {
+        return vec![];
+    }
+    let colors = color.map(|spec| point_colors(&records.iter().collect::<Vec<_>>(), spec));
+    drop(records);
+
+    let tiles = index::partition(&entries, max_memory, index::BYTES_PER_CLOUD_POINT);
+    if tiles.len() > 1 {
+        println!("按内存上限分成{}个瓦片", tiles.len());
+    }
+    // 包围盒在整个图层中心处的东北天坐标系中计算，各瓦片共用，根瓦片才能把它们合起来
+    let frame = tileset::enu_frame(origin.0, origin.1, origin.2 as f64);
+    tiles
+        .iter()
+        .enumerate()
+        .map(|(t, tile)| {
+            let mut points: Vec<([f64; 2], f64, usize, dbase::Record)> = vec![];
+            index::read_shapes(filename, &entries, tile)
+                .into_iter()
+                .zip(&tile.entries)
+                .for_each(|((shape, record), i)| {
+                    index::shape_points(&shape).into_iter().for_each(|(p, z)| {
+                        points.push((p, index::point_height(p, z, shp_tiff), *i, record.clone()));
+                    });
+                });
+            let mut extent = [f64::MAX, f64::MAX, f64::MIN, f64::MIN, f64::MAX];
+            points.iter().for_each(|(p, h, _, _)| {
+                extent = [
+                    extent[0].min(p[0]),
+                    extent[1].min(p[1]),
+                    extent[2].max(p[0]),
+                    extent[3].max(p[1]),
+                    extent[4].min(*h),
+                ];
+            });
+            let (cx, cy) = ((extent[0] + extent[2]) / 2., (extent[1] + extent[3]) / 2.);
+
+            let rtc_center = tileset::cartographic_to_ecef(cx, cy, extent[4]);
+            let positions: Vec<[f32; 3]> = points
+                .iter()
+                .map(|(p, h, _, _)| {
+                    let ecef = tileset::cartographic_to_ecef(p[0], p[1], *h);
+                    [0, 1, 2].map(|k| (ecef[k] - rtc_center[k]) as f32)
+                })
+                .collect();
+            // 点的坐标是 f32，按写出的值计算包围范围，留出舍入误差
+            let local: Vec<[f64; 3]> = positions
+                .iter()
+                .map(|p| tileset::to_local(&frame, [0, 1, 2].map(|k| rtc_center[k] + p[k] as f64)))
+                .collect();
+            let bounds = Bounds::new(&local, &frame, 0.).unwrap();
+            let records: Vec<&dbase::Record> = points.iter().map(|(_, _, _, record)| record).collect();
+            let tile_colors: Option<Vec<[u8; 3]>> =
+                colors.as_ref().map(|colors| points.iter().map(|(_, _, i, _)| colors[*i]).collect());
+
+            let (feature_table, feature_binary) = get_feature_table(&positions, tile_colors.as_deref(), rtc_center);
+            let test1 = serde_json::to_string(&field::batch_columns(&records)).expect("Serialization error");
+            let test2 = serde_json::to_string(&feature_table).expect("Serialization error");
+            let mut data = vec![];
+            to_writer(&mut data, test2.into_bytes(), feature_binary, test1.into_bytes())
+                .expect("glTF binary output error");
+            let uri = format!("{}.pnts", t);
+            write(&uri, &data);
+            (uri, bounds)
+        })
+        .collect()
+}
+
+/// 点的颜色：`R,G,B` 三个字段直接取值；单个字段为数值时按最小到最大值渐变，为文本时按 `#RRGGBB` 解析，
+/// 取不到的点为白色
+pub fn point_colors(records: &[&dbase::Record], spec: &str) -> Vec<[u8; 3]> {
+    let white = [255, 255, 255];
+    let names: Vec<&str> = spec.split(',').map(str::trim).collect();
+    if names.len() == 3 {
+        return records
+            .iter()
+            .map(|record| {
+                let mut rgb = white;
+                names.iter().enumerate().for_each(|(i, name)| {
+                    if let Some(x) = field::get_number(record, name) {
+                        rgb[i] = x.clamp(0., 255.) as u8;
+                    }
+                });
+                rgb
+            })
+            .collect();
+    }
+    let values: Vec<Option<f64>> = records.iter().map(|record| field::get_number(record, spec)).collect();
+    let min = values.iter().flatten().cloned().fold(f64::MAX, f64::min);
+    let max = values.iter().flatten().cloned().fold(f64::MIN, f64::max);
+    if min <= max {
+        return values
+            .iter()
+            .map(|x| match x {
+                Some(x) if max > min => ramp((x - min) / (max - min)),
+                Some(_) => ramp(0.),
+                None => white,
+            })
+            .collect();
+    }
+    records
+        .iter()
+        .map(|record| {
+            field::get_text(record, spec)
+                .and_then(|x| parse_hex(&x))
+                .unwrap_or(white)
+        })
+        .collect()
+}
+
+//生成pnts文件
+pub fn to_writer<W>(
+    mut writer: W,
+    feature_string_vec: Vec<u8>,
+    feature_binary_vec: Vec<u8>,
+    table_string_vec: Vec<u8>,
+) -> Result<(), Error>
+where
+    W: io::Write,
+{
+    // 要素表 JSON 的结尾要让二进制部分从8字节对齐的位置开始
+    let mut feature_table_json_byte_length = 28 + feature_string_vec.len();
+    align_to_multiple_of_eight(&mut feature_table_json_byte_length);
+    feature_table_json_byte_length -= 28;
+    let mut feature_table_binary_byte_length = feature_binary_vec.len();
+    align_to_multiple_of_eight(&mut feature_table_binary_byte_length);
+    let mut batch_table_json_byte_length = table_string_vec.len();
+    align_to_multiple_of_eight(&mut batch_table_json_byte_length);
+
+    // 写入pnts文件头部
+    {
+        let magic = b"pnts";
+        let version = 1;
+        let length = 28
+            + feature_table_json_byte_length
+            + feature_table_binary_byte_length
+            + batch_table_json_byte_length;
+
+        writer.write_all(&magic[..])?;
+        writer.write_u32::<LittleEndian>(version)?;
+        writer.write_u32::<LittleEndian>(length as u32)?;
+        writer.write_u32::<LittleEndian>(feature_table_json_byte_length as u32)?;
+        writer.write_u32::<LittleEndian>(feature_table_binary_byte_length as u32)?;
+        writer.write_u32::<LittleEndian>(batch_table_json_byte_length as u32)?;
+        //批量表全部写在 JSON 中，二进制为空
+        writer.write_u32::<LittleEndian>(0)?;
+    }
+
+    for (data, length, pad) in [
+        (&feature_string_vec, feature_table_json_byte_length, 0x20),
+        (&feature_binary_vec, feature_table_binary_byte_length, 0),
+        (&table_string_vec, batch_table_json_byte_length, 0x20),
+    ] {
+        writer.write_all(data)?;
+        for _ in 0..length - data.len() {
+            writer.write_u8(pad)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shapefile::dbase::{FieldValue, Record, TableWriterBuilder};
+    use std::convert::TryInto;
+
+    fn record(values: &[(&str, FieldValue)]) -> Record {
+        let mut record = Record::default();
+        values.iter().for_each(|(name, value)| {
+            record.insert(name.to_string(), value.clone());
+        });
+        record
+    }
+
+    fn header(data: &[u8], k: usize) -> usize {
+        u32::from_le_bytes(data[k * 4..k * 4 + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn point_colors_from_fields() {
+        let n = |x: f64| FieldValue::Numeric(Some(x));
+        let a = record(&[("r", n(300.)), ("g", n(10.)), ("v", n(5.)), ("c", FieldValue::Character(Some("#ff8000".into())))]);
+        let b = record(&[("r", n(-1.)), ("b", n(7.)), ("v", n(15.)), ("c", FieldValue::Character(Some("red".into())))]);
+        let c = record(&[("v", FieldValue::Numeric(None))]);
+        let records = [&a, &b, &c];
+        // 三个字段分别取 R、G、B，超出范围的截断，缺的通道为255
+        assert_eq!(point_colors(&records, "r, g, b"), [[255, 10, 255], [0, 255, 7], [255, 255, 255]]);
+        // 数值字段从最小到最大渐变，取不到的为白色
+        assert_eq!(point_colors(&records, "v"), [ramp(0.), ramp(1.), [255, 255, 255]]);
+        assert_eq!(point_colors(&records[..1], "v"), [ramp(0.)]);
+        // 文本字段按 #RRGGBB 解析
+        assert_eq!(point_colors(&records, "c"), [[255, 128, 0], [255, 255, 255], [255, 255, 255]]);
+    }
+
+    #[test]
+    fn get_pnts_writes_one_tile_per_partition() {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_pnts");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cloud.shp");
+        let table = TableWriterBuilder::new().add_numeric_field("v".try_into().unwrap(), 10, 2);
+        let writer = shapefile::Writer::from_path(&path, table).unwrap();
+        let points: Vec<shapefile::PointZ> = (0..40)
+            .map(|i| shapefile::PointZ::new(116.39 + (i % 8) as f64 * 1e-4, 39.9 + (i / 8) as f64 * 1e-4, i as f64, 0.))
+            .collect();
+        let records: Vec<Record> = (0..40).map(|i| record(&[("v", FieldValue::Numeric(Some(i as f64)))])).collect();
+        writer.write_shapes_and_records(points.iter().zip(records.iter())).unwrap();
+
+        let mut written = vec![];
+        let budget = 12 * index::BYTES_PER_CLOUD_POINT;
+        let children = get_pnts(path.to_str().unwrap(), &None, Some("v"), Some(budget), |uri, data| {
+            written.push((uri.to_string(), data.to_vec()))
+        });
+        assert!(children.len() > 1);
+        assert_eq!(children.iter().map(|(uri, _)| uri).collect::<Vec<_>>(), written.iter().map(|(uri, _)| uri).collect::<Vec<_>>());
+        let mut count = 0;
+        for ((uri, data), (_, bounds)) in written.iter().zip(&children) {
+            assert_eq!(&data[..4], b"pnts", "{}", uri);
+            assert_eq!(header(data, 2), data.len());
+            assert_eq!(data.len() % 8, 0);
+            let json = &data[28..28 + header(data, 3)];
+            assert_eq!((28 + json.len()) % 8, 0);
+            let feature: serde_json::Value = serde_json::from_slice(json).unwrap();
+            let n = feature["POINTS_LENGTH"].as_u64().unwrap() as usize;
+            assert!(n <= 12);
+            assert_eq!(feature["RGB"]["byteOffset"], 12 * n);
+            let center: Vec<f64> = feature["RTC_CENTER"].as_array().unwrap().iter().map(|x| x.as_f64().unwrap()).collect();
+            let binary = &data[28 + json.len()..];
+            let batch_start = 28 + header(data, 3) + header(data, 4);
+            let batch: serde_json::Value = serde_json::from_slice(&data[batch_start..batch_start + header(data, 5)]).unwrap();
+            // 每个点换回经纬度后与输入的点一致，落在瓦片的范围内，颜色按 v 渐变
+            for i in 0..n {
+                let p: Vec<f64> = (0..3)
+                    .map(|k| f32::from_le_bytes(binary[i * 12 + k * 4..i * 12 + k * 4 + 4].try_into().unwrap()) as f64)
+                    .collect();
+                let [lon, lat, h] = tileset::ecef_to_cartographic([0, 1, 2].map(|k| center[k] + p[k]));
+                let v = batch["v"][i].as_f64().unwrap();
+                let j = v as usize;
+                assert!((lon - points[j].x).abs() < 1e-7 && (lat - points[j].y).abs() < 1e-7 && (h - v).abs() < 1e-2);
+                let r = bounds.region;
+                assert!(lon >= r[0] && lon <= r[2] && lat >= r[1] && lat <= r[3] && h >= r[4] && h <= r[5]);
+                assert_eq!(binary[12 * n + i * 3..12 * n + i * 3 + 3], ramp(v / 39.));
+            }
+            count += n;
+        }
+        assert_eq!(count, 40);
+
+        let empty = dir.join("empty.shp");
+        let table = TableWriterBuilder::new().add_numeric_field("v".try_into().unwrap(), 10, 2);
+        shapefile::Writer::from_path(&empty, table)
+            .unwrap()
+            .write_shapes_and_records(std::iter::empty::<(&shapefile::PointZ, &Record)>())
+            .unwrap();
+        assert!(get_pnts(empty.to_str().unwrap(), &None, None, None, |_, _| panic!()).is_empty());
+    }
+
+    #[test]
+    fn sections_are_padded_to_eight_bytes() {
+        let mut data = vec![];
+        to_writer(&mut data, b"{\"a\":1}".to_vec(), vec![1; 15], b"{}".to_vec()).unwrap();
+        // 28字节的文件头之后，要素表 JSON 补空格到第40字节，二进制补0到16字节，批量表 JSON 补空格到8字节
+        assert_eq!((1..7).map(|k| header(&data, k)).collect::<Vec<_>>(), [1, 64, 12, 16, 8, 0]);
+        assert_eq!(data.len(), 64);
+        assert_eq!(&data[28..40], b"{\"a\":1}     ");
+        assert_eq!(&data[40..55], [1; 15]);
+        assert_eq!(data[55], 0);
+        assert_eq!(&data[56..], b"{}      ");
+    }
+
+    #[test]
+    fn feature_table_layout() {
+        let positions = [[1., 2., 3.], [-4., 5.5, 6.]];
+        let (table, binary) = get_feature_table(&positions, None, [1e6, 2e6, 3e6]);
+        assert_eq!(
+            serde_json::to_value(&table).unwrap(),
+            serde_json::json!({"POINTS_LENGTH": 2, "RTC_CENTER": [1e6, 2e6, 3e6], "POSITION": {"byteOffset": 0}})
+        );
+        assert_eq!(binary.len(), 24);
+        assert_eq!(&binary[12..16], (-4f32).to_le_bytes());
+
+        // 颜色紧跟在位置之后，每个点3个字节
+        let (table, binary) = get_feature_table(&positions, Some(&[[1, 2, 3], [4, 5, 6]]), [0.; 3]);
+        assert_eq!(table.rgb.unwrap().byte_offset, 24);
+        assert_eq!(&binary[24..], [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn ramp_and_hex_colors() {
+        assert_eq!(ramp(0.), [0, 0, 255]);
+        assert_eq!(ramp(0.25), [0, 255, 255]);
+        assert_eq!(ramp(0.5), [0, 255, 0]);
+        assert_eq!(ramp(0.625), [127, 255, 0]);
+        assert_eq!(ramp(1.), [255, 0, 0]);
+        // 超出范围的截断，NaN 取起点
+        assert_eq!(ramp(2.), [255, 0, 0]);
+        assert_eq!(ramp(f64::NAN), [0, 0, 255]);
+        assert_eq!(parse_hex(" #FF8000 "), Some([255, 128, 0]));
+        assert_eq!(parse_hex("ff8000"), None);
+        assert_eq!(parse_hex("#ff800"), None);
+        assert_eq!(parse_hex("#ff80zz"), None);
+    }
+}
@@ -0,0 +1,569 @@
+//! 按 OSM Simple 3D Buildings 的约定生成屋顶
+//!
+//! 建筑总高度包含屋顶，墙体只建到 `height - roof_height`，屋顶再从墙顶往上生成。
+use crate::field::{get_number, get_text};
+use crate::skeleton::straight_skeleton;
+use earcutr::earcut;
+use shapefile::dbase;
+
+// 圆顶沿高度方向的分段数
+const DOME_STEPS: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoofShape {
+    Flat,
+    Gabled,
+    Hipped,
+    Pyramidal,
+    Skillion,
+    Dome,
+}
+
+impl RoofShape {
+    /// 解析 `roof_shape` 字段的值，无法识别的按平顶处理
+    pub fn parse(value: &str) -> RoofShape {
+        match value.trim().to_lowercase().as_str() {
+            "gabled" | "gable" => RoofShape::Gabled,
+            "hipped" | "hip" => RoofShape::Hipped,
+            "pyramidal" | "pyramid" => RoofShape::Pyramidal,
+            "skillion" | "shed" => RoofShape::Skillion,
+            "dome" => RoofShape::Dome,
+            _ => RoofShape::Flat,
+        }
+    }
+}
+
+pub struct Roof {
+    pub shape: RoofShape,
+    pub height: f64,
+    // 屋面朝向，正北为0度顺时针，单坡屋顶从高处指向低处
+    pub direction: Option<f64>,
+}
+
+impl Roof {
+    pub fn flat() -> Roof {
+        Roof {
+            shape: RoofShape::Flat,
+            height: 0.,
+            direction: None,
+        }
+    }
+
+    /// 从属性表中读取屋顶形状、屋顶高度和朝向，缺少高度的坡屋顶退化为平顶
+    pub fn from_record(
+        record: &dbase::Record,
+        shape_field: &str,
+        height_field: &str,
+        direction_field: &str,
+    ) -> Roof {
+        let shape = get_text(record, shape_field)
+            .map(|x| RoofShape::parse(&x))
+            .unwrap_or(RoofShape::Flat);
+        let height = get_number(record, height_field).unwrap_or(0.);
+        if shape == RoofShape::Flat || height <= 0. {
+            return Roof::flat();
+        }
+        Roof {
+            shape,
+            height,
+            direction: get_number(record, direction_field),
+        }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.shape == RoofShape::Flat
+    }
+
+    /// 屋顶高度不超过 `max`（墙底到建筑顶的高度），整栋只有屋顶时总高度仍为建筑高度；`max` 不大于0时为平顶
+    pub fn clamp(&self, max: f64) -> Roof {
+        if self.is_flat() || max <= 0. {
+            return Roof::flat();
+        }
+        Roof {
+            height: self.height.min(max),
+            ..*self
+        }
+    }
+
+    /// 根据外环生成屋顶三角面，`base` 为墙顶高度，三角面按逆时针（法向朝外）给出；`id` 为要素序号，只用于日志
+    pub fn build(&self, ring: &[[f64; 2]], base: f64, id: &str) -> Vec<[[f64; 3]; 3]> {
+        let ring = clean_ring(ring);
+        if ring.len() < 3 {
+            return vec![];
+        }
+        match self.shape {
+            RoofShape::Flat => vec![],
+            RoofShape::Gabled => self.skeleton_roof(&ring, base, true, id),
+            RoofShape::Hipped => self.skeleton_roof(&ring, base, false, id),
+            RoofShape::Pyramidal | RoofShape::Dome => match kernel_point(&ring) {
+                Some(c) if self.shape == RoofShape::Pyramidal => self.pyramidal(&ring, base, c),
+                Some(c) => self.dome(&ring, base, c),
+                // 从外环内任何一点都看不全所有的边（如U形），顶点放在哪里屋面都会穿出去
+                None => {
+                    let name = if self.shape == RoofShape::Dome { "圆顶" } else { "攒尖顶" };
+                    println!("要素{}的外环内找不到能看到所有边的点，{}改为四坡屋顶", id, name);
+                    self.skeleton_roof(&ring, base, false, id)
+                }
+            },
+            RoofShape::Skillion => self.skillion(&ring, base),
+        }
+    }
+
+    fn skeleton_roof(&self, ring: &[[f64; 2]], base: f64, gable: bool, id: &str) -> Vec<[[f64; 3]; 3]> {
+        let skeleton = match straight_skeleton(ring) {
+            Some(x) => x,
+            None => {
+                let name = if gable { "双坡" } else { "四坡" };
+                println!("要素{}的直骨架没能闭合，{}屋顶改为攒尖顶", id, name);
+                return self.pyramidal(ring, base, centroid(ring));
+            }
+        };
+        let max_time = skeleton.nodes.iter().fold(0., |m: f64, n| m.max(n.time));
+        if max_time <= 0. {
+            return vec![];
+        }
+        let mut points: Vec<[f64; 3]> = skeleton
+            .nodes
+            .iter()
+            .map(|n| [n.point[0], n.point[1], base + self.height * n.time / max_time])
+            .collect();
+
+        if gable {
+            // 三角形的面是山墙：把它的顶点水平移到对应的外墙线上，使其立起来
+            let mut apex_count = vec![0; points.len()];
+            skeleton
+                .faces
+                .iter()
+                .filter(|f| f.len() == 3)
+                .for_each(|f| apex_count[f[2]] += 1);
+            skeleton.faces.iter().filter(|f| f.len() == 3).for_each(|f| {
+                if apex_count[f[2]] == 1 && f[2] >= ring.len() {
+                    let (a, b, c) = (points[f[0]], points[f[1]], points[f[2]]);
+                    let d = [b[0] - a[0], b[1] - a[1]];
+                    let len2 = d[0] * d[0] + d[1] * d[1];
+                    let s = ((c[0] - a[0]) * d[0] + (c[1] - a[1]) * d[1]) / len2;
+                    points[f[2]] = [a[0] + s * d[0], a[1] + s * d[1], c[2]];
+                }
+            });
+        }
+
+        let mut triangles = vec![];
+        for face in &skeleton.faces {
+            if face.len() == 3 {
+                triangles.push([points[face[0]], points[face[1]], points[face[2]]]);
+                continue;
+            }
+            let flat: Vec<f64> = face
+                .iter()
+                .flat_map(|&i| [points[i][0], points[i][1]])
+                .collect();
+            earcut(&flat, &[], 2).chunks(3).for_each(|t| {
+                triangles.push(upward([points[face[t[0]]], points[face[t[1]]], points[face[t[2]]]]));
+            });
+        }
+        triangles
+    }
+
+    // `c` 为顶点的平面位置
+    fn pyramidal(&self, ring: &[[f64; 2]], base: f64, c: [f64; 2]) -> Vec<[[f64; 3]; 3]> {
+        let apex = [c[0], c[1], base + self.height];
+        let n = ring.len();
+        (0..n)
+            .map(|i| {
+                let (a, b) = (ring[i], ring[(i + 1) % n]);
+                [[a[0], a[1], base], [b[0], b[1], base], apex]
+            })
+            .collect()
+    }
+
+    fn skillion(&self, ring: &[[f64; 2]], base: f64) -> Vec<[[f64; 3]; 3]> {
+        let n = ring.len();
+        let dir = match self.direction {
+            Some(deg) => [deg.to_radians().sin(), deg.to_radians().cos()],
+            None => {
+                // 未给出朝向时以最长边为高边，向内侧倾斜
+                let i = (0..n)
+                    .max_by(|&i, &j| {
+                        let li = dist2(ring[i], ring[(i + 1) % n]);
+                        let lj = dist2(ring[j], ring[(j + 1) % n]);
+                        li.partial_cmp(&lj).unwrap()
+                    })
+                    .unwrap();
+                let (a, b) = (ring[i], ring[(i + 1) % n]);
+                let len = dist2(a, b).sqrt();
+                [-(b[1] - a[1]) / len, (b[0] - a[0]) / len]
+            }
+        };
+        let proj: Vec<f64> = ring.iter().map(|p| p[0] * dir[0] + p[1] * dir[1]).collect();
+        let min = proj.iter().cloned().fold(f64::MAX, f64::min);
+        let max = proj.iter().cloned().fold(f64::MIN, f64::max);
+        if max - min <= 0. {
+            return vec![];
+        }
+        let points: Vec<[f64; 3]> = ring
+            .iter()
+            .zip(&proj)
+            .map(|(p, d)| [p[0], p[1], base + self.height * (max - d) / (max - min)])
+            .collect();
+
+        let flat: Vec<f64> = ring.iter().flat_map(|p| [p[0], p[1]]).collect();
+        let mut triangles: Vec<[[f64; 3]; 3]> = earcut(&flat, &[], 2)
+            .chunks(3)
+            .map(|t| upward([points[t[0]], points[t[1]], points[t[2]]]))
+            .collect();
+        // 屋面与墙顶之间的竖直墙面
+        for i in 0..n {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            let (a0, b0) = ([a[0], a[1], base], [b[0], b[1], base]);
+            if b[2] > base {
+                triangles.push([a0, b0, b]);
+            }
+            if a[2] > base {
+                triangles.push([a0, b, a]);
+            }
+        }
+        triangles
+    }
+
+    // 各层把外环向 `c` 缩放，`c` 能看到所有的边时每一层都在外环以内
+    fn dome(&self, ring: &[[f64; 2]], base: f64, c: [f64; 2]) -> Vec<[[f64; 3]; 3]> {
+        let n = ring.len();
+        let level = |k: usize| -> Vec<[f64; 3]> {
+            let angle = k as f64 / DOME_STEPS as f64 * std::f64::consts::FRAC_PI_2;
+            let (s, z) = (angle.cos(), base + self.height * angle.sin());
+            ring.iter()
+                .map(|p| [c[0] + (p[0] - c[0]) * s, c[1] + (p[1] - c[1]) * s, z])
+                .collect()
+        };
+        let apex = [c[0], c[1], base + self.height];
+        let mut triangles = vec![];
+        let mut lower = level(0);
+        for k in 1..DOME_STEPS {
+            let upper = level(k);
+            for i in 0..n {
+                let j = (i + 1) % n;
+                triangles.push([lower[i], lower[j], upper[j]]);
+                triangles.push([lower[i], upper[j], upper[i]]);
+            }
+            lower = upper;
+        }
+        for i in 0..n {
+            triangles.push([lower[i], lower[(i + 1) % n], apex]);
+        }
+        triangles
+    }
+}
+
+fn dist2(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)
+}
+
+fn signed_area(ring: &[[f64; 2]]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % n]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f64>()
+        / 2.
+}
+
+fn centroid(ring: &[[f64; 2]]) -> [f64; 2] {
+    let n = ring.len();
+    let area = signed_area(ring);
+    if area.abs() < 1e-12 {
+        let sum = ring.iter().fold([0., 0.], |s, p| [s[0] + p[0], s[1] + p[1]]);
+        return [sum[0] / n as f64, sum[1] / n as f64];
+    }
+    let mut c = [0., 0.];
+    for i in 0..n {
+        let (a, b) = (ring[i], ring[(i + 1) % n]);
+        let f = a[0] * b[1] - b[0] * a[1];
+        c[0] += (a[0] + b[0]) * f;
+        c[1] += (a[1] + b[1]) * f;
+    }
+    [c[0] / (6. * area), c[1] / (6. * area)]
+}
+
+// 外环（逆时针）的核中的一点，从这里能看到所有的边，攒尖顶和圆顶的顶点放在这里：
+// 形心在核内时取形心，否则用每条边左侧的半平面依次裁剪外环得到核，取核的形心；核为空时为 None
+fn kernel_point(ring: &[[f64; 2]]) -> Option<[f64; 2]> {
+    let n = ring.len();
+    let side = |i: usize, p: [f64; 2]| {
+        let (a, b) = (ring[i], ring[(i + 1) % n]);
+        (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+    };
+    let c = centroid(ring);
+    if (0..n).all(|i| side(i, c) > 1e-9 * dist2(ring[i], ring[(i + 1) % n])) {
+        return Some(c);
+    }
+    let mut kernel = ring.to_vec();
+    for i in 0..n {
+        let m = kernel.len();
+        let mut clipped = vec![];
+        for k in 0..m {
+            let (p, q) = (kernel[k], kernel[(k + 1) % m]);
+            let (sp, sq) = (side(i, p), side(i, q));
+            if sp >= 0. {
+                clipped.push(p);
+            }
+            if (sp >= 0.) != (sq >= 0.) {
+                let t = sp / (sp - sq);
+                clipped.push([p[0] + (q[0] - p[0]) * t, p[1] + (q[1] - p[1]) * t]);
+            }
+        }
+        kernel = clipped;
+        if kernel.len() < 3 {
+            return None;
+        }
+    }
+    // 核只剩一条线或一个点时顶点会落在边上
+    if signed_area(&kernel) < 1e-9 * signed_area(ring) {
+        return None;
+    }
+    Some(centroid(&kernel))
+}
+
+// 去掉闭合点、重复点和共线点，并统一为逆时针
+fn clean_ring(ring: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let mut points: Vec<[f64; 2]> = vec![];
+    for p in ring {
+        if points.last().is_none_or(|q| dist2(*p, *q) > 1e-12) {
+            points.push(*p);
+        }
+    }
+    while points.len() > 1 && dist2(points[0], points[points.len() - 1]) <= 1e-12 {
+        points.pop();
+    }
+    let mut changed = true;
+    while changed && points.len() >= 3 {
+        changed = false;
+        let n = points.len();
+        for i in 0..n {
+            let (a, b, c) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            let cross = (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0]);
+            if cross.abs() < 1e-9 * dist2(a, c).sqrt().max(1.) {
+                points.remove(i);
+                changed = true;
+                break;
+            }
+        }
+    }
+    if signed_area(&points) < 0. {
+        points.reverse();
+    }
+    points
+}
+
+// 保证三角面法向朝上
+fn upward(t: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let u = [t[1][0] - t[0][0], t[1][1] - t[0][1]];
+    let v = [t[2][0] - t[0][0], t[2][1] - t[0][1]];
+    if u[0] * v[1] - u[1] * v[0] < 0. {
+        [t[0], t[2], t[1]]
+    } else {
+        t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const BASE: f64 = 12.;
+    const HEIGHT: f64 = 4.;
+
+    fn rectangle() -> Vec<[f64; 2]> {
+        vec![[0., 0.], [12., 0.], [12., 8.], [0., 8.]]
+    }
+
+    fn l_shape() -> Vec<[f64; 2]> {
+        vec![[0., 0.], [10., 0.], [10., 4.], [4., 4.], [4., 10.], [0., 10.]]
+    }
+
+    // 长臂很长的L形，形心不在核内，核为左下角 2×2 的正方形
+    fn long_l() -> Vec<[f64; 2]> {
+        vec![[0., 0.], [20., 0.], [20., 2.], [2., 2.], [2., 4.], [0., 4.]]
+    }
+
+    // U形，从里面任何一点都看不全所有的边
+    fn u_shape() -> Vec<[f64; 2]> {
+        vec![[0., 0.], [12., 0.], [12., 10.], [8., 10.], [8., 4.], [4., 4.], [4., 10.], [0., 10.]]
+    }
+
+    fn roof(shape: RoofShape) -> Roof {
+        Roof {
+            shape,
+            height: HEIGHT,
+            direction: None,
+        }
+    }
+
+    fn normal(t: &[[f64; 3]; 3]) -> [f64; 3] {
+        let u = [t[1][0] - t[0][0], t[1][1] - t[0][1], t[1][2] - t[0][2]];
+        let v = [t[2][0] - t[0][0], t[2][1] - t[0][1], t[2][2] - t[0][2]];
+        [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]]
+    }
+
+    // 屋顶加上墙顶高度处朝下的底面应该是封闭的：每条有向边都正好有一条反向的边与它配对
+    fn assert_watertight(triangles: &[[[f64; 3]; 3]], ring: &[[f64; 2]]) {
+        let flat: Vec<f64> = ring.iter().flat_map(|p| [p[0], p[1]]).collect();
+        let cap = earcut(&flat, &[], 2)
+            .chunks(3)
+            .map(|t| [t[0], t[2], t[1]].map(|i| [ring[i][0], ring[i][1], BASE]))
+            .collect::<Vec<_>>();
+        let key = |p: [f64; 3]| p.map(|x| (x * 1e6).round() as i64);
+        let mut edges: HashMap<([i64; 3], [i64; 3]), i32> = HashMap::new();
+        triangles
+            .iter()
+            .chain(&cap)
+            .filter(|t| normal(t).iter().map(|x| x * x).sum::<f64>() > 1e-12)
+            .for_each(|t| {
+                (0..3).for_each(|k| *edges.entry((key(t[k]), key(t[(k + 1) % 3]))).or_default() += 1);
+            });
+        edges.iter().for_each(|((a, b), n)| {
+            assert_eq!(edges.get(&(*b, *a)), Some(n), "边{:?}→{:?}出现{}次，反向的边数不同", a, b, n);
+        });
+    }
+
+    // 屋脊正好在墙顶之上 `height` 处，所有点都在墙顶和屋脊之间，屋面不朝下
+    fn check(shape: RoofShape, ring: &[[f64; 2]]) -> Vec<[[f64; 3]; 3]> {
+        let triangles = roof(shape).build(ring, BASE, "0");
+        assert!(!triangles.is_empty(), "{:?}没有生成屋顶", shape);
+        let points = triangles.iter().flatten();
+        let top = points.clone().map(|p| p[2]).fold(f64::MIN, f64::max);
+        let bottom = points.map(|p| p[2]).fold(f64::MAX, f64::min);
+        assert!((top - BASE - HEIGHT).abs() < 1e-9, "{:?}屋脊高{}", shape, top);
+        assert!((bottom - BASE).abs() < 1e-9, "{:?}最低点{}", shape, bottom);
+        triangles.iter().for_each(|t| assert!(normal(t)[2] > -1e-9, "{:?}有朝下的面{:?}", shape, t));
+        assert_watertight(&triangles, ring);
+        triangles
+    }
+
+    // 屋脊上的点
+    fn ridge(triangles: &[[[f64; 3]; 3]]) -> Vec<[f64; 3]> {
+        let mut points: Vec<[f64; 3]> = vec![];
+        triangles.iter().flatten().filter(|p| (p[2] - BASE - HEIGHT).abs() < 1e-9).for_each(|p| {
+            if !points.iter().any(|q| (0..3).all(|k| (p[k] - q[k]).abs() < 1e-6)) {
+                points.push(*p);
+            }
+        });
+        points
+    }
+
+    fn vertical(triangles: &[[[f64; 3]; 3]]) -> usize {
+        triangles.iter().filter(|t| normal(t)[2].abs() < 1e-9).count()
+    }
+
+    #[test]
+    fn gabled_roof() {
+        let triangles = check(RoofShape::Gabled, &rectangle());
+        // 屋脊沿长边方向贯通整栋，两端是竖直的山墙
+        let mut ridge = ridge(&triangles);
+        ridge.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+        assert_eq!(ridge, vec![[0., 4., 16.], [12., 4., 16.]]);
+        assert_eq!(vertical(&triangles), 2);
+        let triangles = check(RoofShape::Gabled, &l_shape());
+        assert_eq!(vertical(&triangles), 2);
+    }
+
+    #[test]
+    fn hipped_roof() {
+        let triangles = check(RoofShape::Hipped, &rectangle());
+        let mut ridge = ridge(&triangles);
+        ridge.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+        // 屋脊两端离短边各半个宽度
+        assert_eq!(ridge.len(), 2);
+        for (p, expected) in ridge.iter().zip([[4., 4., 16.], [8., 4., 16.]]) {
+            (0..3).for_each(|k| assert!((p[k] - expected[k]).abs() < 1e-9, "屋脊端点{:?}", p));
+        }
+        assert_eq!(vertical(&triangles), 0);
+        let triangles = check(RoofShape::Hipped, &l_shape());
+        assert_eq!(vertical(&triangles), 0);
+    }
+
+    #[test]
+    fn pyramidal_roof() {
+        for ring in [rectangle(), l_shape()] {
+            let triangles = check(RoofShape::Pyramidal, &ring);
+            let c = centroid(&ring);
+            assert_eq!(ridge(&triangles), vec![[c[0], c[1], BASE + HEIGHT]]);
+            assert_eq!(triangles.len(), ring.len());
+        }
+        // 形心不在核内时顶点放在核的形心
+        let triangles = check(RoofShape::Pyramidal, &long_l());
+        let apex = ridge(&triangles);
+        assert_eq!(apex.len(), 1);
+        assert!((apex[0][0] - 1.).abs() < 1e-9 && (apex[0][1] - 1.).abs() < 1e-9, "顶点{:?}", apex);
+    }
+
+    #[test]
+    fn concave_footprint_without_kernel_falls_back_to_hipped() {
+        assert_eq!(kernel_point(&u_shape()), None);
+        for shape in [RoofShape::Pyramidal, RoofShape::Dome] {
+            let triangles = check(shape, &u_shape());
+            assert_eq!(triangles.len(), roof(RoofShape::Hipped).build(&u_shape(), BASE, "0").len());
+            assert!(ridge(&triangles).len() > 1);
+        }
+        let triangles = check(RoofShape::Dome, &long_l());
+        assert_eq!(triangles.len(), long_l().len() * (2 * DOME_STEPS - 1));
+    }
+
+    #[test]
+    fn flat_roof() {
+        assert!(roof(RoofShape::Flat).build(&rectangle(), BASE, "0").is_empty());
+        // 缺少屋顶高度的坡屋顶按平顶处理
+        let mut record = dbase::Record::default();
+        record.insert("shape".to_string(), dbase::FieldValue::Character(Some("gabled".to_string())));
+        let roof = Roof::from_record(&record, "shape", "roof_height", "direction");
+        assert!(roof.is_flat() && roof.height == 0.);
+        record.insert("roof_height".to_string(), dbase::FieldValue::Numeric(Some(3.)));
+        let roof = Roof::from_record(&record, "shape", "roof_height", "direction");
+        assert_eq!((roof.shape, roof.height), (RoofShape::Gabled, 3.));
+        // 屋顶高度截到墙底到建筑顶的高度
+        assert_eq!(roof.clamp(2.).height, 2.);
+        assert_eq!(roof.clamp(5.).height, 3.);
+        assert!(roof.clamp(0.).is_flat());
+    }
+
+    #[test]
+    fn skillion_roof() {
+        // 没有朝向时最长边为高边
+        let ridge_y: Vec<f64> = ridge(&check(RoofShape::Skillion, &rectangle())).iter().map(|p| p[1]).collect();
+        assert!(ridge_y.len() == 2 && (ridge_y.iter().all(|y| *y == 0.) || ridge_y.iter().all(|y| *y == 8.)));
+        check(RoofShape::Skillion, &l_shape());
+        // 朝向正北时南边最高
+        let north = Roof {
+            direction: Some(0.),
+            ..roof(RoofShape::Skillion)
+        };
+        let triangles = north.build(&l_shape(), BASE, "0");
+        assert!(ridge(&triangles).iter().all(|p| p[1] == 0.));
+        assert_watertight(&triangles, &l_shape());
+    }
+
+    #[test]
+    fn dome_roof() {
+        for ring in [rectangle(), l_shape()] {
+            let triangles = check(RoofShape::Dome, &ring);
+            let ridge = ridge(&triangles);
+            assert_eq!(ridge.len(), 1);
+            let c = centroid(&ring);
+            assert!((ridge[0][0] - c[0]).abs() < 1e-9 && (ridge[0][1] - c[1]).abs() < 1e-9);
+            assert_eq!(triangles.len(), ring.len() * (2 * DOME_STEPS - 1));
+        }
+    }
+
+    #[test]
+    fn clockwise_ring_gives_same_roof() {
+        let mut ring = l_shape();
+        ring.reverse();
+        ring.push(ring[0]);
+        for shape in [RoofShape::Gabled, RoofShape::Hipped, RoofShape::Pyramidal, RoofShape::Skillion, RoofShape::Dome] {
+            assert_eq!(roof(shape).build(&ring, BASE, "0").len(), roof(shape).build(&l_shape(), BASE, "0").len());
+            assert_watertight(&roof(shape).build(&ring, BASE, "0"), &l_shape());
+        }
+    }
+}
@@ -4,7 +4,9 @@ pub struct ShpTiff {
    pub xr: f32,
    pub yr: f32,
    pub px: f32,
-   pub py: f32
+   pub py: f32,
+   pub width: usize,
+   pub height: usize,
 }
 
 impl ShpTiff {
@@ -13,4 +15,57 @@ impl ShpTiff {
         let y = (self.yr - lat) / self.py;
         self.tiff.get_pixel(x.abs() as usize, y.abs() as usize)
     }
-}
\ No newline at end of file
+
+    /// 地形覆盖的经纬度范围 [西, 南, 东, 北]
+    pub fn extent(&self) -> [f64; 4] {
+        let (west, north) = (self.xr as f64, self.yr as f64);
+        let east = west + self.px.abs() as f64 * self.width as f64;
+        let south = north - self.py.abs() as f64 * self.height as f64;
+        [west, south, east, north]
+    }
+
+    /// 范围内的点按 `get_height_by_geo_info` 取高程，落在东、南边界上的点取边上的像素，范围外为 None
+    pub fn sample(&self, lon: f64, lat: f64) -> Option<i32> {
+        let [west, south, east, north] = self.extent();
+        if lon < west || lon > east || lat < south || lat > north {
+            return None;
+        }
+        let lon = lon.min(east - self.px.abs() as f64 / 2.);
+        let lat = lat.max(south + self.py.abs() as f64 / 2.);
+        Some(self.get_height_by_geo_info(lon as f32, lat as f32))
+    }
+}
+
+/// 从 TIFF 文件头读出第一幅图像的宽和高
+pub fn tiff_size(data: &[u8]) -> Option<(usize, usize)> {
+    let big_endian = match data.get(0..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let u16_at = |i: usize| -> Option<u16> {
+        let b = [*data.get(i)?, *data.get(i + 1)?];
+        Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    };
+    let u32_at = |i: usize| -> Option<u32> {
+        let b = [*data.get(i)?, *data.get(i + 1)?, *data.get(i + 2)?, *data.get(i + 3)?];
+        Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    };
+    let ifd = u32_at(4)? as usize;
+    let (mut width, mut height) = (None, None);
+    for i in 0..u16_at(ifd)? as usize {
+        let entry = ifd + 2 + i * 12;
+        // 值为 SHORT 时放在值字段的前两个字节
+        let value = match u16_at(entry + 2)? {
+            3 => u16_at(entry + 8)? as usize,
+            4 => u32_at(entry + 8)? as usize,
+            _ => continue,
+        };
+        match u16_at(entry)? {
+            256 => width = Some(value),
+            257 => height = Some(value),
+            _ => (),
+        }
+    }
+    Some((width?, height?))
+}
@@ -0,0 +1,644 @@
+//! 直骨架（straight skeleton）计算
+//!
+//! 采用 Felkel & Obdržálek 的波前算法，只处理不含内环的简单多边形，
+//! 输入为逆时针的局部米制坐标，用于生成四坡和双坡屋顶。
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+const EPSILON: f64 = 1e-7;
+
+type Point = [f64; 2];
+
+/// 骨架节点，`time` 为波前到达该点的时间，即到原始边的距离
+#[derive(Clone, Copy, Debug)]
+pub struct Node {
+    pub point: Point,
+    pub time: f64,
+}
+
+/// `faces[i]` 为原始多边形第 `i` 条边扫过的面，节点按逆时针排列，前两个节点就是这条边的起止点
+pub struct Skeleton {
+    pub nodes: Vec<Node>,
+    pub faces: Vec<Vec<usize>>,
+}
+
+struct Edge {
+    start: Point,
+    dir: Point,
+}
+
+struct Vertex {
+    point: Point,
+    time: f64,
+    node: usize,
+    edge_left: usize,
+    edge_right: usize,
+    // 波前顶点的移动速度，单位时间内到两条边的距离各增加1，两边反向平行时为 None
+    velocity: Option<Point>,
+    prev: usize,
+    next: usize,
+    valid: bool,
+    version: u32,
+    // 已确认无法命中的分裂边
+    excluded: Vec<usize>,
+}
+
+enum EventKind {
+    Edge { a: usize, b: usize },
+    Split { edge: usize },
+}
+
+struct Event {
+    time: f64,
+    seq: usize,
+    point: Point,
+    vertex: usize,
+    version: u32,
+    kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    // BinaryHeap 为大顶堆，这里反过来让时间最早的事件先出队
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .partial_cmp(&self.time)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn cross(a: Point, b: Point) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn dot(a: Point, b: Point) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn left_normal(d: Point) -> Point {
+    [-d[1], d[0]]
+}
+
+fn velocity(left: &Edge, right: &Edge) -> Option<Point> {
+    let nl = left_normal(left.dir);
+    let nr = left_normal(right.dir);
+    let d = 1. + dot(nl, nr);
+    if d < 1e-6 {
+        return None;
+    }
+    Some([(nl[0] + nr[0]) / d, (nl[1] + nr[1]) / d])
+}
+
+// 点到边所在直线的有向距离，内侧（左侧）为正
+fn distance(edge: &Edge, p: Point) -> f64 {
+    cross(edge.dir, sub(p, edge.start))
+}
+
+// 求 p + s*d 与 q + u*e 的交点参数 (s, u)
+fn ray_intersection(p: Point, d: Point, q: Point, e: Point) -> Option<(f64, f64)> {
+    let den = cross(d, e);
+    if den.abs() < 1e-12 {
+        return None;
+    }
+    let w = sub(q, p);
+    Some((cross(w, e) / den, cross(w, d) / den))
+}
+
+struct Wavefront {
+    edges: Vec<Edge>,
+    nodes: Vec<Node>,
+    vertices: Vec<Vertex>,
+    arcs: Vec<(usize, usize, usize, usize)>,
+    events: BinaryHeap<Event>,
+    seq: usize,
+    // 当前处理到的时刻，早于它的事件都已失效
+    now: f64,
+}
+
+impl Wavefront {
+    fn add_vertex(&mut self, point: Point, time: f64, node: usize, left: usize, right: usize) -> usize {
+        let velocity = velocity(&self.edges[left], &self.edges[right]);
+        self.vertices.push(Vertex {
+            point,
+            time,
+            node,
+            edge_left: left,
+            edge_right: right,
+            velocity,
+            prev: 0,
+            next: 0,
+            valid: true,
+            version: 0,
+            excluded: vec![],
+        });
+        self.vertices.len() - 1
+    }
+
+    fn add_node(&mut self, point: Point, time: f64) -> usize {
+        self.nodes.push(Node { point, time });
+        self.nodes.len() - 1
+    }
+
+    fn add_arc(&mut self, v: usize, node: usize) {
+        let vertex = &self.vertices[v];
+        self.arcs
+            .push((vertex.node, node, vertex.edge_left, vertex.edge_right));
+    }
+
+    fn push_event(&mut self, time: f64, point: Point, vertex: usize, kind: EventKind) {
+        self.seq += 1;
+        self.events.push(Event {
+            time,
+            seq: self.seq,
+            point,
+            vertex,
+            version: self.vertices[vertex].version,
+            kind,
+        });
+    }
+
+    fn edge_event(&self, a: usize, b: usize) -> Option<(f64, Point)> {
+        let va = &self.vertices[a];
+        let vb = &self.vertices[b];
+        let (da, db) = match (va.velocity, vb.velocity) {
+            (Some(da), Some(db)) => (da, db),
+            // 相邻顶点已经坍缩成线时，另一个顶点到达坍缩时刻的位置就是交点
+            (None, Some(_)) => return Some(Self::collapse_event(va, vb)),
+            (Some(_), None) => return Some(Self::collapse_event(vb, va)),
+            (None, None) => {
+                let d = sub(va.point, vb.point);
+                return (dot(d, d) < EPSILON * EPSILON).then_some((f64::max(va.time, vb.time), va.point));
+            }
+        };
+        let (s, u) = match ray_intersection(va.point, da, vb.point, db) {
+            Some(x) => x,
+            None => {
+                // 两条射线平行，只有同一时刻位置重合时才算相遇
+                let time = f64::max(va.time, vb.time);
+                let pa = Self::position(va, time);
+                let pb = Self::position(vb, time);
+                let d = sub(pa, pb);
+                return (dot(d, d) < EPSILON * EPSILON).then_some((time, pa));
+            }
+        };
+        if s < -EPSILON || u < -EPSILON {
+            return None;
+        }
+        let point = [va.point[0] + s * da[0], va.point[1] + s * da[1]];
+        let time = distance(&self.edges[va.edge_right], point);
+        Some((time, point))
+    }
+
+    fn collapse_event(collapsed: &Vertex, moving: &Vertex) -> (f64, Point) {
+        let time = f64::max(collapsed.time, moving.time);
+        (time, Self::position(moving, time))
+    }
+
+    fn position(v: &Vertex, time: f64) -> Point {
+        match v.velocity {
+            Some(d) => [v.point[0] + (time - v.time) * d[0], v.point[1] + (time - v.time) * d[1]],
+            None => v.point,
+        }
+    }
+
+    // 判断 time 时刻的点是否落在 x 与 x.next 之间那段波前边上
+    fn in_segment(&self, x: usize, p: Point, time: f64) -> bool {
+        let vx = &self.vertices[x];
+        let vy = &self.vertices[vx.next];
+        if vx.time > time + EPSILON || vy.time > time + EPSILON {
+            return false;
+        }
+        let dir = self.edges[vx.edge_right].dir;
+        let (px, py) = (Self::position(vx, time), Self::position(vy, time));
+        dot(sub(p, px), dir) >= -EPSILON && dot(sub(py, p), dir) >= -EPSILON
+    }
+
+    fn find_segment(&self, v: usize, edge: usize, p: Point, time: f64) -> Option<usize> {
+        (0..self.vertices.len()).find(|&x| {
+            let vx = &self.vertices[x];
+            vx.valid
+                && vx.edge_right == edge
+                && x != v
+                && vx.next != v
+                && self.in_segment(x, p, time)
+        })
+    }
+
+    fn split_event(&self, v: usize) -> Option<(f64, Point, usize)> {
+        let vertex = &self.vertices[v];
+        let d = vertex.velocity?;
+        let left = &self.edges[vertex.edge_left];
+        let right = &self.edges[vertex.edge_right];
+        // 只有凹点才会产生分裂事件
+        if cross(left.dir, right.dir) >= 0. {
+            return None;
+        }
+        let mut best: Option<(f64, Point, usize)> = None;
+        for (idx, edge) in self.edges.iter().enumerate() {
+            if idx == vertex.edge_left || idx == vertex.edge_right || vertex.excluded.contains(&idx) {
+                continue;
+            }
+            let gap = distance(edge, vertex.point) - vertex.time;
+            let closing = 1. - cross(edge.dir, d);
+            if gap < -EPSILON || closing < 1e-9 {
+                continue;
+            }
+            let s = gap / closing;
+            if s <= EPSILON {
+                continue;
+            }
+            let point = [vertex.point[0] + s * d[0], vertex.point[1] + s * d[1]];
+            let time = vertex.time + s;
+            // 是否真的落在这条边的波前上要等到事件出队时才能确定，这里只取最早的一个
+            if best.is_none_or(|(t, _, _)| time < t) {
+                best = Some((time, point, idx));
+            }
+        }
+        best
+    }
+
+    // 三条波前边围成的三角形收拢的时刻和位置：到三条边的距离都等于时间
+    fn triangle_event(&self, v: usize) -> Option<(f64, Point)> {
+        let a = &self.vertices[v];
+        let b = &self.vertices[a.next];
+        let rows: Vec<[f64; 4]> = [a.edge_left, a.edge_right, b.edge_right]
+            .iter()
+            .map(|&e| {
+                let edge = &self.edges[e];
+                // cross(dir, p - start) - t = 0
+                [-edge.dir[1], edge.dir[0], -1., cross(edge.dir, edge.start)]
+            })
+            .collect();
+        let det3 = |m: [[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let column = |c: usize| -> [[f64; 3]; 3] {
+            let mut m = [[0.; 3]; 3];
+            for (i, row) in rows.iter().enumerate() {
+                for j in 0..3 {
+                    m[i][j] = if j == c { row[3] } else { row[j] };
+                }
+            }
+            m
+        };
+        let d = det3(column(3));
+        if d.abs() < 1e-12 {
+            return None;
+        }
+        let x = det3(column(0)) / d;
+        let y = det3(column(1)) / d;
+        let t = det3(column(2)) / d;
+        Some((f64::max(t, self.now), [x, y]))
+    }
+
+    fn compute_events(&mut self, v: usize) {
+        self.vertices[v].version += 1;
+        let prev = self.vertices[v].prev;
+        let next = self.vertices[v].next;
+        if self.loop_len(v) == 3 {
+            if let Some((time, point)) = self.triangle_event(v) {
+                self.push_event(time, point, v, EventKind::Edge { a: v, b: next });
+            }
+            return;
+        }
+        let mut candidates = vec![];
+        if let Some((time, point)) = self.edge_event(prev, v) {
+            candidates.push((time, point, EventKind::Edge { a: prev, b: v }));
+        }
+        if let Some((time, point)) = self.edge_event(v, next) {
+            candidates.push((time, point, EventKind::Edge { a: v, b: next }));
+        }
+        if let Some((time, point, edge)) = self.split_event(v) {
+            candidates.push((time, point, EventKind::Split { edge }));
+        }
+        let now = self.now;
+        let best = candidates
+            .into_iter()
+            .filter(|c| c.0 >= now - EPSILON)
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        if let Some((time, point, kind)) = best {
+            self.push_event(time, point, v, kind);
+        }
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        self.vertices[a].next = b;
+        self.vertices[b].prev = a;
+    }
+
+    fn loop_len(&self, v: usize) -> usize {
+        let mut n = 1;
+        let mut x = self.vertices[v].next;
+        while x != v && n <= self.vertices.len() {
+            x = self.vertices[x].next;
+            n += 1;
+        }
+        n
+    }
+
+    // 新生成的环只剩两个顶点时，另一个顶点此刻也已走到新顶点处，环直接收拢
+    fn close_loop(&mut self, v: usize) -> bool {
+        match self.loop_len(v) {
+            1 => {
+                self.vertices[v].valid = false;
+                true
+            }
+            2 => {
+                let w = self.vertices[v].next;
+                let node = self.vertices[v].node;
+                self.add_arc(w, node);
+                self.vertices[v].valid = false;
+                self.vertices[w].valid = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // 新顶点两侧的边反向平行且已经重合时，波前在这里是一根零宽的尖刺，
+    // 当场沿着重合线吃掉离得近的邻点，直到顶点重新有速度
+    fn settle(&mut self, w: usize) {
+        if !self.vertices[w].valid || self.close_loop(w) {
+            return;
+        }
+        if self.vertices[w].velocity.is_some() {
+            self.refresh(w);
+            return;
+        }
+        let vertex = &self.vertices[w];
+        let (p, n, time, tip) = (vertex.prev, vertex.next, vertex.time, vertex.point);
+        let pp = Self::position(&self.vertices[p], time);
+        let pn = Self::position(&self.vertices[n], time);
+        let (dp, dn) = (dot(sub(pp, tip), sub(pp, tip)).sqrt(), dot(sub(pn, tip), sub(pn, tip)).sqrt());
+        let both = (dp - dn).abs() < EPSILON && self.loop_len(w) > 3;
+        let (point, left, right, before, after) = if both || dp < dn {
+            let after = if both { self.vertices[n].next } else { n };
+            let right = if both { self.vertices[n].edge_right } else { self.vertices[w].edge_right };
+            (pp, self.vertices[p].edge_left, right, self.vertices[p].prev, after)
+        } else {
+            (pn, self.vertices[w].edge_left, self.vertices[n].edge_right, p, self.vertices[n].next)
+        };
+        let node = self.add_node(point, time);
+        let ended: Vec<usize> = if both {
+            vec![p, w, n]
+        } else if dp < dn {
+            vec![p, w]
+        } else {
+            vec![w, n]
+        };
+        for x in ended {
+            self.add_arc(x, node);
+            self.vertices[x].valid = false;
+        }
+        let u = self.add_vertex(point, time, node, left, right);
+        self.link(before, u);
+        self.link(u, after);
+        self.settle(u);
+    }
+
+    fn refresh(&mut self, v: usize) {
+        let prev = self.vertices[v].prev;
+        let next = self.vertices[v].next;
+        self.compute_events(v);
+        self.compute_events(prev);
+        self.compute_events(next);
+    }
+
+    fn handle_edge(&mut self, a: usize, b: usize, point: Point, time: f64) {
+        let node = self.add_node(point, time);
+        let c = self.vertices[b].next;
+        if self.vertices[c].next == a {
+            // 只剩三个顶点，汇聚于屋脊顶点
+            for v in [a, b, c] {
+                self.add_arc(v, node);
+                self.vertices[v].valid = false;
+            }
+            return;
+        }
+        self.add_arc(a, node);
+        self.add_arc(b, node);
+        let (left, right) = (self.vertices[a].edge_left, self.vertices[b].edge_right);
+        let prev = self.vertices[a].prev;
+        let w = self.add_vertex(point, time, node, left, right);
+        self.vertices[a].valid = false;
+        self.vertices[b].valid = false;
+        self.link(prev, w);
+        self.link(w, c);
+        self.settle(w);
+    }
+
+    fn handle_split(&mut self, v: usize, edge: usize, point: Point, time: f64) {
+        let x = match self.find_segment(v, edge, point, time) {
+            Some(x) => x,
+            None => {
+                // 对应的波前边已经不在了，换一条边重新计算
+                self.vertices[v].excluded.push(edge);
+                self.compute_events(v);
+                return;
+            }
+        };
+        let y = self.vertices[x].next;
+        let (prev, next) = (self.vertices[v].prev, self.vertices[v].next);
+        let (left, right) = (self.vertices[v].edge_left, self.vertices[v].edge_right);
+        let near = |p: Point| {
+            let d = sub(p, point);
+            dot(d, d) < EPSILON * EPSILON
+        };
+        let node = self.add_node(point, time);
+        self.add_arc(v, node);
+        self.vertices[v].valid = false;
+        // 凹点正好撞上另一个波前顶点时是顶点事件，两个顶点一起消失
+        let (v1, v2) = if near(Self::position(&self.vertices[y], time)) && y != prev {
+            self.add_arc(y, node);
+            self.vertices[y].valid = false;
+            let (after, y_right) = (self.vertices[y].next, self.vertices[y].edge_right);
+            let v1 = self.add_vertex(point, time, node, left, y_right);
+            let v2 = self.add_vertex(point, time, node, edge, right);
+            self.link(prev, v1);
+            self.link(v1, after);
+            self.link(x, v2);
+            self.link(v2, next);
+            (v1, v2)
+        } else if near(Self::position(&self.vertices[x], time)) && x != next {
+            self.add_arc(x, node);
+            self.vertices[x].valid = false;
+            let (before, x_left) = (self.vertices[x].prev, self.vertices[x].edge_left);
+            let v1 = self.add_vertex(point, time, node, left, edge);
+            let v2 = self.add_vertex(point, time, node, x_left, right);
+            self.link(prev, v1);
+            self.link(v1, y);
+            self.link(before, v2);
+            self.link(v2, next);
+            (v1, v2)
+        } else {
+            let v1 = self.add_vertex(point, time, node, left, edge);
+            let v2 = self.add_vertex(point, time, node, edge, right);
+            self.link(prev, v1);
+            self.link(v1, y);
+            self.link(x, v2);
+            self.link(v2, next);
+            (v1, v2)
+        };
+        for w in [v1, v2] {
+            self.settle(w);
+        }
+    }
+
+    fn run(&mut self) {
+        while let Some(event) = self.events.pop() {
+            let v = event.vertex;
+            if !self.vertices[v].valid || self.vertices[v].version != event.version {
+                continue;
+            }
+            self.now = f64::max(self.now, event.time);
+            match event.kind {
+                EventKind::Edge { a, b } => {
+                    if self.vertices[a].valid && self.vertices[b].valid && self.vertices[a].next == b {
+                        self.handle_edge(a, b, event.point, event.time);
+                    }
+                }
+                EventKind::Split { edge } => self.handle_split(v, edge, event.point, event.time),
+            }
+        }
+        // 数值误差可能让个别环没能收拢，剩下的顶点直接汇聚到它们当前位置的中心
+        for v in 0..self.vertices.len() {
+            if !self.vertices[v].valid {
+                continue;
+            }
+            let mut members = vec![v];
+            let mut x = self.vertices[v].next;
+            while x != v && members.len() <= self.vertices.len() {
+                members.push(x);
+                x = self.vertices[x].next;
+            }
+            let now = self.now;
+            let sum = members.iter().fold([0., 0.], |s, &m| {
+                let p = Self::position(&self.vertices[m], now);
+                [s[0] + p[0], s[1] + p[1]]
+            });
+            let count = members.len() as f64;
+            let node = self.add_node([sum[0] / count, sum[1] / count], now);
+            for m in members {
+                self.add_arc(m, node);
+                self.vertices[m].valid = false;
+            }
+        }
+    }
+
+    // 沿着属于某条边的骨架弧，从边的终点走回起点，得到这条边扫过的面
+    fn face(&self, edge: usize, arcs: &HashMap<usize, Vec<usize>>) -> Option<Vec<usize>> {
+        let n = self.edges.len();
+        let (start, end) = (edge, (edge + 1) % n);
+        let mut from: HashMap<usize, usize> = HashMap::new();
+        from.insert(end, end);
+        let mut queue = VecDeque::from([end]);
+        while let Some(node) = queue.pop_front() {
+            if node == start {
+                break;
+            }
+            for &next in arcs.get(&node).into_iter().flatten() {
+                if let Entry::Vacant(e) = from.entry(next) {
+                    e.insert(node);
+                    queue.push_back(next);
+                }
+            }
+        }
+        if !from.contains_key(&start) {
+            return None;
+        }
+        let mut chain = vec![];
+        let mut node = from[&start];
+        while node != end {
+            chain.push(node);
+            node = from[&node];
+        }
+        chain.reverse();
+        let mut face = vec![start, end];
+        face.extend(chain);
+        Some(face)
+    }
+}
+
+/// 计算逆时针简单多边形的直骨架，算法未能闭合时返回 None
+pub fn straight_skeleton(polygon: &[Point]) -> Option<Skeleton> {
+    let n = polygon.len();
+    if n < 3 {
+        return None;
+    }
+    let edges: Vec<Edge> = (0..n)
+        .map(|i| {
+            let start = polygon[i];
+            let d = sub(polygon[(i + 1) % n], start);
+            let len = dot(d, d).sqrt();
+            Edge {
+                start,
+                dir: [d[0] / len, d[1] / len],
+            }
+        })
+        .collect();
+    if edges.iter().any(|e| !e.dir[0].is_finite() || !e.dir[1].is_finite()) {
+        return None;
+    }
+    let mut wavefront = Wavefront {
+        edges,
+        nodes: vec![],
+        vertices: vec![],
+        arcs: vec![],
+        events: BinaryHeap::new(),
+        seq: 0,
+        now: 0.,
+    };
+    for (i, p) in polygon.iter().enumerate() {
+        let node = wavefront.add_node(*p, 0.);
+        wavefront.add_vertex(*p, 0., node, (i + n - 1) % n, i);
+    }
+    for i in 0..n {
+        wavefront.link(i, (i + 1) % n);
+    }
+    for i in 0..n {
+        wavefront.compute_events(i);
+    }
+    wavefront.run();
+
+    let mut arcs: HashMap<usize, HashMap<usize, Vec<usize>>> = HashMap::new();
+    for &(a, b, left, right) in &wavefront.arcs {
+        if a == b {
+            continue;
+        }
+        for edge in [left, right] {
+            let adjacent = arcs.entry(edge).or_default();
+            adjacent.entry(a).or_default().push(b);
+            adjacent.entry(b).or_default().push(a);
+        }
+    }
+    let empty = HashMap::new();
+    let faces = (0..n)
+        .map(|edge| wavefront.face(edge, arcs.get(&edge).unwrap_or(&empty)))
+        .collect::<Option<Vec<_>>>()?;
+    Some(Skeleton {
+        nodes: wavefront.nodes,
+        faces,
+    })
+}
@@ -0,0 +1,194 @@
+use crate::config::{self, StyleConfig};
+use crate::pnts;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+// 分类时没有指定颜色使用的调色板
+const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7", "#9c755f",
+    "#bab0ac",
+];
+
+/// 按批量表 JSON 中样式字段的实际取值生成 Cesium 3D Tiles 样式，
+/// 全是数值时按分位数分级，否则按不同的取值分类，空值和其他取值使用 `default` 颜色
+pub fn get_style(config: &StyleConfig, batch_table: &Value) -> Result<Value, String> {
+    // 批量表的列名来自 dbf，字段名最长10个字符，找不到时再按截断后的名字查找
+    let (key, column) = batch_table
+        .get(&config.field)
+        .map(|x| (config.field.as_str(), x))
+        .or_else(|| {
+            let short = config.field.get(..10)?;
+            batch_table.get(short).map(|x| (short, x))
+        })
+        .and_then(|(key, x)| Some((key, x.as_array()?)))
+        .ok_or_else(|| format!("批量表中没有样式字段{}", config.field))?;
+    let name = variable(key);
+    let default = config.default.as_deref().unwrap_or("#ffffff");
+    let values: Vec<&Value> = column.iter().filter(|x| !x.is_null()).collect();
+
+    let mut conditions = vec![];
+    if values.len() < column.len() {
+        conditions.push(json!([format!("{} === null", name), css(default)]));
+    }
+    if !values.is_empty() && values.iter().all(|x| x.is_number()) {
+        let mut numbers: Vec<f64> = values.iter().filter_map(|x| x.as_f64()).collect();
+        numbers.sort_by(f64::total_cmp);
+        // 第 i 个断点取排序后 i/breaks 处的值，重复的断点合并
+        let mut breaks: Vec<f64> = (1..config.breaks)
+            .map(|i| numbers[i * numbers.len() / config.breaks])
+            .filter(|x| *x > numbers[0])
+            .collect();
+        breaks.dedup();
+        let classes = breaks.len() + 1;
+        let stops: Vec<[u8; 3]> = config.colors.iter().filter_map(|x| pnts::parse_hex(x)).collect();
+        (0..classes).for_each(|i| {
+            let t = if classes > 1 { i as f64 / (classes - 1) as f64 } else { 0. };
+            // 没有指定颜色时与点云一样从蓝到红渐变
+            let color = match stops.len() {
+                0 => pnts::ramp(t),
+                1 => stops[0],
+                _ => config::interpolate(&stops, t),
+            };
+            let condition = match breaks.get(i) {
+                Some(b) => format!("{} < {}", name, b),
+                None => "true".to_string(),
+            };
+            conditions.push(json!([condition, css(&hex(color))]));
+        });
+    } else {
+        // 按 JSON 文本去重排序，保证每次输出的顺序一致
+        let classes: BTreeMap<String, &Value> = values.iter().map(|x| (x.to_string(), *x)).collect();
+        if classes.len() > 100 {
+            println!("样式字段{}有{}个不同的取值，样式会很大", config.field, classes.len());
+        }
+        classes.values().enumerate().for_each(|(i, value)| {
+            let color = if config.colors.is_empty() {
+                PALETTE[i % PALETTE.len()]
+            } else {
+                &config.colors[i % config.colors.len()]
+            };
+            conditions.push(json!([format!("{} === {}", name, literal(value)), css(color)]));
+        });
+        conditions.push(json!(["true", css(default)]));
+    }
+
+    let show = match config.show.len() {
+        0 => "true".to_string(),
+        1 => config.show[0].clone(),
+        _ => config
+            .show
+            .iter()
+            .map(|x| format!("({})", x))
+            .collect::<Vec<_>>()
+            .join(" && "),
+    };
+    Ok(json!({
+        "color": { "conditions": conditions },
+        "show": show,
+    }))
+}
+
+fn css(color: &str) -> String {
+    format!("color('{}')", color)
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// 样式表达式里的变量，不是标识符的字段名（如中文、含空格）用 `${feature['…']}` 的写法
+fn variable(field: &str) -> String {
+    let identifier = field.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        format!("${{{}}}", field)
+    } else {
+        format!("${{feature[{}]}}", literal(&Value::String(field.to_string())))
+    }
+}
+
+// 样式表达式里的常量，字符串用单引号并转义
+fn literal(value: &Value) -> String {
+    match value {
+        Value::String(x) => format!("'{}'", x.replace('\\', "\\\\").replace('\'', "\\'")),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(field: &str, breaks: usize, colors: &[&str]) -> StyleConfig {
+        StyleConfig {
+            field: field.to_string(),
+            breaks,
+            colors: colors.iter().map(|x| x.to_string()).collect(),
+            default: None,
+            show: vec![],
+        }
+    }
+
+    fn conditions(style: &Value) -> Vec<(String, String)> {
+        style["color"]["conditions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x[0].as_str().unwrap().to_string(), x[1].as_str().unwrap().to_string()))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+    }
+
+    #[test]
+    fn quantile_breaks_merge_duplicates() {
+        let table = json!({"height": [1, 1, 1, 1, 2, 3]});
+        let style = get_style(&config("height", 3, &["#000000", "#ffffff"]), &table).unwrap();
+        let expected = [("${height} < 2", "color('#000000')"), ("true", "color('#ffffff')")];
+        assert_eq!(conditions(&style), pairs(&expected));
+    }
+
+    #[test]
+    fn more_breaks_than_values() {
+        let table = json!({"height": [7.5, 5]});
+        let style = get_style(&config("height", 5, &["#000000"]), &table).unwrap();
+        let expected = [("${height} < 7.5", "color('#000000')"), ("true", "color('#000000')")];
+        assert_eq!(conditions(&style), pairs(&expected));
+        // 只有一个取值时只有一级
+        let table = json!({"height": [4, 4, 4]});
+        let style = get_style(&config("height", 5, &[]), &table).unwrap();
+        assert_eq!(conditions(&style), pairs(&[("true", &css(&hex(pnts::ramp(0.))))]));
+    }
+
+    #[test]
+    fn categories_are_ordered_with_null_first() {
+        let table = json!({"kind": ["b", null, "a", 3, "b"]});
+        let style = get_style(&config("kind", 5, &["#111111", "#222222"]), &table).unwrap();
+        let expected = [
+            ("${kind} === null", "color('#ffffff')"),
+            ("${kind} === 'a'", "color('#111111')"),
+            ("${kind} === 'b'", "color('#222222')"),
+            ("${kind} === 3", "color('#111111')"),
+            ("true", "color('#ffffff')"),
+        ];
+        assert_eq!(conditions(&style), pairs(&expected));
+    }
+
+    #[test]
+    fn field_name_is_resolved_and_quoted() {
+        // dbf 把 building_height 截断成 building_h
+        let table = json!({"building_h": [1, 2]});
+        let style = get_style(&config("building_height", 2, &[]), &table).unwrap();
+        assert_eq!(conditions(&style)[0].0, "${building_h} < 2");
+
+        let table = json!({"用途": ["住宅"], "it's": ["x"]});
+        let style = get_style(&config("用途", 5, &[]), &table).unwrap();
+        assert_eq!(conditions(&style)[0].0, "${feature['用途']} === '住宅'");
+        let style = get_style(&config("it's", 5, &[]), &table).unwrap();
+        assert_eq!(conditions(&style)[0].0, "${feature['it\\'s']} === 'x'");
+
+        assert!(get_style(&config("missing", 5, &[]), &table).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+// 把地形 tif 切成 Cesium 的 quantized-mesh-1.0 地形瓦片，地理坐标切片（第0级东西两块），
+// 行号从南往北（TMS），高程用与建筑相同的 `ShpTiff` 取值，建筑和地形自然贴合
+
+use crate::parallel;
+use crate::shptiff::ShpTiff;
+use crate::tileset;
+use serde_json::json;
+use std::fs;
+
+// 每块瓦片的格网边数，顶点数为 (GRID + 1)²
+const GRID: usize = 32;
+// u、v 和高度量化后的最大值
+const QUANTIZED_MAX: f64 = 32767.;
+// WGS84 椭球的长短半轴，地平线遮挡点在按椭球缩放后的空间里计算
+const RADII: [f64; 3] = [6378137.0, 6378137.0, 6356752.314245179];
+
+/// 生成 0 到 `max_level` 级的地形瓦片和 layer.json，写到 `dir` 目录，返回瓦片数；
+/// 第0级两块总是输出，更高的层级只输出与地形范围相交的瓦片，各瓦片用 `threads` 个线程并行生成；
+/// 目录或文件写不了时返回错误信息
+pub fn write_terrain(tiff: &ShpTiff, max_level: u32, dir: &str, threads: usize) -> Result<usize, String> {
+    let [west, south, east, north] = tiff.extent();
+    let mut tiles = vec![];
+    let mut available = vec![];
+    for level in 0..=max_level {
+        let size = 180. / (1u64 << level) as f64;
+        let columns = 2u64 << level;
+        let rows = 1u64 << level;
+        let index = |x: f64, origin: f64, n: u64| (((x - origin) / size).floor().max(0.) as u64).min(n - 1);
+        let (x0, x1, y0, y1) = if level == 0 {
+            (0, 1, 0, 0)
+        } else {
+            (
+                index(west, -180., columns),
+                index(east, -180., columns),
+                index(south, -90., rows),
+                index(north, -90., rows),
+            )
+        };
+        for x in x0..=x1 {
+            let path = format!("{}/{}/{}", dir, level, x);
+            fs::create_dir_all(&path).map_err(|e| format!("目录{}创建错误: {}", path, e))?;
+            (y0..=y1).for_each(|y| {
+                let rect = [
+                    -180. + x as f64 * size,
+                    -90. + y as f64 * size,
+                    -180. + (x + 1) as f64 * size,
+                    -90. + (y + 1) as f64 * size,
+                ];
+                tiles.push((format!("{}/{}.terrain", path, y), rect));
+            });
+        }
+        available.push(json!([{ "startX": x0, "startY": y0, "endX": x1, "endY": y1 }]));
+    }
+    parallel::map(&tiles, threads, |_, (path, rect)| {
+        fs::write(path, encode_tile(tiff, *rect)).map_err(|e| format!("文件{}写入错误: {}", path, e))
+    })
+    .into_iter()
+    .collect::<Result<(), _>>()?;
+
+    let layer = json!({
+        "tilejson": "2.1.0",
+        "name": "terrain",
+        "version": "1.0.0",
+        "format": "quantized-mesh-1.0",
+        "scheme": "tms",
+        "tiles": ["{z}/{x}/{y}.terrain?v={version}"],
+        "projection": "EPSG:4326",
+        "bounds": [west, south, east, north],
+        "minzoom": 0,
+        "maxzoom": max_level,
+        "available": available,
+    });
+    let path = format!("{}/layer.json", dir);
+    fs::write(&path, layer.to_string()).map_err(|e| format!("文件{}写入错误: {}", path, e))?;
+    Ok(tiles.len())
+}
+
+// 一块瓦片：规则格网取高程，地形范围外的高度为0
+fn encode_tile(tiff: &ShpTiff, rect: [f64; 4]) -> Vec<u8> {
+    let [west, south, east, north] = rect;
+    let mut grid = vec![];
+    (0..=GRID).for_each(|j| {
+        (0..=GRID).for_each(|i| {
+            let lon = west + (east - west) * i as f64 / GRID as f64;
+            let lat = south + (north - south) * j as f64 / GRID as f64;
+            let h = tiff.sample(lon, lat).unwrap_or(0) as f64;
+            grid.push((i, j, lon, lat, h));
+        })
+    });
+    let min_h = grid.iter().map(|x| x.4).fold(f64::MAX, f64::min);
+    let max_h = grid.iter().map(|x| x.4).fold(f64::MIN, f64::max);
+
+    // 每个格子两个三角形，从西南往东北逆时针；顶点按第一次使用的顺序编号，索引才能用高水位编码
+    let mut triangles = vec![];
+    (0..GRID).for_each(|j| {
+        (0..GRID).for_each(|i| {
+            let a = j * (GRID + 1) + i;
+            let (b, c, d) = (a + 1, a + GRID + 2, a + GRID + 1);
+            triangles.extend([a, b, c, a, c, d]);
+        })
+    });
+    let mut remap = vec![usize::MAX; grid.len()];
+    let mut order = vec![];
+    let indices: Vec<u32> = triangles
+        .iter()
+        .map(|i| {
+            if remap[*i] == usize::MAX {
+                remap[*i] = order.len();
+                order.push(*i);
+            }
+            remap[*i] as u32
+        })
+        .collect();
+    let vertices: Vec<_> = order.iter().map(|i| grid[*i]).collect();
+
+    let ecef: Vec<[f64; 3]> = vertices
+        .iter()
+        .map(|(_, _, lon, lat, h)| tileset::cartographic_to_ecef(*lon, *lat, *h))
+        .collect();
+    let center = tileset::cartographic_to_ecef((west + east) / 2., (south + north) / 2., (min_h + max_h) / 2.);
+    let radius = ecef.iter().map(|p| distance(p, &center)).fold(0., f64::max);
+    let occlusion = horizon_occlusion_point(&center, &ecef);
+
+    let mut data = vec![];
+    center.iter().for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+    data.extend_from_slice(&(min_h as f32).to_le_bytes());
+    data.extend_from_slice(&(max_h as f32).to_le_bytes());
+    center.iter().for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+    data.extend_from_slice(&radius.to_le_bytes());
+    occlusion.iter().for_each(|x| data.extend_from_slice(&x.to_le_bytes()));
+
+    // 顶点的 u、v、高度各自按与前一个的差值 zigzag 编码
+    let range = if max_h > min_h { max_h - min_h } else { 1. };
+    let quantized: Vec<[u16; 3]> = vertices
+        .iter()
+        .map(|(i, j, _, _, h)| {
+            [
+                (*i as f64 * QUANTIZED_MAX / GRID as f64).round() as u16,
+                (*j as f64 * QUANTIZED_MAX / GRID as f64).round() as u16,
+                ((h - min_h) / range * QUANTIZED_MAX).round() as u16,
+            ]
+        })
+        .collect();
+    data.extend_from_slice(&(vertices.len() as u32).to_le_bytes());
+    (0..3).for_each(|k| {
+        let mut last = 0i32;
+        quantized.iter().for_each(|q| {
+            let delta = q[k] as i32 - last;
+            last = q[k] as i32;
+            data.extend_from_slice(&(((delta << 1) ^ (delta >> 31)) as u16).to_le_bytes());
+        });
+    });
+
+    // 顶点不超过 65536 个，索引用16位，顶点数据之后已经是2字节对齐
+    data.extend_from_slice(&((indices.len() / 3) as u32).to_le_bytes());
+    let mut highest = 0u32;
+    indices.iter().for_each(|i| {
+        data.extend_from_slice(&((highest - i) as u16).to_le_bytes());
+        if *i == highest {
+            highest += 1;
+        }
+    });
+
+    // 四条边上的顶点，按边的方向排序，用来生成裙边和拼接相邻瓦片
+    let edge = |filter: &dyn Fn(&[u16; 3]) -> bool, axis: usize| {
+        let mut edge: Vec<u32> = (0..quantized.len() as u32)
+            .filter(|i| filter(&quantized[*i as usize]))
+            .collect();
+        edge.sort_by_key(|i| quantized[*i as usize][axis]);
+        edge
+    };
+    let max = QUANTIZED_MAX as u16;
+    [
+        edge(&|q| q[0] == 0, 1),
+        edge(&|q| q[1] == 0, 0),
+        edge(&|q| q[0] == max, 1),
+        edge(&|q| q[1] == max, 0),
+    ]
+    .iter()
+    .for_each(|edge| {
+        data.extend_from_slice(&(edge.len() as u32).to_le_bytes());
+        edge.iter().for_each(|i| data.extend_from_slice(&(*i as u16).to_le_bytes()));
+    });
+    data
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// 与 Cesium 的 EllipsoidalOccluder 相同：在按椭球缩放的空间里，沿中心方向找到能挡住所有点的最近位置
+fn horizon_occlusion_point(center: &[f64; 3], points: &[[f64; 3]]) -> [f64; 3] {
+    let scale = |p: &[f64; 3]| [p[0] / RADII[0], p[1] / RADII[1], p[2] / RADII[2]];
+    let c = scale(center);
+    let len = (c[0] * c[0] + c[1] * c[1] + c[2] * c[2]).sqrt();
+    let direction = c.map(|x| x / len);
+    let magnitude = points
+        .iter()
+        .map(|p| {
+            let p = scale(p);
+            let m2 = p[0] * p[0] + p[1] * p[1] + p[2] * p[2];
+            let unit = p.map(|x| x / m2.sqrt());
+            // 低于椭球面的点按在椭球面上计算
+            let m2 = m2.max(1.);
+            let m = m2.sqrt();
+            let cos_alpha = unit[0] * direction[0] + unit[1] * direction[1] + unit[2] * direction[2];
+            let cross = [
+                unit[1] * direction[2] - unit[2] * direction[1],
+                unit[2] * direction[0] - unit[0] * direction[2],
+                unit[0] * direction[1] - unit[1] * direction[0],
+            ];
+            let sin_alpha = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+            let cos_beta = 1. / m;
+            let sin_beta = (m2 - 1.).sqrt() * cos_beta;
+            1. / (cos_alpha * cos_beta - sin_alpha * sin_beta)
+        })
+        .filter(|x| x.is_finite() && *x > 0.)
+        .fold(0., f64::max);
+    direction.map(|x| x * magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geotiff_rs::GeoTiff;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    // 16×16 的 int16 分块 TIFF，只有一块、不压缩，第 i 列的高程为 100 + 10i；
+    // 左上角 (116, 40)，像素 0.25 度，覆盖 [116, 36, 120, 40]
+    fn dem(name: &str) -> ShpTiff {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_terrain");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.tif", name));
+        let entries: [(u16, u16, u32); 9] = [
+            (256, 3, 16),
+            (257, 3, 16),
+            (258, 3, 16),
+            (259, 3, 1),
+            (277, 3, 1),
+            (322, 3, 16),
+            (323, 3, 16),
+            (324, 4, 8 + 2 + 9 * 12 + 4),
+            (325, 4, 16 * 16 * 2),
+        ];
+        let mut data = b"II*\0".to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        entries.iter().for_each(|(tag, kind, value)| {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&kind.to_le_bytes());
+            data.extend_from_slice(&1u32.to_le_bytes());
+            data.extend_from_slice(&value.to_le_bytes());
+        });
+        data.extend_from_slice(&0u32.to_le_bytes());
+        (0..16 * 16).for_each(|i| data.extend_from_slice(&(100 + 10 * (i % 16) as i16).to_le_bytes()));
+        fs::write(&path, data).unwrap();
+        ShpTiff {
+            tiff: GeoTiff::from_file(&path).unwrap(),
+            xr: 116.,
+            yr: 40.,
+            px: 0.25,
+            py: -0.25,
+            width: 16,
+            height: 16,
+        }
+    }
+
+    struct Tile {
+        center: [f64; 3],
+        heights: [f32; 2],
+        vertices: Vec<[u16; 3]>,
+        indices: Vec<u32>,
+        edges: Vec<Vec<u16>>,
+    }
+
+    // 按 quantized-mesh-1.0 规范解码：zigzag 差值的顶点、高水位编码的16位索引、四条边
+    fn decode(data: &[u8]) -> Tile {
+        let f64_at = |i: usize| f64::from_le_bytes(data[i..i + 8].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap()) as usize;
+        let u16_at = |i: usize| u16::from_le_bytes(data[i..i + 2].try_into().unwrap());
+        let mut offset = 88;
+        let count = u32_at(offset);
+        offset += 4;
+        let mut vertices = vec![[0u16; 3]; count];
+        (0..3).for_each(|k| {
+            let mut value = 0i32;
+            (0..count).for_each(|i| {
+                let zigzag = u16_at(offset) as i32;
+                value += (zigzag >> 1) ^ -(zigzag & 1);
+                vertices[i][k] = value as u16;
+                offset += 2;
+            });
+        });
+        let triangles = u32_at(offset);
+        offset += 4;
+        let mut highest = 0;
+        let indices = (0..triangles * 3)
+            .map(|i| {
+                let index = highest - u16_at(offset + i * 2) as u32;
+                if index == highest {
+                    highest += 1;
+                }
+                index
+            })
+            .collect();
+        offset += triangles * 6;
+        let edges = (0..4)
+            .map(|_| {
+                let n = u32_at(offset);
+                let edge = (0..n).map(|i| u16_at(offset + 4 + i * 2)).collect();
+                offset += 4 + n * 2;
+                edge
+            })
+            .collect();
+        assert_eq!(offset, data.len());
+        Tile {
+            center: [f64_at(0), f64_at(8), f64_at(16)],
+            heights: [f32_at(24), f32_at(28)],
+            vertices,
+            indices,
+            edges,
+        }
+    }
+
+    #[test]
+    fn tile_decodes_to_grid() {
+        let tiff = dem("grid");
+        let tile = decode(&encode_tile(&tiff, [116., 36., 120., 40.]));
+        assert_eq!(tile.heights, [100., 250.]);
+        let center = tileset::cartographic_to_ecef(118., 38., 175.);
+        (0..3).for_each(|k| assert!((tile.center[k] - center[k]).abs() < 1e-6));
+
+        // 每个格点一个顶点，u、v 落在格网上，高度还原到所在像素的高程
+        assert_eq!(tile.vertices.len(), (GRID + 1) * (GRID + 1));
+        let mut cells = vec![false; tile.vertices.len()];
+        tile.vertices.iter().for_each(|[u, v, h]| {
+            let i = (*u as f64 * GRID as f64 / QUANTIZED_MAX).round() as usize;
+            let j = (*v as f64 * GRID as f64 / QUANTIZED_MAX).round() as usize;
+            assert_eq!(*u, (i as f64 * QUANTIZED_MAX / GRID as f64).round() as u16);
+            assert_eq!(*v, (j as f64 * QUANTIZED_MAX / GRID as f64).round() as u16);
+            cells[j * (GRID + 1) + i] = true;
+            let height = 100. + *h as f64 / QUANTIZED_MAX * 150.;
+            let expected = 100. + 10. * (i / 2).min(15) as f64;
+            assert!((height - expected).abs() < 0.01, "{} {}", height, expected);
+        });
+        assert!(cells.iter().all(|x| *x));
+
+        // 每个格子两个逆时针三角形，面积之和等于整块瓦片
+        assert_eq!(tile.indices.len(), GRID * GRID * 6);
+        let area: f64 = tile
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| tile.vertices[t[k] as usize].map(|x| x as f64));
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.
+            })
+            .sum();
+        assert!((area - QUANTIZED_MAX * QUANTIZED_MAX).abs() < 1.);
+
+        // 西、南、东、北四条边各 GRID + 1 个顶点，沿边的方向递增
+        let max = QUANTIZED_MAX as u16;
+        let sides: [(usize, u16, usize); 4] = [(0, 0, 1), (1, 0, 0), (0, max, 1), (1, max, 0)];
+        tile.edges.iter().zip(sides).for_each(|(edge, (axis, value, along))| {
+            assert_eq!(edge.len(), GRID + 1);
+            let vertices: Vec<_> = edge.iter().map(|i| tile.vertices[*i as usize]).collect();
+            assert!(vertices.iter().all(|q| q[axis] == value));
+            assert!(vertices.windows(2).all(|w| w[0][along] < w[1][along]));
+        });
+    }
+
+    fn output(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_terrain").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn layer_lists_available_tiles() {
+        let tiff = dem("layer");
+        let dir = output("layer");
+        assert_eq!(write_terrain(&tiff, 1, dir.to_str().unwrap(), 2), Ok(3));
+        ["0/0/0", "0/1/0", "1/3/1"].iter().for_each(|tile| {
+            let data = fs::read(dir.join(format!("{}.terrain", tile))).unwrap();
+            assert_eq!(decode(&data).vertices.len(), (GRID + 1) * (GRID + 1));
+        });
+        let layer: Value = serde_json::from_slice(&fs::read(dir.join("layer.json")).unwrap()).unwrap();
+        assert_eq!(layer["format"], "quantized-mesh-1.0");
+        assert_eq!(layer["scheme"], "tms");
+        assert_eq!(layer["maxzoom"], 1);
+        assert_eq!(layer["bounds"], serde_json::json!([116., 36., 120., 40.]));
+        assert_eq!(
+            layer["available"],
+            serde_json::json!([
+                [{ "startX": 0, "startY": 0, "endX": 1, "endY": 0 }],
+                [{ "startX": 3, "startY": 1, "endX": 3, "endY": 1 }],
+            ])
+        );
+    }
+
+    #[test]
+    fn unwritable_directory_is_an_error() {
+        let tiff = dem("blocked");
+        let dir = output("blocked");
+        fs::create_dir_all(dir.parent().unwrap()).unwrap();
+        fs::write(&dir, b"").unwrap();
+        let error = write_terrain(&tiff, 0, dir.to_str().unwrap(), 1).unwrap_err();
+        assert!(error.starts_with("目录") && error.contains("创建错误"), "{}", error);
+        fs::remove_file(&dir).unwrap();
+    }
+}
@@ -1,9 +1,33 @@
-use gfx_maths::Vec3;
+use geo::ConvexHull;
 use serde::{Deserialize, Serialize};
 
+/// 包围体，region 为经纬度范围，box 为中心加三个半轴，sphere 为中心加半径
 #[derive(Serialize, Deserialize, Debug)]
-pub struct BoundingVolume {
-    pub region: [f32; 6],
+#[serde(rename_all = "lowercase")]
+pub enum BoundingVolume {
+    Region([f64; 6]),
+    Box([f64; 12]),
+    Sphere([f64; 4]),
+}
+
+/// 输出的包围体类型
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum VolumeKind {
+    #[default]
+    Region,
+    Box,
+    Sphere,
+}
+
+impl VolumeKind {
+    pub fn parse(name: &str) -> Option<VolumeKind> {
+        match name {
+            "region" => Some(VolumeKind::Region),
+            "box" => Some(VolumeKind::Box),
+            "sphere" => Some(VolumeKind::Sphere),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,7 +43,7 @@ pub struct Child {
     #[serde(rename = "geometricError")]
     pub geometric_error: f32,
     pub refine: String,
-    pub transform: [f32; 16],
+    pub transform: [f64; 16],
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -47,96 +71,586 @@ pub struct Tiles {
     pub root: Root,
 }
 
-pub fn get_root_bounding_volume(
-    min_x: f32,
-    max_x: f32,
-    min_y: f32,
-    max_y: f32,
-    min_h: f32,
-    max_h: f32,
-) -> BoundingVolume {
-    let min_dx = min_x.to_radians();
-    let max_dx = max_x.to_radians();
-    let min_dy = min_y.to_radians();
-    let max_dy = max_y.to_radians();
-    let region = [min_dx, min_dy, max_dx, max_dy, min_h, max_h];
-    BoundingVolume { region }
-}
-
-pub fn get_child_bounding_volume(
-    cx: f32,
-    cy: f32,
-    xd: f32,
-    yd: f32,
-    min_h: f32,
-    max_h: f32,
-) -> BoundingVolume {
-    let cxr = cx.to_radians();
-    let cyr = cy.to_radians();
-    let xdr = xd.to_radians() * 1.05;
-    let ydr = yd.to_radians() * 1.05;
-    let region = [
-        cxr - xdr / 2.,
-        cyr + ydr / 2.,
-        cxr + xdr / 2.,
-        cyr - ydr / 2.,
-        min_h,
-        max_h,
-    ];
-    BoundingVolume { region }
-}
-
-pub fn get_transform(lon: f32, lat: f32, min_h: f32) -> [f32; 16] {
-    let lonr = lon.to_radians();
-    let latr = lat.to_radians();
-    let ellipsod_a = 40680631590769.;
-    let ellipsod_b = 40680631590769.;
-    let ellipsod_c = 40408299984661.4;
-    let xn = lonr.cos() * latr.cos();
-    let yn = lonr.sin() * latr.cos();
-    let zn = latr.sin();
-    let x0 = ellipsod_a * xn;
-    let y0 = ellipsod_b * yn;
-    let z0 = ellipsod_c * zn;
-    let gamma = (xn * x0 + yn * y0 + zn * z0).sqrt();
-    let px = x0 / gamma;
-    let py = y0 / gamma;
-    let pz = z0 / gamma;
-    let dx = xn * min_h;
-    let dy = yn * min_h;
-    let dz = zn * min_h;
-    let east_vec = Vec3::new(-y0, x0, 0.);
-    let north_vec = Vec3::new(
-        y0 * east_vec.z - east_vec.y * z0,
-        z0 * east_vec.x - east_vec.z * x0,
-        x0 * east_vec.y - east_vec.x * y0,
-    );
-    let e1 = (east_vec.x as f64) * (east_vec.x as f64);
-    let e2 = (east_vec.y as f64) * (east_vec.y as f64);
-    let e3 = (east_vec.z as f64) * (east_vec.z as f64);
-    let east_len = (e1+ e2 + e3).sqrt();
-    let l1 = (north_vec.x as f64) * (north_vec.x as f64);
-    let l2 = (north_vec.y as f64) * (north_vec.y as f64);
-    let l3 = (north_vec.z as f64) * (north_vec.z as f64);
-    let nor_len = (l1+ l2 + l3).sqrt();
-    let east_unit = east_vec / (east_len as f32);
-    let north_unit = north_vec / (nor_len as f32);
+/// 瓦片局部坐标的原点：经度、纬度和高度
+pub type Origin = (f64, f64, i32);
+
+// 子午圈曲率半径的最小值（赤道处），米换算成纬度时取它，得到的范围只会偏大
+const MIN_MERIDIAN_RADIUS: f64 = 6335439.327;
+
+/// 一个瓦片内容的包围范围：经纬度和高度范围，以及瓦片局部坐标系下的有向包围盒和包围球
+#[derive(Clone)]
+pub struct Bounds {
+    /// [西, 南, 东, 北, 最低, 最高]（度、米）
+    pub region: [f64; 6],
+    // 局部坐标和地心坐标下的包围盒（中心加三个半轴）和包围球（中心加半径）
+    local_box: [f64; 12],
+    local_sphere: [f64; 4],
+    ecef_box: [f64; 12],
+    ecef_sphere: [f64; 4],
+    // 局部坐标到地心坐标的变换
+    frame: [f64; 16],
+}
+
+impl Bounds {
+    /// 局部坐标的点按 `frame`（列主序，局部坐标到地心坐标）求包围范围，
+    /// `margin` 为各方向向外扩的距离（米），没有点时为 None
+    pub fn new(points: &[[f64; 3]], frame: &[f64; 16], margin: f64) -> Option<Bounds> {
+        if points.is_empty() {
+            return None;
+        }
+        // 不同的经纬度换算在最后几位上舍入不同，再多留1毫米
+        let margin = margin + 1e-3;
+        let ecef: Vec<[f64; 3]> = points.iter().map(|p| apply(frame, *p, 1.)).collect();
+
+        let mut b = [f64::MAX, f64::MAX, f64::MIN, f64::MIN, f64::MAX, f64::MIN];
+        ecef.iter().for_each(|p| {
+            let [lon, lat, h] = ecef_to_cartographic(*p);
+            b = [b[0].min(lon), b[1].min(lat), b[2].max(lon), b[3].max(lat), b[4].min(h), b[5].max(h)];
+        });
+        let dy = (margin / MIN_MERIDIAN_RADIUS).to_degrees();
+        let max_lat = b[1].abs().max(b[3].abs()).to_radians();
+        let dx = (margin / (MIN_MERIDIAN_RADIUS * max_lat.cos())).to_degrees();
+        let region = [b[0] - dx, b[1] - dy, b[2] + dx, b[3] + dy, b[4] - margin, b[5] + margin];
+
+        let (local_box, _) = boxes(points, &ecef, frame, margin);
+        // 子瓦片写的是局部包围体，变换到地心坐标后角点会比地心坐标下直接求的盒子略向外，
+        // 地心坐标下的包围体要把变换后的局部包围体也包住，根瓦片用它时才能包住子瓦片
+        let corners: Vec<[f64; 3]> = box_corners(&local_box)
+            .iter()
+            .map(|p| apply(frame, *p, 1.))
+            .chain(ecef.iter().copied())
+            .collect();
+        let (_, ecef_box) = boxes(points, &corners, frame, margin);
+        let local_sphere = bounding_sphere(points, [local_box[0], local_box[1], local_box[2]], margin);
+        let ecef_sphere = bounding_sphere(&ecef, [ecef_box[0], ecef_box[1], ecef_box[2]], margin);
+        let c = apply(frame, [local_sphere[0], local_sphere[1], local_sphere[2]], 1.);
+        let d = sub(c, [ecef_sphere[0], ecef_sphere[1], ecef_sphere[2]]);
+        let reach = dot(d, d).sqrt() + local_sphere[3] * max_stretch(frame) + 1e-3;
+        let ecef_sphere = [ecef_sphere[0], ecef_sphere[1], ecef_sphere[2], ecef_sphere[3].max(reach)];
+        Some(Bounds {
+            region,
+            local_box,
+            local_sphere,
+            ecef_box,
+            ecef_sphere,
+            frame: *frame,
+        })
+    }
+
+    /// 共用同一个局部坐标系的几个瓦片合起来的范围：region 取并集，box 包住各个盒子的角点，
+    /// sphere 包住各个球，父瓦片的包围体因此总能包住子瓦片的；只有一个时原样返回
+    pub fn merge(parts: &[Bounds]) -> Option<Bounds> {
+        let frame = match parts {
+            [] => return None,
+            [one] => return Some(one.clone()),
+            [first, ..] => &first.frame,
+        };
+        let mut region = [f64::MAX, f64::MAX, f64::MIN, f64::MIN, f64::MAX, f64::MIN];
+        parts.iter().for_each(|b| {
+            let r = &b.region;
+            region = [
+                region[0].min(r[0]),
+                region[1].min(r[1]),
+                region[2].max(r[2]),
+                region[3].max(r[3]),
+                region[4].min(r[4]),
+                region[5].max(r[5]),
+            ];
+        });
+        let local: Vec<[f64; 3]> = parts.iter().flat_map(|b| box_corners(&b.local_box)).collect();
+        // 子瓦片的局部包围体经 transform 变换后才是实际的范围，地心坐标下的根包围体要同时包住它们
+        let ecef: Vec<[f64; 3]> = parts
+            .iter()
+            .flat_map(|b| box_corners(&b.ecef_box))
+            .chain(local.iter().map(|p| apply(frame, *p, 1.)))
+            .collect();
+        let (local_box, ecef_box) = boxes(&local, &ecef, frame, 1e-3);
+        let local_sphere = enclosing_sphere(parts.iter().map(|b| b.local_sphere), &local_box);
+        let stretch = max_stretch(frame);
+        let ecef_sphere = enclosing_sphere(
+            parts.iter().flat_map(|b| {
+                let s = b.local_sphere;
+                let c = apply(frame, [s[0], s[1], s[2]], 1.);
+                [b.ecef_sphere, [c[0], c[1], c[2], s[3] * stretch]]
+            }),
+            &ecef_box,
+        );
+        Some(Bounds {
+            region,
+            local_box,
+            local_sphere,
+            ecef_box,
+            ecef_sphere,
+            frame: *frame,
+        })
+    }
+
+    /// 指定类型的包围体，`local` 为 true 时 box 和 sphere 用局部坐标，否则用地心坐标；region 总是经纬度
+    pub fn bounding_volume(&self, kind: VolumeKind, local: bool) -> BoundingVolume {
+        let r = &self.region;
+        match kind {
+            VolumeKind::Region => BoundingVolume::Region([
+                r[0].to_radians(),
+                r[1].to_radians(),
+                r[2].to_radians(),
+                r[3].to_radians(),
+                r[4],
+                r[5],
+            ]),
+            VolumeKind::Box if local => BoundingVolume::Box(self.local_box),
+            VolumeKind::Box => BoundingVolume::Box(self.ecef_box),
+            VolumeKind::Sphere if local => BoundingVolume::Sphere(self.local_sphere),
+            VolumeKind::Sphere => BoundingVolume::Sphere(self.ecef_sphere),
+        }
+    }
+}
+
+/// 经纬度（度）和高度处的东北天坐标系，列主序，全程用 f64 计算
+pub fn enu_frame(lon: f64, lat: f64, h: f64) -> [f64; 16] {
+    let (lonr, latr) = (lon.to_radians(), lat.to_radians());
+    let east = [-lonr.sin(), lonr.cos(), 0.];
+    let north = [-latr.sin() * lonr.cos(), -latr.sin() * lonr.sin(), latr.cos()];
+    let up = [latr.cos() * lonr.cos(), latr.cos() * lonr.sin(), latr.sin()];
+    let o = cartographic_to_ecef(lon, lat, h);
     [
-        east_unit.x,
-        east_unit.y,
-        east_unit.z,
-        0.,
-        north_unit.x,
-        north_unit.y,
-        north_unit.z,
-        0.,
-        xn,
-        yn,
-        zn,
-        0.,
-        px + dx,
-        py + dy,
-        pz + dz,
+        east[0], east[1], east[2], 0., north[0], north[1], north[2], 0., up[0], up[1], up[2], 0., o[0], o[1], o[2],
         1.,
     ]
 }
+
+/// 地心坐标转到 `frame` 的局部坐标，`frame` 的旋转部分须为正交矩阵
+pub fn to_local(frame: &[f64; 16], p: [f64; 3]) -> [f64; 3] {
+    let d = [p[0] - frame[12], p[1] - frame[13], p[2] - frame[14]];
+    [0, 4, 8].map(|k| frame[k] * d[0] + frame[k + 1] * d[1] + frame[k + 2] * d[2])
+}
+
+/// 列主序的 4×4 矩阵乘点（w 为1）或方向（w 为0）
+pub fn apply(m: &[f64; 16], p: [f64; 3], w: f64) -> [f64; 3] {
+    [0, 1, 2].map(|k| m[k] * p[0] + m[4 + k] * p[1] + m[8 + k] * p[2] + m[12 + k] * w)
+}
+
+// 局部坐标和对应的地心坐标的包围盒：局部坐标下竖直方向为z轴，水平方向取平面投影的最小面积外接矩形；
+// `frame` 不一定严格正交，地心坐标下的盒子按变换后的轴正交化以后重新求
+fn boxes(points: &[[f64; 3]], ecef: &[[f64; 3]], frame: &[f64; 16], margin: f64) -> ([f64; 12], [f64; 12]) {
+    let axis = footprint_axis(points);
+    let local_axes = [[axis[0], axis[1], 0.], [-axis[1], axis[0], 0.], [0., 0., 1.]];
+    let local_box = oriented_box(points, &local_axes, margin);
+    let x = normalize(apply(frame, local_axes[0], 0.));
+    let z = apply(frame, local_axes[2], 0.);
+    let z = normalize(sub(z, x.map(|v| v * dot(z, x))));
+    let ecef_box = oriented_box(ecef, &[x, cross(z, x), z], margin);
+    (local_box, ecef_box)
+}
+
+// 包围盒的八个角点
+fn box_corners(b: &[f64; 12]) -> Vec<[f64; 3]> {
+    (0..8)
+        .map(|i| {
+            let sign = [0, 1, 2].map(|k| if i >> k & 1 == 1 { 1. } else { -1. });
+            [0, 1, 2].map(|n| b[n] + (0..3).map(|k| sign[k] * b[3 + k * 3 + n]).sum::<f64>())
+        })
+        .collect()
+}
+
+// 以包围盒中心为球心、包住几个球的球
+fn enclosing_sphere(spheres: impl Iterator<Item = [f64; 4]>, b: &[f64; 12]) -> [f64; 4] {
+    let center = [b[0], b[1], b[2]];
+    let radius = spheres
+        .map(|s| dot(sub([s[0], s[1], s[2]], center), sub([s[0], s[1], s[2]], center)).sqrt() + s[3])
+        .fold(0., f64::max);
+    [center[0], center[1], center[2], radius + 1e-3]
+}
+
+/// 矩阵旋转部分把向量拉长的最大倍数的上界：MᵀM 的最大特征值不超过它每行绝对值之和的最大值
+pub fn max_stretch(m: &[f64; 16]) -> f64 {
+    let col = |i: usize| [m[i * 4], m[i * 4 + 1], m[i * 4 + 2]];
+    (0..3)
+        .map(|i| (0..3).map(|j| dot(col(i), col(j)).abs()).sum::<f64>())
+        .fold(0., f64::max)
+        .sqrt()
+}
+
+// 沿三个正交的单位轴求包围盒，三个半轴各加 `margin`
+fn oriented_box(points: &[[f64; 3]], axes: &[[f64; 3]; 3], margin: f64) -> [f64; 12] {
+    let mut lo = [f64::MAX; 3];
+    let mut hi = [f64::MIN; 3];
+    points.iter().for_each(|p| {
+        for k in 0..3 {
+            let t = dot(*p, axes[k]);
+            lo[k] = lo[k].min(t);
+            hi[k] = hi[k].max(t);
+        }
+    });
+    let mut obb = [0.; 12];
+    for k in 0..3 {
+        let mid = (lo[k] + hi[k]) / 2.;
+        let half = (hi[k] - lo[k]) / 2. + margin;
+        for n in 0..3 {
+            obb[n] += axes[k][n] * mid;
+            obb[3 + k * 3 + n] = axes[k][n] * half;
+        }
+    }
+    obb
+}
+
+fn bounding_sphere(points: &[[f64; 3]], center: [f64; 3], margin: f64) -> [f64; 4] {
+    let radius = points
+        .iter()
+        .map(|p| dot(sub(*p, center), sub(*p, center)).sqrt())
+        .fold(0., f64::max);
+    [center[0], center[1], center[2], radius + margin]
+}
+
+// 点在水平面上投影的最小面积外接矩形的一条边的方向：最优矩形总有一条边与凸包的某条边共线，逐条边试一遍
+fn footprint_axis(points: &[[f64; 3]]) -> [f64; 2] {
+    let multi: geo::MultiPoint<f64> = points.iter().map(|p| geo::Point::new(p[0], p[1])).collect();
+    let hull: Vec<[f64; 2]> = multi.convex_hull().exterior().points().map(|p| [p.x(), p.y()]).collect();
+    let area = |axis: [f64; 2]| {
+        let mut lo = [f64::MAX; 2];
+        let mut hi = [f64::MIN; 2];
+        hull.iter().for_each(|p| {
+            let q = [p[0] * axis[0] + p[1] * axis[1], -p[0] * axis[1] + p[1] * axis[0]];
+            for k in 0..2 {
+                lo[k] = lo[k].min(q[k]);
+                hi[k] = hi[k].max(q[k]);
+            }
+        });
+        (hi[0] - lo[0]) * (hi[1] - lo[1])
+    };
+    let mut best = ([1., 0.], area([1., 0.]));
+    hull.windows(2).for_each(|e| {
+        let d = [e[1][0] - e[0][0], e[1][1] - e[0][1]];
+        let len = (d[0] * d[0] + d[1] * d[1]).sqrt();
+        if len > 1e-9 {
+            let axis = [d[0] / len, d[1] / len];
+            let a = area(axis);
+            if a < best.1 - 1e-9 {
+                best = (axis, a);
+            }
+        }
+    });
+    best.0
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let len = dot(a, a).sqrt();
+    a.map(|x| x / len)
+}
+
+/// 不做变换的单位矩阵，内容本身已经是地心坐标时使用
+pub const IDENTITY: [f64; 16] = [
+    1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+];
+
+/// 地心坐标转 WGS84 经纬度（度）和椭球高，纬度迭代到收敛
+pub fn ecef_to_cartographic(p: [f64; 3]) -> [f64; 3] {
+    let a = 6378137.0;
+    let e2 = 0.006694379990141317;
+    let lon = p[1].atan2(p[0]);
+    let r = (p[0] * p[0] + p[1] * p[1]).sqrt();
+    let mut lat = p[2].atan2(r * (1. - e2));
+    let mut h = 0.;
+    for _ in 0..10 {
+        let n = a / (1. - e2 * lat.sin() * lat.sin()).sqrt();
+        h = if lat.cos().abs() > 1e-10 {
+            r / lat.cos() - n
+        } else {
+            p[2].abs() - n * (1. - e2)
+        };
+        let next = p[2].atan2(r * (1. - e2 * n / (n + h)));
+        if (next - lat).abs() < 1e-14 {
+            lat = next;
+            break;
+        }
+        lat = next;
+    }
+    [lon.to_degrees(), lat.to_degrees(), h]
+}
+
+/// WGS84 经纬度（度）和椭球高转地心坐标
+pub fn cartographic_to_ecef(lon: f64, lat: f64, h: f64) -> [f64; 3] {
+    let a = 6378137.0;
+    let e2 = 0.006694379990141317;
+    let (lonr, latr) = (lon.to_radians(), lat.to_radians());
+    let n = a / (1. - e2 * latr.sin() * latr.sin()).sqrt();
+    [
+        (n + h) * latr.cos() * lonr.cos(),
+        (n + h) * latr.cos() * lonr.sin(),
+        (n * (1. - e2) + h) * latr.sin(),
+    ]
+}
+
+/// 写出 tileset.json，每个子瓦片为内容的 uri 和包围范围，`origin` 为子瓦片局部坐标的原点，
+/// 内容已经是地心坐标时为 None；根瓦片没有 transform，box 和 sphere 换到地心坐标
+pub fn write_tileset(
+    children: &[(String, Bounds)],
+    gltf_up_axis: &str,
+    volume: VolumeKind,
+    origin: Option<Origin>,
+) -> std::io::Result<()> {
+    let test = tileset_json(children, gltf_up_axis, volume, origin);
+    std::fs::create_dir_all("b3dm")?;
+    let data = serde_json::to_string(&test).expect("Serialization error");
+    // println!("{:?}", data);
+    std::fs::write("b3dm/tileset.json", data.as_bytes())
+}
+
+fn tileset_json(
+    children: &[(String, Bounds)],
+    gltf_up_axis: &str,
+    volume: VolumeKind,
+    origin: Option<Origin>,
+) -> Tiles {
+    let transform = match origin {
+        Some((cx, cy, bottom_h)) => enu_frame(cx, cy, bottom_h as f64),
+        None => IDENTITY,
+    };
+    let bounds: Vec<Bounds> = children.iter().map(|(_, x)| x.clone()).collect();
+    let root = Bounds::merge(&bounds).unwrap();
+    Tiles {
+        asset: Asset {
+            gltf_up_axis: gltf_up_axis.to_string(),
+            version: "1.0".to_string(),
+        },
+        geometric_error: 200,
+        root: Root {
+            bounding_volume: root.bounding_volume(volume, false),
+            geometric_error: 200,
+            refine: "REPLACE".to_string(),
+            children: children
+                .iter()
+                .map(|(uri, bounds)| Child {
+                    content: Content { uri: uri.clone() },
+                    geometric_error: 100.,
+                    refine: "REPLACE".to_string(),
+                    bounding_volume: bounds.bounding_volume(volume, origin.is_some()),
+                    transform,
+                })
+                .collect(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: Origin = (116.391, 39.907, 45);
+
+    fn frame() -> [f64; 16] {
+        enu_frame(ORIGIN.0, ORIGIN.1, ORIGIN.2 as f64)
+    }
+
+    // 斜放的L形建筑，底面在局部高度0，屋顶高30米，(dx, dy) 为它在局部坐标下的偏移
+    fn building(dx: f64, dy: f64) -> Vec<[f64; 3]> {
+        let (c, s) = (0.6f64, 0.8f64);
+        let outline = [[0., 0.], [40., 0.], [40., 15.], [15., 15.], [15., 35.], [0., 35.]];
+        outline
+            .iter()
+            .flat_map(|p| {
+                let q = [p[0] * c - p[1] * s + dx, p[0] * s + p[1] * c + dy];
+                [[q[0], q[1], 0.], [q[0], q[1], 30.]]
+            })
+            .collect()
+    }
+
+    fn in_box(b: &[f64; 12], p: [f64; 3]) -> bool {
+        let d = sub(p, [b[0], b[1], b[2]]);
+        (0..3).all(|k| {
+            let h = [b[3 + k * 3], b[4 + k * 3], b[5 + k * 3]];
+            dot(d, h).abs() <= dot(h, h) + 1e-6
+        })
+    }
+
+    fn in_sphere(s: &[f64; 4], p: [f64; 3]) -> bool {
+        let d = sub(p, [s[0], s[1], s[2]]);
+        dot(d, d).sqrt() <= s[3] + 1e-6
+    }
+
+    // region 为度
+    fn in_region(r: &[f64; 6], p: [f64; 3]) -> bool {
+        let [lon, lat, h] = ecef_to_cartographic(p);
+        lon >= r[0] && lon <= r[2] && lat >= r[1] && lat <= r[3] && h >= r[4] && h <= r[5]
+    }
+
+    fn volume(tile: &Child) -> (&BoundingVolume, [f64; 16]) {
+        (&tile.bounding_volume, tile.transform)
+    }
+
+    #[test]
+    fn content_inside_every_volume() {
+        let frame = frame();
+        let points = building(-20., 10.);
+        let b = Bounds::new(&points, &frame, 0.).unwrap();
+        for p in &points {
+            let q = apply(&frame, *p, 1.);
+            assert!(in_region(&b.region, q), "{:?} 不在 region 内", p);
+            assert!(in_box(&b.local_box, *p) && in_box(&b.ecef_box, q), "{:?} 不在 box 内", p);
+            assert!(in_sphere(&b.local_sphere, *p) && in_sphere(&b.ecef_sphere, q), "{:?} 不在 sphere 内", p);
+        }
+    }
+
+    #[test]
+    fn region_covers_roof_and_base() {
+        let frame = frame();
+        let points = building(0., 0.);
+        let b = Bounds::new(&points, &frame, 0.5).unwrap();
+        let heights: Vec<f64> = points.iter().map(|p| ecef_to_cartographic(apply(&frame, *p, 1.))[2]).collect();
+        let base = heights.iter().cloned().fold(f64::MAX, f64::min);
+        let roof = heights.iter().cloned().fold(f64::MIN, f64::max);
+        // 每个底面点正上方30米都有屋顶点，东北天坐标系严格正交，只差地球曲率带来的亚毫米
+        assert!((roof - base - 30.).abs() < 1e-3);
+        assert!(b.region[4] <= base - 0.5 && b.region[5] >= roof + 0.5);
+        // 原点就在建筑底面，底面的椭球高与原点高度相差不大
+        assert!((base - ORIGIN.2 as f64).abs() < 1.);
+    }
+
+    #[test]
+    fn root_contains_every_child() {
+        let frame = frame();
+        for count in [1, 4] {
+            let children: Vec<(String, Bounds)> = (0..count)
+                .map(|i| {
+                    let points = building(i as f64 * 300. - 400., i as f64 * 150.);
+                    (format!("{}.b3dm", i), Bounds::new(&points, &frame, 0.).unwrap())
+                })
+                .collect();
+            for kind in [VolumeKind::Region, VolumeKind::Box, VolumeKind::Sphere] {
+                let tiles = tileset_json(&children, "Z", kind, Some(ORIGIN));
+                let root = &tiles.root.bounding_volume;
+                for child in &tiles.root.children {
+                    let (child, m) = volume(child);
+                    match (root, child) {
+                        (BoundingVolume::Region(r), BoundingVolume::Region(c)) => {
+                            assert!(r[0] <= c[0] && r[1] <= c[1] && r[2] >= c[2] && r[3] >= c[3]);
+                            assert!(r[4] <= c[4] && r[5] >= c[5]);
+                        }
+                        (BoundingVolume::Box(r), BoundingVolume::Box(c)) => {
+                            for p in box_corners(c) {
+                                assert!(in_box(r, apply(&m, p, 1.)), "{}个子瓦片时 box 包不住子瓦片", count);
+                            }
+                        }
+                        (BoundingVolume::Sphere(r), BoundingVolume::Sphere(c)) => {
+                            let center = apply(&m, [c[0], c[1], c[2]], 1.);
+                            let d = sub(center, [r[0], r[1], r[2]]);
+                            assert!(
+                                dot(d, d).sqrt() + c[3] * max_stretch(&m) <= r[3] + 1e-6,
+                                "{}个子瓦片时 sphere 包不住子瓦片",
+                                count
+                            );
+                        }
+                        _ => panic!("根瓦片与子瓦片的包围体类型不同"),
+                    }
+                }
+            }
+        }
+    }
+
+    // 按 `axis` 方向和与它垂直的方向量出的水平外接矩形面积
+    fn footprint_area(points: &[[f64; 3]], axis: [f64; 2]) -> f64 {
+        let b = oriented_box(points, &[[axis[0], axis[1], 0.], [-axis[1], axis[0], 0.], [0., 0., 1.]], 0.);
+        let half = |k: usize| {
+            let h = [b[3 + k * 3], b[4 + k * 3], b[5 + k * 3]];
+            dot(h, h).sqrt()
+        };
+        4. * half(0) * half(1)
+    }
+
+    #[test]
+    fn local_box_is_minimum_area_rectangle() {
+        // 40×10 的矩形转过 atan(4/3)，四角之外再加几个内部点
+        let (c, s) = (0.6f64, 0.8f64);
+        let rotate = |p: [f64; 2], z: f64| [p[0] * c - p[1] * s + 100., p[0] * s + p[1] * c - 50., z];
+        let mut points: Vec<[f64; 3]> = [[0., 0.], [40., 0.], [40., 10.], [0., 10.]]
+            .iter()
+            .flat_map(|p| [rotate(*p, 0.), rotate(*p, 30.)])
+            .collect();
+        points.extend([rotate([20., 5.], 12.), rotate([3., 7.], 20.)]);
+        let axis = footprint_axis(&points);
+        assert!((axis[0] * s - axis[1] * c).abs() < 1e-9 || (axis[0] * c + axis[1] * s).abs() < 1e-9);
+
+        let b = Bounds::new(&points, &frame(), 0.).unwrap().local_box;
+        let center = rotate([20., 5.], 15.);
+        (0..3).for_each(|k| assert!((b[k] - center[k]).abs() < 1e-9));
+        let mut half: Vec<f64> = (0..3)
+            .map(|k| {
+                let h = [b[3 + k * 3], b[4 + k * 3], b[5 + k * 3]];
+                dot(h, h).sqrt()
+            })
+            .collect();
+        half.sort_by(f64::total_cmp);
+        // Bounds 给每个半轴多留1毫米
+        assert!(half.iter().zip([5., 15., 20.]).all(|(a, b)| (a - b - 1e-3).abs() < 1e-9), "{:?}", half);
+
+        // 各半轴加上 margin
+        let wide = oriented_box(&points, &[[axis[0], axis[1], 0.], [-axis[1], axis[0], 0.], [0., 0., 1.]], 0.5);
+        assert!((wide[11] - 15.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn footprint_axis_beats_every_rotation() {
+        // 凹的L形和不规则的点集，逐个角度转一遍都找不到更小的外接矩形
+        let scattered: Vec<[f64; 3]> = (0..30)
+            .map(|i| {
+                let t = i as f64 * 2.399;
+                [(i as f64).sqrt() * 7. * t.cos() + 3. * t.sin(), (i as f64).sqrt() * 4. * t.sin(), 0.]
+            })
+            .collect();
+        for points in [building(5., -3.), scattered] {
+            let best = footprint_area(&points, footprint_axis(&points));
+            let brute = (0..1800)
+                .map(|i| (i as f64 / 10.).to_radians())
+                .map(|a| footprint_area(&points, [a.cos(), a.sin()]))
+                .fold(f64::MAX, f64::min);
+            assert!(best <= brute + 1e-6, "{} > {}", best, brute);
+        }
+        // 只有一个点时取东向，盒子退化成一个点
+        let axis = footprint_axis(&[[1., 2., 3.]]);
+        assert!(axis.iter().all(|x| x.is_finite()));
+        assert_eq!(oriented_box(&[[1., 2., 3.]], &[[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]], 0.)[..3], [1., 2., 3.]);
+    }
+
+    #[test]
+    fn child_transform_matches_origin_json() {
+        let children = vec![("0.b3dm".to_string(), Bounds::new(&building(0., 0.), &frame(), 0.).unwrap())];
+        let text = serde_json::to_string(&tileset_json(&children, "Z", VolumeKind::Box, Some(ORIGIN))).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let transform: [f64; 16] = serde_json::from_value(json["root"]["children"][0]["transform"].clone()).unwrap();
+        // 按 f64 写出；serde_json 默认的解析可能差最后一位，这里只要求在毫米以下
+        transform.iter().zip(frame()).for_each(|(a, b)| assert!((a - b).abs() < 1e-6));
+        let dir = std::env::temp_dir().join("shp_to_3dtiles_tileset");
+        std::fs::create_dir_all(&dir).unwrap();
+        crate::export::write_origin(ORIGIN, &dir).unwrap();
+        let origin: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("origin.json")).unwrap()).unwrap();
+        let exported: [f64; 16] = serde_json::from_value(origin["transform"].clone()).unwrap();
+        assert_eq!(exported, transform);
+
+        // 旋转部分为单位正交矩阵，平移为原点的地心坐标
+        let axes = [0, 4, 8].map(|k| [transform[k], transform[k + 1], transform[k + 2]]);
+        (0..3).for_each(|i| {
+            (0..3).for_each(|j| {
+                let expected = if i == j { 1. } else { 0. };
+                assert!((dot(axes[i], axes[j]) - expected).abs() < 1e-15);
+            })
+        });
+        let o = cartographic_to_ecef(ORIGIN.0, ORIGIN.1, ORIGIN.2 as f64);
+        (0..3).for_each(|i| assert!((transform[12 + i] - o[i]).abs() < 1e-6));
+        assert_eq!(tileset_json(&children, "Z", VolumeKind::Box, None).root.children[0].transform, IDENTITY);
+    }
+}
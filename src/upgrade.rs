@@ -0,0 +1,670 @@
+// 把按 3D Tiles 1.0 生成的瓦片升级到 1.1：b3dm 换成内嵌的 glb，_BATCHID 改为 EXT_mesh_features 的要素 ID，
+// 批量表写成 EXT_structural_metadata 的属性表；tileset.json 改为 1.1 版本，内容的 uri 指向新文件，
+// cmpt 拆成多个内容，i3dm 和 pnts 在 1.1 中仍然可用，原样保留
+
+use crate::inspect;
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 升级时转换和复制的文件数
+#[derive(Default)]
+pub struct Summary {
+    pub tilesets: usize,
+    pub glbs: usize,
+    pub copied: usize,
+}
+
+/// 把 b3dm 转成 glb：`z_up` 为原 tileset 的 gltfUpAxis 是否为Z，1.1 的 glb 总是y轴朝上，
+/// 为Z时以及有 RTC_CENTER 时在场景外再套一层节点
+pub fn b3dm_to_glb(data: &[u8], z_up: bool) -> Result<Vec<u8>, String> {
+    let mut issues = vec![];
+    let tile = inspect::parse_tile(data, &mut issues)?;
+    if tile.magic != "b3dm" {
+        return Err(format!("不是 b3dm 文件: {}", tile.magic));
+    }
+    let batch_length = tile.feature_table["BATCH_LENGTH"]
+        .as_u64()
+        .ok_or("要素表缺少 BATCH_LENGTH")? as usize;
+    let rtc = match &tile.feature_table["RTC_CENTER"] {
+        Value::Null => [0.; 3],
+        x => numbers(x).ok_or("RTC_CENTER 必须是3个数")?,
+    };
+    let glb = gltf::binary::Glb::from_slice(inspect::trim_glb(tile.glb)).map_err(|e| format!("glb 读取错误: {}", e))?;
+    let mut root: Value = serde_json::from_slice(&glb.json).map_err(|e| format!("glTF JSON 解析错误: {}", e))?;
+    let mut bin = glb.bin.map(Cow::into_owned).unwrap_or_default();
+
+    wrap_scenes(&mut root, z_up, rtc);
+    let mut extensions = vec![];
+    let table = match &tile.batch_table {
+        Some(batch_table) if batch_length > 0 => {
+            property_table(&mut root, &mut bin, batch_table, tile.batch_binary, batch_length)?;
+            extensions.push("EXT_structural_metadata");
+            Some(0)
+        }
+        _ => None,
+    };
+    if batch_length > 0 && feature_ids(&mut root, batch_length, table) {
+        extensions.push("EXT_mesh_features");
+    }
+    if !extensions.is_empty() {
+        let used = root["extensionsUsed"].as_array().cloned().unwrap_or_default();
+        let mut used: Vec<Value> = used.into_iter().filter(|x| !extensions.iter().any(|e| x == e)).collect();
+        used.extend(extensions.iter().map(|x| Value::from(*x)));
+        root["extensionsUsed"] = used.into();
+    }
+    if !bin.is_empty() {
+        match root["buffers"].as_array_mut().and_then(|x| x.first_mut()) {
+            Some(buffer) => buffer["byteLength"] = bin.len().into(),
+            None => root["buffers"] = json!([{ "byteLength": bin.len() }]),
+        }
+    }
+
+    let json = serde_json::to_vec(&root).expect("Serialization error");
+    let glb = gltf::binary::Glb {
+        header: glb.header,
+        json: Cow::Owned(json),
+        bin: (!bin.is_empty()).then_some(Cow::Owned(bin)),
+    };
+    glb.to_vec().map_err(|e| format!("glb 输出错误: {}", e))
+}
+
+// 场景的根节点外再套一个节点：Z轴朝上时先转成y轴朝上，再按 RTC_CENTER 平移（换到y轴朝上的坐标）
+fn wrap_scenes(root: &mut Value, z_up: bool, rtc: [f64; 3]) {
+    if !z_up && rtc == [0.; 3] {
+        return;
+    }
+    let rotation = if z_up {
+        [1., 0., 0., 0., 0., 0., -1., 0., 0., 1., 0., 0.]
+    } else {
+        [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0.]
+    };
+    let mut matrix = rotation.to_vec();
+    matrix.extend([rtc[0], rtc[2], -rtc[1], 1.]);
+    let Some(scenes) = root["scenes"].as_array().cloned() else {
+        return;
+    };
+    let mut nodes = root["nodes"].as_array().cloned().unwrap_or_default();
+    let scenes: Vec<Value> = scenes
+        .into_iter()
+        .map(|mut scene| {
+            let children = scene["nodes"].take();
+            nodes.push(json!({ "children": children, "matrix": matrix }));
+            scene["nodes"] = json!([nodes.len() - 1]);
+            scene
+        })
+        .collect();
+    root["nodes"] = nodes.into();
+    root["scenes"] = scenes.into();
+}
+
+// 图元的 _BATCHID 改名为 _FEATURE_ID_0，并用 EXT_mesh_features 指向属性表；Draco 扩展里的属性一起改名。
+// 有图元带要素 ID 时返回 true
+fn feature_ids(root: &mut Value, batch_length: usize, table: Option<usize>) -> bool {
+    let mut found = false;
+    let Some(meshes) = root.get_mut("meshes").and_then(Value::as_array_mut) else {
+        return false;
+    };
+    let primitives = meshes
+        .iter_mut()
+        .filter_map(|mesh| mesh.get_mut("primitives").and_then(Value::as_array_mut))
+        .flatten();
+    for primitive in primitives {
+        let Some(id) = primitive["attributes"].as_object_mut().and_then(|x| x.remove("_BATCHID")) else {
+            continue;
+        };
+        primitive["attributes"]["_FEATURE_ID_0"] = id;
+        // 用索引取不存在的键会插入 null，这里只能按路径查找
+        let draco = primitive
+            .pointer_mut("/extensions/KHR_draco_mesh_compression/attributes")
+            .and_then(Value::as_object_mut);
+        if let Some(attributes) = draco {
+            if let Some(id) = attributes.remove("_BATCHID") {
+                attributes.insert("_FEATURE_ID_0".to_string(), id);
+            }
+        }
+        let mut feature_id = json!({ "featureCount": batch_length, "attribute": 0 });
+        if let Some(table) = table {
+            feature_id["propertyTable"] = table.into();
+        }
+        primitive["extensions"]["EXT_mesh_features"] = json!({ "featureIds": [feature_id] });
+        found = true;
+    }
+    found
+}
+
+// 批量表写成 EXT_structural_metadata：每列一个类属性，数据追加到 BIN 数据块末尾，各自按8字节对齐
+fn property_table(
+    root: &mut Value,
+    bin: &mut Vec<u8>,
+    batch_table: &Value,
+    batch_binary: &[u8],
+    count: usize,
+) -> Result<(), String> {
+    let columns = batch_table.as_object().ok_or("批量表 JSON 不是对象")?;
+    let mut views = root["bufferViews"].as_array().cloned().unwrap_or_default();
+    let mut view = |data: Vec<u8>| {
+        bin.resize((bin.len() + 7) & !7, 0);
+        views.push(json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": data.len() }));
+        bin.extend(data);
+        views.len() - 1
+    };
+    let mut class = Map::new();
+    let mut table = Map::new();
+    for (key, column) in columns {
+        if key == "extensions" || key == "extras" {
+            println!("批量表的{}没有转换", key);
+            continue;
+        }
+        let id = identifier(key, &class);
+        let (mut definition, property) = match column {
+            Value::Array(values) if values.len() == count => encode_column(values, &mut view),
+            Value::Array(values) => {
+                return Err(format!("批量表属性{}有{}个值，BATCH_LENGTH 为{}", key, values.len(), count));
+            }
+            reference => {
+                let (definition, data) = binary_column(key, reference, batch_binary, count)?;
+                (definition, json!({ "values": view(data) }))
+            }
+        };
+        if id != *key {
+            definition["name"] = key.clone().into();
+        }
+        class.insert(id.clone(), definition);
+        table.insert(id, property);
+    }
+    root["bufferViews"] = views.into();
+    root["extensions"]["EXT_structural_metadata"] = json!({
+        "schema": {
+            "id": "batch_table",
+            "classes": { "feature": { "properties": class } }
+        },
+        "propertyTables": [{ "class": "feature", "count": count, "properties": table }]
+    });
+    Ok(())
+}
+
+// 按 JSON 数组中的值选类型：全是布尔值为 BOOLEAN，全是 32 位整数为 INT32，全是数值为 FLOAT64，其他都写成 STRING；
+// 有 null 时数值用 noData 表示，布尔值改用 STRING
+fn encode_column(values: &[Value], view: &mut impl FnMut(Vec<u8>) -> usize) -> (Value, Value) {
+    let present: Vec<&Value> = values.iter().filter(|x| !x.is_null()).collect();
+    let nulls = present.len() < values.len();
+    let all = |f: fn(&Value) -> bool| !present.is_empty() && present.iter().all(|x| f(x));
+    if all(Value::is_boolean) && !nulls {
+        let mut data = vec![0u8; values.len().div_ceil(8)];
+        values.iter().enumerate().for_each(|(i, x)| {
+            if x.as_bool() == Some(true) {
+                data[i / 8] |= 1 << (i % 8);
+            }
+        });
+        return (json!({ "type": "BOOLEAN" }), json!({ "values": view(data) }));
+    }
+    if all(|x| x.as_i64().is_some_and(|v| i32::try_from(v).is_ok() && v != i32::MIN as i64)) {
+        let data = values
+            .iter()
+            .flat_map(|x| (x.as_i64().unwrap_or(i32::MIN as i64) as i32).to_le_bytes())
+            .collect();
+        let mut definition = json!({ "type": "SCALAR", "componentType": "INT32" });
+        if nulls {
+            definition["noData"] = i32::MIN.into();
+        }
+        return (definition, json!({ "values": view(data) }));
+    }
+    if all(Value::is_number) {
+        let data = values.iter().flat_map(|x| x.as_f64().unwrap_or(f64::MIN).to_le_bytes()).collect();
+        let mut definition = json!({ "type": "SCALAR", "componentType": "FLOAT64" });
+        if nulls {
+            definition["noData"] = f64::MIN.into();
+        }
+        return (definition, json!({ "values": view(data) }));
+    }
+    let mut data = vec![];
+    let mut offsets = vec![0u32];
+    values.iter().for_each(|x| {
+        match x {
+            Value::Null => (),
+            Value::String(s) => data.extend(s.as_bytes()),
+            x => data.extend(x.to_string().into_bytes()),
+        }
+        offsets.push(data.len() as u32);
+    });
+    let mut definition = json!({ "type": "STRING" });
+    if nulls {
+        definition["noData"] = "".into();
+    }
+    let offsets = offsets.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let property = json!({ "values": view(data), "stringOffsets": view(offsets), "stringOffsetType": "UINT32" });
+    (definition, property)
+}
+
+// 批量表二进制部分中的列，分量类型换成元数据的写法，数据原样复制
+fn binary_column(key: &str, reference: &Value, binary: &[u8], count: usize) -> Result<(Value, Vec<u8>), String> {
+    let offset = reference["byteOffset"].as_u64();
+    let component = reference["componentType"].as_str().and_then(|x| {
+        Some(match x {
+            "BYTE" => ("INT8", 1),
+            "UNSIGNED_BYTE" => ("UINT8", 1),
+            "SHORT" => ("INT16", 2),
+            "UNSIGNED_SHORT" => ("UINT16", 2),
+            "INT" => ("INT32", 4),
+            "UNSIGNED_INT" => ("UINT32", 4),
+            "FLOAT" => ("FLOAT32", 4),
+            "DOUBLE" => ("FLOAT64", 8),
+            _ => return None,
+        })
+    });
+    let kind = reference["type"].as_str().and_then(|x| {
+        Some(match x {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            _ => return None,
+        })
+    });
+    let (Some(offset), Some((component, size)), Some(components)) = (offset, component, kind) else {
+        return Err(format!("批量表属性{}既不是数组也不是完整的二进制引用", key));
+    };
+    let start = offset as usize;
+    let end = start + size * components * count;
+    let data = binary
+        .get(start..end)
+        .ok_or(format!("批量表属性{}超出了二进制部分", key))?;
+    let definition = json!({ "type": reference["type"], "componentType": component });
+    Ok((definition, data.to_vec()))
+}
+
+// 属性 ID 只能由字母、数字和下划线组成且不以数字开头，其他字符换成下划线，重名时加序号
+fn identifier(key: &str, used: &Map<String, Value>) -> String {
+    let mut id: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if id.is_empty() || id.starts_with(|c: char| c.is_ascii_digit()) {
+        id.insert(0, '_');
+    }
+    let base = id.clone();
+    let mut n = 1;
+    while used.contains_key(&id) {
+        n += 1;
+        id = format!("{}_{}", base, n);
+    }
+    id
+}
+
+/// 升级 `input` 这个 tileset.json 及其引用的内容，写到 `output` 目录下，目录结构不变
+pub fn upgrade(input: &str, output: &str) -> Result<Summary, String> {
+    let input = Path::new(input);
+    let input_dir = input.parent().unwrap_or(Path::new(""));
+    let output = Path::new(output);
+    fs::create_dir_all(output).map_err(|e| format!("无法创建输出目录{}: {}", output.display(), e))?;
+    let same = match (input_dir.canonicalize(), output.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if same {
+        return Err("输出目录不能与输入的 tileset.json 所在目录相同".to_string());
+    }
+    let file_name = input.file_name().ok_or("输入必须是 tileset.json 文件")?;
+    let mut upgrader = Upgrader {
+        input_dir: input_dir.to_path_buf(),
+        output_dir: output.to_path_buf(),
+        summary: Summary::default(),
+    };
+    upgrader.tileset(Path::new(file_name))?;
+    Ok(upgrader.summary)
+}
+
+struct Upgrader {
+    input_dir: PathBuf,
+    output_dir: PathBuf,
+    summary: Summary,
+}
+
+impl Upgrader {
+    // `path` 相对于输入目录；外部 tileset 的 gltfUpAxis 按它自己的 asset 判断
+    fn tileset(&mut self, path: &Path) -> Result<(), String> {
+        let source = self.input_dir.join(path);
+        let data = fs::read(&source).map_err(|e| format!("文件{}读取错误: {}", source.display(), e))?;
+        let mut tileset: Value =
+            serde_json::from_slice(&data).map_err(|e| format!("{}解析错误: {}", source.display(), e))?;
+        let z_up = matches!(tileset["asset"]["gltfUpAxis"].as_str(), Some("Z" | "z"));
+        if let Some(asset) = tileset["asset"].as_object_mut() {
+            asset.insert("version".to_string(), "1.1".into());
+            asset.remove("gltfUpAxis");
+        }
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let root = tileset.get_mut("root").ok_or(format!("{}缺少 root", source.display()))?;
+        self.tile(root, &dir, z_up)?;
+        self.write(path, &serde_json::to_vec(&tileset).expect("Serialization error"))?;
+        self.summary.tilesets += 1;
+        Ok(())
+    }
+
+    fn tile(&mut self, tile: &mut Value, dir: &Path, z_up: bool) -> Result<(), String> {
+        // 1.0 的 content 只有一个，转换后可能变成多个，写进 contents
+        let mut contents = match (tile["content"].take(), tile["contents"].take()) {
+            (Value::Null, Value::Array(x)) => x,
+            (Value::Null, _) => vec![],
+            (x, _) => vec![x],
+        };
+        let mut upgraded = vec![];
+        for content in contents.iter_mut() {
+            if let Some(url) = content.as_object_mut().and_then(|x| x.remove("url")) {
+                content["uri"] = url;
+            }
+            let Some(uri) = content["uri"].as_str().map(str::to_string) else {
+                upgraded.push(content.take());
+                continue;
+            };
+            for uri in self.content(&uri, dir, z_up)? {
+                let mut content = content.clone();
+                content["uri"] = uri.into();
+                upgraded.push(content);
+            }
+        }
+        if let Some(object) = tile.as_object_mut() {
+            object.remove("content");
+            object.remove("contents");
+            match upgraded.len() {
+                0 => (),
+                1 => {
+                    object.insert("content".to_string(), upgraded.remove(0));
+                }
+                _ => {
+                    object.insert("contents".to_string(), upgraded.into());
+                }
+            }
+        }
+        if let Some(children) = tile.get_mut("children").and_then(Value::as_array_mut) {
+            for child in children {
+                self.tile(child, dir, z_up)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 转换一个内容，返回新的 uri：b3dm 换成同名的 glb，cmpt 按内部瓦片拆成几个文件，其他的原样复制
+    fn content(&mut self, uri: &str, dir: &Path, z_up: bool) -> Result<Vec<String>, String> {
+        if uri.contains("://") || uri.starts_with("data:") {
+            return Ok(vec![uri.to_string()]);
+        }
+        let path = dir.join(uri);
+        let source = self.input_dir.join(&path);
+        let data = fs::read(&source).map_err(|e| format!("文件{}读取错误: {}", source.display(), e))?;
+        let stem = uri.rsplit_once('.').map_or(uri, |x| x.0);
+        match data.get(..4) {
+            Some(b"b3dm") => {
+                let glb = b3dm_to_glb(&data, z_up).map_err(|e| format!("{}: {}", source.display(), e))?;
+                let uri = format!("{}.glb", stem);
+                self.write(&dir.join(&uri), &glb)?;
+                self.summary.glbs += 1;
+                Ok(vec![uri])
+            }
+            Some(b"cmpt") => {
+                let mut issues = vec![];
+                let tiles = inspect::parse_cmpt(&data, &mut issues).map_err(|e| format!("{}: {}", source.display(), e))?;
+                let mut uris = vec![];
+                for (i, tile) in tiles.iter().enumerate() {
+                    let extension = String::from_utf8_lossy(&tile[..4]).to_string();
+                    let uri = format!("{}_{}.{}", stem, i, extension);
+                    let data = match extension.as_str() {
+                        "b3dm" => {
+                            self.summary.glbs += 1;
+                            let glb = b3dm_to_glb(tile, z_up).map_err(|e| format!("{}: {}", source.display(), e))?;
+                            uris.push(format!("{}_{}.glb", stem, i));
+                            self.write(&dir.join(uris.last().unwrap()), &glb)?;
+                            continue;
+                        }
+                        "i3dm" => self.i3dm(tile, &path, z_up)?,
+                        _ => tile.to_vec(),
+                    };
+                    self.write(&dir.join(&uri), &data)?;
+                    self.summary.copied += 1;
+                    uris.push(uri);
+                }
+                Ok(uris)
+            }
+            Some(b"i3dm") => {
+                let data = self.i3dm(&data, &path, z_up)?;
+                self.write(&path, &data)?;
+                self.summary.copied += 1;
+                Ok(vec![uri.to_string()])
+            }
+            _ if data.first() == Some(&b'{') => {
+                self.tileset(&path)?;
+                Ok(vec![uri.to_string()])
+            }
+            _ => {
+                self.write(&path, &data)?;
+                self.summary.copied += 1;
+                Ok(vec![uri.to_string()])
+            }
+        }
+    }
+
+    // i3dm 的模型在 1.1 中总按y轴朝上转换，原来为Z时把上方向换成 右方向×原上方向 的反方向，
+    // 模型的朝向才不变；外部模型一起复制
+    fn i3dm(&mut self, data: &[u8], path: &Path, z_up: bool) -> Result<Vec<u8>, String> {
+        let mut issues = vec![];
+        let tile = inspect::parse_tile(data, &mut issues)?;
+        if tile.gltf_format == Some(0) {
+            let uri = String::from_utf8_lossy(tile.glb).trim_end_matches(['\0', ' ']).to_string();
+            let model = path.parent().unwrap_or(Path::new("")).join(&uri);
+            match fs::read(self.input_dir.join(&model)) {
+                Ok(x) => self.write(&model, &x)?,
+                Err(e) => println!("模型文件{}读取错误: {}", model.display(), e),
+            }
+        }
+        if !z_up {
+            return Ok(data.to_vec());
+        }
+        let table = &tile.feature_table;
+        let count = table["INSTANCES_LENGTH"].as_u64().unwrap_or(0) as usize;
+        let (Some(up), Some(right)) = (table["NORMAL_UP"]["byteOffset"].as_u64(), table["NORMAL_RIGHT"]["byteOffset"].as_u64())
+        else {
+            println!("{}没有 NORMAL_UP 和 NORMAL_RIGHT，模型的朝向会改变", path.display());
+            return Ok(data.to_vec());
+        };
+        // 要素表二进制部分在文件中的起点
+        let start = 32 + u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+        let read = |offset: usize| -> [f32; 3] {
+            [0, 1, 2].map(|k| f32::from_le_bytes(data[offset + k * 4..offset + k * 4 + 4].try_into().unwrap()))
+        };
+        let mut data = data.to_vec();
+        if start + right as usize + count * 12 > data.len() || start + up as usize + count * 12 > data.len() {
+            return Err(format!("{}的 NORMAL_UP 或 NORMAL_RIGHT 超出了要素表", path.display()));
+        }
+        for i in 0..count {
+            let u = read(start + up as usize + i * 12);
+            let r = read(start + right as usize + i * 12);
+            let new_up = [u[1] * r[2] - u[2] * r[1], u[2] * r[0] - u[0] * r[2], u[0] * r[1] - u[1] * r[0]];
+            let offset = start + up as usize + i * 12;
+            for (k, v) in new_up.iter().enumerate() {
+                data[offset + k * 4..offset + k * 4 + 4].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+        Ok(data)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), String> {
+        let target = self.output_dir.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("无法创建目录{}: {}", parent.display(), e))?;
+        }
+        fs::write(&target, data).map_err(|e| format!("文件{}写入错误: {}", target.display(), e))
+    }
+}
+
+fn numbers(json: &Value) -> Option<[f64; 3]> {
+    let values: Vec<f64> = json.as_array()?.iter().map(Value::as_f64).collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b3dm::MakeB3dm;
+
+    // 一个三角形的 glb，三个顶点的 _BATCHID 为 0、1、2；`draco` 时图元再带一个 Draco 扩展（没有码流，只看属性名）
+    fn glb(draco: bool) -> Vec<u8> {
+        let mut bin = vec![];
+        [[0f32, 0., 0.], [1., 0., 0.], [0., 1., 0.]]
+            .iter()
+            .flatten()
+            .for_each(|x| bin.extend(x.to_le_bytes()));
+        [0u16, 1, 2, 0].iter().for_each(|x| bin.extend(x.to_le_bytes()));
+        let mut primitive = json!({ "attributes": { "POSITION": 0, "_BATCHID": 1 } });
+        if draco {
+            primitive["extensions"] = json!({
+                "KHR_draco_mesh_compression": { "bufferView": 0, "attributes": { "POSITION": 0, "_BATCHID": 2 } }
+            });
+        }
+        let root = json!({
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "primitives": [primitive] }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "buffers": [{ "byteLength": bin.len() }]
+        });
+        gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                length: 0,
+            },
+            json: Cow::Owned(serde_json::to_vec(&root).unwrap()),
+            bin: Some(Cow::Owned(bin)),
+        }
+        .to_vec()
+        .unwrap()
+    }
+
+    fn b3dm(glb: &[u8], feature_table: &Value, batch_table: &Value) -> Vec<u8> {
+        let mut data = vec![];
+        MakeB3dm {
+            glb: Some(Cow::Borrowed(glb)),
+        }
+        .to_writer(&mut data, feature_table.to_string().into_bytes(), batch_table.to_string().into_bytes())
+        .unwrap();
+        data
+    }
+
+    // 转换后的 glTF JSON 和 BIN 数据块
+    fn upgraded(data: &[u8], z_up: bool) -> (Value, Vec<u8>) {
+        let glb = b3dm_to_glb(data, z_up).unwrap();
+        let glb = gltf::binary::Glb::from_slice(&glb).unwrap();
+        (serde_json::from_slice(&glb.json).unwrap(), glb.bin.unwrap().into_owned())
+    }
+
+    // 属性表中某个属性引用的 bufferView 的数据
+    fn view<'a>(root: &Value, bin: &'a [u8], index: &Value) -> &'a [u8] {
+        let view = &root["bufferViews"][index.as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        assert_eq!(offset % 8, 0);
+        &bin[offset..offset + view["byteLength"].as_u64().unwrap() as usize]
+    }
+
+    #[test]
+    fn batch_table_becomes_property_table() {
+        let batch_table = json!({
+            "name": ["a", null, "ccc"],
+            "height": [1.5, 2, 3],
+            "floors": [1, 2, 3],
+            "flat": [true, false, true],
+            "用途": ["住宅", "商业", "办公"],
+            "area": { "byteOffset": 0, "componentType": "FLOAT", "type": "SCALAR" }
+        });
+        let mut data = b3dm(&glb(false), &json!({ "BATCH_LENGTH": 3 }), &batch_table);
+        // 二进制部分放在批量表 JSON 后面，改写文件头中的长度
+        let binary: Vec<u8> = [10f32, 20., 30., 0.].iter().flat_map(|x| x.to_le_bytes()).collect();
+        let glb_start = 28 + u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize
+            + u32::from_le_bytes(data[20..24].try_into().unwrap()) as usize;
+        data.splice(glb_start..glb_start, binary.iter().copied());
+        let length = data.len() as u32;
+        data[8..12].copy_from_slice(&length.to_le_bytes());
+        data[24..28].copy_from_slice(&(binary.len() as u32).to_le_bytes());
+
+        let (root, bin) = upgraded(&data, false);
+        let attributes = &root["meshes"][0]["primitives"][0]["attributes"];
+        assert_eq!((&attributes["_FEATURE_ID_0"], &attributes["_BATCHID"]), (&json!(1), &Value::Null));
+        assert_eq!(
+            root["meshes"][0]["primitives"][0]["extensions"]["EXT_mesh_features"],
+            json!({ "featureIds": [{ "featureCount": 3, "attribute": 0, "propertyTable": 0 }] })
+        );
+        assert_eq!(root["extensionsUsed"], json!(["EXT_structural_metadata", "EXT_mesh_features"]));
+        assert_eq!(root["buffers"][0]["byteLength"], bin.len());
+
+        let metadata = &root["extensions"]["EXT_structural_metadata"];
+        assert_eq!(metadata["propertyTables"].as_array().unwrap().len(), 1);
+        let table = &metadata["propertyTables"][0];
+        assert_eq!((&table["class"], &table["count"]), (&json!("feature"), &json!(3)));
+        let class = &metadata["schema"]["classes"]["feature"]["properties"];
+        let properties = &table["properties"];
+
+        assert_eq!(class["name"], json!({ "type": "STRING", "noData": "" }));
+        assert_eq!(view(&root, &bin, &properties["name"]["values"]), b"accc");
+        let offsets: Vec<u8> = [0u32, 1, 1, 4].iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!(view(&root, &bin, &properties["name"]["stringOffsets"]), offsets.as_slice());
+        assert_eq!(properties["name"]["stringOffsetType"], "UINT32");
+
+        assert_eq!(class["height"]["componentType"], "FLOAT64");
+        let height: Vec<u8> = [1.5f64, 2., 3.].iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!(view(&root, &bin, &properties["height"]["values"]), height.as_slice());
+        assert_eq!(class["floors"]["componentType"], "INT32");
+        let floors: Vec<u8> = [1i32, 2, 3].iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!(view(&root, &bin, &properties["floors"]["values"]), floors.as_slice());
+        assert_eq!(class["flat"]["type"], "BOOLEAN");
+        assert_eq!(view(&root, &bin, &properties["flat"]["values"]), [0b101]);
+        assert_eq!(class["area"], json!({ "type": "SCALAR", "componentType": "FLOAT32" }));
+        assert_eq!(view(&root, &bin, &properties["area"]["values"]), &binary[..12]);
+        // 不是标识符的列名换成下划线，原名写在 name 里
+        assert_eq!(class["__"]["name"], "用途");
+        assert_eq!(view(&root, &bin, &properties["__"]["values"]), "住宅商业办公".as_bytes());
+
+        // 原来的顶点数据不变
+        assert_eq!(&bin[..42], &glb(false)[glb(false).len() - 44..][..42]);
+    }
+
+    #[test]
+    fn draco_attribute_is_renamed() {
+        let data = b3dm(&glb(true), &json!({ "BATCH_LENGTH": 3, "RTC_CENTER": [1, 2, 3] }), &json!({ "name": ["a", "b", "c"] }));
+        let (root, _) = upgraded(&data, true);
+        let primitive = &root["meshes"][0]["primitives"][0];
+        assert_eq!(
+            primitive["extensions"]["KHR_draco_mesh_compression"]["attributes"],
+            json!({ "POSITION": 0, "_FEATURE_ID_0": 2 })
+        );
+        assert_eq!(primitive["attributes"]["_FEATURE_ID_0"], 1);
+        // Z 轴朝上和 RTC_CENTER 换成套在场景外的节点
+        assert_eq!(root["scenes"][0]["nodes"], json!([1]));
+        let matrix = json!([1., 0., 0., 0., 0., 0., -1., 0., 0., 1., 0., 0., 1., 3., -2., 1.]);
+        assert_eq!(root["nodes"][1], json!({ "children": [0], "matrix": matrix }));
+    }
+
+    #[test]
+    fn mismatched_batch_table_is_an_error() {
+        let data = b3dm(&glb(false), &json!({ "BATCH_LENGTH": 3 }), &json!({ "name": ["a", "b"] }));
+        assert!(b3dm_to_glb(&data, false).unwrap_err().contains("BATCH_LENGTH 为3"));
+        // 没有批量表时只有要素 ID
+        let mut data = vec![];
+        MakeB3dm {
+            glb: Some(Cow::Owned(glb(false))),
+        }
+        .to_writer(&mut data, br#"{"BATCH_LENGTH":3}"#.to_vec(), vec![])
+        .unwrap();
+        let (root, _) = upgraded(&data, false);
+        assert_eq!(root["extensionsUsed"], json!(["EXT_mesh_features"]));
+        assert!(root["meshes"][0]["primitives"][0]["extensions"]["EXT_mesh_features"]["featureIds"][0]["propertyTable"].is_null());
+    }
+}
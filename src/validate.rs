@@ -0,0 +1,917 @@
+// 检查生成的 tileset：从 tileset.json 开始逐个瓦片检查包围体是否包住子瓦片和内容、几何误差是否逐级递减、
+// 内容的 uri 能否找到，再用 `inspect` 拆开内容文件检查文件头、批量表的长度和 _BATCHID，结果写成 JSON 报告
+
+use crate::draco;
+use crate::inspect::{self, TileFile};
+use crate::tileset::{apply, cartographic_to_ecef, ecef_to_cartographic, max_stretch};
+use gltf::accessor::{DataType, Dimensions};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 包围体允许超出的距离（米），容纳经纬度换算和 f32 坐标的舍入
+pub const TOLERANCE: f64 = 0.01;
+
+// 把经纬度上超出的角度换算成米时用的地球半径
+const EARTH_RADIUS: f64 = 6378137.;
+
+const IDENTITY: [f64; 16] = [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.];
+
+// glTF 的 y 轴朝上，换成 3D Tiles 的 z 轴朝上：(x, y, z) → (x, -z, y)
+const Y_UP_TO_Z_UP: [f64; 16] = [1., 0., 0., 0., 0., 0., 1., 0., 0., -1., 0., 0., 0., 0., 0., 1.];
+
+/// 报告中的一条问题：所在瓦片的路径（如 `root.children[0]`）、内容的 uri 和说明
+#[derive(Serialize)]
+pub struct Issue {
+    pub tile: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    pub message: String,
+}
+
+/// 检查结果，`errors` 为违反规范或包围体不对的问题，`warnings` 为没能检查或不影响显示的问题
+#[derive(Serialize, Default)]
+pub struct Report {
+    pub tileset: String,
+    pub valid: bool,
+    pub tiles: usize,
+    pub contents: usize,
+    pub tolerance: f64,
+    pub errors: Vec<Issue>,
+    pub warnings: Vec<Issue>,
+}
+
+// 换算到地心坐标的包围体：region 为弧度和米，box 为中心和三个半轴，sphere 为中心和半径
+enum Volume {
+    Region([f64; 6]),
+    Box([f64; 3], [[f64; 3]; 3]),
+    Sphere([f64; 3], f64),
+}
+
+// 上一级瓦片传下来的信息
+struct Parent<'a> {
+    /// tileset.json 所在的目录，内容的 uri 相对于它
+    dir: &'a Path,
+    transform: [f64; 16],
+    volume: Option<&'a Volume>,
+    geometric_error: Option<f64>,
+    up_axis: &'a str,
+}
+
+struct Validator {
+    report: Report,
+    /// 正在检查的 tileset.json，外部 tileset 循环引用时用来停下
+    stack: Vec<PathBuf>,
+}
+
+/// 检查一个 tileset.json 及其引用的全部内容
+pub fn validate(path: &str) -> Report {
+    let mut validator = Validator {
+        report: Report {
+            tileset: path.to_string(),
+            tolerance: TOLERANCE,
+            ..Default::default()
+        },
+        stack: vec![],
+    };
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let parent = Parent {
+        dir,
+        transform: IDENTITY,
+        volume: None,
+        geometric_error: None,
+        up_axis: "Y",
+    };
+    validator.tileset(Path::new(path), "", &parent, None);
+    validator.report.valid = validator.report.errors.is_empty();
+    validator.report
+}
+
+impl Validator {
+    fn error(&mut self, tile: &str, uri: Option<&str>, message: String) {
+        self.report.errors.push(Issue {
+            tile: tile.to_string(),
+            uri: uri.map(str::to_string),
+            message,
+        });
+    }
+
+    fn warning(&mut self, tile: &str, uri: Option<&str>, message: String) {
+        self.report.warnings.push(Issue {
+            tile: tile.to_string(),
+            uri: uri.map(str::to_string),
+            message,
+        });
+    }
+
+    // 检查一个 tileset.json，外部 tileset 的根瓦片接在引用它的瓦片下面
+    fn tileset(&mut self, path: &Path, name: &str, parent: &Parent, uri: Option<&str>) {
+        let canonical = path.canonicalize().unwrap_or(path.to_path_buf());
+        if self.stack.contains(&canonical) {
+            self.error(name, uri, format!("外部 tileset {}循环引用", path.display()));
+            return;
+        }
+        let tileset = match fs::read(path).map_err(|e| e.to_string()).and_then(|x| {
+            serde_json::from_slice::<Value>(&x).map_err(|e| e.to_string())
+        }) {
+            Ok(x) => x,
+            Err(e) => {
+                self.error(name, uri, format!("tileset {}读取错误: {}", path.display(), e));
+                return;
+            }
+        };
+        let version = tileset["asset"]["version"].as_str();
+        match version {
+            Some("1.0" | "1.1") => (),
+            Some(x) => self.warning(name, uri, format!("asset.version 为{}，只按 1.0 和 1.1 检查", x)),
+            None => self.error(name, uri, "缺少 asset.version".to_string()),
+        }
+        let up_axis = tileset["asset"]["gltfUpAxis"].as_str().unwrap_or("Y").to_string();
+        let geometric_error = tileset["geometricError"].as_f64();
+        match geometric_error {
+            Some(x) if x < 0. => self.error(name, uri, format!("tileset 的几何误差{}小于0", x)),
+            None => self.error(name, uri, "缺少 tileset 的 geometricError".to_string()),
+            _ => (),
+        }
+        let root_name = if name.is_empty() { "root".to_string() } else { format!("{}>root", name) };
+        if let (Some(x), Some(root)) = (geometric_error, tileset["root"]["geometricError"].as_f64()) {
+            if root > x {
+                self.warning(&root_name, None, format!("根瓦片的几何误差{}大于 tileset 的{}", root, x));
+            }
+        }
+        if !tileset["root"].is_object() {
+            self.error(name, uri, "缺少 root".to_string());
+            return;
+        }
+        self.stack.push(canonical);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let parent = Parent {
+            dir,
+            up_axis: &up_axis,
+            ..*parent
+        };
+        self.tile(&tileset["root"], &root_name, &parent);
+        self.stack.pop();
+    }
+
+    fn tile(&mut self, tile: &Value, name: &str, parent: &Parent) {
+        self.report.tiles += 1;
+        let transform = match &tile["transform"] {
+            Value::Null => parent.transform,
+            x => match numbers::<16>(x) {
+                Some(x) => multiply(&parent.transform, &x),
+                None => {
+                    self.error(name, None, "transform 必须是16个数".to_string());
+                    parent.transform
+                }
+            },
+        };
+        let volume = match volume(&tile["boundingVolume"], &transform) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                self.error(name, None, e);
+                None
+            }
+        };
+        if let (Some(outer), Some(inner)) = (parent.volume, &volume) {
+            let excess = volume_excess(outer, inner);
+            if excess > TOLERANCE {
+                self.error(name, None, format!("包围体超出父瓦片的包围体{:.3}米", excess));
+            }
+        }
+        let geometric_error = tile["geometricError"].as_f64();
+        match (geometric_error, parent.geometric_error) {
+            (None, _) => self.error(name, None, "缺少 geometricError".to_string()),
+            (Some(x), _) if x < 0. => self.error(name, None, format!("几何误差{}小于0", x)),
+            (Some(x), Some(p)) if x > p => {
+                self.error(name, None, format!("几何误差{}大于父瓦片的{}", x, p));
+            }
+            _ => (),
+        }
+        match tile["refine"].as_str() {
+            None | Some("ADD" | "REPLACE") => (),
+            Some(x) => self.error(name, None, format!("refine 为{}，应为 ADD 或 REPLACE", x)),
+        }
+
+        // 1.0 为 content，1.1 还可以是 contents 数组；更早的 tileset 用 url
+        let contents: Vec<&Value> = match (&tile["content"], &tile["contents"]) {
+            (Value::Null, Value::Array(x)) => x.iter().collect(),
+            (Value::Null, _) => vec![],
+            (x, _) => vec![x],
+        };
+        for content in contents {
+            let Some(uri) = content["uri"].as_str().or(content["url"].as_str()) else {
+                self.error(name, None, "内容缺少 uri".to_string());
+                continue;
+            };
+            let content_volume = match &content["boundingVolume"] {
+                Value::Null => None,
+                x => match crate::validate::volume(x, &transform) {
+                    Ok(x) => Some(x),
+                    Err(e) => {
+                        self.error(name, Some(uri), format!("内容的{}", e));
+                        None
+                    }
+                },
+            };
+            let volumes: Vec<(&str, &Volume)> = [("瓦片的包围体", volume.as_ref()), ("内容的包围体", content_volume.as_ref())]
+                .into_iter()
+                .filter_map(|(label, x)| Some((label, x?)))
+                .collect();
+            let parent = Parent {
+                transform,
+                volume: volume.as_ref(),
+                geometric_error: geometric_error.or(parent.geometric_error),
+                ..*parent
+            };
+            self.content(uri, name, &parent, &volumes);
+        }
+
+        if let Some(children) = tile["children"].as_array() {
+            let parent = Parent {
+                transform,
+                volume: volume.as_ref(),
+                geometric_error: geometric_error.or(parent.geometric_error),
+                ..*parent
+            };
+            children.iter().enumerate().for_each(|(i, child)| {
+                self.tile(child, &format!("{}.children[{}]", name, i), &parent);
+            });
+        }
+    }
+
+    fn content(&mut self, uri: &str, name: &str, parent: &Parent, volumes: &[(&str, &Volume)]) {
+        if uri.contains("://") || uri.starts_with("data:") {
+            self.warning(name, Some(uri), "远程或内嵌的内容，没有检查".to_string());
+            return;
+        }
+        let path = parent.dir.join(uri.split(['?', '#']).next().unwrap_or(uri));
+        let data = match fs::read(&path) {
+            Ok(x) => x,
+            Err(e) => {
+                self.error(name, Some(uri), format!("找不到内容文件{}: {}", path.display(), e));
+                return;
+            }
+        };
+        self.report.contents += 1;
+        if data.first() == Some(&b'{') || uri.ends_with(".json") {
+            self.tileset(&path, name, parent, Some(uri));
+            return;
+        }
+        self.data(&data, name, uri, parent, volumes);
+    }
+
+    // 检查一个内容文件，cmpt 逐个检查内部的瓦片
+    fn data(&mut self, data: &[u8], name: &str, uri: &str, parent: &Parent, volumes: &[(&str, &Volume)]) {
+        let mut issues = vec![];
+        match data.get(..4) {
+            Some(b"cmpt") => {
+                let tiles = inspect::parse_cmpt(data, &mut issues);
+                issues.into_iter().for_each(|x| self.error(name, Some(uri), x));
+                match tiles {
+                    Ok(tiles) => tiles.iter().enumerate().for_each(|(i, tile)| {
+                        self.data(tile, name, &format!("{}[{}]", uri, i), parent, volumes);
+                    }),
+                    Err(e) => self.error(name, Some(uri), e),
+                }
+            }
+            Some(b"glTF") => {
+                inspect::gltf_stats(data, &mut issues);
+                issues.into_iter().for_each(|x| self.error(name, Some(uri), x));
+                // 1.1 直接引用的 glb 总是 y 轴朝上
+                let mut exact = true;
+                let points = gltf_points(data, &Y_UP_TO_Z_UP, &mut exact);
+                self.points(&points, exact, &parent.transform, volumes, name, uri);
+            }
+            _ => {
+                let tile = inspect::parse_tile(data, &mut issues);
+                issues.into_iter().for_each(|x| self.error(name, Some(uri), x));
+                match tile {
+                    Ok(tile) => self.tile_file(&tile, name, uri, parent, volumes),
+                    Err(e) => self.error(name, Some(uri), e),
+                }
+            }
+        }
+    }
+
+    fn tile_file(&mut self, tile: &TileFile, name: &str, uri: &str, parent: &Parent, volumes: &[(&str, &Volume)]) {
+        let table = &tile.feature_table;
+        let binary = tile.feature_binary;
+        let mut issues = vec![];
+        let rtc = match &table["RTC_CENTER"] {
+            Value::Null => [0.; 3],
+            x => numbers::<3>(x).unwrap_or_else(|| {
+                issues.push("RTC_CENTER 必须是3个数".to_string());
+                [0.; 3]
+            }),
+        };
+        let up = match parent.up_axis {
+            "Z" | "z" => IDENTITY,
+            _ => Y_UP_TO_Z_UP,
+        };
+        let mut exact = true;
+        let mut points = vec![];
+        let batch_length = match tile.magic.as_str() {
+            "b3dm" => {
+                let batch_length = count(table, "BATCH_LENGTH", &mut issues);
+                let mut stats = vec![];
+                inspect::gltf_stats(tile.glb, &mut stats);
+                issues.extend(stats);
+                let local = multiply(&translation(rtc), &up);
+                points = gltf_points(tile.glb, &local, &mut exact);
+                let ids = batch_ids(tile.glb);
+                match (batch_length, ids.max) {
+                    (Some(n), _) if n > 0 && !ids.present => issues.push("BATCH_LENGTH 大于0，但图元没有 _BATCHID".to_string()),
+                    (Some(n), Some(max)) if max as usize >= n => {
+                        issues.push(format!("_BATCHID 最大为{}，应小于 BATCH_LENGTH {}", max, n));
+                    }
+                    _ => (),
+                }
+                if ids.compressed {
+                    self.warning(name, Some(uri), "_BATCHID 经过压缩，没有检查取值".to_string());
+                }
+                batch_length
+            }
+            "i3dm" => {
+                let instances = count(table, "INSTANCES_LENGTH", &mut issues).unwrap_or(0);
+                let ids = read_binary(table, binary, "BATCH_ID", instances, 1, "UNSIGNED_SHORT", &mut issues);
+                let model = match tile.gltf_format {
+                    // 外部模型的 uri 相对于 i3dm 所在的目录，模型的 buffer 不读，按访问器的 min/max 估计范围
+                    Some(0) => {
+                        let model = String::from_utf8_lossy(tile.glb).trim_end_matches(['\0', ' ']).to_string();
+                        let file = parent.dir.join(uri.split(['?', '#', '[']).next().unwrap_or(uri));
+                        let path = file.parent().unwrap_or(parent.dir).join(&model);
+                        match fs::read(&path) {
+                            Ok(data) if !model.contains("://") => Some(gltf_points(&data, &up, &mut exact)),
+                            Ok(_) | Err(_) => {
+                                self.warning(name, Some(uri), format!("外部模型{}读不到，没有检查实例的范围", model));
+                                None
+                            }
+                        }
+                    }
+                    _ => {
+                        let mut stats = vec![];
+                        inspect::gltf_stats(tile.glb, &mut stats);
+                        issues.extend(stats);
+                        Some(gltf_points(tile.glb, &up, &mut exact))
+                    }
+                };
+                let instance = instance_points(table, binary, instances, rtc, model.as_deref().unwrap_or(&[]), &mut issues);
+                if model.is_some() {
+                    points = instance;
+                }
+                Some(ids.map_or(instances, |x| x.iter().copied().fold(0., f64::max) as usize + 1))
+            }
+            "pnts" => {
+                let length = count(table, "POINTS_LENGTH", &mut issues).unwrap_or(0);
+                points = point_positions(table, binary, length, &mut issues)
+                    .into_iter()
+                    .map(|p| [p[0] + rtc[0], p[1] + rtc[1], p[2] + rtc[2]])
+                    .collect();
+                // 有 BATCH_ID 时批量表按 BATCH_LENGTH 计，否则每个点一条
+                match read_binary(table, binary, "BATCH_ID", length, 1, "UNSIGNED_SHORT", &mut issues) {
+                    Some(ids) => {
+                        let batch_length = count(table, "BATCH_LENGTH", &mut issues);
+                        let max = ids.iter().copied().fold(0., f64::max) as usize;
+                        if let Some(n) = batch_length.filter(|n| max >= *n) {
+                            issues.push(format!("BATCH_ID 最大为{}，应小于 BATCH_LENGTH {}", max, n));
+                        }
+                        batch_length
+                    }
+                    None => Some(length),
+                }
+            }
+            _ => unreachable!(),
+        };
+        if let (Some(batch_table), Some(n)) = (&tile.batch_table, batch_length) {
+            check_batch_table(batch_table, tile.batch_binary, n, &mut issues);
+        }
+        issues.into_iter().for_each(|x| self.error(name, Some(uri), x));
+        self.points(&points, exact, &parent.transform, volumes, name, uri);
+    }
+
+    // 内容的顶点换到地心坐标后是否都在包围体内；顶点读不出来时按访问器 min/max 的角点估计，超出时只给警告
+    fn points(
+        &mut self,
+        points: &[[f64; 3]],
+        exact: bool,
+        transform: &[f64; 16],
+        volumes: &[(&str, &Volume)],
+        name: &str,
+        uri: &str,
+    ) {
+        let world: Vec<[f64; 3]> = points.iter().map(|p| apply(transform, *p, 1.)).collect();
+        for (label, volume) in volumes {
+            let excess = world.iter().map(|p| point_excess(volume, *p, 0.)).fold(f64::MIN, f64::max);
+            if excess <= TOLERANCE {
+                continue;
+            }
+            if exact {
+                self.error(name, Some(uri), format!("内容超出{}{:.3}米", label, excess));
+            } else {
+                let message = format!("顶点经过压缩，按访问器的 min/max 估计内容超出{}{:.3}米", label, excess);
+                self.warning(name, Some(uri), message);
+            }
+        }
+    }
+}
+
+// 批量表的每个属性：JSON 数组的长度要等于批量长度，二进制引用不能超出批量表二进制部分
+fn check_batch_table(batch_table: &Value, binary: &[u8], length: usize, issues: &mut Vec<String>) {
+    let Some(properties) = batch_table.as_object() else {
+        issues.push("批量表 JSON 不是对象".to_string());
+        return;
+    };
+    for (key, value) in properties {
+        if key == "extensions" || key == "extras" {
+            continue;
+        }
+        match value {
+            Value::Array(x) if x.len() != length => {
+                issues.push(format!("批量表属性{}有{}个值，批量长度为{}", key, x.len(), length));
+            }
+            Value::Array(_) => (),
+            Value::Object(x) => {
+                let offset = x.get("byteOffset").and_then(Value::as_u64);
+                let size = x.get("componentType").and_then(Value::as_str).and_then(component_size);
+                let components = x.get("type").and_then(Value::as_str).and_then(type_components);
+                match (offset, size, components) {
+                    (Some(offset), Some(size), Some(components)) => {
+                        let end = offset as usize + size * components * length;
+                        if end > binary.len() {
+                            issues.push(format!("批量表属性{}到第{}字节，超出了二进制部分的{}字节", key, end, binary.len()));
+                        }
+                        if !(offset as usize).is_multiple_of(size) {
+                            issues.push(format!("批量表属性{}的 byteOffset {}没有按分量大小对齐", key, offset));
+                        }
+                    }
+                    _ => issues.push(format!("批量表属性{}的二进制引用缺少 byteOffset、componentType 或 type", key)),
+                }
+            }
+            _ => issues.push(format!("批量表属性{}既不是数组也不是二进制引用", key)),
+        }
+    }
+}
+
+// 要素表中的计数，如 BATCH_LENGTH、INSTANCES_LENGTH
+fn count(table: &Value, name: &str, issues: &mut Vec<String>) -> Option<usize> {
+    match table[name].as_u64() {
+        Some(x) => Some(x as usize),
+        None => {
+            issues.push(format!("要素表缺少{}或不是非负整数", name));
+            None
+        }
+    }
+}
+
+// 读取要素表二进制部分中按 `{"byteOffset": n}` 引用的属性，没有这个属性时为 None
+fn read_binary(
+    table: &Value,
+    binary: &[u8],
+    name: &str,
+    count: usize,
+    components: usize,
+    default_type: &str,
+    issues: &mut Vec<String>,
+) -> Option<Vec<f64>> {
+    let reference = table.get(name)?;
+    let Some(offset) = reference["byteOffset"].as_u64() else {
+        issues.push(format!("要素表属性{}缺少 byteOffset", name));
+        return None;
+    };
+    let component_type = reference["componentType"].as_str().unwrap_or(default_type);
+    let Some(size) = component_size(component_type) else {
+        issues.push(format!("要素表属性{}的 componentType {}不正确", name, component_type));
+        return None;
+    };
+    let start = offset as usize;
+    let end = start + size * components * count;
+    if end > binary.len() {
+        issues.push(format!("要素表属性{}到第{}字节，超出了二进制部分的{}字节", name, end, binary.len()));
+        return None;
+    }
+    Some(
+        binary[start..end]
+            .chunks_exact(size)
+            .map(|x| match (component_type, x) {
+                ("BYTE", [a]) => *a as i8 as f64,
+                ("UNSIGNED_BYTE", [a]) => *a as f64,
+                ("SHORT", [a, b]) => i16::from_le_bytes([*a, *b]) as f64,
+                ("UNSIGNED_SHORT", [a, b]) => u16::from_le_bytes([*a, *b]) as f64,
+                ("INT", x) => i32::from_le_bytes(x.try_into().unwrap()) as f64,
+                ("UNSIGNED_INT", x) => u32::from_le_bytes(x.try_into().unwrap()) as f64,
+                ("FLOAT", x) => f32::from_le_bytes(x.try_into().unwrap()) as f64,
+                (_, x) => f64::from_le_bytes(x.try_into().unwrap()),
+            })
+            .collect(),
+    )
+}
+
+fn component_size(name: &str) -> Option<usize> {
+    match name {
+        "BYTE" | "UNSIGNED_BYTE" => Some(1),
+        "SHORT" | "UNSIGNED_SHORT" => Some(2),
+        "INT" | "UNSIGNED_INT" | "FLOAT" => Some(4),
+        "DOUBLE" => Some(8),
+        _ => None,
+    }
+}
+
+fn type_components(name: &str) -> Option<usize> {
+    match name {
+        "SCALAR" => Some(1),
+        "VEC2" => Some(2),
+        "VEC3" => Some(3),
+        "VEC4" => Some(4),
+        _ => None,
+    }
+}
+
+// pnts 和 i3dm 的位置：POSITION 为 float，POSITION_QUANTIZED 按量化范围还原
+fn point_positions(table: &Value, binary: &[u8], count: usize, issues: &mut Vec<String>) -> Vec<[f64; 3]> {
+    let values = if table.get("POSITION").is_some() {
+        read_binary(table, binary, "POSITION", count, 3, "FLOAT", issues)
+    } else if table.get("POSITION_QUANTIZED").is_some() {
+        let offset = numbers::<3>(&table["QUANTIZED_VOLUME_OFFSET"]);
+        let scale = numbers::<3>(&table["QUANTIZED_VOLUME_SCALE"]);
+        let (Some(offset), Some(scale)) = (offset, scale) else {
+            issues.push("POSITION_QUANTIZED 缺少 QUANTIZED_VOLUME_OFFSET 或 QUANTIZED_VOLUME_SCALE".to_string());
+            return vec![];
+        };
+        read_binary(table, binary, "POSITION_QUANTIZED", count, 3, "UNSIGNED_SHORT", issues).map(|x| {
+            x.chunks_exact(3)
+                .flat_map(|q| [0, 1, 2].map(|k| offset[k] + q[k] / 65535. * scale[k]))
+                .collect()
+        })
+    } else {
+        issues.push("要素表缺少 POSITION 或 POSITION_QUANTIZED".to_string());
+        None
+    };
+    values
+        .map(|x| x.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect())
+        .unwrap_or_default()
+}
+
+// i3dm 各实例的模型顶点：模型先转成 z 轴朝上，再按 NORMAL_RIGHT、NORMAL_UP 旋转、按 SCALE 缩放，平移到实例的位置
+fn instance_points(
+    table: &Value,
+    binary: &[u8],
+    count: usize,
+    rtc: [f64; 3],
+    model: &[[f64; 3]],
+    issues: &mut Vec<String>,
+) -> Vec<[f64; 3]> {
+    let positions = point_positions(table, binary, count, issues);
+    let up = read_binary(table, binary, "NORMAL_UP", count, 3, "FLOAT", issues);
+    let right = read_binary(table, binary, "NORMAL_RIGHT", count, 3, "FLOAT", issues);
+    let scale = read_binary(table, binary, "SCALE", count, 1, "FLOAT", issues);
+    let non_uniform = read_binary(table, binary, "SCALE_NON_UNIFORM", count, 3, "FLOAT", issues);
+    let model = if model.is_empty() { &[[0.; 3]][..] } else { model };
+    positions
+        .iter()
+        .enumerate()
+        .flat_map(|(i, position)| {
+            let (right, up) = match (&right, &up) {
+                (Some(r), Some(u)) => ([r[i * 3], r[i * 3 + 1], r[i * 3 + 2]], [u[i * 3], u[i * 3 + 1], u[i * 3 + 2]]),
+                _ => ([1., 0., 0.], [0., 1., 0.]),
+            };
+            let forward = cross(right, up);
+            let s = match (&scale, &non_uniform) {
+                (_, Some(x)) => [x[i * 3], x[i * 3 + 1], x[i * 3 + 2]],
+                (Some(x), None) => [x[i]; 3],
+                _ => [1.; 3],
+            };
+            model.iter().map(move |p| {
+                [0, 1, 2].map(|k| {
+                    position[k] + rtc[k] + right[k] * p[0] * s[0] + up[k] * p[1] * s[1] + forward[k] * p[2] * s[2]
+                })
+            })
+        })
+        .collect()
+}
+
+// glTF 场景中各图元的顶点，已按节点的变换和 `matrix` 换算；Draco 压缩的图元解码后读出，
+// 其他压缩方式读不出来时换成访问器 min/max 的角点，并把 `exact` 置为 false
+fn gltf_points(glb: &[u8], matrix: &[f64; 16], exact: &mut bool) -> Vec<[f64; 3]> {
+    let Ok(gltf) = gltf::Gltf::from_slice_without_validation(inspect::trim_glb(glb)) else {
+        return vec![];
+    };
+    let blob = gltf.blob.as_deref();
+    let json = glb_json(glb);
+    let mut points = vec![];
+    gltf.scenes().for_each(|scene| {
+        scene.nodes().for_each(|node| node_points(&node, matrix, blob, &json, exact, &mut points));
+    });
+    points
+}
+
+fn node_points(
+    node: &gltf::Node,
+    parent: &[f64; 16],
+    blob: Option<&[u8]>,
+    json: &Value,
+    exact: &mut bool,
+    points: &mut Vec<[f64; 3]>,
+) {
+    let local: Vec<f64> = node.transform().matrix().iter().flatten().map(|x| *x as f64).collect();
+    let matrix = multiply(parent, &local.try_into().unwrap());
+    if let Some(mesh) = node.mesh() {
+        mesh.primitives().for_each(|p| {
+            let Some(accessor) = p.get(&gltf::Semantic::Positions) else { return };
+            let readable = accessor.data_type() == DataType::F32 && accessor.dimensions() == Dimensions::Vec3;
+            let values = readable
+                .then(|| accessor_data::<[f32; 3]>(&accessor, blob))
+                .flatten()
+                .map(|x| x.collect())
+                .or_else(|| draco_positions(json, blob, mesh.index(), p.index()));
+            match values {
+                Some(values) => values.iter().for_each(|v| points.push(apply(&matrix, v.map(|x| x as f64), 1.))),
+                None => {
+                    *exact = false;
+                    let (Some(min), Some(max)) = (accessor.min(), accessor.max()) else { return };
+                    let get = |v: &Value, k: usize| v.get(k).and_then(Value::as_f64).unwrap_or(0.);
+                    (0..8).for_each(|i| {
+                        let c = [0, 1, 2].map(|k| if i >> k & 1 == 1 { get(&max, k) } else { get(&min, k) });
+                        points.push(apply(&matrix, c, 1.));
+                    });
+                }
+            }
+        });
+    }
+    node.children().for_each(|child| node_points(&child, &matrix, blob, json, exact, points));
+}
+
+// glb 的 JSON 数据块，gltf 库不保留 KHR_draco_mesh_compression 扩展，这里自己解析
+fn glb_json(glb: &[u8]) -> Value {
+    let length = glb.get(12..16).map_or(0, |x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]) as usize);
+    glb.get(20..20 + length)
+        .and_then(|x| serde_json::from_slice(x).ok())
+        .unwrap_or(Value::Null)
+}
+
+// 解码 Draco 压缩的图元，返回解码结果和扩展中属性名到属性id的对应；不是 Draco 压缩或码流解不出来时返回 None
+fn draco_mesh<'a>(
+    json: &'a Value,
+    blob: Option<&[u8]>,
+    mesh: usize,
+    primitive: usize,
+) -> Option<(draco::DecodedMesh, &'a Value)> {
+    let extension = &json["meshes"][mesh]["primitives"][primitive]["extensions"]["KHR_draco_mesh_compression"];
+    let view = &json["bufferViews"][extension["bufferView"].as_u64()? as usize];
+    if view["buffer"].as_u64()? != 0 || !json["buffers"][0]["uri"].is_null() {
+        return None;
+    }
+    let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+    let length = view["byteLength"].as_u64()? as usize;
+    let data = blob?.get(offset..offset.checked_add(length)?)?;
+    Some((draco::decode_mesh(data)?, &extension["attributes"]))
+}
+
+// 解码 Draco 压缩图元的顶点位置
+fn draco_positions(json: &Value, blob: Option<&[u8]>, mesh: usize, primitive: usize) -> Option<Vec<[f32; 3]>> {
+    let (mesh, attributes) = draco_mesh(json, blob, mesh, primitive)?;
+    let (3, values) = mesh.attribute(attributes["POSITION"].as_u64()? as u32)? else {
+        return None;
+    };
+    // 只取三角形用到的顶点
+    let mut used = vec![false; values.len() / 3];
+    mesh.index.iter().flatten().for_each(|i| used[*i as usize] = true);
+    let points = values.chunks_exact(3).zip(used).filter(|x| x.1).map(|(x, _)| [x[0], x[1], x[2]]);
+    Some(points.collect())
+}
+
+// 解码 Draco 压缩图元的 _BATCHID，返回最大值
+fn draco_batch_id(json: &Value, blob: Option<&[u8]>, mesh: usize, primitive: usize) -> Option<u32> {
+    let (mesh, attributes) = draco_mesh(json, blob, mesh, primitive)?;
+    let (1, values) = mesh.attribute(attributes["_BATCHID"].as_u64()? as u32)? else {
+        return None;
+    };
+    Some(values.iter().fold(0., |a: f32, b| a.max(*b)) as u32)
+}
+
+// 图元的 _BATCHID：是否存在、能读出来时的最大值、是否有经过压缩读不出来的（meshopt 或其他编码方式的 Draco）
+#[derive(Default)]
+struct BatchIds {
+    present: bool,
+    max: Option<u32>,
+    compressed: bool,
+}
+
+fn batch_ids(glb: &[u8]) -> BatchIds {
+    let mut ids = BatchIds::default();
+    let Ok(gltf) = gltf::Gltf::from_slice_without_validation(inspect::trim_glb(glb)) else {
+        return ids;
+    };
+    let blob = gltf.blob.as_deref();
+    let json = glb_json(glb);
+    let semantic = gltf::Semantic::Extras("BATCHID".to_string());
+    for mesh in gltf.meshes() {
+        for p in mesh.primitives() {
+            let Some(accessor) = p.get(&semantic) else { continue };
+            ids.present = true;
+            let max = match accessor.data_type() {
+                DataType::U8 => accessor_data::<u8>(&accessor, blob).map(|x| x.map(u32::from).max()),
+                DataType::U16 => accessor_data::<u16>(&accessor, blob).map(|x| x.map(u32::from).max()),
+                DataType::U32 => accessor_data::<u32>(&accessor, blob).map(|x| x.max()),
+                DataType::F32 => accessor_data::<f32>(&accessor, blob).map(|x| x.map(|v| v as u32).max()),
+                _ => None,
+            };
+            // Draco 压缩后访问器没有 bufferView，解码后再取
+            let max = max.or_else(|| draco_batch_id(&json, blob, mesh.index(), p.index()).map(Some));
+            match max {
+                Some(max) => ids.max = ids.max.max(max),
+                None => ids.compressed = true,
+            }
+        }
+    }
+    ids
+}
+
+// 读取访问器的数据；只读 BIN 数据块中的、范围不越界的非稀疏访问器，Draco 压缩后没有 bufferView，
+// meshopt 压缩的 bufferView 指向没有数据的备用缓冲区，都读不出来，Draco 的顶点另见 `draco_positions`
+fn accessor_data<'a, T: gltf::accessor::Item>(
+    accessor: &gltf::Accessor<'a>,
+    blob: Option<&'a [u8]>,
+) -> Option<gltf::accessor::Iter<'a, T>> {
+    let view = accessor.view()?;
+    let blob = blob?;
+    if accessor.sparse().is_some() || view.buffer().index() != 0 || !matches!(view.buffer().source(), gltf::buffer::Source::Bin) {
+        return None;
+    }
+    let stride = view.stride().unwrap_or(accessor.size());
+    let end = accessor.offset() + stride * accessor.count().saturating_sub(1) + accessor.size();
+    if view.offset() + view.length() > blob.len() || end > view.length() || accessor.count() == 0 {
+        return None;
+    }
+    gltf::accessor::Iter::new(accessor.clone(), |buffer: gltf::Buffer| (buffer.index() == 0).then_some(blob))
+}
+
+// JSON 中的包围体按 `transform` 换到地心坐标，region 不受 transform 影响
+fn volume(json: &Value, transform: &[f64; 16]) -> Result<Volume, String> {
+    if let Some(x) = json.get("region") {
+        let r = numbers::<6>(x).ok_or("包围体 region 必须是6个数")?;
+        if r[1] > r[3] || r[4] > r[5] {
+            return Err("包围体 region 的南边大于北边或最低高度大于最高高度".to_string());
+        }
+        return Ok(Volume::Region(r));
+    }
+    if let Some(x) = json.get("box") {
+        let b = numbers::<12>(x).ok_or("包围体 box 必须是12个数")?;
+        let center = apply(transform, [b[0], b[1], b[2]], 1.);
+        let axes = [0, 1, 2].map(|k| apply(transform, [b[3 + k * 3], b[4 + k * 3], b[5 + k * 3]], 0.));
+        return Ok(Volume::Box(center, axes));
+    }
+    if let Some(x) = json.get("sphere") {
+        let s = numbers::<4>(x).ok_or("包围体 sphere 必须是4个数")?;
+        if s[3] < 0. {
+            return Err("包围体 sphere 的半径小于0".to_string());
+        }
+        return Ok(Volume::Sphere(apply(transform, [s[0], s[1], s[2]], 1.), s[3] * max_stretch(transform)));
+    }
+    Err("缺少包围体，或包围体不是 region、box、sphere".to_string())
+}
+
+// 内层包围体超出外层的最大距离（米），不超出时不大于0；region 套 region、球套球、球放进盒子直接比较，
+// 其余按内层包围体表面上的采样点计算
+fn volume_excess(outer: &Volume, inner: &Volume) -> f64 {
+    match (outer, inner) {
+        (Volume::Region(o), Volume::Region(i)) => {
+            let lat = o[1].abs().max(o[3].abs()).max(i[1].abs()).max(i[3].abs());
+            let horizontal = [o[0] - i[0], i[2] - o[2]].map(|x| x * EARTH_RADIUS * lat.cos());
+            let vertical = [o[1] - i[1], i[3] - o[3]].map(|x| x * EARTH_RADIUS);
+            horizontal.into_iter().chain(vertical).chain([o[4] - i[4], i[5] - o[5]]).fold(f64::MIN, f64::max)
+        }
+        (Volume::Sphere(..) | Volume::Box(..), Volume::Sphere(center, radius)) => point_excess(outer, *center, *radius),
+        _ => samples(inner).into_iter().map(|p| point_excess(outer, p, 0.)).fold(f64::MIN, f64::max),
+    }
+}
+
+// 以 `p` 为中心、半径为 `radius` 的球超出包围体的距离（米）；region 只用于点
+fn point_excess(volume: &Volume, p: [f64; 3], radius: f64) -> f64 {
+    match volume {
+        Volume::Region(r) => {
+            let [lon, lat, h] = ecef_to_cartographic(p);
+            let (lon, lat) = (lon.to_radians(), lat.to_radians());
+            let horizontal = f64::max(r[0] - lon, lon - r[2]) * EARTH_RADIUS * lat.cos();
+            let vertical = f64::max(r[1] - lat, lat - r[3]) * EARTH_RADIUS;
+            horizontal.max(vertical).max(r[4] - h).max(h - r[5])
+        }
+        Volume::Sphere(center, r) => distance(p, *center) + radius - r,
+        Volume::Box(center, axes) => {
+            // 半轴不一定正交，按半轴组成的矩阵的逆求点在盒子中的坐标，逆矩阵的各行为三组对面的法向
+            let Some(inverse) = inverse(axes) else {
+                return f64::MAX;
+            };
+            let d = sub(p, *center);
+            (0..3)
+                .map(|k| {
+                    let t = dot(inverse[k], d).abs();
+                    let length = dot(axes[k], axes[k]).sqrt();
+                    let normal = dot(inverse[k], inverse[k]).sqrt();
+                    (t + radius * normal - 1.) * length
+                })
+                .fold(f64::MIN, f64::max)
+        }
+    }
+}
+
+// 包围体表面上的采样点（地心坐标）：盒子每个面取 5×5 个点，球按经纬网取点，region 的顶面和底面各取 9×9 个点
+fn samples(volume: &Volume) -> Vec<[f64; 3]> {
+    match volume {
+        Volume::Box(center, axes) => {
+            let steps = [-1., -0.5, 0., 0.5, 1.];
+            let mut points = vec![];
+            for k in 0..3 {
+                for side in [-1., 1.] {
+                    for u in steps {
+                        for v in steps {
+                            let t = [(k, side), ((k + 1) % 3, u), ((k + 2) % 3, v)];
+                            points.push([0, 1, 2].map(|n| center[n] + t.iter().map(|(i, s)| axes[*i][n] * s).sum::<f64>()));
+                        }
+                    }
+                }
+            }
+            points
+        }
+        Volume::Sphere(center, radius) => {
+            let mut points = vec![[center[0], center[1], center[2] + radius], [center[0], center[1], center[2] - radius]];
+            for i in 1..12 {
+                let theta = std::f64::consts::PI * i as f64 / 12.;
+                for j in 0..24 {
+                    let phi = std::f64::consts::PI * j as f64 / 12.;
+                    let u = [theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()];
+                    points.push([0, 1, 2].map(|k| center[k] + u[k] * radius));
+                }
+            }
+            points
+        }
+        Volume::Region(r) => {
+            let mut points = vec![];
+            for h in [r[4], r[5]] {
+                for i in 0..9 {
+                    for j in 0..9 {
+                        let lon = r[0] + (r[2] - r[0]) * i as f64 / 8.;
+                        let lat = r[1] + (r[3] - r[1]) * j as f64 / 8.;
+                        points.push(cartographic_to_ecef(lon.to_degrees(), lat.to_degrees(), h));
+                    }
+                }
+            }
+            points
+        }
+    }
+}
+
+// 以三个半轴为列的矩阵的逆，按行返回；半轴退化时为 None
+fn inverse(axes: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let [a, b, c] = *axes;
+    let det = dot(a, cross(b, c));
+    let scale = [a, b, c].iter().map(|x| dot(*x, *x).sqrt()).product::<f64>();
+    if det.abs() <= scale * 1e-12 || scale == 0. {
+        return None;
+    }
+    Some([cross(b, c), cross(c, a), cross(a, b)].map(|row| row.map(|x| x / det)))
+}
+
+// 列主序的 4×4 矩阵相乘
+fn multiply(a: &[f64; 16], b: &[f64; 16]) -> [f64; 16] {
+    let mut m = [0.; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            m[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k]).sum();
+        }
+    }
+    m
+}
+
+fn translation(t: [f64; 3]) -> [f64; 16] {
+    let mut m = IDENTITY;
+    m[12..15].copy_from_slice(&t);
+    m
+}
+
+fn numbers<const N: usize>(json: &Value) -> Option<[f64; N]> {
+    let values: Vec<f64> = json.as_array()?.iter().map(Value::as_f64).collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let d = sub(a, b);
+    dot(d, d).sqrt()
+}
@@ -0,0 +1,247 @@
+// 运行命令行程序做完整的转换，检查写出的瓦片和 tileset.json
+
+use shapefile::dbase::{FieldValue, Record, TableWriterBuilder};
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// 每个测试用自己的临时目录，程序在其中运行，瓦片写到其中的 b3dm 目录
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("shp_to_3dtiles_convert").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_shp_to_3dtiles"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "{:?}: {}{}", args, stdout, String::from_utf8_lossy(&output.stderr));
+    stdout
+}
+
+// 中心在 (lon, lat)、半径约 r 度、`n` 个点的多边形；r 为0时所有点重合，生成不了网格
+fn ring(lon: f64, lat: f64, r: f64, n: usize) -> Vec<shapefile::Point> {
+    let mut points: Vec<shapefile::Point> = (0..n)
+        .map(|i| {
+            let a = -(i as f64) / n as f64 * std::f64::consts::TAU;
+            shapefile::Point::new(lon + r * a.cos(), lat + r * a.sin())
+        })
+        .collect();
+    points.push(points[0]);
+    points
+}
+
+// 面图层，每个要素为 (经度, 半径, 点数)，高度依次为 10、20、30……
+fn polygons(dir: &Path, features: &[(f64, f64, usize)]) -> String {
+    let path = dir.join("poly.shp");
+    let table = TableWriterBuilder::new().add_float_field("height".try_into().unwrap(), 10, 2);
+    let writer = shapefile::Writer::from_path(&path, table).unwrap();
+    let shapes: Vec<shapefile::Polygon> = features
+        .iter()
+        .map(|(lon, r, n)| shapefile::Polygon::new(shapefile::PolygonRing::Outer(ring(*lon, 39.9, *r, *n))))
+        .collect();
+    let records: Vec<Record> = (0..features.len())
+        .map(|i| {
+            let mut record = Record::default();
+            record.insert("height".to_string(), FieldValue::Float(Some(10. * (i + 1) as f32)));
+            record
+        })
+        .collect();
+    writer.write_shapes_and_records(shapes.iter().zip(records.iter())).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn points(dir: &Path, lons: &[f64]) -> String {
+    let path = dir.join("points.shp");
+    let table = TableWriterBuilder::new().add_numeric_field("scale".try_into().unwrap(), 10, 2);
+    let writer = shapefile::Writer::from_path(&path, table).unwrap();
+    let shapes: Vec<shapefile::Point> = lons.iter().map(|x| shapefile::Point::new(*x, 39.9)).collect();
+    let records: Vec<Record> = lons
+        .iter()
+        .map(|_| {
+            let mut record = Record::default();
+            record.insert("scale".to_string(), FieldValue::Numeric(Some(1.)));
+            record
+        })
+        .collect();
+    writer.write_shapes_and_records(shapes.iter().zip(records.iter())).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+// 没有网格的 glb 模型，buffer 等都省略
+fn model(dir: &Path) -> String {
+    let mut json = br#"{"asset":{"version":"2.0"},"scene":0,"scenes":[{"nodes":[]}]}"#.to_vec();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let mut data = b"glTF".to_vec();
+    [2, 20 + json.len() as u32, json.len() as u32, 0x4E4F534A]
+        .iter()
+        .for_each(|x| data.extend(x.to_le_bytes()));
+    data.extend(json);
+    let path = dir.join("tree.glb");
+    std::fs::write(&path, data).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn tileset(dir: &Path) -> serde_json::Value {
+    serde_json::from_slice(&std::fs::read(dir.join("b3dm/tileset.json")).unwrap()).unwrap()
+}
+
+fn inner_magics(data: &[u8]) -> Vec<String> {
+    assert_eq!(&data[..4], b"cmpt");
+    let count = u32::from_le_bytes(data[12..16].try_into().unwrap());
+    let mut offset = 16;
+    (0..count)
+        .map(|_| {
+            let magic = String::from_utf8_lossy(&data[offset..offset + 4]).into_owned();
+            offset += u32::from_le_bytes(data[offset + 8..offset + 12].try_into().unwrap()) as usize;
+            magic
+        })
+        .collect()
+}
+
+#[test]
+fn instances_are_kept_in_tiles_without_buildings() {
+    let dir = dir("points");
+    // 每个圆 200 个点，约 0.8MB，--max-memory 1 时三个要素各成一个瓦片，第三个瓦片只有一个生成不了网格的面
+    let poly = polygons(&dir, &[(116.39, 0.0002, 200), (116.40, 0.0002, 200), (116.41, 0., 260)]);
+    let points = points(&dir, &[116.39, 116.41, 116.4101]);
+    let model = model(&dir);
+    let stdout = run(&dir, &[&poly, "height", "--points", &points, "--model", &model, "--max-memory", "1"]);
+    assert!(stdout.contains("按内存上限分成3个瓦片"), "{}", stdout);
+
+    let tileset = tileset(&dir);
+    let children = tileset["root"]["children"].as_array().unwrap();
+    let uris: Vec<&str> = children.iter().map(|x| x["content"]["uri"].as_str().unwrap()).collect();
+    assert_eq!(uris, ["0.cmpt", "1.cmpt", "2.cmpt"]);
+    let magics: Vec<Vec<String>> = uris
+        .iter()
+        .map(|uri| inner_magics(&std::fs::read(dir.join("b3dm").join(uri)).unwrap()))
+        .collect();
+    assert_eq!(magics, [vec!["b3dm", "i3dm"], vec!["b3dm"], vec!["i3dm"]]);
+    let data = std::fs::read(dir.join("b3dm/2.cmpt")).unwrap();
+    let json_length = u32::from_le_bytes(data[28..32].try_into().unwrap()) as usize;
+    let feature: serde_json::Value = serde_json::from_slice(&data[48..48 + json_length]).unwrap();
+    assert_eq!(feature["INSTANCES_LENGTH"], 2);
+
+    let report = run(&dir, &["validate", "b3dm/tileset.json"]);
+    assert!(report.contains("\"valid\": true"), "{}", report);
+}
+
+// b3dm 目录下的全部文件，按文件名排列
+fn outputs(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(dir.join("b3dm"))
+        .unwrap()
+        .map(|x| {
+            let path = x.unwrap().path();
+            (path.file_name().unwrap().to_str().unwrap().to_string(), std::fs::read(&path).unwrap())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn output_does_not_depend_on_threads() {
+    // 西边两个圆各220个点约0.9MB，东边六个各20个点约80KB，--max-memory 1 时分成大小不一的4个瓦片，
+    // 小瓦片可以同时处理
+    let features: Vec<(f64, f64, usize)> = (0..8)
+        .map(|i| (116.39 + i as f64 * 0.001, 0.0002, if i < 2 { 220 } else { 20 }))
+        .collect();
+    let mut runs = vec![];
+    for threads in ["1", "8"] {
+        let dir = dir(&format!("threads{}", threads));
+        let poly = polygons(&dir, &features);
+        let points = points(&dir, &[116.39, 116.3935, 116.397]);
+        let model = model(&dir);
+        let args = [&poly, "height", "--points", &points, "--model", &model, "--max-memory", "1", "--ao", "8", "--threads", threads];
+        let stdout = run(&dir, &args);
+        assert!(stdout.contains("按内存上限分成4个瓦片"), "{}", stdout);
+        runs.push(outputs(&dir));
+    }
+    assert_eq!(runs[0].len(), 5);
+    assert_eq!(runs[0], runs[1]);
+}
+
+// 运行 validate 子命令，返回退出码和 JSON 报告
+fn validate(dir: &Path) -> (Option<i32>, serde_json::Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_shp_to_3dtiles"))
+        .args(["validate", "b3dm/tileset.json"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    (output.status.code(), serde_json::from_slice(&output.stdout).unwrap())
+}
+
+// 报告中某个瓦片的错误说明
+fn errors(report: &serde_json::Value, tile: &str) -> Vec<String> {
+    report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|x| x["tile"] == tile)
+        .map(|x| x["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+// 把瓦片要素表中的 BATCH_LENGTH 改成0，长度不变
+fn clear_batch_length(path: &Path) {
+    let mut data = std::fs::read(path).unwrap();
+    let key = b"\"BATCH_LENGTH\":1";
+    let at = data.windows(key.len()).position(|x| x == key).unwrap();
+    data[at + key.len() - 1] = b'0';
+    std::fs::write(path, data).unwrap();
+}
+
+#[test]
+fn validator_reports_broken_tileset() {
+    let dir = dir("validate");
+    let poly = polygons(&dir, &[(116.39, 0.0002, 200), (116.40, 0.0002, 200), (116.41, 0.0002, 200)]);
+    run(&dir, &[&poly, "height", "--max-memory", "1"]);
+    let (code, report) = validate(&dir);
+    assert_eq!((code, &report["valid"]), (Some(0), &serde_json::json!(true)), "{}", report);
+
+    // 第一个子瓦片移出父瓦片，第二个的几何误差大于父瓦片，第三个的内容找不到
+    let mut tileset = tileset(&dir);
+    let children = tileset["root"]["children"].as_array_mut().unwrap();
+    let region = children[0]["boundingVolume"]["region"].as_array_mut().unwrap();
+    for k in [0, 2] {
+        region[k] = serde_json::json!(region[k].as_f64().unwrap() + 0.001);
+    }
+    children[1]["geometricError"] = serde_json::json!(500.);
+    children[2]["content"]["uri"] = serde_json::json!("missing.b3dm");
+    std::fs::write(dir.join("b3dm/tileset.json"), tileset.to_string()).unwrap();
+    let (code, report) = validate(&dir);
+    assert_eq!((code, &report["valid"]), (Some(1), &serde_json::json!(false)), "{}", report);
+    let first = errors(&report, "root.children[0]");
+    assert!(first[0].starts_with("包围体超出父瓦片的包围体"), "{:?}", first);
+    assert!(first.iter().any(|x| x.starts_with("内容超出瓦片的包围体")), "{:?}", first);
+    assert_eq!(errors(&report, "root.children[1]"), ["几何误差500大于父瓦片的200"]);
+    let third = errors(&report, "root.children[2]");
+    assert!(third.len() == 1 && third[0].starts_with("找不到内容文件"), "{:?}", third);
+    assert!(errors(&report, "root").is_empty());
+}
+
+#[test]
+fn validator_checks_batch_length() {
+    for (name, compression) in [("batch", None), ("batch_draco", Some("--draco")), ("batch_meshopt", Some("--meshopt"))] {
+        let dir = dir(name);
+        let poly = polygons(&dir, &[(116.39, 0.0002, 20)]);
+        let mut args = vec![poly.as_str(), "height"];
+        args.extend(compression.iter().flat_map(|x| [*x, "14"]));
+        run(&dir, &args);
+        clear_batch_length(&dir.join("b3dm/0.b3dm"));
+        let (code, report) = validate(&dir);
+        assert_eq!(code, Some(1), "{}", report);
+        let messages = errors(&report, "root.children[0]");
+        assert!(messages.iter().any(|x| x.starts_with("批量表属性") && x.ends_with("有1个值，批量长度为0")), "{:?}", messages);
+        // Draco 压缩的 _BATCHID 解码后检查，meshopt 压缩的只给警告
+        let checked = messages.contains(&"_BATCHID 最大为0，应小于 BATCH_LENGTH 0".to_string());
+        let skipped = report["warnings"].to_string().contains("_BATCHID 经过压缩，没有检查取值");
+        assert_eq!((checked, skipped), (compression != Some("--meshopt"), compression == Some("--meshopt")), "{}", report);
+    }
+}